@@ -1,65 +1,303 @@
+use crate::cfg::ExpireBackend;
 use crate::ssh::SshSession;
+use crate::util;
 
 use anyhow::{bail, Context, Result};
 use chrono::prelude::*;
-use humantime::parse_duration;
-use std::path::Path;
+use chrono::{Days, LocalResult};
+use itertools::Itertools;
+use regex::Regex;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 use std::time::Duration;
 
-/// Wrapper to at-system on the remote side.
+/// Pending expiration job on the remote side.
+pub struct ExpirationJob {
+    /// Job id as reported by `atq` (or name of the systemd unit or marker of the crontab entry).
+    pub id: String,
+
+    /// Scheduler the job was submitted to.
+    pub backend: ExpireBackend,
+
+    /// File to expire (relative to the remote base folder).
+    pub path: PathBuf,
+
+    /// When the job runs (in the local time of the remote site), if it could be determined.
+    pub date: Option<NaiveDateTime>,
+}
+
+/// Expiration scheduled via `At::expire`.
+pub struct Scheduled {
+    /// Expected expiration date.
+    pub date: DateTime<Local>,
+
+    /// Job id as reported by `at` (or name of the systemd unit or marker of the crontab entry),
+    /// if it could be determined.
+    pub id: Option<String>,
+}
+
+/// List all pending jobs created by `At::expire` for files in the host's base folder, regardless
+/// of the scheduler they were submitted to.
+pub fn list_jobs(session: &SshSession) -> Result<Vec<ExpirationJob>> {
+    if !session.host.exec {
+        log::debug!("Remote commands disabled, no expiration jobs to list.");
+        return Ok(vec![]);
+    }
+    let capabilities = session.capabilities()?;
+    let bsd_atq = capabilities.bsd_atq();
+    let mut listings = Vec::new();
+    if capabilities.has("atq") {
+        let job_ids = if bsd_atq {
+            "atq | awk 'NR > 1 {print $NF}'"
+        } else {
+            "atq | awk '{print $1}'"
+        };
+        listings.push(format!(
+            "atq | sed 's/^/atq /'; \
+            for job in $({}); do \
+                printf 'job %s\\n' \"$job\"; at -c \"$job\" | grep -E \"^rm (-r )?'.*' && rmdir '\"; \
+            done",
+            job_ids
+        ));
+    }
+    if capabilities.has("systemd-run") {
+        listings.push(format!(
+            "systemctl --user list-timers --no-legend '{prefix}*' 2>/dev/null | sed 's/^/timer /'; \
+            systemctl --user show --property=Id,ExecStart '{prefix}*.service' 2>/dev/null \
+                | sed 's/^/unit /'",
+            prefix = JOB_PREFIX
+        ));
+    }
+    if capabilities.has("crontab") {
+        listings.push(format!(
+            "crontab -l 2>/dev/null | grep -F '# {}' | sed 's/^/cron /'",
+            JOB_PREFIX
+        ));
+    }
+    if listings.is_empty() {
+        log::debug!("No scheduler available at remote site.");
+        return Ok(vec![]);
+    }
+
+    let listing = session
+        .exec_remote(&listings.join("; "))?
+        .expect("Could not list pending expiration jobs.")?;
+
+    let mut dates = HashMap::new();
+    let mut timers = HashMap::new();
+    let mut scripts = Vec::new();
+    let mut at_job = None;
+    // Properties of a unit are printed in a block of their own, in no particular order.
+    let mut unit = (None, None);
+    for line in listing.stdout().lines().chain(std::iter::once("unit ")) {
+        if let Some(queued) = line.strip_prefix("atq ") {
+            dates.extend(parse_atq_line(queued, bsd_atq));
+        } else if let Some(id) = line.strip_prefix("job ") {
+            at_job = Some(id.to_string());
+        } else if let Some(timer) = line.strip_prefix("timer ") {
+            timers.extend(parse_timer_line(timer));
+        } else if let Some(property) = line.strip_prefix("unit ") {
+            if let Some(id) = property.strip_prefix("Id=") {
+                unit.0 = id.strip_suffix(".service").map(str::to_string);
+            } else if let Some(exec) = property.strip_prefix("ExecStart=") {
+                unit.1 = Some(exec.to_string());
+            } else if property.is_empty() {
+                if let (Some(id), Some(exec)) = std::mem::take(&mut unit) {
+                    scripts.push((ExpireBackend::Systemd, id, exec));
+                }
+            }
+        } else if let Some(entry) = line.strip_prefix("cron ") {
+            if let Some((marker, date, script)) = parse_cron_entry(entry) {
+                dates.insert(marker.clone(), date);
+                scripts.push((ExpireBackend::Cron, marker, script));
+            }
+        } else if let Some(id) = at_job.as_ref() {
+            scripts.push((ExpireBackend::At, id.clone(), line.to_string()));
+        }
+    }
+
+    let mut jobs = Vec::new();
+    for (backend, id, script) in scripts {
+        let date = match backend {
+            // Units whose timer is gone (e.g. already elapsed) never run.
+            ExpireBackend::Systemd => match timers.get(&id) {
+                Some(date) => *date,
+                None => continue,
+            },
+            _ => dates.get(&id).cloned(),
+        };
+        let path = match expired_path(&script) {
+            Some(path) => path,
+            None => continue,
+        };
+        if let Ok(path) = path.strip_prefix(&session.host.folder) {
+            jobs.push(ExpirationJob {
+                id,
+                backend,
+                path: path.to_path_buf(),
+                date,
+            });
+        }
+    }
+    Ok(jobs)
+}
+
+/// Postpone the given job by the given duration, keeping its script. Returns the new date (in
+/// the local time of the remote site).
+///
+/// Only jobs submitted to `at` can be postponed, others have to be replaced.
+pub fn postpone_job(
+    session: &SshSession,
+    job: &ExpirationJob,
+    by: Duration,
+) -> Result<NaiveDateTime> {
+    if job.backend != ExpireBackend::At {
+        bail!(
+            "Expiration of {} is scheduled via `{}` and cannot be postponed, use \
+            `asfa expire --set <delay>` to replace it.",
+            job.path.display(),
+            tool(job.backend)
+        );
+    }
+    let date = job.date.with_context(|| {
+        format!(
+            "Could not determine when job {} expiring {} runs.",
+            job.id,
+            job.path.display()
+        )
+    })?;
+    let postponed = date + chrono::Duration::from_std(by)?;
+    // Resubmit the exact script of the job before removing it, so that it is never lost.
+    session
+        .exec_remote(&format!(
+            "tmp=\"$(mktemp)\" && at -c '{id}' > \"$tmp\" && at -t {time} -f \"$tmp\"; \
+            rc=$?; rm -f \"$tmp\"; [ $rc -eq 0 ] && atrm '{id}'",
+            id = job.id,
+            time = postponed.format("%Y%m%d%H%M.%S")
+        ))?
+        .expect("Could not postpone expiration job.")?;
+    Ok(postponed)
+}
+
+/// Remove the given pending job.
+pub fn remove_job(session: &SshSession, job: &ExpirationJob) -> Result<()> {
+    let cmd = match job.backend {
+        ExpireBackend::Systemd => format!(
+            "systemctl --user stop '{id}.timer' '{id}.service'",
+            id = job.id
+        ),
+        ExpireBackend::Cron => format!("crontab -l | grep -vF '{}' | crontab -", job.id),
+        ExpireBackend::At | ExpireBackend::Auto => format!("atrm '{}'", job.id),
+    };
+    session
+        .exec_remote(&cmd)?
+        .expect("Could not remove expiration job.")?;
+    Ok(())
+}
+
+/// Expiration settings that disable a default expiration, e.g. `--expire none`.
+pub const DISABLED: &[&str] = &["no", "none", "disabled", "false"];
+
+/// Minimum delay until files are expired.
+const MIN_DELAY: Duration = Duration::from_secs(60);
+
+/// Marker identifying crontab entries and systemd units created by `At::expire`.
+const JOB_PREFIX: &str = "asfa-expire-";
+
+/// Wrapper to the scheduler on the remote side (`at`, or transient systemd timers and crontab
+/// entries if `at` is not available, see `expire_backend`).
 pub struct At<'a> {
     session: &'a SshSession<'a>,
     duration: Duration,
+    backend: ExpireBackend,
 }
 
 impl<'a> At<'a> {
-    /// Check if `at` is available on the remote side and return wrapper.
-    ///
-    /// If it is, return Some(At), otherwise return None.
+    /// Check if a scheduler is available on the remote side and return wrapper.
     pub fn new(session: &'a SshSession<'a>, human_duration: &str) -> Result<Self> {
-        let duration = parse_duration(human_duration)
-            .with_context(|| format!("Could not parse duration: {}", human_duration))?;
+        let duration = parse_delay(human_duration, Local::now())?;
 
-        if duration < Duration::from_secs(60) {
+        if duration < MIN_DELAY {
             bail!("Expiration delay needs to be at least one minute!");
         }
 
-        let which = session.exec_remote("which at")?;
-
-        match which.exit_status() {
-            0 => Ok(Self { session, duration }),
-            s => {
-                log::debug!(
-                    "Checking for `at` command returned {}. Stdout: {} Stderr: {}",
-                    s,
-                    which.stdout(),
-                    which.stderr()
-                );
-                bail!("`at` command not available at remote site.");
-            }
+        if !session.host.exec {
+            bail!(
+                "Expiration is not available for host '{}' because it does not allow to execute \
+                remote commands (`exec: false`).",
+                session.host.alias
+            );
+        }
+        let backend = Self::backend(session)?;
+        log::debug!("Scheduling expirations via `{}`.", tool(backend));
+        Ok(Self {
+            session,
+            duration,
+            backend,
+        })
+    }
+
+    /// Resolve the configured backend depending on the tools available at the remote site.
+    fn backend(session: &SshSession) -> Result<ExpireBackend> {
+        let capabilities = session.capabilities()?;
+        let candidates: &[ExpireBackend] = match session.host.expire_backend {
+            ExpireBackend::Auto => &[
+                ExpireBackend::At,
+                ExpireBackend::Systemd,
+                ExpireBackend::Cron,
+            ],
+            ExpireBackend::At => &[ExpireBackend::At],
+            ExpireBackend::Systemd => &[ExpireBackend::Systemd],
+            ExpireBackend::Cron => &[ExpireBackend::Cron],
+        };
+        match candidates
+            .iter()
+            .find(|backend| capabilities.has(tool(**backend)))
+        {
+            Some(backend) => Ok(*backend),
+            None if candidates.len() == 1 => bail!(
+                "`{}` command not available at remote site (run `asfa doctor` after installing it).",
+                tool(candidates[0])
+            ),
+            None => bail!(
+                "Neither `at`, `systemd-run` nor `crontab` available at remote site (run `asfa \
+                doctor` after installing one of them)."
+            ),
         }
     }
 
     /// Expire the given path relative to the remote base folder.
     ///
-    /// First expires the file the parent folder.
+    /// First expires the file (or folder, e.g. of a recursively uploaded directory), then the
+    /// parent folder.
+    ///
+    /// Any paths in `also_remove` (relative to the remote base folder) are removed recursively
+    /// prior to the file.
     ///
-    /// Returns the expected expiration date.
-    pub fn expire(&self, path: &Path) -> Result<DateTime<Local>> {
+    /// Returns the expected expiration date and the id of the created job.
+    pub fn expire(&self, path: &Path, also_remove: &[PathBuf]) -> Result<Scheduled> {
         let stat = self
             .session
             .stat_single(path)
             .with_context(|| "File to expire missing.")?;
 
-        if !stat.is_file() {
+        let rm = if stat.is_file() {
+            "rm"
+        } else if stat.is_dir() {
+            "rm -r"
+        } else {
             bail!("Object to expire is no file: {}", path.display());
-        }
+        };
         let now = Local::now();
 
-        let tempfile = self.session.mktemp()?;
-
-        let cmd_rm = format!(
-            "#!/usr/bin/env bash\nrm '{}' && rmdir '{}'",
+        let mut commands: Vec<String> = also_remove
+            .iter()
+            .map(|p| format!("rm -rf '{}'", self.session.prepend_base_folder(p).display()))
+            .collect();
+        commands.push(format!(
+            "{} '{}' && rmdir '{}'",
+            rm,
             self.session.prepend_base_folder(path).display(),
             self.session
                 .prepend_base_folder(path.parent().with_context(|| format!(
@@ -67,9 +305,29 @@ impl<'a> At<'a> {
                     path.display()
                 ))?)
                 .display()
+        ));
+
+        let id = match self.backend {
+            ExpireBackend::Systemd => Some(self.submit_systemd(&commands)?),
+            ExpireBackend::Cron => Some(self.submit_cron(&commands)?),
+            ExpireBackend::At | ExpireBackend::Auto => self.submit_at(&commands)?,
+        };
+
+        self.session.audit(
+            &format!("expire in {}", humantime::format_duration(self.duration)),
+            &[path],
         );
 
-        tempfile.write_str(&cmd_rm)?;
+        Ok(Scheduled {
+            date: now + chrono::Duration::from_std(self.duration)?,
+            id,
+        })
+    }
+
+    /// Submit the given commands as `at`-job, returning its id if it could be determined.
+    fn submit_at(&self, commands: &[String]) -> Result<Option<String>> {
+        let tempfile = self.session.mktemp()?;
+        tempfile.write_str(&format!("#!/usr/bin/env bash\n{}", commands.join("\n")))?;
 
         let cmd_at = format!(
             "at -f '{}' now + {} minutes",
@@ -98,11 +356,439 @@ impl<'a> At<'a> {
                     .join("\n")
             );
         }
+        Ok(parse_job_id(submission.stderr()))
+    }
+
+    /// Run the given commands from a transient timer of the user's service manager, returning the
+    /// name of the unit.
+    ///
+    /// Transient timers do not survive a reboot of the remote site and only run while the user's
+    /// service manager does (see `loginctl enable-linger`), which is warned about once if lingering
+    /// is disabled.
+    fn submit_systemd(&self, commands: &[String]) -> Result<String> {
+        static LINGER_CHECKED: OnceLock<()> = OnceLock::new();
+
+        let unit = format!("{}{}", JOB_PREFIX, util::random_token(8));
+        let submitted = self
+            .session
+            .exec_remote(&format!(
+                "systemd-run --user --quiet --collect --unit={unit} --on-active={secs}s \
+                /bin/sh -c {script} && \
+                {{ loginctl show-user \"$(id -un)\" --property=Linger --value 2>/dev/null; true; }}",
+                unit = unit,
+                secs = self.duration.as_secs(),
+                script = util::shell_quote(&commands.join("; "))
+            ))?
+            .expect("Could not set remote expiration.")?;
+        if submitted.stdout().trim() == "no" && LINGER_CHECKED.set(()).is_ok() {
+            log::warn!(
+                "Lingering is disabled for your user on the remote site: The expiration only \
+                happens while you are logged in there (see `loginctl enable-linger`) and is lost \
+                on reboot."
+            );
+        }
+        Ok(unit)
+    }
 
-        Ok(now + chrono::Duration::from_std(self.duration)?)
+    /// Add a crontab entry running the given commands (and removing the entry itself), returning
+    /// the marker of the entry.
+    fn submit_cron(&self, commands: &[String]) -> Result<String> {
+        let marker = format!("{}{}", JOB_PREFIX, util::random_token(8));
+        // Cron runs in the time zone of the remote site.
+        let remote_now = self
+            .session
+            .exec_remote("date '+%s %z'")?
+            .expect("Could not determine time of remote site.")?;
+        let when = cron_time(remote_now.stdout(), self.duration)?;
+        let entry = cron_entry(&when, commands, &marker);
+        self.session
+            .exec_remote(&format!(
+                "(crontab -l 2>/dev/null; printf '%s\\n' {}) | crontab -",
+                util::shell_quote(&entry)
+            ))?
+            .expect("Could not set remote expiration.")?;
+        Ok(marker)
+    }
+
+    /// Command to run on the remote site to cancel the jobs with the given ids.
+    pub fn cancel_command(&self, ids: &[String]) -> String {
+        match self.backend {
+            ExpireBackend::Systemd => format!(
+                "systemctl --user stop {}",
+                ids.iter().map(|id| format!("{}.timer", id)).join(" ")
+            ),
+            ExpireBackend::Cron => format!(
+                "crontab -l | grep -vF {} | crontab -",
+                ids.iter().map(|id| format!("-e {}", id)).join(" ")
+            ),
+            ExpireBackend::At | ExpireBackend::Auto => format!("atrm {}", ids.join(" ")),
+        }
+    }
+
+    /// Tool used to schedule expirations, e.g. `at`.
+    pub fn tool(&self) -> &'static str {
+        tool(self.backend)
+    }
+
+    /// Delay after which paths are expired.
+    pub fn duration(&self) -> Duration {
+        self.duration
     }
 
     fn num_mins(&self) -> u64 {
         self.duration.as_secs() / 60
     }
 }
+
+/// Tool the given backend needs on the remote site.
+fn tool(backend: ExpireBackend) -> &'static str {
+    match backend {
+        ExpireBackend::At | ExpireBackend::Auto => "at",
+        ExpireBackend::Systemd => "systemd-run",
+        ExpireBackend::Cron => "crontab",
+    }
+}
+
+/// Local time of the remote site after the given delay (rounded up to the next minute), given
+/// the output of `date '+%s %z'` on the remote site.
+fn cron_time(remote_now: &str, delay: Duration) -> Result<DateTime<FixedOffset>> {
+    let parse = || -> Option<DateTime<FixedOffset>> {
+        let (epoch, offset) = remote_now.trim().split_once(' ')?;
+        let epoch: i64 = epoch.parse().ok()?;
+        let offset: i32 = offset.parse().ok()?;
+        let offset = FixedOffset::east_opt((offset / 100 * 60 + offset % 100) * 60)?;
+        let target = (epoch + delay.as_secs() as i64 + 59) / 60 * 60;
+        offset.timestamp_opt(target, 0).single()
+    };
+    parse().with_context(|| format!("Could not parse time of remote site: {}", remote_now))
+}
+
+/// Crontab entry running the given commands once at the given time and then removing itself
+/// (identified by the given marker).
+///
+/// Cron has no notion of years, so the commands are skipped in all other years.
+fn cron_entry(when: &DateTime<FixedOffset>, commands: &[String], marker: &str) -> String {
+    let script = format!(
+        "[ \"$(date +%Y)\" = {year} ] || exit 0; {commands}; \
+        crontab -l | grep -vF {marker} | crontab - # {marker}",
+        year = when.year(),
+        commands = commands.join("; "),
+        marker = marker
+    );
+    format!(
+        "{minute} {hour} {day} {month} * {script}",
+        minute = when.minute(),
+        hour = when.hour(),
+        day = when.day(),
+        month = when.month(),
+        // Cron turns unescaped `%` into newlines.
+        script = script.replace('%', "\\%")
+    )
+}
+
+/// Parse the delay until expiration, given either as duration (e.g. `3days`) or as calendar
+/// phrase, in which case files expire at the end of the given day:
+///
+/// * `today`, `tomorrow`
+/// * `<weekday>` or `next <weekday>`, e.g. `next monday` (never today)
+/// * `end of week`, `end of month`, `end of year`
+/// * explicit dates, e.g. `2024-12-24`
+pub fn parse_delay(input: &str, now: DateTime<Local>) -> Result<Duration> {
+    if let Ok(duration) = humantime::parse_duration(input) {
+        return Ok(duration);
+    }
+    let phrase = input.trim().to_lowercase();
+    let phrase = phrase.split_whitespace().collect::<Vec<_>>().join(" ");
+    let today = now.date_naive();
+
+    let day = match phrase.as_str() {
+        "today" | "end of day" => Some(today),
+        "tomorrow" => today.succ_opt(),
+        "end of week" => {
+            today.checked_add_days(Days::new(6 - today.weekday().num_days_from_monday() as u64))
+        }
+        "end of month" => {
+            let (year, month) = match today.month() {
+                12 => (today.year() + 1, 1),
+                month => (today.year(), month + 1),
+            };
+            NaiveDate::from_ymd_opt(year, month, 1).and_then(|first| first.pred_opt())
+        }
+        "end of year" => NaiveDate::from_ymd_opt(today.year(), 12, 31),
+        phrase => {
+            let weekday = phrase.strip_prefix("next ").unwrap_or(phrase);
+            match (
+                weekday.parse::<Weekday>(),
+                NaiveDate::parse_from_str(phrase, "%Y-%m-%d"),
+            ) {
+                (Ok(weekday), _) => {
+                    let days_ahead = (7 + weekday.num_days_from_monday()
+                        - today.weekday().num_days_from_monday())
+                        % 7;
+                    today.checked_add_days(Days::new(if days_ahead == 0 {
+                        7
+                    } else {
+                        days_ahead as u64
+                    }))
+                }
+                (_, Ok(date)) => Some(date),
+                _ => bail!(
+                    "Could not parse expiration (expected a duration such as '3days' or a phrase \
+                    such as 'next monday' or 'end of month'): {}",
+                    input
+                ),
+            }
+        }
+    }
+    .with_context(|| format!("Invalid date for expiration: {}", input))?;
+
+    let end_of_day = match Local.from_local_datetime(&day.and_hms_opt(23, 59, 0).unwrap()) {
+        LocalResult::Single(end) | LocalResult::Ambiguous(end, _) => end,
+        LocalResult::None => bail!("Invalid local time for expiration: {}", input),
+    };
+    if end_of_day <= now {
+        bail!("Expiration lies in the past: {}", input);
+    }
+    Ok((end_of_day - now).to_std()?)
+}
+
+/// Check an expiration setting from config (see `DISABLED` and `parse_delay`), so that typos are
+/// reported when loading the config rather than when pushing.
+pub fn validate_delay(input: &str) -> Result<()> {
+    if DISABLED.contains(&input) {
+        return Ok(());
+    }
+    if let Ok(duration) = humantime::parse_duration(input) {
+        if duration < MIN_DELAY {
+            bail!(
+                "Expiration delay needs to be at least one minute: {}",
+                input
+            );
+        }
+        return Ok(());
+    }
+    // Relative to the start of the day, so that e.g. `today` is valid at any time.
+    let start_of_day = Local
+        .from_local_datetime(&Local::now().date_naive().and_hms_opt(0, 0, 0).unwrap())
+        .earliest()
+        .unwrap_or_else(Local::now);
+    parse_delay(input, start_of_day).map(|_| ())
+}
+
+/// Extract job id and date from a line printed by `atq`, e.g. `5 Thu Jun 30 10:00:00 2022 a user`
+/// or `Thu Jun 30 10:00:00 CEST 2022 user a 5` (FreeBSD).
+fn parse_atq_line(line: &str, bsd_atq: bool) -> Option<(String, NaiveDateTime)> {
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    let (id, date) = if bsd_atq {
+        // Skip the time zone.
+        let date = [tokens.get(..4)?, &tokens.get(5..6)?].concat();
+        (*tokens.last()?, date)
+    } else {
+        (*tokens.first()?, tokens.get(1..6)?.to_vec())
+    };
+    let date = NaiveDateTime::parse_from_str(&date.join(" "), "%a %b %d %H:%M:%S %Y").ok()?;
+    Some((id.to_string(), date))
+}
+
+/// Extract unit id and date from a line printed by `systemctl list-timers`, e.g.
+/// `Thu 2022-06-30 13:00:00 CEST 2h left n/a n/a asfa-expire-abc.timer asfa-expire-abc.service`.
+fn parse_timer_line(line: &str) -> Option<(String, Option<NaiveDateTime>)> {
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    let id = tokens
+        .iter()
+        .find_map(|t| t.strip_suffix(".timer"))
+        .filter(|id| id.starts_with(JOB_PREFIX))?;
+    let date = tokens
+        .get(1..3)
+        .and_then(|date| NaiveDateTime::parse_from_str(&date.join(" "), "%Y-%m-%d %H:%M:%S").ok());
+    Some((id.to_string(), date))
+}
+
+/// Extract marker, date and script from a crontab entry created by `cron_entry`.
+fn parse_cron_entry(entry: &str) -> Option<(String, NaiveDateTime, String)> {
+    let entry = entry.replace("\\%", "%");
+    let (_, marker) = entry.rsplit_once(&format!("# {}", JOB_PREFIX))?;
+    let fields: Vec<u32> = entry
+        .split_whitespace()
+        .take(4)
+        .map(|f| f.parse().ok())
+        .collect::<Option<_>>()?;
+    let year = entry
+        .split_once("\" = ")?
+        .1
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()?;
+    let date = NaiveDate::from_ymd_opt(year, fields[3], fields[2])?
+        .and_hms_opt(fields[1], fields[0], 0)?;
+    Some((
+        format!("{}{}", JOB_PREFIX, marker.trim()),
+        date,
+        entry.clone(),
+    ))
+}
+
+/// Path removed by the script of an expiration job (see `At::expire`).
+fn expired_path(script: &str) -> Option<PathBuf> {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"(?:^|[ ;])rm (?:-r )?'([^']*)' && rmdir '").unwrap())
+        .captures(script)
+        .map(|captures| PathBuf::from(&captures[1]))
+}
+
+/// Extract the job id from the output of `at`, e.g. `job 5 at Thu Jun 30 10:00:00 2022` (GNU) or
+/// `Job 5 will be executed using /bin/sh` (BSD).
+fn parse_job_id(output: &str) -> Option<String> {
+    output.lines().find_map(|line| {
+        let mut words = line.split_whitespace();
+        match (words.next(), words.next()) {
+            (Some(job), Some(id)) if job.eq_ignore_ascii_case("job") => Some(id.to_string()),
+            _ => None,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn calendar_phrases() {
+        // Wednesday
+        let now = Local.with_ymd_and_hms(2024, 5, 15, 12, 0, 0).unwrap();
+        let expires = |phrase: &str| {
+            now + chrono::Duration::from_std(parse_delay(phrase, now).unwrap()).unwrap()
+        };
+        let end_of =
+            |month: u32, day: u32| Local.with_ymd_and_hms(2024, month, day, 23, 59, 0).unwrap();
+
+        assert_eq!(
+            parse_delay("3days", now).unwrap(),
+            Duration::from_secs(3 * 86400)
+        );
+        assert_eq!(expires("tomorrow"), end_of(5, 16));
+        assert_eq!(expires("next Monday"), end_of(5, 20));
+        assert_eq!(expires("wednesday"), end_of(5, 22));
+        assert_eq!(expires("end of week"), end_of(5, 19));
+        assert_eq!(expires("end  of month"), end_of(5, 31));
+        assert_eq!(expires("end of year"), end_of(12, 31));
+        assert_eq!(expires("2024-06-01"), end_of(6, 1));
+        assert!(parse_delay("2024-05-01", now).is_err());
+        assert!(parse_delay("someday", now).is_err());
+    }
+
+    #[test]
+    fn validate_config_delays() {
+        for valid in [
+            "3days",
+            "1h 30m",
+            "none",
+            "today",
+            "next monday",
+            "end of month",
+        ] {
+            assert!(validate_delay(valid).is_ok(), "{}", valid);
+        }
+        for invalid in ["3dayz", "30s", "2000-01-01", "someday"] {
+            assert!(validate_delay(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn parse_atq() {
+        let date = NaiveDate::from_ymd_opt(2022, 6, 3)
+            .unwrap()
+            .and_hms_opt(10, 0, 0)
+            .unwrap();
+        assert_eq!(
+            parse_atq_line("5\tFri Jun  3 10:00:00 2022 a user", false),
+            Some(("5".to_string(), date))
+        );
+        assert_eq!(
+            parse_atq_line("Fri Jun  3 10:00:00 CEST 2022 user a 5", true),
+            Some(("5".to_string(), date))
+        );
+        assert_eq!(
+            parse_atq_line("Date                          Owner      Queue Job#", true),
+            None
+        );
+    }
+
+    #[test]
+    fn job_id_from_at_output() {
+        assert_eq!(
+            parse_job_id("warning: commands will be executed using /bin/sh\njob 5 at Thu Jun 30 10:00:00 2022\n"),
+            Some("5".to_string())
+        );
+        assert_eq!(
+            parse_job_id("Job 12 will be executed using /bin/sh\n"),
+            Some("12".to_string())
+        );
+        assert_eq!(parse_job_id("No atd running?\n"), None);
+    }
+
+    #[test]
+    fn crontab_entry() {
+        // 2022-06-30 09:59:30 UTC, two hours ahead on the remote site.
+        let when = cron_time("1656583170 +0200\n", Duration::from_secs(3600)).unwrap();
+        assert_eq!(when.to_rfc3339(), "2022-06-30T13:00:00+02:00".to_string());
+        assert_eq!(
+            cron_time("1656583170 -0530", Duration::from_secs(60))
+                .unwrap()
+                .to_rfc3339(),
+            "2022-06-30T04:31:00-05:30".to_string()
+        );
+        assert!(cron_time("Thu Jun 30", Duration::from_secs(60)).is_err());
+
+        let entry = cron_entry(
+            &when,
+            &["rm '/var/www/a/100%.txt' && rmdir '/var/www/a'".to_string()],
+            "asfa-expire-abc",
+        );
+        assert_eq!(
+            entry,
+            "0 13 30 6 * [ \"$(date +\\%Y)\" = 2022 ] || exit 0; \
+            rm '/var/www/a/100\\%.txt' && rmdir '/var/www/a'; \
+            crontab -l | grep -vF asfa-expire-abc | crontab - # asfa-expire-abc"
+        );
+    }
+
+    #[test]
+    fn parse_jobs_of_other_backends() {
+        let date = NaiveDate::from_ymd_opt(2022, 6, 30)
+            .unwrap()
+            .and_hms_opt(13, 0, 0)
+            .unwrap();
+        assert_eq!(
+            parse_timer_line(
+                "Thu 2022-06-30 13:00:00 CEST 2h left n/a n/a asfa-expire-abc.timer \
+                asfa-expire-abc.service"
+            ),
+            Some(("asfa-expire-abc".to_string(), Some(date)))
+        );
+        assert_eq!(
+            parse_timer_line("Thu 2022-06-30 13:00:00 CEST 2h left n/a n/a other.timer"),
+            None
+        );
+
+        let entry = cron_entry(
+            &DateTime::parse_from_rfc3339("2022-06-30T13:00:00+02:00").unwrap(),
+            &["rm '/var/www/a/100%.txt' && rmdir '/var/www/a'".to_string()],
+            "asfa-expire-abc",
+        );
+        let (marker, parsed, script) = parse_cron_entry(&entry).unwrap();
+        assert_eq!((marker.as_str(), parsed), ("asfa-expire-abc", date));
+        assert_eq!(
+            expired_path(&script),
+            Some(PathBuf::from("/var/www/a/100%.txt"))
+        );
+        assert_eq!(
+            expired_path(
+                "{ path=/bin/sh ; argv[]=/bin/sh -c rm -rf '/var/www/b'; rm -r '/var/www/a/dir' \
+                && rmdir '/var/www/a' ; ignore_errors=no }"
+            ),
+            Some(PathBuf::from("/var/www/a/dir"))
+        );
+    }
+}
@@ -0,0 +1,301 @@
+use anyhow::{bail, Context, Result};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs::{read_dir, File};
+use std::io::{self, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::thread;
+
+use crate::util;
+
+/// Archive format for uploading directories, selected via `push --archive` or
+/// `push_directories`.
+#[derive(clap::ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    #[clap(name = "tar.gz")]
+    TarGz,
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// File extension (including the leading dot).
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ArchiveFormat::TarGz => ".tar.gz",
+            ArchiveFormat::Zip => ".zip",
+        }
+    }
+}
+
+/// A tar.gz-archive of several local files and folders that is generated on the fly.
+///
+/// The archive is generated deterministically (sorted entries, normalized metadata), so that it
+/// can be generated once to compute hash and size and then again while uploading, without ever
+/// storing it locally.
+pub struct Bundle {
+    inputs: Vec<PathBuf>,
+    hash: String,
+    size: u64,
+}
+
+impl Bundle {
+    /// Create a new bundle of the given inputs and compute its hash and size.
+    pub fn new(inputs: &[PathBuf], prefix_length: u8) -> Result<Self> {
+        if inputs.is_empty() {
+            bail!("No files to bundle specified.");
+        }
+        for input in inputs.iter() {
+            if !input.exists() {
+                bail!("File to bundle does not exist: {}", input.display());
+            }
+        }
+        let mut bundle = Self {
+            inputs: inputs.to_vec(),
+            hash: String::new(),
+            size: 0,
+        };
+
+        let mut reader = CountingReader::new(bundle.reader());
+        bundle.hash = util::get_hash_from(&mut reader, prefix_length)
+            .context("Could not compute hash of bundle.")?;
+        bundle.size = reader.count;
+        log::debug!("Bundle size: {} bytes, hash: {}", bundle.size, bundle.hash);
+
+        Ok(bundle)
+    }
+
+    /// Hash of the archive.
+    pub fn hash(&self) -> &str {
+        &self.hash
+    }
+
+    /// Size of the archive in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Generate the archive in a background thread and return a reader for its contents.
+    pub fn reader(&self) -> impl Read {
+        let (tx, rx) = sync_channel(16);
+        let inputs = self.inputs.clone();
+        thread::spawn(move || {
+            let writer = ChannelWriter { tx: tx.clone() };
+            if let Err(e) = write_archive(&inputs, writer) {
+                // If the receiver hung up, there is nobody to report the error to.
+                let _ = tx.send(Err(e.to_string()));
+            }
+        });
+        ChannelReader {
+            rx,
+            buffer: Vec::new(),
+            pos: 0,
+        }
+    }
+}
+
+/// Write tar.gz-archive of all inputs to the given writer.
+fn write_archive<W: Write>(inputs: &[PathBuf], writer: W) -> Result<()> {
+    let mut builder = tar::Builder::new(GzEncoder::new(writer, Compression::default()));
+    builder.mode(tar::HeaderMode::Deterministic);
+    builder.follow_symlinks(true);
+
+    for input in inputs.iter() {
+        let name = input
+            .file_name()
+            .with_context(|| format!("{} has no filename.", input.display()))?;
+        walk(input, Path::new(name), &mut |path, name| {
+            builder
+                .append_path_with_name(path, name)
+                .with_context(|| format!("Could not add {} to bundle.", path.display()))
+        })?;
+    }
+    builder.into_inner()?.finish()?;
+    Ok(())
+}
+
+/// Write a zip-archive of all inputs to the given local file.
+///
+/// Just like bundles, the archive is created deterministically (sorted entries, normalized
+/// metadata), so that archiving the same files twice results in the same URL.
+pub fn write_zip<W: Write + Seek>(inputs: &[PathBuf], target: W) -> Result<()> {
+    let mut zip = zip::ZipWriter::new(target);
+    let options = zip::write::FileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated)
+        .last_modified_time(zip::DateTime::default());
+
+    for input in inputs.iter() {
+        let name = input
+            .file_name()
+            .with_context(|| format!("{} has no filename.", input.display()))?;
+        walk(input, Path::new(name), &mut |path, name| {
+            // Zip-archives always use forward slashes.
+            let name = name
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            if path.is_dir() {
+                zip.add_directory(name, options.unix_permissions(0o755))?;
+            } else {
+                zip.start_file(name, options.unix_permissions(0o644))?;
+                io::copy(&mut File::open(path)?, &mut zip)
+                    .with_context(|| format!("Could not add {} to archive.", path.display()))?;
+            }
+            Ok(())
+        })?;
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+/// All files in the given folder (recursively, in sorted order) together with their path relative
+/// to the folder.
+pub fn files_recursively(folder: &Path) -> Result<Vec<(PathBuf, PathBuf)>> {
+    let mut files = Vec::new();
+    walk(folder, Path::new(""), &mut |path, name| {
+        if !path.is_dir() {
+            files.push((path.to_path_buf(), name.to_path_buf()));
+        }
+        Ok(())
+    })?;
+    Ok(files)
+}
+
+/// Index of files uploaded recursively: one line per file with its hash and relative path.
+///
+/// It is stored next to the uploaded folder and names the hash folder, so that recursive uploads
+/// are listed (and verified) like any other upload.
+pub fn manifest(files: &[(PathBuf, PathBuf)], length: u8) -> Result<String> {
+    let mut manifest = String::new();
+    for (path, name) in files.iter() {
+        manifest.push_str(&format!(
+            "{}  {}\n",
+            util::get_hash(path, length)
+                .with_context(|| format!("Could not hash {}", path.display()))?,
+            name.display()
+        ));
+    }
+    Ok(manifest)
+}
+
+/// Visit the given path (named `name` in the archive) and, if it is a folder, all contained
+/// entries in sorted order.
+fn walk(path: &Path, name: &Path, visit: &mut dyn FnMut(&Path, &Path) -> Result<()>) -> Result<()> {
+    visit(path, name)?;
+
+    if path.is_dir() {
+        let mut entries = read_dir(path)?
+            .map(|e| e.map(|e| e.path()))
+            .collect::<io::Result<Vec<_>>>()?;
+        entries.sort();
+        for entry in entries {
+            let entry_name = name.join(entry.file_name().unwrap());
+            walk(&entry, &entry_name, visit)?;
+        }
+    }
+    Ok(())
+}
+
+/// Forwards all written data to a channel.
+struct ChannelWriter {
+    tx: SyncSender<Result<Vec<u8>, String>>,
+}
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.tx
+            .send(Ok(buf.to_vec()))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "Reader hung up."))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Reads all data sent by a ChannelWriter.
+struct ChannelReader {
+    rx: Receiver<Result<Vec<u8>, String>>,
+    buffer: Vec<u8>,
+    pos: usize,
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos >= self.buffer.len() {
+            match self.rx.recv() {
+                Ok(Ok(data)) => {
+                    self.buffer = data;
+                    self.pos = 0;
+                }
+                Ok(Err(e)) => return Err(io::Error::other(e)),
+                // Writer finished
+                Err(_) => return Ok(0),
+            }
+        }
+        let num = std::cmp::min(buf.len(), self.buffer.len() - self.pos);
+        buf[..num].copy_from_slice(&self.buffer[self.pos..self.pos + num]);
+        self.pos += num;
+        Ok(num)
+    }
+}
+
+/// Counts all bytes read through it.
+struct CountingReader<R: Read> {
+    inner: R,
+    count: u64,
+}
+
+impl<R: Read> CountingReader<R> {
+    fn new(inner: R) -> Self {
+        Self { inner, count: 0 }
+    }
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let num = self.inner.read(buf)?;
+        self.count += num as u64;
+        Ok(num)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundle_is_deterministic() {
+        let inputs = vec![PathBuf::from("example-config"), PathBuf::from("Cargo.toml")];
+        let first = Bundle::new(&inputs, 32).unwrap();
+        let second = Bundle::new(&inputs, 32).unwrap();
+        assert_eq!(first.hash(), second.hash());
+        assert_eq!(first.size(), second.size());
+
+        let mut streamed = Vec::new();
+        first.reader().read_to_end(&mut streamed).unwrap();
+        assert_eq!(streamed.len() as u64, first.size());
+        assert_eq!(
+            util::get_hash_from(&streamed[..], 32).unwrap(),
+            first.hash()
+        );
+    }
+
+    #[test]
+    fn zip_is_deterministic() {
+        let inputs = vec![PathBuf::from("example-config")];
+        let mut first = io::Cursor::new(Vec::new());
+        let mut second = io::Cursor::new(Vec::new());
+        write_zip(&inputs, &mut first).unwrap();
+        write_zip(&inputs, &mut second).unwrap();
+        assert_eq!(first.into_inner(), second.into_inner());
+
+        let files = files_recursively(Path::new("example-config")).unwrap();
+        assert!(files.contains(&(
+            PathBuf::from("example-config/asfa/config.yaml"),
+            PathBuf::from("asfa/config.yaml")
+        )));
+    }
+}
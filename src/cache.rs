@@ -0,0 +1,459 @@
+//! Local cache of the files present on a remote site as well as of past upload throughput,
+//! shortened URLs and scheduled expirations.
+//!
+//! The cache is updated whenever asfa lists, uploads or removes files and allows cheap queries
+//! (such as `status --cached`) without any network access.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use expanduser::expanduser;
+use std::fs::{create_dir_all, read_to_string, remove_file, write, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+/// Serializes read-modify-write cycles of cache files between threads (e.g. `push --jobs`).
+static LOCK: Mutex<()> = Mutex::new(());
+
+fn lock() -> MutexGuard<'static, ()> {
+    // A panicking thread cannot leave a cache file in an inconsistent state in memory.
+    LOCK.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Files present on a remote site (relative to the base folder), oldest upload first.
+pub struct Cache {
+    path: PathBuf,
+    files: Vec<PathBuf>,
+}
+
+impl Cache {
+    /// Load the cache for the given host. A missing cache is treated as empty.
+    pub fn load(alias: &str) -> Result<Self> {
+        let path = cache_dir()?.join(alias);
+        let files = if path.exists() {
+            read_to_string(&path)
+                .with_context(|| format!("Could not read cache: {}", path.display()))?
+                .lines()
+                .map(PathBuf::from)
+                .collect()
+        } else {
+            Vec::new()
+        };
+        Ok(Self { path, files })
+    }
+
+    /// Write the cache to disk.
+    pub fn store(&self) -> Result<()> {
+        if let Some(folder) = self.path.parent() {
+            create_dir_all(folder)?;
+        }
+        let mut content = String::new();
+        for file in self.files.iter() {
+            content.push_str(&file.to_string_lossy());
+            content.push('\n');
+        }
+        write(&self.path, content)
+            .with_context(|| format!("Could not write cache: {}", self.path.display()))
+    }
+
+    /// All cached files, oldest upload first.
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files[..]
+    }
+
+    /// Replace all cached files with a fresh listing (oldest upload first).
+    pub fn set_files(&mut self, files: &[PathBuf]) {
+        self.files = files.to_vec();
+    }
+
+    /// Record a newly uploaded file.
+    pub fn add(&mut self, file: &Path) {
+        self.files.retain(|f| f != file);
+        self.files.push(file.to_path_buf());
+    }
+
+    /// Replace the given file with its new location, keeping its position.
+    pub fn rename(&mut self, old: &Path, new: &Path) {
+        for file in self.files.iter_mut().filter(|f| *f == old) {
+            *file = new.to_path_buf();
+        }
+    }
+
+    /// Move all files in the given folder to a new folder, keeping their position.
+    pub fn move_folder(&mut self, old: &Path, new: &Path) {
+        for file in self.files.iter_mut() {
+            if let Ok(rest) = file.strip_prefix(old) {
+                *file = new.join(rest);
+            }
+        }
+    }
+
+    /// Forget all files in the given folder.
+    pub fn remove_folder(&mut self, folder: &Path) {
+        self.files.retain(|f| !f.starts_with(folder));
+    }
+}
+
+/// Recorded throughput of the most recent uploads to a host.
+pub struct Throughput {
+    path: PathBuf,
+    /// Bytes uploaded and seconds it took, oldest upload first.
+    samples: Vec<(u64, f64)>,
+}
+
+impl Throughput {
+    /// How many uploads to base estimates on.
+    const NUM_SAMPLES: usize = 20;
+
+    /// Load recorded throughput for the given host. Missing records are treated as empty.
+    pub fn load(alias: &str) -> Result<Self> {
+        let path = cache_dir()?.join(format!("{}.throughput", alias));
+        let samples = if path.exists() {
+            read_to_string(&path)
+                .with_context(|| format!("Could not read cache: {}", path.display()))?
+                .lines()
+                .filter_map(|l| {
+                    let (bytes, secs) = l.split_once('\t')?;
+                    Some((bytes.parse().ok()?, secs.parse().ok()?))
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+        Ok(Self { path, samples })
+    }
+
+    /// Record an upload of the given size that took the given time.
+    ///
+    /// Failing to record is never fatal, it is only reported.
+    pub fn record(alias: &str, bytes: u64, duration: Duration) {
+        let _lock = lock();
+        let recorded = Self::load(alias).and_then(|mut throughput| {
+            throughput.samples.push((bytes, duration.as_secs_f64()));
+            let num_samples = throughput.samples.len();
+            if num_samples > Self::NUM_SAMPLES {
+                throughput.samples.drain(..num_samples - Self::NUM_SAMPLES);
+            }
+            throughput.store()
+        });
+        if let Err(e) = recorded {
+            log::debug!("Could not record throughput: {}", e);
+        }
+    }
+
+    fn store(&self) -> Result<()> {
+        if let Some(folder) = self.path.parent() {
+            create_dir_all(folder)?;
+        }
+        let content: String = self
+            .samples
+            .iter()
+            .map(|(bytes, secs)| format!("{}\t{}\n", bytes, secs))
+            .collect();
+        write(&self.path, content)
+            .with_context(|| format!("Could not write cache: {}", self.path.display()))
+    }
+
+    /// Estimate how long uploading the given number of bytes will take.
+    pub fn estimate(&self, bytes: u64) -> Option<Duration> {
+        let bytes_total: u64 = self.samples.iter().map(|(b, _)| b).sum();
+        let secs_total: f64 = self.samples.iter().map(|(_, s)| s).sum();
+        if bytes_total == 0 || secs_total <= 0.0 {
+            return None;
+        }
+        Some(Duration::from_secs_f64(
+            bytes as f64 * secs_total / bytes_total as f64,
+        ))
+    }
+}
+
+/// Journal of URLs shortened for a host.
+pub struct ShortUrls {
+    path: PathBuf,
+    /// Long and short URL, oldest first.
+    urls: Vec<(String, String)>,
+}
+
+impl ShortUrls {
+    /// Load shortened URLs for the given host. A missing journal is treated as empty.
+    pub fn load(alias: &str) -> Result<Self> {
+        let path = cache_dir()?.join(format!("{}.short", alias));
+        let urls = if path.exists() {
+            read_to_string(&path)
+                .with_context(|| format!("Could not read cache: {}", path.display()))?
+                .lines()
+                .filter_map(|l| {
+                    let (long, short) = l.split_once('\t')?;
+                    Some((long.to_string(), short.to_string()))
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+        Ok(Self { path, urls })
+    }
+
+    /// Short URL previously recorded for the given long URL.
+    pub fn get(&self, long: &str) -> Option<&str> {
+        self.urls
+            .iter()
+            .rev()
+            .find(|(l, _)| l == long)
+            .map(|(_, s)| s.as_str())
+    }
+
+    /// Record the short URL for the given long URL.
+    ///
+    /// Failing to record is never fatal, it is only reported.
+    pub fn record(alias: &str, long: &str, short: &str) {
+        let _lock = lock();
+        let recorded = Self::load(alias).and_then(|mut urls| {
+            urls.urls.push((long.to_string(), short.to_string()));
+            urls.store()
+        });
+        if let Err(e) = recorded {
+            log::debug!("Could not record short URL: {}", e);
+        }
+    }
+
+    fn store(&self) -> Result<()> {
+        if let Some(folder) = self.path.parent() {
+            create_dir_all(folder)?;
+        }
+        let content: String = self
+            .urls
+            .iter()
+            .map(|(long, short)| format!("{}\t{}\n", long, short))
+            .collect();
+        write(&self.path, content)
+            .with_context(|| format!("Could not write cache: {}", self.path.display()))
+    }
+}
+
+/// Expiration of an uploaded file as scheduled by asfa.
+pub struct Expiration {
+    pub date: DateTime<Local>,
+    /// Path relative to the base folder.
+    pub path: PathBuf,
+    pub url: String,
+}
+
+/// Journal of expirations scheduled for a host, so that upcoming expirations can be shown without
+/// network access (see `expire --upcoming`).
+pub struct Expirations {
+    path: PathBuf,
+    /// Pending expirations, soonest first.
+    expirations: Vec<Expiration>,
+}
+
+impl Expirations {
+    /// Load expirations for the given host, dropping those that already happened. A missing
+    /// journal is treated as empty.
+    pub fn load(alias: &str) -> Result<Self> {
+        let path = cache_dir()?.join(format!("{}.expirations", alias));
+        let now = Local::now();
+        let mut expirations: Vec<_> = if path.exists() {
+            read_to_string(&path)
+                .with_context(|| format!("Could not read cache: {}", path.display()))?
+                .lines()
+                .filter_map(|l| {
+                    let mut fields = l.splitn(3, '\t');
+                    Some(Expiration {
+                        date: DateTime::parse_from_rfc3339(fields.next()?)
+                            .ok()?
+                            .with_timezone(&Local),
+                        path: PathBuf::from(fields.next()?),
+                        url: fields.next()?.to_string(),
+                    })
+                })
+                .filter(|e| e.date > now)
+                .collect()
+        } else {
+            Vec::new()
+        };
+        expirations.sort_by_key(|e| e.date);
+        Ok(Self { path, expirations })
+    }
+
+    /// Pending expirations, soonest first.
+    pub fn pending(&self) -> &[Expiration] {
+        &self.expirations[..]
+    }
+
+    /// Record the expiration of the given file, replacing a previously recorded one.
+    ///
+    /// Failing to record is never fatal, it is only reported.
+    pub fn record(alias: &str, expiration: Expiration) {
+        let _lock = lock();
+        let recorded = Self::load(alias).and_then(|mut expirations| {
+            expirations
+                .expirations
+                .retain(|e| e.path != expiration.path);
+            expirations.expirations.push(expiration);
+            expirations.expirations.sort_by_key(|e| e.date);
+            expirations.store()
+        });
+        if let Err(e) = recorded {
+            log::debug!("Could not record expiration: {}", e);
+        }
+    }
+
+    /// Postpone the recorded expiration of the given file (if any) by the given duration.
+    ///
+    /// Failing to update the journal is never fatal, it is only reported.
+    pub fn postpone(alias: &str, path: &Path, by: chrono::Duration) {
+        let _lock = lock();
+        let updated = Self::load(alias).and_then(|mut expirations| {
+            let mut changed = false;
+            for e in expirations
+                .expirations
+                .iter_mut()
+                .filter(|e| e.path == path)
+            {
+                e.date += by;
+                changed = true;
+            }
+            if !changed {
+                return Ok(());
+            }
+            expirations.expirations.sort_by_key(|e| e.date);
+            expirations.store()
+        });
+        if let Err(e) = updated {
+            log::debug!("Could not update recorded expirations: {}", e);
+        }
+    }
+
+    /// Forget expirations of all files in the given folder (e.g. once it was removed).
+    ///
+    /// Failing to update the journal is never fatal, it is only reported.
+    pub fn forget_folder(alias: &str, folder: &Path) {
+        let _lock = lock();
+        let updated = Self::load(alias).and_then(|mut expirations| {
+            let num_recorded = expirations.expirations.len();
+            expirations
+                .expirations
+                .retain(|e| !e.path.starts_with(folder));
+            if expirations.expirations.len() == num_recorded {
+                return Ok(());
+            }
+            expirations.store()
+        });
+        if let Err(e) = updated {
+            log::debug!("Could not update recorded expirations: {}", e);
+        }
+    }
+
+    fn store(&self) -> Result<()> {
+        if let Some(folder) = self.path.parent() {
+            create_dir_all(folder)?;
+        }
+        let content: String = self
+            .expirations
+            .iter()
+            .map(|e| format!("{}\t{}\t{}\n", e.date.to_rfc3339(), e.path.display(), e.url))
+            .collect();
+        write(&self.path, content)
+            .with_context(|| format!("Could not write cache: {}", self.path.display()))
+    }
+}
+
+/// Journal of a multi-file push to a host, so that an interrupted push can be continued (see
+/// `push --continue`).
+///
+/// Each file to upload is recorded as `file\t<local path>\t<alias>` when the push starts and
+/// marked as `done\t<local path>` once uploaded.
+pub struct PushJournal;
+
+impl PushJournal {
+    fn path(alias: &str) -> Result<PathBuf> {
+        Ok(cache_dir()?.join(format!("{}.push-journal", alias)))
+    }
+
+    /// Files (with their aliases) of an interrupted push to the given host that were not uploaded
+    /// yet, None if there is no interrupted push.
+    pub fn pending(alias: &str) -> Result<Option<Vec<(PathBuf, String)>>> {
+        let path = Self::path(alias)?;
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = read_to_string(&path)
+            .with_context(|| format!("Could not read cache: {}", path.display()))?;
+        let done: Vec<&str> = content
+            .lines()
+            .filter_map(|l| l.strip_prefix("done\t"))
+            .collect();
+        Ok(Some(
+            content
+                .lines()
+                .filter_map(|l| l.strip_prefix("file\t")?.split_once('\t'))
+                .filter(|(file, _)| !done.contains(file))
+                .map(|(file, alias)| (PathBuf::from(file), alias.to_string()))
+                .collect(),
+        ))
+    }
+
+    /// Start a new journal for the given files (and their aliases), replacing any previous one.
+    ///
+    /// Failing to record is never fatal, it is only reported.
+    pub fn start(alias: &str, files: &[(PathBuf, String)]) {
+        let started = Self::path(alias).and_then(|path| {
+            if let Some(folder) = path.parent() {
+                create_dir_all(folder)?;
+            }
+            let content: String = files
+                .iter()
+                .map(|(file, alias)| format!("file\t{}\t{}\n", file.display(), alias))
+                .collect();
+            Ok(write(path, content)?)
+        });
+        if let Err(e) = started {
+            log::debug!("Could not start push journal: {}", e);
+        }
+    }
+
+    /// Mark the given file as uploaded.
+    ///
+    /// Failing to record is never fatal, it is only reported.
+    pub fn done(alias: &str, file: &Path) {
+        let _lock = lock();
+        let recorded = Self::path(alias).and_then(|path| {
+            let mut journal = OpenOptions::new().append(true).open(path)?;
+            Ok(writeln!(journal, "done\t{}", file.display())?)
+        });
+        if let Err(e) = recorded {
+            log::debug!("Could not update push journal: {}", e);
+        }
+    }
+
+    /// Remove the journal once all files have been pushed.
+    pub fn finish(alias: &str) {
+        if let Err(e) = Self::path(alias).and_then(|path| Ok(remove_file(path)?)) {
+            log::debug!("Could not remove push journal: {}", e);
+        }
+    }
+}
+
+/// Apply the given modification to the cache of the given host.
+///
+/// Failing to update the cache is never fatal, it is only reported.
+pub fn update<F: FnOnce(&mut Cache)>(alias: &str, modify: F) {
+    let _lock = lock();
+    let updated = Cache::load(alias).and_then(|mut cache| {
+        modify(&mut cache);
+        cache.store()
+    });
+    if let Err(e) = updated {
+        log::debug!("Could not update local cache: {}", e);
+    }
+}
+
+/// Folder holding the caches for all hosts: `$XDG_CACHE_HOME/asfa` or `~/.cache/asfa`.
+pub fn cache_dir() -> Result<PathBuf> {
+    let base = match std::env::var_os("XDG_CACHE_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => expanduser("~/.cache")?,
+    };
+    Ok(base.join("asfa"))
+}
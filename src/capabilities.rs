@@ -0,0 +1,217 @@
+//! Tools available on a remote site, probed once per host and cached locally.
+//!
+//! Remote commands are chosen depending on the detected platform and tools, e.g. BSD `stat -f`
+//! instead of GNU `stat -c` or `shasum` if `sha256sum` is not installed.
+
+use anyhow::{bail, Context, Result};
+use std::collections::BTreeSet;
+use std::fs::{create_dir_all, read_to_string, write};
+
+use crate::cache;
+
+/// Tools whose presence is probed.
+const TOOLS: &[&str] = &[
+    "at",
+    "atq",
+    "atrm",
+    "crontab",
+    "du",
+    "file",
+    "find",
+    "mktemp",
+    "sha256",
+    "sha256sum",
+    "sha512",
+    "sha512sum",
+    "shasum",
+    "shred",
+    "stat",
+    "systemd-run",
+    "xargs",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Operating system as reported by `uname -s`, e.g. `Linux`, `Darwin` or `FreeBSD`.
+    pub platform: String,
+
+    /// Whether the remote site runs busybox (whose applets only support a subset of flags).
+    pub busybox: bool,
+
+    /// Whether `find`, `stat` and `xargs` are the GNU versions.
+    pub gnu: bool,
+
+    /// Tools found on the remote site (out of `TOOLS`).
+    pub tools: BTreeSet<String>,
+}
+
+impl Capabilities {
+    /// Command printing everything needed to determine the capabilities of a remote site.
+    pub fn probe_command() -> String {
+        format!(
+            "echo \"platform $(uname -s)\"; \
+            for tool in {}; do command -v $tool >/dev/null 2>&1 && echo \"tool $tool\"; done; \
+            find --version 2>/dev/null | grep -q GNU && echo gnu; \
+            ls --help 2>&1 | grep -q BusyBox && echo busybox; true",
+            TOOLS.join(" ")
+        )
+    }
+
+    /// Capabilities of a remote site that does not allow to execute commands (`exec: false`).
+    pub fn none() -> Self {
+        Self {
+            platform: "unknown".to_string(),
+            busybox: false,
+            gnu: false,
+            tools: BTreeSet::new(),
+        }
+    }
+
+    /// Parse the output of `probe_command` (also used for the local cache).
+    pub fn parse(output: &str) -> Result<Self> {
+        let mut platform = None;
+        let mut busybox = false;
+        let mut gnu = false;
+        let mut tools = BTreeSet::new();
+        for line in output.lines() {
+            match line.split_once(' ') {
+                Some(("platform", name)) => platform = Some(name.trim().to_string()),
+                Some(("tool", tool)) => {
+                    tools.insert(tool.trim().to_string());
+                }
+                None if line.trim() == "busybox" => busybox = true,
+                None if line.trim() == "gnu" => gnu = true,
+                _ => log::debug!("Ignoring unexpected capability: {}", line),
+            }
+        }
+        Ok(Self {
+            platform: platform.context("Could not determine remote platform.")?,
+            busybox,
+            gnu,
+            tools,
+        })
+    }
+
+    /// Load cached capabilities for the given host.
+    pub fn load(alias: &str) -> Result<Option<Self>> {
+        let path = cache::cache_dir()?.join(format!("{}.capabilities", alias));
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = read_to_string(&path)
+            .with_context(|| format!("Could not read cache: {}", path.display()))?;
+        Ok(Some(Self::parse(&content)?))
+    }
+
+    /// Cache capabilities for the given host.
+    ///
+    /// Failing to store is never fatal, it is only reported.
+    pub fn store(&self, alias: &str) {
+        let stored = cache::cache_dir().and_then(|dir| {
+            create_dir_all(&dir)?;
+            let mut content = format!("platform {}\n", self.platform);
+            for tool in self.tools.iter() {
+                content.push_str(&format!("tool {}\n", tool));
+            }
+            if self.busybox {
+                content.push_str("busybox\n");
+            }
+            if self.gnu {
+                content.push_str("gnu\n");
+            }
+            Ok(write(dir.join(format!("{}.capabilities", alias)), content)?)
+        });
+        if let Err(e) = stored {
+            log::debug!("Could not cache capabilities: {}", e);
+        }
+    }
+
+    /// Whether the given tool is available.
+    pub fn has(&self, tool: &str) -> bool {
+        self.tools.contains(tool)
+    }
+
+    /// Command printing `<hex digest> <path>` for each given path, suitable for hashes of the
+    /// given length (see `util::get_hash_from`).
+    pub fn hasher(&self, length: u8) -> Result<String> {
+        let bits = if length == 0 {
+            bail!("Length cannot be zero!");
+        } else if length <= 32 {
+            256
+        } else if length <= 64 {
+            512
+        } else {
+            bail!("Length should be equal to or smaller than 64.");
+        };
+        if self.has(&format!("sha{}sum", bits)) {
+            Ok(format!("sha{}sum", bits))
+        } else if self.has("shasum") {
+            Ok(format!("shasum -a {}", bits))
+        } else if self.has(&format!("sha{}", bits)) {
+            // FreeBSD, `-r` prints digest first
+            Ok(format!("sha{} -r", bits))
+        } else {
+            bail!(
+                "Neither `sha{bits}sum`, `shasum` nor `sha{bits}` found on remote site.",
+                bits = bits
+            );
+        }
+    }
+
+    /// `xargs` reading null-separated arguments that does not run the command for empty input.
+    pub fn xargs(&self) -> &'static str {
+        if self.gnu || self.platform == "Linux" {
+            "xargs -0r"
+        } else {
+            // BSD xargs never runs the command without arguments, `-r` is not supported everywhere.
+            "xargs -0"
+        }
+    }
+
+    /// Whether `atq` prints a header and the job id in the last column (FreeBSD) instead of the
+    /// first one (Linux, macOS).
+    pub fn bsd_atq(&self) -> bool {
+        self.platform == "FreeBSD"
+    }
+
+    /// Command printing `<mtime> <size> <path>` for each given path, if available.
+    pub fn stat_command(&self) -> Option<&'static str> {
+        if !self.has("stat") {
+            None
+        } else if self.gnu || self.platform == "Linux" {
+            Some("stat -c '%Y %s %n'")
+        } else {
+            // BSD (including macOS)
+            Some("stat -f '%m %z %N'")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn degrade_on_macos() {
+        let capabilities =
+            Capabilities::parse("platform Darwin\ntool find\ntool shasum\ntool stat\n").unwrap();
+        assert!(!capabilities.gnu);
+        assert_eq!(capabilities.hasher(32).unwrap(), "shasum -a 256");
+        assert_eq!(capabilities.hasher(64).unwrap(), "shasum -a 512");
+        assert_eq!(capabilities.stat_command(), Some("stat -f '%m %z %N'"));
+        assert!(!capabilities.has("at"));
+        assert_eq!(capabilities.xargs(), "xargs -0");
+    }
+
+    #[test]
+    fn degrade_on_freebsd() {
+        let capabilities = Capabilities::parse(
+            "platform FreeBSD\ntool atq\ntool sha256\ntool sha512\ntool stat\ntool xargs\n",
+        )
+        .unwrap();
+        assert_eq!(capabilities.hasher(32).unwrap(), "sha256 -r");
+        assert_eq!(capabilities.hasher(64).unwrap(), "sha512 -r");
+        assert_eq!(capabilities.stat_command(), Some("stat -f '%m %z %N'"));
+        assert!(capabilities.bsd_atq());
+    }
+}
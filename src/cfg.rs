@@ -1,31 +1,55 @@
 use anyhow::{bail, Context, Result};
 use expanduser::expanduser;
 use log::{debug, warn};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::default::Default;
 use std::fmt::Display;
 use std::fs::{read_dir, read_to_string};
 use std::path::PathBuf;
+use std::time::Duration;
 use yaml_rust::{yaml::Hash, Yaml, YamlLoader};
 
-use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS, NON_ALPHANUMERIC};
 const CONTROLS_ENHANCED: &AsciiSet = &CONTROLS.add(b' ').add(b'"').add(b'<').add(b'>').add(b'`');
-
+/// Everything but unreserved characters (RFC 3986) is encoded in path segments.
+const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+use crate::at;
+use crate::bundle::ArchiveFormat;
+use crate::file_type::ExpireByType;
+use crate::host_rules::HostRules;
+use crate::i18n::Language;
+use crate::layout::Layout;
+use crate::schedule::LimitSchedule;
+use crate::shortener::Shortener;
+use crate::theme::Theme;
 use crate::util::*;
 
 /// The main configuration
 #[derive(Debug)]
 pub struct Config {
+    /// Append destructive operations to an audit log on the remote site, unless overwritten in
+    /// host.
+    pub audit_log: bool,
+
     /// Authentication settings to use if no host-specific authentication settings specified.
     pub auth: Auth,
 
+    /// Skip details enabled in config (which require the stats of all listed files) when listing
+    /// more than the given number of files, unless overwritten in host.
+    pub auto_stats_threshold: Option<usize>,
+
+    /// Copy resulting URLs to the clipboard in `push`, `list` and `rename`, as if `--clipboard`
+    /// was given.
+    pub clipboard: bool,
+
     /// Default host to upload to.
     default_host: Option<String>,
 
-    /// Decide if detailed information should be displayed where applicable.
-    /// Defaults to false.
-    pub details: bool,
-
     /// Expire the uploaded file after the given amount of time via `at`-scheduled remote job.
     ///
     /// Select files newer than the given duration. Durations can be: seconds (sec, s), minutes
@@ -34,17 +58,173 @@ pub struct Config {
     /// Mininum time till expiration is a minute.
     pub expire: Option<String>,
 
+    /// How expirations are scheduled on the remote site, unless overwritten in host.
+    pub expire_backend: ExpireBackend,
+
+    /// Default expirations depending on the type of the uploaded file (taking precedence over
+    /// `expire`), unless overwritten in host.
+    pub expire_by_type: ExpireByType,
+
+    /// Show a desktop notification the given duration before an upload expires (scheduled via the
+    /// local `at`-daemon). Also the default window of `expire --upcoming`.
+    pub expire_reminder: Option<Duration>,
+
+    /// Number of files to hash per remote invocation (e.g. during `verify`), unless overwritten
+    /// in host. Chosen depending on the latency of the connection if unset.
+    pub hash_batch_size: Option<usize>,
+
+    /// Rules selecting the default host depending on context (Wi-Fi network, local user or files
+    /// to push), taking precedence over `default_host`.
+    host_rules: HostRules,
+
     /// List of all configured hosts.
     hosts: HashMap<String, Host>,
 
+    /// Language of prompts, summaries and errors, taken from the locale if unset.
+    pub language: Option<Language>,
+
+    /// Upload speed limits depending on the time of day, unless overwritten in host.
+    pub limit_schedule: LimitSchedule,
+
+    /// Folder (relative to the host's base folder) in which named symlinks are created, unless
+    /// overwritten in host.
+    pub links_folder: Option<String>,
+
+    /// Print URLs as before: Keep internationalized domain names and non-ASCII characters in file
+    /// names as is instead of converting them to punycode/percent-encoding, unless overwritten in
+    /// host.
+    pub legacy_url_encoding: bool,
+
     /// Explicit loglevel set because simple logger has no easy way to retrieve it.
     pub loglevel: log::LevelFilter,
 
+    /// Maximum number of remote commands run concurrently by parallel features, unless
+    /// overwritten in host.
+    pub max_parallel_commands: usize,
+
+    /// Maximum number of files transferred concurrently by parallel features, unless overwritten
+    /// in host.
+    pub max_parallel_transfers: usize,
+
+    /// File to export statistics of all operations to (in the textfile-collector format of
+    /// Prometheus' node_exporter).
+    pub metrics_file: Option<PathBuf>,
+
+    /// Never retrieve stats of remote files unless required by the options of a command (set via
+    /// `--no-stats`).
+    pub no_stats: bool,
+
+    /// Show a desktop notification with the resulting URL for uploads that take longer than the
+    /// given duration.
+    pub notify_after: Option<Duration>,
+
     /// Length of prefix to use unless overwritten in host
     pub prefix_length: u8,
 
-    /// Compute hash on remote side after upload to verify.
-    pub verify_via_hash: bool,
+    /// How `push` uploads directories, unless overwritten in host.
+    pub push_directories: DirectoryMode,
+
+    /// Defaults for the output of all commands.
+    pub output: Output,
+
+    /// Allow short aliases `ls`, `rm` and `up` for `list`, `clean` and `push`.
+    pub short_aliases: bool,
+
+    /// Service to shorten URLs with (see `push --short`), unless overwritten in host.
+    pub shortener: Option<Shortener>,
+
+    /// Colors and frame characters used for output.
+    pub theme: Theme,
+
+    /// How files are transferred to the remote site, unless overwritten in host.
+    pub transfer_protocol: TransferProtocol,
+
+    /// How to verify uploads, unless overwritten in host.
+    pub verify_strategy: VerifyStrategy,
+}
+
+/// Defaults for the output of all commands, configured via `output`.
+#[derive(Debug, Clone, Default)]
+pub struct Output {
+    /// When to color output.
+    pub color: ColorMode,
+
+    /// Display detailed information where applicable, as if `--details` was given.
+    pub details: bool,
+
+    /// Suppress all output but errors, as if `-qqq` was given, unless the loglevel is set on the
+    /// command line.
+    pub silent: bool,
+
+    /// Only print URLs in `list` and `check`, as if `--url-only` was given, unless a different
+    /// presentation is requested on the command line.
+    pub url_only: bool,
+}
+
+/// When to color output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorMode {
+    /// Color output if it is written to a terminal.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// How uploads are verified, configured via `verify_strategy` (or the legacy `verify_via_hash`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifyStrategy {
+    /// Compute hash on remote side after upload (needs remote commands).
+    Hash,
+
+    /// Compare the size of the remote file with the local one, which is instant even for large
+    /// files.
+    Size,
+
+    /// Do not verify uploads.
+    Off,
+}
+
+/// How files are transferred to the remote site, configured via `transfer_protocol`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferProtocol {
+    /// Use SCP and fall back to SFTP if the remote site does not support SCP.
+    Auto,
+
+    /// Use the (legacy) SCP protocol, which is faster for many small files.
+    Scp,
+
+    /// Use SFTP, e.g. for servers on which SCP is disabled.
+    Sftp,
+}
+
+/// How expirations are scheduled on the remote site, configured via `expire_backend`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpireBackend {
+    /// Use the first available of `at`, `systemd-run` and `crontab`.
+    Auto,
+
+    /// Submit a job to the `at`-daemon.
+    At,
+
+    /// Start a transient systemd timer in the user's service manager (via `systemd-run --user`).
+    ///
+    /// Such timers are lost on reboot and, unless lingering is enabled for the user, on logout.
+    Systemd,
+
+    /// Add a crontab entry that removes itself once run.
+    Cron,
+}
+
+/// How `push` uploads directories, configured via `push_directories`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DirectoryMode {
+    /// Archive the directory locally and upload the archive (`tar.gz` or `zip`).
+    Archive(ArchiveFormat),
+
+    /// Upload all contained files, preserving their relative paths below a single hash folder
+    /// (`recursive`).
+    Recursive,
 }
 
 /// Authentication configuration
@@ -63,19 +243,60 @@ pub struct Auth {
     /// Explicit password for private key (unsafe)
     pub private_key_file_password: Option<String>,
 
+    /// How to handle host keys that are not (yet) in `~/.ssh/known_hosts`.
+    pub strict_host_key_checking: StrictHostKeyChecking,
+
     /// Perform agent authentication
     pub use_agent: bool,
 }
 
+/// Handling of unknown host keys, configured via `strict_host_key_checking` (same values as in
+/// OpenSSH). Host keys not matching the known ones are refused unless checking is disabled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StrictHostKeyChecking {
+    /// Refuse unknown host keys.
+    Yes,
+    /// Ask whether to add unknown host keys if interactive, refuse them otherwise.
+    Ask,
+    /// Add unknown host keys without asking.
+    AcceptNew,
+    /// Do not verify host keys at all.
+    No,
+}
+
 /// A host entry
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Host {
     /// Alias under which the host is known
     pub alias: String,
 
+    /// Append a line (timestamp, local user, operation, paths) to `.asfa-audit.log` in the base
+    /// folder whenever files are removed, renamed or scheduled for expiration.
+    ///
+    /// Overrides the global setting.
+    pub audit_log: bool,
+
     /// Overwrite global authentication settings for this host.
     pub auth: Auth,
 
+    /// Skip details enabled in config when listing more than the given number of files, e.g. for
+    /// metered or high-latency connections.
+    ///
+    /// Overrides the global setting.
+    pub auto_stats_threshold: Option<usize>,
+
+    /// The remote site runs busybox with a limited shell (`shell: busybox`): listing, stats and
+    /// deletion are performed via SFTP. Auto-detected if not set.
+    pub busybox: bool,
+
+    /// Create missing parent folders of `folder` when creating folders for uploads.
+    pub create_parent_folders: bool,
+
+    /// Whether remote commands can be executed. If not (e.g. SFTP-only chroot accounts), all
+    /// operations are performed via SFTP and features needing remote commands (such as
+    /// expiration) are disabled.
+    pub exec: bool,
+
     /// Expire the uploaded file after the given amount of time via `at`-scheduled remote job.
     ///
     /// Select files newer than the given duration. Durations can be: seconds (sec, s), minutes
@@ -86,6 +307,17 @@ pub struct Host {
     /// Overrides the global setting.
     pub expire: Option<String>,
 
+    /// How expirations are scheduled on the remote site.
+    ///
+    /// Overrides the global setting.
+    pub expire_backend: ExpireBackend,
+
+    /// Default expirations depending on the type of the uploaded file (taking precedence over
+    /// `expire`).
+    ///
+    /// Overrides the global setting.
+    pub expire_by_type: ExpireByType,
+
     /// In which folder do we store files on the host.
     pub folder: PathBuf,
 
@@ -93,9 +325,45 @@ pub struct Host {
     /// readable by the webserver.
     pub group: Option<String>,
 
+    /// Number of files to hash per remote invocation (e.g. during `verify`). Chosen depending on
+    /// the latency of the connection if unset.
+    ///
+    /// Overrides the global setting.
+    pub hash_batch_size: Option<usize>,
+
     /// Self-explanatory (if not set alias will be used)
     pub hostname: Option<String>,
 
+    /// Print URLs as before: Keep internationalized domain names and non-ASCII characters in file
+    /// names as is.
+    ///
+    /// Overrides the global setting.
+    pub legacy_url_encoding: bool,
+
+    /// Shape of uploaded files below `folder`, which determines how the remote site is listed.
+    pub layout: Layout,
+
+    /// Upload speed limits depending on the time of day, consulted during uploads unless a limit
+    /// is given explicitly.
+    ///
+    /// Overrides the global setting.
+    pub limit_schedule: LimitSchedule,
+
+    /// Folder (relative to `folder`) in which named symlinks (see `link` command and `push
+    /// --latest-link`) are created. Defaults to the base folder itself.
+    pub links_folder: PathBuf,
+
+    /// Maximum number of remote commands run concurrently on this host by parallel features, so
+    /// that shared machines are not hammered.
+    ///
+    /// Overrides the global setting.
+    pub max_parallel_commands: usize,
+
+    /// Maximum number of files transferred concurrently to this host by parallel features.
+    ///
+    /// Overrides the global setting.
+    pub max_parallel_transfers: usize,
+
     /// If the user REALLY REALLY wants to, a plaintext password can be provided (but it is not
     /// recommended!).
     pub password: Option<String>,
@@ -103,11 +371,60 @@ pub struct Host {
     /// Length of prefix to use
     pub prefix_length: u8,
 
+    /// Jump host(s) to connect through (`[user@]host[:port]`, comma-separated for several hops),
+    /// like `ProxyJump` in the OpenSSH config, which is used if not set. `none` disables jumping.
+    pub proxy_jump: Option<String>,
+
+    /// How `push` uploads directories.
+    ///
+    /// Overrides the global setting.
+    pub push_directories: DirectoryMode,
+
+    /// Refuse to run commands modifying the remote site (e.g. `push` or `clean`) for this host,
+    /// only allowing to inspect it.
+    pub read_only: bool,
+
+    /// Path of `folder` as seen via SFTP/SCP, used for uploads and other file operations, if it
+    /// differs from the path seen by remote commands (e.g. for chrooted SFTP accounts).
+    pub remote_path: Option<PathBuf>,
+
+    /// Always confirm destructive commands (`clean`, `fsck`) for this host, even if
+    /// `--no-confirm`/`--fix` is given (unless `--force` is specified as well).
+    pub require_confirmation: bool,
+
+    /// Service to shorten URLs with (see `push --short`).
+    ///
+    /// Overrides the global setting.
+    pub shortener: Option<Shortener>,
+
+    /// How files are transferred to the remote site (ignored without remote commands, which
+    /// always use SFTP).
+    ///
+    /// Overrides the global setting.
+    pub transfer_protocol: TransferProtocol,
+
     /// url-prefix to apply to file link
     pub url: String,
 
+    /// Folder (on the remote site) served at `url`, if it differs from `folder`. The path of
+    /// `folder` below it is part of generated URLs.
+    pub url_path: Option<PathBuf>,
+
+    /// Which URL variant to print by default (see `url_variants`): `default` for `url`, `all` for
+    /// every variant or the name of a variant.
+    pub url_variant: Option<String>,
+
+    /// Additional named url-prefixes under which uploads are reachable as well (e.g. an internal
+    /// and an external one), selected via `push --url-variant`.
+    pub url_variants: BTreeMap<String, String>,
+
     /// The user to sign in, otherwise ssh config will be used.
     pub user: Option<String>,
+
+    /// How to verify uploads.
+    ///
+    /// Overrides the global setting.
+    pub verify_strategy: VerifyStrategy,
 }
 
 fn default_config_directories() -> Vec<&'static str> {
@@ -115,17 +432,24 @@ fn default_config_directories() -> Vec<&'static str> {
 }
 
 pub fn load<T: AsRef<str> + Display>(path: &Option<T>) -> Result<Config> {
+    match load_optional(path)? {
+        Some(cfg) => Ok(cfg),
+        None => bail!("Did not find valid configuration!"),
+    }
+}
+
+/// Load configuration like `load`, but without requiring any configuration to exist.
+pub fn load_optional<T: AsRef<str> + Display>(path: &Option<T>) -> Result<Option<Config>> {
     let possible_paths: Vec<&str> = match path {
         Some(path) => vec![path.as_ref()],
         None => default_config_directories(),
     };
     for path in possible_paths.iter() {
-        match Config::load(path)? {
-            None => continue,
-            Some(cfg) => return Ok(cfg),
+        if let Some(cfg) = Config::load(path)? {
+            return Ok(Some(cfg));
         }
     }
-    bail!("Did not find valid configuration!");
+    Ok(None)
 }
 
 #[allow(dead_code)]
@@ -145,14 +469,36 @@ pub fn dummy_host() -> Host {
 impl Default for Config {
     fn default() -> Self {
         Config {
+            audit_log: false,
             auth: Auth::default(),
+            auto_stats_threshold: None,
+            clipboard: false,
             default_host: None,
-            details: false,
             expire: None,
+            expire_backend: ExpireBackend::Auto,
+            expire_by_type: ExpireByType::default(),
+            expire_reminder: None,
+            hash_batch_size: None,
+            host_rules: HostRules::default(),
             hosts: HashMap::new(),
+            language: None,
+            legacy_url_encoding: false,
+            limit_schedule: LimitSchedule::default(),
+            links_folder: None,
             loglevel: log::LevelFilter::Info,
+            max_parallel_commands: 4,
+            max_parallel_transfers: 2,
+            metrics_file: None,
+            no_stats: false,
+            notify_after: None,
+            output: Output::default(),
             prefix_length: 32,
-            verify_via_hash: true,
+            push_directories: DirectoryMode::Archive(ArchiveFormat::TarGz),
+            short_aliases: true,
+            shortener: None,
+            theme: Theme::default(),
+            transfer_protocol: TransferProtocol::Auto,
+            verify_strategy: VerifyStrategy::Hash,
         }
     }
 }
@@ -182,14 +528,13 @@ impl Config {
             Ok(raw) => raw,
         };
 
-        let mut config = Self::from_yaml(&raw)?;
-
         let hosts_dir = {
             let mut hosts_dir = config_dir;
             hosts_dir.push("hosts");
             hosts_dir
         };
 
+        let mut host_files = Vec::new();
         if hosts_dir.is_dir() {
             for entry in read_dir(&hosts_dir)? {
                 let possible_host = entry?.path();
@@ -216,22 +561,24 @@ impl Config {
                         .context("Could not convert host file name to String.")?
                         .to_string(),
                 };
-                if config.hosts.contains_key(&alias) {
-                    bail!("Host {} configured in config.yaml and as host-file.", alias);
-                };
 
                 let host_yaml = YamlLoader::load_from_str(&read_to_string(&possible_host)?)?;
-                let error = format!("Invalid host-file for host {}", &alias);
-                let host =
-                    Host::from_yaml_with_config(alias, &host_yaml[0], &config).context(error)?;
-
-                config.hosts.insert(host.alias.clone(), host);
+                host_files.push((alias, host_yaml[0].clone()));
             }
         }
-        Ok(Some(config))
+        Ok(Some(Self::from_yaml_with_host_files(&raw, host_files)?))
     }
 
+    #[cfg(test)]
     pub fn from_yaml(input: &str) -> Result<Config> {
+        Self::from_yaml_with_host_files(input, Vec::new())
+    }
+
+    /// Read the global configuration, including the hosts defined in it as well as the given
+    /// hosts read from host-files (by alias).
+    ///
+    /// Hosts are only read once all of them are known, because they can `extend` each other.
+    fn from_yaml_with_host_files(input: &str, host_files: Vec<(String, Yaml)>) -> Result<Config> {
         let documents = match YamlLoader::load_from_str(input) {
             Ok(data) => data,
             Err(e) => {
@@ -256,6 +603,10 @@ impl Config {
             length as u8
         };
 
+        if let Some(audit_log) = get_bool_from(config_yaml, "audit_log")?.cloned() {
+            config.audit_log = audit_log;
+        }
+
         config.auth = if let Some(Yaml::Hash(auth)) = config_yaml.get(&yaml_string("auth")) {
             match Auth::from_yaml(&auth, None) {
                 Ok(auth) => auth,
@@ -267,21 +618,97 @@ impl Config {
             config.auth
         };
 
-        config.default_host =
-            std::env::var("ASFA_HOST")
-                .ok()
-                .or(get_string_from(config_yaml, "default_host")?.cloned());
+        config.default_host = get_string_from(config_yaml, "default_host")?.cloned();
 
+        if let Some(rules) = get_array_from(config_yaml, "host_rules")? {
+            config.host_rules = HostRules::from_yaml(rules).context("Invalid 'host_rules'.")?;
+        }
+
+        if let Some(output) = get_dict_from(config_yaml, "output")? {
+            config.output = Output::from_yaml(output).context("Invalid 'output'.")?;
+        }
+        // Top-level `details` predates the `output` section.
         if let Some(details) = get_bool_from(config_yaml, "details")?.cloned() {
-            config.details = details;
+            config.output.details = details;
         }
 
         config.expire = get_string_from(config_yaml, "expire")?.cloned();
+        if let Some(expire) = config.expire.as_deref() {
+            at::validate_delay(expire).context("Invalid 'expire'.")?;
+        }
+
+        if let Some(expire_by_type) = get_dict_from(config_yaml, "expire_by_type")? {
+            config.expire_by_type =
+                ExpireByType::from_yaml(expire_by_type).context("Invalid 'expire_by_type'.")?;
+        }
 
-        config.verify_via_hash = get_bool_from(config_yaml, "verify_via_hash")?
+        config.legacy_url_encoding = get_bool_from(config_yaml, "legacy_url_encoding")?
             .cloned()
-            .unwrap_or(config.verify_via_hash);
+            .unwrap_or(config.legacy_url_encoding);
+
+        config.hash_batch_size = get_positive_from(config_yaml, "hash_batch_size")?;
+        config.auto_stats_threshold = get_positive_from(config_yaml, "auto_stats_threshold")?;
+
+        if let Some(schedule) = get_dict_from(config_yaml, "limit_schedule")? {
+            config.limit_schedule =
+                LimitSchedule::from_yaml(schedule).context("Invalid 'limit_schedule'.")?;
+        }
+
+        config.links_folder = get_string_from(config_yaml, "links_folder")?.cloned();
+
+        if let Some(max) = get_positive_from(config_yaml, "max_parallel_commands")? {
+            config.max_parallel_commands = max;
+        }
+        if let Some(max) = get_positive_from(config_yaml, "max_parallel_transfers")? {
+            config.max_parallel_transfers = max;
+        }
+
+        config.metrics_file = get_string_from(config_yaml, "metrics_file")?
+            .map(expanduser)
+            .transpose()?;
+
+        config.expire_reminder = get_string_from(config_yaml, "expire_reminder")?
+            .map(|before| {
+                humantime::parse_duration(before)
+                    .with_context(|| format!("Invalid duration for 'expire_reminder': {}", before))
+            })
+            .transpose()?;
 
+        config.notify_after = get_string_from(config_yaml, "notify_after")?
+            .map(|after| {
+                humantime::parse_duration(after)
+                    .with_context(|| format!("Invalid duration for 'notify_after': {}", after))
+            })
+            .transpose()?;
+
+        config.language = match get_string_from(config_yaml, "language")?.map(|s| s.as_str()) {
+            None | Some("auto") => None,
+            Some(code) => Some(Language::from_code(code).context("Invalid 'language'.")?),
+        };
+
+        config.clipboard = get_bool_from(config_yaml, "clipboard")?
+            .cloned()
+            .unwrap_or(config.clipboard);
+
+        config.short_aliases = get_bool_from(config_yaml, "short_aliases")?
+            .cloned()
+            .unwrap_or(config.short_aliases);
+
+        config.shortener = get_dict_from(config_yaml, "shortener")?
+            .map(|dict| Shortener::from_yaml(dict).context("Invalid 'shortener'."))
+            .transpose()?;
+
+        if let Some(theme) = get_dict_from(config_yaml, "theme")? {
+            config.theme = Theme::from_yaml(theme).context("Invalid 'theme'.")?;
+        }
+
+        config.verify_strategy = VerifyStrategy::from_yaml(config_yaml, config.verify_strategy)?;
+        config.expire_backend = ExpireBackend::from_yaml(config_yaml, config.expire_backend)?;
+        config.push_directories = DirectoryMode::from_yaml(config_yaml, config.push_directories)?;
+        config.transfer_protocol =
+            TransferProtocol::from_yaml(config_yaml, config.transfer_protocol)?;
+
+        let mut raw_hosts = HashMap::new();
         match config_yaml.get(&yaml_string("hosts")) {
             Some(Yaml::Hash(dict)) => {
                 for entry in dict.clone().entries() {
@@ -292,9 +719,7 @@ impl Config {
                             continue;
                         }
                     };
-                    let host_yaml = entry.get();
-                    let host = Host::from_yaml_with_config(alias.clone(), host_yaml, &config)?;
-                    config.hosts.insert(alias, host);
+                    raw_hosts.insert(alias, entry.get().clone());
                 }
             }
             // Some(Yaml::Array(a)) => a,
@@ -306,9 +731,102 @@ impl Config {
             }
         };
 
+        let mut from_files = HashSet::new();
+        for (alias, host_yaml) in host_files {
+            if raw_hosts.contains_key(&alias) {
+                bail!("Host {} configured in config.yaml and as host-file.", alias);
+            }
+            from_files.insert(alias.clone());
+            raw_hosts.insert(alias, host_yaml);
+        }
+
+        for alias in raw_hosts.keys() {
+            let host_yaml = resolve_extends(alias, &raw_hosts, &mut Vec::new())?;
+            // Templates only provide settings to hosts extending them.
+            if let Yaml::Hash(dict) = &host_yaml {
+                if get_bool_from(dict, "template")?.cloned().unwrap_or(false) {
+                    continue;
+                }
+            }
+            let host = Host::from_yaml_with_config(alias.clone(), &host_yaml, &config);
+            let host = if from_files.contains(alias) {
+                host.with_context(|| format!("Invalid host-file for host {}", alias))?
+            } else {
+                host?
+            };
+            config.hosts.insert(alias.clone(), host);
+        }
+        if let Some(unknown) = config
+            .host_rules
+            .hosts()
+            .find(|host| !config.hosts.contains_key(*host))
+        {
+            bail!("'host_rules' refer to unknown host: {}", unknown);
+        }
+
         Ok(config)
     }
 
+    /// Host to use for running a command on the given local files (only relevant for `push`):
+    /// The given alias or `ASFA_HOST`, the host selected by `host_rules` or the default host.
+    pub fn select_host(&self, alias: Option<&str>, files: &[PathBuf]) -> Result<&Host> {
+        let alias = alias
+            .map(str::to_string)
+            .or_else(|| std::env::var("ASFA_HOST").ok())
+            .or_else(|| self.host_rules.select(files).map(str::to_string));
+        self.get_host(alias)
+    }
+
+    /// Add a host given entirely on the command line (`-H [user@]hostname[:port] --remote-folder
+    /// <folder> --url <url>`), known under `spec` as alias. All other settings are taken from the
+    /// global configuration.
+    pub fn add_adhoc_host(&mut self, spec: &str, folder: &str, url: &str) -> Result<()> {
+        if self.hosts.contains_key(spec) {
+            bail!(
+                "Host '{}' is already configured, --remote-folder/--url cannot be used with it.",
+                spec
+            );
+        }
+        let (user, hostname) = match spec.split_once('@') {
+            Some((user, hostname)) => (Some(user), hostname),
+            None => (None, spec),
+        };
+        let invalid = || format!("Invalid host '{}', expected [user@]hostname[:port].", spec);
+        if hostname.is_empty() || user == Some("") {
+            bail!(invalid());
+        }
+        if let Some((name, port)) = hostname.split_once(':') {
+            if name.is_empty() {
+                bail!(invalid());
+            }
+            port.parse::<u16>().with_context(invalid)?;
+        }
+
+        let mut dict = Hash::new();
+        let mut insert = |key: &str, value: &str| {
+            dict.insert(
+                Yaml::String(key.to_string()),
+                Yaml::String(value.to_string()),
+            )
+        };
+        insert("hostname", hostname);
+        if let Some(user) = user {
+            insert("user", user);
+        }
+        insert("folder", folder);
+        insert("url", url);
+        let host = Host::from_yaml_with_config(spec.to_string(), &Yaml::Hash(dict), self)?;
+        self.hosts.insert(spec.to_string(), host);
+        Ok(())
+    }
+
+    /// All configured hosts, sorted by alias.
+    pub fn hosts(&self) -> impl Iterator<Item = &Host> {
+        let mut hosts: Vec<&Host> = self.hosts.values().collect();
+        hosts.sort_by(|a, b| a.alias.cmp(&b.alias));
+        hosts.into_iter()
+    }
+
     pub fn get_host<T: AsRef<str>>(&self, alias: Option<T>) -> Result<&Host> {
         match alias
             .as_ref()
@@ -334,6 +852,251 @@ impl Config {
     pub fn is_silent(&self) -> bool {
         matches!(self.loglevel, log::LevelFilter::Off)
     }
+
+    /// Whether to display details given the `--details`/`--no-details` flags of a command.
+    ///
+    /// Details enabled in config are skipped if `--no-stats` is given.
+    pub fn show_details(&self, details: bool, no_details: bool) -> bool {
+        (details || (self.output.details && !self.no_stats)) && !no_details
+    }
+
+    /// Whether to copy URLs to the clipboard given the `--clipboard`/`--no-clipboard` flags of a
+    /// command.
+    pub fn copy_to_clipboard(&self, clipboard: bool, no_clipboard: bool) -> bool {
+        (clipboard || self.clipboard) && !no_clipboard
+    }
+
+    /// Whether to display details for the given number of listed files (see `show_details`).
+    ///
+    /// Details enabled in config are also skipped if more files than the host's
+    /// `auto_stats_threshold` are listed, since retrieving their stats might be expensive.
+    pub fn show_details_for(
+        &self,
+        host: &Host,
+        details: bool,
+        no_details: bool,
+        listed: usize,
+    ) -> bool {
+        if !self.show_details(details, no_details) {
+            return false;
+        }
+        match host.auto_stats_threshold {
+            Some(threshold) if !details && listed > threshold => {
+                log::info!(
+                    "Not showing details for {} files (`auto_stats_threshold: {}`), specify \
+                    --details to show them anyway.",
+                    listed,
+                    threshold
+                );
+                false
+            }
+            _ => true,
+        }
+    }
+}
+
+impl VerifyStrategy {
+    /// Read `verify_strategy` (or the legacy `verify_via_hash`) from the given dictionary.
+    fn from_yaml(dict: &Hash, default: Self) -> Result<Self> {
+        Ok(
+            match get_string_from(dict, "verify_strategy")?.map(|s| s.as_str()) {
+                Some("hash") => VerifyStrategy::Hash,
+                Some("size") => VerifyStrategy::Size,
+                Some("none") => VerifyStrategy::Off,
+                Some(strategy) => bail!(
+                    "Invalid value for 'verify_strategy' (expected 'hash', 'size' or 'none'): {}",
+                    strategy
+                ),
+                None => match get_bool_from(dict, "verify_via_hash")? {
+                    Some(true) => VerifyStrategy::Hash,
+                    Some(false) => VerifyStrategy::Off,
+                    None => default,
+                },
+            },
+        )
+    }
+}
+
+impl ExpireBackend {
+    /// Read `expire_backend` from the given dictionary.
+    fn from_yaml(dict: &Hash, default: Self) -> Result<Self> {
+        Ok(
+            match get_string_from(dict, "expire_backend")?.map(|s| s.as_str()) {
+                None => default,
+                Some("auto") => ExpireBackend::Auto,
+                Some("at") => ExpireBackend::At,
+                Some("systemd") => ExpireBackend::Systemd,
+                Some("cron") => ExpireBackend::Cron,
+                Some(backend) => bail!(
+                    "Invalid value for 'expire_backend' (expected 'auto', 'at', 'systemd' or \
+                    'cron'): {}",
+                    backend
+                ),
+            },
+        )
+    }
+}
+
+impl TransferProtocol {
+    /// Read `transfer_protocol` from the given dictionary.
+    fn from_yaml(dict: &Hash, default: Self) -> Result<Self> {
+        Ok(
+            match get_string_from(dict, "transfer_protocol")?.map(|s| s.as_str()) {
+                None => default,
+                Some("auto") => TransferProtocol::Auto,
+                Some("scp") => TransferProtocol::Scp,
+                Some("sftp") => TransferProtocol::Sftp,
+                Some(protocol) => bail!(
+                    "Invalid value for 'transfer_protocol' (expected 'auto', 'scp' or 'sftp'): {}",
+                    protocol
+                ),
+            },
+        )
+    }
+}
+
+impl StrictHostKeyChecking {
+    /// Read `strict_host_key_checking` from the given dictionary.
+    fn from_yaml(dict: &Hash, default: Self) -> Result<Self> {
+        Ok(match dict.get(&yaml_string("strict_host_key_checking")) {
+            None => default,
+            Some(Yaml::Boolean(true)) => StrictHostKeyChecking::Yes,
+            Some(Yaml::Boolean(false)) => StrictHostKeyChecking::No,
+            Some(Yaml::String(value)) => match value.as_str() {
+                "yes" => StrictHostKeyChecking::Yes,
+                "ask" => StrictHostKeyChecking::Ask,
+                "accept-new" => StrictHostKeyChecking::AcceptNew,
+                "no" | "off" => StrictHostKeyChecking::No,
+                value => bail!(
+                    "Invalid value for 'strict_host_key_checking' (expected 'yes', 'ask', \
+                    'accept-new' or 'no'): {}",
+                    value
+                ),
+            },
+            Some(_) => bail!("Invalid value for 'strict_host_key_checking'."),
+        })
+    }
+}
+
+impl DirectoryMode {
+    /// Read `push_directories` from the given dictionary.
+    fn from_yaml(dict: &Hash, default: Self) -> Result<Self> {
+        Ok(
+            match get_string_from(dict, "push_directories")?.map(|s| s.as_str()) {
+                Some("tar.gz") => DirectoryMode::Archive(ArchiveFormat::TarGz),
+                Some("zip") => DirectoryMode::Archive(ArchiveFormat::Zip),
+                Some("recursive") => DirectoryMode::Recursive,
+                Some(mode) => bail!(
+                    "Invalid value for 'push_directories' (expected 'tar.gz', 'zip' or \
+                    'recursive'): {}",
+                    mode
+                ),
+                None => default,
+            },
+        )
+    }
+}
+
+impl Output {
+    fn from_yaml(dict: &Hash) -> Result<Self> {
+        let color = match get_string_from(dict, "color")?.map(|s| s.as_str()) {
+            None | Some("auto") => ColorMode::Auto,
+            Some("always") => ColorMode::Always,
+            Some("never") => ColorMode::Never,
+            Some(color) => bail!(
+                "Invalid value for 'color' (expected 'auto', 'always' or 'never'): {}",
+                color
+            ),
+        };
+        let mut output = Self {
+            color,
+            ..Self::default()
+        };
+        for (key, value) in [
+            ("details", &mut output.details),
+            ("silent", &mut output.silent),
+            ("url_only", &mut output.url_only),
+        ] {
+            if let Some(v) = get_bool_from(dict, key)? {
+                *value = *v;
+            }
+        }
+        Ok(output)
+    }
+
+    /// Enable or disable colors (on stdout and stderr), detecting whether they are supported
+    /// (like `console` does) if chosen automatically.
+    ///
+    /// Colors are always set explicitly, since the daemon runs invocations with different
+    /// settings and terminals in one process.
+    pub fn apply_color(&self) {
+        let (stdout, stderr) = match self.color {
+            ColorMode::Auto => {
+                let supported = |term: console::Term| {
+                    (term.features().colors_supported()
+                        && std::env::var("CLICOLOR").map_or(true, |v| v != "0"))
+                        || std::env::var("CLICOLOR_FORCE").is_ok_and(|v| v != "0")
+                };
+                (
+                    supported(console::Term::stdout()),
+                    supported(console::Term::stderr()),
+                )
+            }
+            ColorMode::Always => (true, true),
+            ColorMode::Never => (false, false),
+        };
+        console::set_colors_enabled(stdout);
+        console::set_colors_enabled_stderr(stderr);
+    }
+}
+
+/// Settings of the given host merged with the settings of the host it `extends` (recursively).
+///
+/// Nested dictionaries (e.g. `auth`) are merged as well, all other settings of the extending host
+/// replace the inherited ones.
+fn resolve_extends(
+    alias: &str,
+    raw_hosts: &HashMap<String, Yaml>,
+    chain: &mut Vec<String>,
+) -> Result<Yaml> {
+    if chain.iter().any(|a| a == alias) {
+        chain.push(alias.to_string());
+        bail!("Hosts extend each other in a cycle: {}", chain.join(" → "));
+    }
+    let dict = match raw_hosts.get(alias) {
+        Some(Yaml::Hash(dict)) => dict,
+        // Reported when reading the host.
+        Some(other) => return Ok(other.clone()),
+        None => bail!("Host {} not configured.", alias),
+    };
+    let base = match get_string_from(dict, "extends")? {
+        Some(base) => base,
+        None => return Ok(Yaml::Hash(dict.clone())),
+    };
+    if !raw_hosts.contains_key(base) {
+        bail!("Host {} extends unknown host {}.", alias, base);
+    }
+    chain.push(alias.to_string());
+    let mut merged = match resolve_extends(base, raw_hosts, chain)? {
+        Yaml::Hash(merged) => merged,
+        _ => bail!("Host {} extends {}, which is no dictionary.", alias, base),
+    };
+    merged.remove(&yaml_string("template"));
+    merge_yaml(&mut merged, dict);
+    merged.remove(&yaml_string("extends"));
+    Ok(Yaml::Hash(merged))
+}
+
+/// Merge `from` into `into`, recursing into dictionaries present in both.
+fn merge_yaml(into: &mut Hash, from: &Hash) {
+    for (key, value) in from.iter() {
+        match (into.get_mut(key), value) {
+            (Some(Yaml::Hash(existing)), Yaml::Hash(value)) => merge_yaml(existing, value),
+            _ => {
+                into.insert(key.clone(), value.clone());
+            }
+        }
+    }
 }
 
 impl Host {
@@ -344,20 +1107,91 @@ impl Host {
     fn from_yaml_with_config(alias: String, input: &Yaml, config: &Config) -> Result<Host> {
         log::trace!("Reading host: {}", alias);
         if let Yaml::Hash(dict) = input {
-            let url = get_required(dict, "url", get_string_from)?.clone();
+            let legacy_url_encoding = get_bool_from(dict, "legacy_url_encoding")?
+                .cloned()
+                .unwrap_or(config.legacy_url_encoding);
+
+            let url = get_required(dict, "url", get_string_from)?;
+            let url = if legacy_url_encoding {
+                url.clone()
+            } else {
+                normalize_url(url)?
+            };
+
+            let mut url_variants = BTreeMap::new();
+            for (name, url) in get_dict_from(dict, "url_variants")?
+                .into_iter()
+                .flat_map(|variants| variants.iter())
+            {
+                let (name, url) = match (name.as_str(), url.as_str()) {
+                    (Some(name), Some(url)) => (name, url),
+                    _ => bail!("Invalid 'url_variants', expected names mapped to URLs."),
+                };
+                if ["all", "default"].contains(&name) {
+                    bail!("URL variant '{}' is reserved.", name);
+                }
+                let url = if legacy_url_encoding {
+                    url.to_string()
+                } else {
+                    normalize_url(url)?
+                };
+                url_variants.insert(name.to_string(), url);
+            }
+
+            let url_variant = get_string_from(dict, "url_variant")?.cloned();
 
             let hostname = get_string_from(dict, "hostname")?.cloned();
 
+            let hash_batch_size =
+                get_positive_from(dict, "hash_batch_size")?.or(config.hash_batch_size);
+
+            let auto_stats_threshold =
+                get_positive_from(dict, "auto_stats_threshold")?.or(config.auto_stats_threshold);
+
             let user = get_string_from(dict, "user")?.cloned();
 
-            let expire = get_string_from(dict, "expire")?
-                .cloned()
-                .or_else(|| config.expire.clone());
+            let expire = match get_string_from(dict, "expire")? {
+                Some(expire) => {
+                    at::validate_delay(expire)
+                        .with_context(|| format!("Invalid 'expire' for host '{}'.", alias))?;
+                    Some(expire.clone())
+                }
+                None => config.expire.clone(),
+            };
+
+            let expire_by_type = get_dict_from(dict, "expire_by_type")?
+                .map(|dict| ExpireByType::from_yaml(dict).context("Invalid 'expire_by_type'."))
+                .transpose()?
+                .unwrap_or_else(|| config.expire_by_type.clone());
 
             let folder = expanduser(get_required(dict, "folder", get_string_from)?)?;
 
             let group = get_string_from(dict, "group")?.cloned();
 
+            let remote_path = get_string_from(dict, "remote_path")?
+                .map(expanduser)
+                .transpose()?;
+
+            let url_path = get_string_from(dict, "url_path")?.map(PathBuf::from);
+            if let Some(url_path) = url_path.as_ref() {
+                if !folder.starts_with(url_path) {
+                    bail!(
+                        "'folder' ({}) needs to be contained in 'url_path' ({}).",
+                        folder.display(),
+                        url_path.display()
+                    );
+                }
+            }
+
+            let audit_log = get_bool_from(dict, "audit_log")?
+                .cloned()
+                .unwrap_or(config.audit_log);
+
+            let verify_strategy = VerifyStrategy::from_yaml(dict, config.verify_strategy)?;
+            let expire_backend = ExpireBackend::from_yaml(dict, config.expire_backend)?;
+            let push_directories = DirectoryMode::from_yaml(dict, config.push_directories)?;
+            let transfer_protocol = TransferProtocol::from_yaml(dict, config.transfer_protocol)?;
+
             let auth = match get_dict_from(dict, "auth")? {
                 Some(auth) => Auth::from_yaml(auth, Some(&config.auth))?,
                 None => config.auth.clone(),
@@ -373,18 +1207,117 @@ impl Host {
 
             let password = get_string_from(dict, "password")?.cloned();
 
-            Ok(Host {
+            let proxy_jump = get_string_from(dict, "proxy_jump")?.cloned();
+
+            let max_parallel_commands = get_positive_from(dict, "max_parallel_commands")?
+                .unwrap_or(config.max_parallel_commands);
+            let max_parallel_transfers = get_positive_from(dict, "max_parallel_transfers")?
+                .unwrap_or(config.max_parallel_transfers);
+
+            let create_parent_folders = get_bool_from(dict, "create_parent_folders")?
+                .cloned()
+                .unwrap_or(false);
+
+            let exec = get_bool_from(dict, "exec")?.cloned().unwrap_or(true);
+
+            let read_only = get_bool_from(dict, "read_only")?.cloned().unwrap_or(false);
+
+            let busybox = match get_string_from(dict, "shell")? {
+                None => false,
+                Some(s) if s == "busybox" => true,
+                Some(s) if s == "default" => false,
+                Some(s) => bail!(
+                    "Invalid value for 'shell' (expected 'busybox' or 'default'): {}",
+                    s
+                ),
+            };
+
+            let require_confirmation = match get_string_from(dict, "require_confirmation")? {
+                None => false,
+                Some(s) if s == "always" => true,
+                Some(s) if s == "default" => false,
+                Some(s) => bail!(
+                    "Invalid value for 'require_confirmation' (expected 'always' or 'default'): {}",
+                    s
+                ),
+            };
+
+            let shortener = match get_dict_from(dict, "shortener")? {
+                Some(dict) => Some(Shortener::from_yaml(dict).context("Invalid 'shortener'.")?),
+                None => config.shortener.clone(),
+            };
+
+            let limit_schedule = match get_dict_from(dict, "limit_schedule")? {
+                Some(schedule) => {
+                    LimitSchedule::from_yaml(schedule).context("Invalid 'limit_schedule'.")?
+                }
+                None => config.limit_schedule.clone(),
+            };
+
+            let layout = match get_dict_from(dict, "layout")? {
+                Some(layout) => match get_positive_from(layout, "depth")? {
+                    Some(depth) => Layout::new(depth).context("Invalid 'layout'.")?,
+                    None => Layout::default(),
+                },
+                None => Layout::default(),
+            };
+
+            let links_folder = PathBuf::from(
+                get_string_from(dict, "links_folder")?
+                    .or(config.links_folder.as_ref())
+                    .map(|s| s.as_str())
+                    .unwrap_or(""),
+            );
+            if links_folder
+                .components()
+                .any(|c| !matches!(c, std::path::Component::Normal(_)))
+            {
+                bail!(
+                    "'links_folder' needs to be relative to 'folder': {}",
+                    links_folder.display()
+                );
+            }
+
+            let host = Host {
                 alias,
+                audit_log,
                 auth,
+                auto_stats_threshold,
+                busybox,
+                create_parent_folders,
+                exec,
                 expire,
+                expire_backend,
+                expire_by_type,
                 folder,
                 group,
+                hash_batch_size,
                 hostname,
+                legacy_url_encoding,
+                layout,
+                limit_schedule,
+                links_folder,
+                max_parallel_commands,
+                max_parallel_transfers,
                 password,
                 prefix_length,
+                proxy_jump,
+                push_directories,
+                read_only,
+                remote_path,
+                require_confirmation,
+                shortener,
+                transfer_protocol,
                 url,
+                url_path,
+                url_variant,
+                url_variants,
                 user,
-            })
+                verify_strategy,
+            };
+            // Fail early on unknown default variants.
+            host.url_prefixes(None)?;
+            Ok(host)
         } else {
             bail!("Invalid yaml data for Host-alias '{}'", alias);
         }
@@ -394,16 +1327,68 @@ impl Host {
     ///
     /// Prepends url and performs character escapes.
     pub fn get_url(&self, file: &str) -> Result<String> {
-        Ok(format!(
-            "{}/{}",
-            &self.url,
-            utf8_percent_encode(file, CONTROLS_ENHANCED)
-        ))
+        self.get_url_with_prefix(&self.url, file)
+    }
+
+    /// Get URLs to given destination for the given URL variant (if not given, the configured
+    /// `url_variant` is used).
+    pub fn get_urls(&self, file: &str, variant: Option<&str>) -> Result<Vec<String>> {
+        self.url_prefixes(variant)?
+            .into_iter()
+            .map(|prefix| self.get_url_with_prefix(prefix, file))
+            .collect()
+    }
+
+    /// url-prefixes of the given URL variant (if not given, the configured `url_variant` is used).
+    pub fn url_prefixes(&self, variant: Option<&str>) -> Result<Vec<&str>> {
+        match variant.or(self.url_variant.as_deref()).unwrap_or("default") {
+            "default" => Ok(vec![&self.url]),
+            "all" => Ok(std::iter::once(self.url.as_str())
+                .chain(self.url_variants.values().map(|url| url.as_str()))
+                .collect()),
+            name => match self.url_variants.get(name) {
+                Some(url) => Ok(vec![url]),
+                None => bail!(
+                    "Unknown URL variant '{}' for host '{}' (available: {}).",
+                    name,
+                    self.alias,
+                    ["default", "all"]
+                        .into_iter()
+                        .chain(self.url_variants.keys().map(|k| k.as_str()))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            },
+        }
+    }
+
+    fn get_url_with_prefix(&self, prefix: &str, file: &str) -> Result<String> {
+        let file = match self.url_path.as_ref() {
+            Some(url_path) => self
+                .folder
+                .strip_prefix(url_path)?
+                .join(file)
+                .to_string_lossy()
+                .into_owned(),
+            None => file.to_string(),
+        };
+        if self.legacy_url_encoding {
+            return Ok(format!(
+                "{}/{}",
+                prefix,
+                utf8_percent_encode(&file, CONTROLS_ENHANCED)
+            ));
+        }
+        let path: Vec<String> = file
+            .split('/')
+            .map(|segment| utf8_percent_encode(segment, PATH_SEGMENT).to_string())
+            .collect();
+        Ok(format!("{}/{}", prefix, path.join("/")))
     }
 }
 
 impl Auth {
-    fn from_yaml(dict: &Hash, default: Option<&Auth>) -> Result<Auth, InvalidYamlTypeError> {
+    fn from_yaml(dict: &Hash, default: Option<&Auth>) -> Result<Auth> {
         let auth_default = Self::default();
         let default = default.unwrap_or(&auth_default);
         let use_agent = get_bool_from(dict, "use_agent")?
@@ -421,12 +1406,15 @@ impl Auth {
         let from_openssh = get_bool_from(dict, "from_openssh")?
             .cloned()
             .unwrap_or(default.from_openssh);
+        let strict_host_key_checking =
+            StrictHostKeyChecking::from_yaml(dict, default.strict_host_key_checking)?;
 
         Ok(Auth {
             from_openssh,
             interactive,
             private_key_file,
             private_key_file_password,
+            strict_host_key_checking,
             use_agent,
         })
     }
@@ -439,14 +1427,34 @@ impl Default for Auth {
             interactive: true,
             private_key_file: None,
             private_key_file_password: None,
+            strict_host_key_checking: StrictHostKeyChecking::Ask,
             use_agent: true,
         }
     }
 }
 
+/// Convert the host portion of the given URL to punycode and percent-encode its path.
+fn normalize_url(url: &str) -> Result<String> {
+    let parsed =
+        url::Url::parse(url).with_context(|| format!("Invalid value for 'url': {}", url))?;
+    // The parser appends a slash to URLs without path, but URLs are joined with a slash later.
+    Ok(parsed.as_str().trim_end_matches('/').to_string())
+}
+
+fn get_positive_from(dict: &Hash, key: &str) -> Result<Option<usize>> {
+    match get_int_from(dict, key)? {
+        Some(value) if *value < 1 => bail!("'{}' needs to be positive.", key),
+        value => Ok(value.map(|v| *v as usize)),
+    }
+}
+
 fn check_prefix_length(length: i64) -> Result<()> {
-    if !(8..=128).contains(&length) {
-        bail! {"Prefix needs to be between 8 and 128 characters."};
+    if !(MIN_PREFIX_LENGTH as i64..=MAX_PREFIX_LENGTH as i64).contains(&length) {
+        bail!(
+            "Prefix needs to be between {} and {} characters.",
+            MIN_PREFIX_LENGTH,
+            MAX_PREFIX_LENGTH
+        );
     }
     Ok(())
 }
@@ -454,6 +1462,7 @@ fn check_prefix_length(length: i64) -> Result<()> {
 #[cfg(test)]
 mod tests {
     use crate::util;
+    use yaml_rust::YamlLoader;
 
     #[test]
     fn load_example_config() {
@@ -469,4 +1478,269 @@ mod tests {
             &Some("my-hostname-2.eu".to_string())
         );
     }
+
+    #[test]
+    fn output_section() {
+        let cfg = crate::cfg::Config::from_yaml(
+            "details: true\noutput:\n  url_only: true\n  color: never",
+        )
+        .unwrap();
+        assert!(cfg.output.details);
+        assert!(cfg.output.url_only);
+        assert!(!cfg.output.silent);
+        assert_eq!(cfg.output.color, super::ColorMode::Never);
+        assert!(cfg.show_details(false, false));
+        assert!(!cfg.show_details(true, true));
+        assert!(crate::cfg::Config::from_yaml("output:\n  color: sometimes").is_err());
+    }
+
+    #[test]
+    fn auto_stats_threshold() {
+        let mut cfg = crate::cfg::Config::from_yaml(
+            "details: true
+auto_stats_threshold: 10
+hosts:
+  metered:
+    folder: /srv/asfa
+    url: https://example.org
+  fast:
+    folder: /srv/asfa
+    url: https://example.org
+    auto_stats_threshold: 100",
+        )
+        .unwrap();
+        let metered = cfg.get_host(Some("metered")).unwrap();
+        let fast = cfg.get_host(Some("fast")).unwrap();
+        assert!(cfg.show_details_for(metered, false, false, 10));
+        assert!(!cfg.show_details_for(metered, false, false, 11));
+        assert!(cfg.show_details_for(metered, true, false, 11));
+        assert!(cfg.show_details_for(fast, false, false, 11));
+
+        cfg.no_stats = true;
+        assert!(!cfg.show_details(false, false));
+        assert!(cfg.show_details(true, false));
+    }
+
+    #[test]
+    fn verify_strategy() {
+        use super::VerifyStrategy;
+        let cfg = crate::cfg::Config::from_yaml(
+            "verify_via_hash: false\nhosts:\n  a:\n    folder: /asfa\n    url: https://a.eu\n    \
+            verify_strategy: size\n  b:\n    folder: /asfa\n    url: https://b.eu\n",
+        )
+        .unwrap();
+        assert_eq!(cfg.verify_strategy, VerifyStrategy::Off);
+        assert_eq!(
+            cfg.get_host(Some("a")).unwrap().verify_strategy,
+            VerifyStrategy::Size
+        );
+        assert_eq!(
+            cfg.get_host(Some("b")).unwrap().verify_strategy,
+            VerifyStrategy::Off
+        );
+        assert!(crate::cfg::Config::from_yaml("verify_strategy: mtime").is_err());
+    }
+
+    #[test]
+    fn layout_per_host() {
+        use crate::layout::Layout;
+        let cfg = crate::cfg::Config::from_yaml(
+            "hosts:\n  a:\n    folder: /asfa\n    url: https://a.eu\n    layout:\n      depth: 3\n  \
+            b:\n    folder: /asfa\n    url: https://b.eu\n",
+        )
+        .unwrap();
+        assert_eq!(cfg.get_host(Some("a")).unwrap().layout.depth(), 3);
+        assert_eq!(cfg.get_host(Some("b")).unwrap().layout, Layout::default());
+        assert!(crate::cfg::Config::from_yaml(
+            "hosts:\n  c:\n    folder: /asfa\n    url: https://c.eu\n    layout:\n      depth: 1\n"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn expire_per_host() {
+        let cfg = crate::cfg::Config::from_yaml(
+            "expire: 3days\nhosts:\n  a:\n    folder: /asfa\n    url: https://a.eu\n  \
+            b:\n    folder: /asfa\n    url: https://b.eu\n    expire: none\n",
+        )
+        .unwrap();
+        assert_eq!(
+            cfg.get_host(Some("a")).unwrap().expire.as_deref(),
+            Some("3days")
+        );
+        assert_eq!(
+            cfg.get_host(Some("b")).unwrap().expire.as_deref(),
+            Some("none")
+        );
+
+        assert!(crate::cfg::Config::from_yaml("expire: 3dayz\n").is_err());
+        let error = crate::cfg::Config::from_yaml(
+            "hosts:\n  a:\n    folder: /asfa\n    url: https://a.eu\n    expire: 10s\n",
+        )
+        .unwrap_err();
+        assert!(format!("{:#}", error).contains("host 'a'"));
+    }
+
+    #[test]
+    fn host_extends() {
+        let cfg = crate::cfg::Config::from_yaml(
+            "hosts:\n  mirror:\n    template: true\n    group: www-data\n    prefix_length: 16\n    \
+            auth:\n      use_agent: false\n      interactive: false\n  \
+            a:\n    extends: mirror\n    folder: /asfa\n    url: https://a.eu\n  \
+            b:\n    extends: a\n    url: https://b.eu\n    auth:\n      interactive: true\n",
+        )
+        .unwrap();
+        assert!(cfg.get_host(Some("mirror")).is_err());
+        let a = cfg.get_host(Some("a")).unwrap();
+        assert_eq!(a.group.as_deref(), Some("www-data"));
+        assert_eq!(a.prefix_length, 16);
+        assert!(!a.auth.interactive);
+        let b = cfg.get_host(Some("b")).unwrap();
+        assert_eq!(b.folder, std::path::PathBuf::from("/asfa"));
+        assert_eq!(b.url, "https://b.eu");
+        assert!(b.auth.interactive);
+        assert!(!b.auth.use_agent);
+
+        let error = crate::cfg::Config::from_yaml(
+            "hosts:\n  a:\n    extends: b\n  b:\n    extends: a\n    folder: /asfa\n",
+        )
+        .unwrap_err();
+        assert!(format!("{}", error).contains("cycle"));
+        assert!(crate::cfg::Config::from_yaml(
+            "hosts:\n  a:\n    extends: c\n    folder: /asfa\n    url: https://a.eu\n"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn adhoc_host() {
+        let mut cfg = crate::cfg::Config::from_yaml("prefix_length: 16\n").unwrap();
+        cfg.add_adhoc_host(
+            "me@example.com:2222",
+            "/var/www/share",
+            "https://example.com/share",
+        )
+        .unwrap();
+        let host = cfg.get_host(Some("me@example.com:2222")).unwrap();
+        assert_eq!(host.hostname.as_deref(), Some("example.com:2222"));
+        assert_eq!(host.user.as_deref(), Some("me"));
+        assert_eq!(host.folder, std::path::PathBuf::from("/var/www/share"));
+        assert_eq!(host.prefix_length, 16);
+
+        for invalid in ["me@", "@example.com", "example.com:ssh", ":22"] {
+            assert!(cfg
+                .add_adhoc_host(invalid, "/tmp", "https://example.com")
+                .is_err());
+        }
+        assert!(cfg
+            .add_adhoc_host("me@example.com:2222", "/tmp", "https://example.com")
+            .is_err());
+    }
+
+    #[test]
+    fn push_directories() {
+        use super::DirectoryMode;
+        use crate::bundle::ArchiveFormat;
+        let cfg = crate::cfg::Config::from_yaml(
+            "push_directories: zip\nhosts:\n  a:\n    folder: /asfa\n    url: https://a.eu\n    \
+            push_directories: recursive\n  b:\n    folder: /asfa\n    url: https://b.eu\n",
+        )
+        .unwrap();
+        assert_eq!(
+            cfg.push_directories,
+            DirectoryMode::Archive(ArchiveFormat::Zip)
+        );
+        assert_eq!(
+            cfg.get_host(Some("a")).unwrap().push_directories,
+            DirectoryMode::Recursive
+        );
+        assert_eq!(
+            cfg.get_host(Some("b")).unwrap().push_directories,
+            DirectoryMode::Archive(ArchiveFormat::Zip)
+        );
+        assert!(crate::cfg::Config::from_yaml("push_directories: rar").is_err());
+    }
+
+    #[test]
+    fn transfer_protocol() {
+        use super::TransferProtocol;
+        let cfg = crate::cfg::Config::from_yaml(
+            "transfer_protocol: sftp\nhosts:\n  a:\n    folder: /asfa\n    url: https://a.eu\n    \
+            transfer_protocol: scp\n  b:\n    folder: /asfa\n    url: https://b.eu\n",
+        )
+        .unwrap();
+        assert_eq!(
+            cfg.get_host(Some("a")).unwrap().transfer_protocol,
+            TransferProtocol::Scp
+        );
+        assert_eq!(
+            cfg.get_host(Some("b")).unwrap().transfer_protocol,
+            TransferProtocol::Sftp
+        );
+        assert!(crate::cfg::Config::from_yaml("transfer_protocol: rsync").is_err());
+    }
+
+    #[test]
+    fn url_with_international_domain() {
+        let host = |yaml: &str| {
+            super::Host::from_yaml(
+                "idn".to_string(),
+                &YamlLoader::load_from_str(yaml).unwrap()[0],
+            )
+            .unwrap()
+        };
+        let yaml = "folder: /var/www/asfa\nurl: https://bücher.example/asfa\n";
+        assert_eq!(
+            host(yaml).get_url("abc/Übersicht #1.pdf").unwrap(),
+            "https://xn--bcher-kva.example/asfa/abc/%C3%9Cbersicht%20%231.pdf"
+        );
+        assert_eq!(
+            host(&format!("{}legacy_url_encoding: true\n", yaml))
+                .get_url("abc/Übersicht #1.pdf")
+                .unwrap(),
+            "https://bücher.example/asfa/abc/%C3%9Cbersicht%20#1.pdf"
+        );
+    }
+
+    #[test]
+    fn url_variants() {
+        let host = super::Host::from_yaml(
+            "variants".to_string(),
+            &YamlLoader::load_from_str(
+                "folder: /var/www/asfa\nurl: https://example.com/asfa\n\
+                url_variants:\n  internal: http://nas.lan/asfa/\n",
+            )
+            .unwrap()[0],
+        )
+        .unwrap();
+        assert_eq!(
+            host.get_urls("abc/file.txt", None).unwrap(),
+            vec!["https://example.com/asfa/abc/file.txt"]
+        );
+        assert_eq!(
+            host.get_urls("abc/file.txt", Some("all")).unwrap(),
+            vec![
+                "https://example.com/asfa/abc/file.txt",
+                "http://nas.lan/asfa/abc/file.txt"
+            ]
+        );
+        assert!(host.get_urls("abc/file.txt", Some("external")).is_err());
+    }
+
+    #[test]
+    fn url_path_maps_folder() {
+        let host = super::Host::from_yaml(
+            "chroot".to_string(),
+            &YamlLoader::load_from_str(
+                "folder: /srv/www/files/asfa\nremote_path: /files/asfa\n\
+                url: https://example.com\nurl_path: /srv/www\n",
+            )
+            .unwrap()[0],
+        )
+        .unwrap();
+        assert_eq!(
+            host.get_url("abc/file.txt").unwrap(),
+            "https://example.com/files/asfa/abc/file.txt"
+        );
+    }
 }
@@ -1,14 +1,28 @@
-use anyhow::{bail, Context, Result};
-use clap::{crate_authors, crate_description, crate_version, AppSettings, Parser};
-use indicatif::ProgressStyle;
+use anyhow::{bail, Result};
+use clap::{crate_authors, crate_description, crate_version, AppSettings, CommandFactory, Parser};
+use console::Term;
+use indicatif::{MultiProgress, ProgressDrawTarget, ProgressStyle, TermLike};
+use std::io::{self, IsTerminal};
 use std::iter::IntoIterator;
+use std::path::PathBuf;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc;
 use std::sync::mpsc::channel;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
+use unicode_width::UnicodeWidthChar;
 
-use crate::cmd::{Check, Clean, List, Push, Rename, Verify};
+use crate::cfg::Config;
+use crate::cmd::{
+    Check, Clean, Command, CompleteRemote, Completions, Daemon, Doctor, Expire, ExportManifest,
+    Fsck, Hosts, Link, List, MigratePrefix, Pin, Ping, Push, QuickShare, Rename, Resolve,
+    ShellInit, Stats, Status, Verify,
+};
+use crate::metrics;
+use crate::ssh::SshSession;
+use crate::theme::{FrameChars, Frames, LongLines, Theme};
+use crate::tr;
 
 #[derive(Parser, Debug)]
 #[clap(
@@ -43,10 +57,51 @@ pub struct Opts {
     pub loglevel: Option<String>,
 
     /// Name of remote site to push to. Only relevant if several remote sites are configured.
-    /// The default host can be set in config via `default_host`-option.
+    /// The default host can be set in config via `default_host`-option or chosen depending on
+    /// context via `host_rules`.
     #[clap(short = 'H', long)]
     pub host: Option<String>,
 
+    /// Base folder on a host that is not configured at all, given as `-H
+    /// [user@]hostname[:port]`. Requires `--url`, no config file is needed.
+    #[clap(long, value_name = "folder", requires_all = &["host", "url"])]
+    pub remote_folder: Option<String>,
+
+    /// URL under which `--remote-folder` is served.
+    #[clap(long, requires = "remote-folder")]
+    pub url: Option<String>,
+
+    /// Do not hand the command to a running `asfa daemon` but connect directly.
+    #[clap(long)]
+    pub no_daemon: bool,
+
+    /// When to display progress bars and spinners: 'auto' displays them if stderr is a terminal
+    /// and prints plain progress lines every few seconds otherwise.
+    #[clap(long, arg_enum, default_value = "auto")]
+    pub progress: Progress,
+
+    /// Draw frames with plain ASCII characters (`+--+`) instead of box-drawing characters, e.g.
+    /// for terminals without UTF-8 support. Chosen automatically depending on the locale unless
+    /// set via `frames` in the `theme` config section.
+    #[clap(long)]
+    pub ascii_frames: bool,
+
+    /// Print how long connecting, authenticating and each remote operation took once done (also
+    /// logged at loglevel 'trace').
+    #[clap(long)]
+    pub timings: bool,
+
+    /// Write every remote command executed (with its duration, exit code and truncated output)
+    /// as JSON to the given file, e.g. to attach it to a bug report.
+    #[clap(long, value_name = "file")]
+    pub trace_remote: Option<PathBuf>,
+
+    /// Never retrieve sizes and modification times of remote files unless options of the command
+    /// itself require them (e.g. `--details` or `--sort-size`), even if `details` is enabled in
+    /// config.
+    #[clap(long)]
+    pub no_stats: bool,
+
     #[clap(subcommand)]
     pub cmd: UserCommand,
 }
@@ -68,30 +123,419 @@ impl Opts {
             _ => Ok(()),
         }
     }
+
+    /// Loglevel selected via `--loglevel`, `--verbose` or `--quiet`.
+    pub fn loglevel_filter(&self) -> Result<log::LevelFilter> {
+        Ok(match (self.loglevel.as_deref(), self.verbose, self.quiet) {
+            (Some("trace"), _, _) => log::LevelFilter::Trace,
+            (Some("debug"), _, _) => log::LevelFilter::Debug,
+            (Some("info"), _, _) => log::LevelFilter::Info,
+            (Some("warn"), _, _) => log::LevelFilter::Warn,
+            (Some("error"), _, _) => log::LevelFilter::Error,
+            (None, v, 0) if v > 1 => log::LevelFilter::Trace,
+            (None, 1, 0) => log::LevelFilter::Debug,
+            (None, 0, 0) => log::LevelFilter::Info,
+            (None, 0, 1) => log::LevelFilter::Warn,
+            (None, 0, 2) => log::LevelFilter::Error,
+            (None, 0, _) => log::LevelFilter::Off,
+            _ => {
+                bail!("Restriction of loglevel in clap failed!");
+            }
+        })
+    }
+
+    /// Whether the loglevel was set explicitly via `--loglevel`, `--verbose` or `--quiet`.
+    pub fn loglevel_explicit(&self) -> bool {
+        self.loglevel.is_some() || self.verbose > 0 || self.quiet > 0
+    }
+
+    /// Whether the command should be handed to a running daemon if there is one.
+    pub fn use_daemon(&self) -> bool {
+        // Timings and traces are only meaningful for a session established by this invocation,
+        // ad-hoc hosts are unknown to the daemon.
+        !self.no_daemon
+            && !self.timings
+            && self.trace_remote.is_none()
+            && self.remote_folder.is_none()
+            && !matches!(self.cmd, UserCommand::Daemon(_) | UserCommand::Ping(_))
+    }
+
+    /// Whether the selected command needs the base folder of the host to exist (`doctor` checks
+    /// it by itself, `ping` only checks the connection).
+    pub fn needs_base_folder(&self) -> bool {
+        !matches!(self.cmd, UserCommand::Doctor(_) | UserCommand::Ping(_))
+    }
+
+    /// Exit code to use if the selected command fails without requesting a specific one.
+    pub fn exit_code_on_error(&self) -> i32 {
+        match &self.cmd {
+            UserCommand::Check(check) if check.machine_mode() => 2,
+            _ => 1,
+        }
+    }
 }
 
-#[derive(Parser, Debug)]
-pub enum UserCommand {
-    #[clap(name = "check")]
-    Check(Check),
+/// Load the config and apply all settings of a single invocation with the given command line
+/// (loglevel, progress, colors, language, theme).
+///
+/// Shared by `main` and the daemon, which runs several invocations in one process.
+pub fn setup<I: IntoIterator<Item = String>>(opts: &Opts, args: I) -> Result<Config> {
+    opts.verify()?;
+    let level = opts.loglevel_filter()?;
+    log::set_max_level(level);
+    set_progress(opts.progress);
+
+    let cfg_path = opts.config.clone().or(std::env::var("ASFA_CONFIG").ok());
+    let mut cfg = match (
+        opts.host.as_deref(),
+        opts.remote_folder.as_deref(),
+        opts.url.as_deref(),
+    ) {
+        (Some(spec), Some(folder), Some(url)) => {
+            // Ad-hoc hosts do not need any configuration.
+            let mut cfg = crate::cfg::load_optional(&cfg_path)?.unwrap_or_default();
+            cfg.add_adhoc_host(spec, folder, url)?;
+            cfg
+        }
+        _ => crate::cfg::load(&cfg_path)?,
+    };
+    cfg.loglevel = if cfg.output.silent && !opts.loglevel_explicit() {
+        log::set_max_level(log::LevelFilter::Off);
+        log::LevelFilter::Off
+    } else {
+        level
+    };
+    cfg.no_stats = opts.no_stats;
+
+    cfg.output.apply_color();
+    crate::i18n::set_language(cfg.language.unwrap_or_else(crate::i18n::Language::from_env));
+    set_theme({
+        let mut theme = cfg.theme.clone();
+        if opts.ascii_frames {
+            theme.frames = Frames::Ascii;
+        }
+        theme
+    });
+    check_short_aliases(&cfg, args)?;
+    Ok(cfg)
+}
+
+/// Declare all subcommands in one table.
+///
+/// Each entry names the variant (which is also the type implementing `Command`), the name of the
+/// subcommand, optional aliases and whether the command modifies the remote site (refused for
+/// `read_only` hosts). Generates the `UserCommand` enum as well as dispatching to the commands.
+macro_rules! user_commands {
+    ($(
+        $variant:ident($name:literal $(, aliases = [$($alias:literal),+])?),
+        modifies_remote = $modifies_remote:literal;
+    )+) => {
+        #[derive(Parser, Debug)]
+        pub enum UserCommand {
+            $(
+                #[clap(name = $name $(, visible_aliases = &[$($alias),+])?)]
+                $variant($variant),
+            )+
+        }
+
+        impl UserCommand {
+            /// Run the selected command.
+            pub fn run(&self, session: &SshSession, config: &Config) -> Result<()> {
+                if session.host.read_only && self.modifies_remote() {
+                    bail!(tr!("host.read-only", host = session.host.alias));
+                }
+                let start = Instant::now();
+                let result = match self {
+                    $(UserCommand::$variant(cmd) => cmd.run(session, config),)+
+                };
+                metrics::record(
+                    config.metrics_file.as_deref(),
+                    &session.host.alias,
+                    self.name(),
+                    start.elapsed(),
+                    result.is_ok(),
+                );
+                result
+            }
+
+            /// Name of the subcommand.
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $(UserCommand::$variant(_) => $name,)+
+                }
+            }
+
+            /// Whether the command (potentially) modifies the remote site.
+            fn modifies_remote(&self) -> bool {
+                match self {
+                    $(UserCommand::$variant(_) => $modifies_remote,)+
+                }
+            }
+        }
+    };
+}
+
+user_commands! {
+    Check("check"), modifies_remote = false;
+    Clean("clean", aliases = ["rm"]), modifies_remote = true;
+    CompleteRemote("__complete-remote"), modifies_remote = false;
+    Completions("completions"), modifies_remote = false;
+    Daemon("daemon"), modifies_remote = false;
+    Doctor("doctor"), modifies_remote = false;
+    Expire("expire"), modifies_remote = true;
+    ExportManifest("export-manifest"), modifies_remote = false;
+    Fsck("fsck"), modifies_remote = true;
+    Hosts("hosts"), modifies_remote = false;
+    Link("link"), modifies_remote = true;
+    List("list", aliases = ["ls"]), modifies_remote = false;
+    MigratePrefix("migrate-prefix"), modifies_remote = true;
+    Pin("pin"), modifies_remote = true;
+    Ping("ping"), modifies_remote = false;
+    Push("push", aliases = ["up"]), modifies_remote = true;
+    QuickShare("quickshare"), modifies_remote = true;
+    Rename("rename", aliases = ["mv"]), modifies_remote = true;
+    Resolve("resolve"), modifies_remote = false;
+    ShellInit("shell-init"), modifies_remote = false;
+    Stats("stats"), modifies_remote = false;
+    Status("status"), modifies_remote = false;
+    Verify("verify"), modifies_remote = false;
+}
+
+impl UserCommand {
+    /// Local files the command operates on (used to select the host via `host_rules`).
+    pub fn local_files(&self) -> &[PathBuf] {
+        match self {
+            UserCommand::Push(push) => push.files(),
+            _ => &[],
+        }
+    }
+}
+
+/// Short git-style aliases for frequently used subcommands, can be disabled via
+/// `short_aliases: false` in config.
+const SHORT_ALIASES: &[&str] = &["ls", "rm", "up"];
+
+/// Bail if the subcommand was invoked via a short alias that is disabled in config.
+///
+/// Since clap does not report the name under which a subcommand was invoked, it is looked up in
+/// the raw arguments (skipping global options and their values).
+pub fn check_short_aliases<I: IntoIterator<Item = String>>(config: &Config, args: I) -> Result<()> {
+    if config.short_aliases {
+        return Ok(());
+    }
+    let takes_value = options_taking_values(&Opts::command());
+
+    let mut args = args.into_iter().skip(1);
+    while let Some(arg) = args.next() {
+        if takes_value.contains(&arg) {
+            args.next();
+        } else if !arg.starts_with('-') {
+            if SHORT_ALIASES.contains(&arg.as_str()) {
+                bail!(tr!("short-alias.disabled", alias = arg));
+            }
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Short and long forms of all options of the given command that take a value.
+pub fn options_taking_values(command: &clap::Command) -> Vec<String> {
+    command
+        .get_arguments()
+        .filter(|a| a.is_takes_value_set() && !a.is_positional())
+        .flat_map(|a| {
+            a.get_short()
+                .map(|s| format!("-{}", s))
+                .into_iter()
+                .chain(a.get_long().map(|l| format!("--{}", l)))
+        })
+        .collect()
+}
+
+/// How progress is displayed, selected via `--progress`.
+#[derive(clap::ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Progress {
+    Auto,
+    Always,
+    Never,
+}
+
+static PROGRESS: Mutex<Progress> = Mutex::new(Progress::Auto);
+static THEME: Mutex<Option<&'static Theme>> = Mutex::new(None);
+
+/// How often plain progress lines are printed if stderr is no terminal.
+const PLAIN_PROGRESS_EVERY: Duration = Duration::from_secs(10);
+
+/// Set how progress is displayed for the current invocation (the daemon runs several
+/// invocations in one process, see `setup`).
+pub fn set_progress(progress: Progress) {
+    *PROGRESS.lock().unwrap() = progress;
+}
+
+fn progress() -> Progress {
+    *PROGRESS.lock().unwrap()
+}
+
+/// Set the theme for the current invocation, needs to happen before anything is printed.
+pub fn set_theme(theme: Theme) {
+    // Styles are handed out by reference, so every distinct theme is kept for the remainder of
+    // the process. Invocations handled by the daemon hardly ever use more than a few.
+    static THEMES: Mutex<Vec<&'static Theme>> = Mutex::new(Vec::new());
+    let mut themes = THEMES.lock().unwrap();
+    let theme = match themes.iter().find(|t| ***t == theme) {
+        Some(known) => *known,
+        None => {
+            let leaked: &'static Theme = Box::leak(Box::new(theme));
+            themes.push(leaked);
+            leaked
+        }
+    };
+    *THEME.lock().unwrap() = Some(theme);
+}
+
+fn theme() -> &'static Theme {
+    let mut theme = THEME.lock().unwrap();
+    match *theme {
+        Some(theme) => theme,
+        None => {
+            let default: &'static Theme = Box::leak(Box::default());
+            *theme = Some(default);
+            default
+        }
+    }
+}
+
+/// Whether progress bars/spinners are drawn as such (otherwise they are hidden or replaced by
+/// plain progress lines).
+fn progress_drawn() -> bool {
+    match progress() {
+        Progress::Always => true,
+        Progress::Never => false,
+        Progress::Auto => std::io::stderr().is_terminal(),
+    }
+}
+
+/// Print a line (on stderr) heading the progress bar drawn next, unless progress is hidden.
+pub fn progress_header(line: &str) {
+    if progress() == Progress::Never {
+        return;
+    }
+    match MULTI_PROGRESS.lock().unwrap().as_ref() {
+        Some(multi) if progress_drawn() => {
+            // Printed above all bars, cannot fail for stderr.
+            let _ = multi.println(line);
+        }
+        _ => eprintln!("{}", line),
+    }
+}
+
+/// Create a progress bar of the given length honoring `--progress`.
+pub fn progress_bar(len: u64) -> indicatif::ProgressBar {
+    if let Some(multi) = MULTI_PROGRESS.lock().unwrap().as_ref() {
+        return multi.add(indicatif::ProgressBar::new(len));
+    }
+    indicatif::ProgressBar::with_draw_target(Some(len), progress_draw_target())
+}
+
+fn progress_draw_target() -> ProgressDrawTarget {
+    match progress() {
+        Progress::Never => ProgressDrawTarget::hidden(),
+        _ if progress_drawn() => ProgressDrawTarget::term_like(Box::new(Term::stderr())),
+        _ => ProgressDrawTarget::term_like(Box::<PlainProgress>::default()),
+    }
+}
+
+/// Progress bars drawn together, see `multi_progress`.
+static MULTI_PROGRESS: Mutex<Option<MultiProgress>> = Mutex::new(None);
+
+/// Draw all progress bars created until the returned guard is dropped below each other, e.g. for
+/// concurrent uploads.
+pub fn multi_progress() -> MultiProgressGuard {
+    *MULTI_PROGRESS.lock().unwrap() = Some(MultiProgress::with_draw_target(progress_draw_target()));
+    MultiProgressGuard
+}
+
+pub struct MultiProgressGuard;
+
+impl Drop for MultiProgressGuard {
+    fn drop(&mut self) {
+        MULTI_PROGRESS.lock().unwrap().take();
+    }
+}
+
+/// Draw target printing the current state of a progress bar as plain line (without control
+/// sequences) every `PLAIN_PROGRESS_EVERY`, e.g. for cron jobs.
+#[derive(Debug, Default)]
+struct PlainProgress {
+    state: Mutex<PlainProgressState>,
+}
 
-    #[clap(name = "clean")]
-    Clean(Clean),
+#[derive(Debug, Default)]
+struct PlainProgressState {
+    frame: String,
+    printed_last: Option<Instant>,
+}
+
+impl TermLike for PlainProgress {
+    fn width(&self) -> u16 {
+        u16::MAX
+    }
+
+    fn move_cursor_up(&self, _: usize) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn move_cursor_down(&self, _: usize) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn move_cursor_right(&self, _: usize) -> io::Result<()> {
+        Ok(())
+    }
 
-    #[clap(name = "list")]
-    List(List),
+    fn move_cursor_left(&self, _: usize) -> io::Result<()> {
+        Ok(())
+    }
 
-    #[clap(name = "mv")]
-    Mv(Rename),
+    fn write_line(&self, s: &str) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.frame.push_str(s);
+        state.frame.push('\n');
+        Ok(())
+    }
 
-    #[clap(name = "rename")]
-    Rename(Rename),
+    fn write_str(&self, s: &str) -> io::Result<()> {
+        self.state.lock().unwrap().frame.push_str(s);
+        Ok(())
+    }
 
-    #[clap(name = "push")]
-    Push(Push),
+    fn clear_line(&self) -> io::Result<()> {
+        Ok(())
+    }
 
-    #[clap(name = "verify")]
-    Verify(Verify),
+    fn flush(&self) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let frame = std::mem::take(&mut state.frame);
+        let due = state
+            .printed_last
+            .map(|last| last.elapsed() >= PLAIN_PROGRESS_EVERY)
+            .unwrap_or(true);
+        // Only the last line is of interest, without the (non-ASCII) spinner in front.
+        let line = console::strip_ansi_codes(&frame)
+            .lines()
+            .rev()
+            .map(|l| {
+                l.trim_start_matches(|c: char| !c.is_ascii_graphic())
+                    .to_string()
+            })
+            .find(|l| !l.is_empty());
+        if let (true, Some(line)) = (due, line) {
+            eprintln!("{}", line);
+            state.printed_last = Some(Instant::now());
+        }
+        Ok(())
+    }
 }
 
 /// Progress bar style for file transfers
@@ -115,7 +559,7 @@ pub fn style_progress_bar_count() -> Result<indicatif::ProgressStyle> {
 }
 
 pub fn spinner() -> Result<indicatif::ProgressBar> {
-    let bar = indicatif::ProgressBar::new(!0);
+    let bar = progress_bar(!0);
     bar.set_style(style_spinner()?);
     Ok(bar)
 }
@@ -124,34 +568,82 @@ pub fn style_spinner() -> Result<indicatif::ProgressStyle> {
     Ok(ProgressStyle::default_spinner().template("{spinner:.green} {msg}")?)
 }
 
+/// Report that there is nothing to show or operate on, which is no error.
+///
+/// The message is only printed to stdout if it is a terminal, so that scripts parsing the output
+/// simply receive no lines.
+pub fn report_empty(config: &Config, message: &str) {
+    if config.is_silent() {
+        return;
+    }
+    if std::io::stdout().is_terminal() {
+        println!("{}", message);
+    } else {
+        eprintln!("{}", message);
+    }
+}
+
+/// Print the given value as (pretty) JSON on stdout, see `--json`.
+pub fn print_json<T: serde::Serialize>(value: &T) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+/// Placeholder drawn in frames without content.
+const EMPTY_CONTENT: &str = " (Nothing to show.) ";
+
 pub fn draw_boxed<'a, H: AsRef<str>, I: IntoIterator<Item = &'a str>>(
     header: H,
     content: I,
     color_box: &console::Style,
 ) -> Result<()> {
-    let corner_top_left = color_box.apply_to("┌");
-    let corner_top_right = color_box.apply_to("┐");
-    let corner_bottom_left = color_box.apply_to("└");
-    let corner_bottom_right = color_box.apply_to("┘");
-    let header_left = color_box.apply_to("┤");
-    let header_right = color_box.apply_to("├");
+    let chars = theme().frames.chars();
+    let corner_top_left = color_box.apply_to(chars.top_left);
+    let corner_top_right = color_box.apply_to(chars.top_right);
+    let corner_bottom_left = color_box.apply_to(chars.bottom_left);
+    let corner_bottom_right = color_box.apply_to(chars.bottom_right);
+    let header_left = color_box.apply_to(chars.header_left);
+    let header_right = color_box.apply_to(chars.header_right);
 
-    let content: Vec<&str> = content.into_iter().collect();
+    // Lines wider than the terminal would wreck the frame.
+    let max_len = Term::stdout()
+        .size_checked()
+        .map(|(_, columns)| (columns as usize).saturating_sub(2 /* frame left/right */));
+    let mut content = content.into_iter().peekable();
+    let content: Vec<String> = if content.peek().is_none() {
+        vec![EMPTY_CONTENT.to_string()]
+    } else {
+        content
+            .flat_map(|line| match max_len {
+                Some(max_len) => fit_line(line, max_len, &chars, theme().long_lines),
+                None => vec![line.to_string()],
+            })
+            .collect()
+    };
+    let header = match max_len {
+        Some(max_len) if console::measure_text_width(header.as_ref()) + 2 > max_len => {
+            console::truncate_str(header.as_ref(), max_len.saturating_sub(2), "…").to_string()
+        }
+        _ => header.as_ref().to_string(),
+    };
 
-    let header_len = console::strip_ansi_codes(header.as_ref()).chars().count();
+    let header_len = console::measure_text_width(&header);
 
     let line_len = {
         let content_max = content
             .iter()
-            .map(|l| console::strip_ansi_codes(l).chars().count())
+            .map(|l| console::measure_text_width(l))
             .max()
-            .with_context(|| "Nothing to show.")?;
+            .unwrap_or(0);
 
-        *[60, content_max, header_len + 2].iter().max().unwrap()
+        let line_len = *[60, content_max, header_len + 2].iter().max().unwrap();
+        max_len
+            .map(|max_len| line_len.min(max_len.max(header_len + 2)))
+            .unwrap_or(line_len)
     };
 
-    let line_horizontal = |len: usize| color_box.apply_to("─".repeat(len));
-    let line_vertical = color_box.apply_to("│");
+    let line_horizontal = |len: usize| color_box.apply_to(chars.horizontal.to_string().repeat(len));
+    let line_vertical = color_box.apply_to(chars.vertical);
 
     let header_raw = format!(
         "{cl}{hl}{hdr}{hr}{fl}{cr}",
@@ -159,12 +651,13 @@ pub fn draw_boxed<'a, H: AsRef<str>, I: IntoIterator<Item = &'a str>>(
         cr = corner_top_right,
         hl = header_left,
         hr = header_right,
-        hdr = header.as_ref(),
+        hdr = header,
         fl = line_horizontal(line_len - 2 /* header left/right */ - header_len)
     );
-    println!("{}", join_frames(content[0], &header_raw, '┬'));
+    println!("{}", join_frames(&content[0], &header_raw, chars.join_top));
     for line in content.iter() {
-        let pad_width = line_len - console::strip_ansi_codes(line).chars().count();
+        // Wide characters might exceed shortened lines by a column.
+        let pad_width = line_len.saturating_sub(console::measure_text_width(line));
         println!(
             "{border}{line}{pad}{border}",
             line = line,
@@ -178,28 +671,140 @@ pub fn draw_boxed<'a, H: AsRef<str>, I: IntoIterator<Item = &'a str>>(
         cr = corner_bottom_right,
         l = line_horizontal(line_len)
     );
-    let last_line = join_frames(content[content.len() - 1], &last_line_raw, '┴');
+    let last_line = join_frames(
+        &content[content.len() - 1],
+        &last_line_raw,
+        chars.join_bottom,
+    );
 
     println!("{}", last_line);
 
     Ok(())
 }
 
+/// Shorten the given (possibly colored) line to at most `max_len` columns as selected by
+/// `long_lines`, possibly resulting in several lines.
+///
+/// Only the last column (after the last column separator) is shortened if possible so that
+/// columns stay aligned. At least one column of content is shown per line, even if `max_len`
+/// leaves no room for it.
+fn fit_line(line: &str, max_len: usize, chars: &FrameChars, long_lines: LongLines) -> Vec<String> {
+    let max_len = max_len.max(1);
+    let len = console::measure_text_width(line);
+    if len <= max_len {
+        return vec![line.to_string()];
+    }
+    let plain = console::strip_ansi_codes(line);
+    // Start of the last column, if the remaining space is reasonably wide.
+    let last_column = {
+        let mut column = 0;
+        let mut start = 0;
+        let mut previous = None;
+        for c in plain.chars() {
+            column += c.width().unwrap_or(0);
+            // Separators are surrounded by spaces.
+            if c == chars.vertical || (c == ' ' && previous == Some(chars.vertical)) {
+                start = column;
+            }
+            previous = Some(c);
+        }
+        if max_len.saturating_sub(start) >= 16 {
+            start
+        } else {
+            0
+        }
+    };
+    match long_lines {
+        LongLines::EllipsisEnd => vec![console::truncate_str(line, max_len, "…").to_string()],
+        LongLines::EllipsisMiddle => {
+            let available = max_len.saturating_sub(last_column + 1 /* ellipsis */);
+            let tail = available / 2;
+            let head = last_column + available - tail;
+            vec![format!(
+                "{}…{}",
+                slice_columns(line, 0, head),
+                slice_columns(line, len - tail, len)
+            )]
+        }
+        LongLines::Wrap => {
+            // Keep column separators in continuation lines.
+            let indent: String = plain
+                .chars()
+                .scan(0, |column, c| {
+                    *column += c.width().unwrap_or(0);
+                    Some((*column, c))
+                })
+                .take_while(|(column, _)| *column <= last_column)
+                .map(|(_, c)| {
+                    if c == chars.vertical {
+                        color::frame.apply_to(c).to_string()
+                    } else {
+                        " ".to_string()
+                    }
+                })
+                .collect();
+            let mut lines = vec![slice_columns(line, 0, max_len)];
+            let step = max_len.saturating_sub(last_column + 1 /* padding */).max(1);
+            let mut start = max_len;
+            while start < len {
+                lines.push(format!(
+                    "{}{} ",
+                    indent,
+                    slice_columns(line, start, (start + step).min(len))
+                ));
+                start += step;
+            }
+            lines
+        }
+    }
+}
+
+/// Part of the given (possibly colored) string displayed in the columns `start..end`, keeping all
+/// ANSI codes so that styles are retained.
+fn slice_columns(s: &str, start: usize, end: usize) -> String {
+    let mut sliced = String::new();
+    let mut column = 0;
+    for (part, is_ansi) in console::AnsiCodeIterator::new(s) {
+        if is_ansi {
+            sliced.push_str(part);
+            continue;
+        }
+        for c in part.chars() {
+            if column >= start && column < end {
+                sliced.push(c);
+            }
+            column += c.width().unwrap_or(0);
+        }
+    }
+    sliced
+}
+
 /// Replace portions in raw that are a horizontal line ('─') with `joiner` where content contains a
 /// vertical line ('│').
 /// This makes it possible to join frames.
 fn join_frames(content: &str, raw: &str, joiner: char) -> String {
+    let chars = theme().frames.chars();
     // Make sure any frames drawn in last line are joined
     let mut replacer = text::ColoredTextReplacer::new(raw.to_string());
     let nocolor: Vec<_> = console::strip_ansi_codes(raw).chars().collect();
-    for idx_separator in console::strip_ansi_codes(content)
+    // All characters in raw are one column wide, so separators have to be located by the column
+    // they are displayed in (wide characters take up two columns).
+    let columns = console::strip_ansi_codes(content)
         .chars()
-        .enumerate()
-        .filter_map(|(idx, c)| if c == '│' { Some(idx) } else { None })
+        .scan(0, |column, c| {
+            let current = *column;
+            *column += c.width().unwrap_or(0);
+            Some((current, c))
+        })
+        .collect::<Vec<_>>();
+    for idx_separator in
+        columns
+            .into_iter()
+            .filter_map(|(idx, c)| if c == chars.vertical { Some(idx) } else { None })
     {
         // replacer.replace_if(idx_separator, '─', joiner);
         let idx = idx_separator + /* frame */ 1;
-        if nocolor[idx] == '─' {
+        if nocolor.get(idx) == Some(&chars.horizontal) {
             replacer.replace(idx_separator + /* frame */ 1, joiner);
         }
     }
@@ -225,13 +830,15 @@ impl WaitingSpinner {
         let stop_token_pbar = Arc::clone(&stop_token);
         let handle = thread::spawn(move || {
             let spinner = crate::cli::spinner().expect("couldn't create spinner");
+            let drawn = progress_drawn();
             spinner.set_message(message.clone());
 
             let handle_messages = || {
                 while let Ok(msg) = rx.try_recv() {
                     match msg {
                         SpinnerSetting::Message(msg) => spinner.set_message(msg.clone()),
-                        SpinnerSetting::Println(msg) => spinner.println(&msg),
+                        SpinnerSetting::Println(msg) if drawn => spinner.println(&msg),
+                        SpinnerSetting::Println(msg) => eprintln!("{}", msg),
                     }
                 }
             };
@@ -272,16 +879,27 @@ impl WaitingSpinner {
 pub mod color {
 
     use console::Style;
+    use std::ops::Deref;
+
+    use crate::theme::Theme;
 
-    lazy_static::lazy_static! {
-        pub static ref dot : Style = Style::new().cyan();
-        pub static ref entry : Style = Style::new();
-        pub static ref expire : Style = Style::new().red();
-        pub static ref failure : Style = Style::new().red().bright();
-        pub static ref filename : Style = Style::new().blue().bright();
-        pub static ref frame : Style = Style::new().blue();
-        pub static ref success : Style = Style::new().green().bright();
+    /// Style of the theme of the current invocation (see `set_theme`).
+    pub struct ThemeStyle(fn(&Theme) -> &Style);
+
+    impl Deref for ThemeStyle {
+        type Target = Style;
+
+        fn deref(&self) -> &Style {
+            (self.0)(super::theme())
+        }
     }
+
+    pub static dot: ThemeStyle = ThemeStyle(|t| &t.dot);
+    pub static expire: ThemeStyle = ThemeStyle(|t| &t.expire);
+    pub static failure: ThemeStyle = ThemeStyle(|t| &t.failure);
+    pub static filename: ThemeStyle = ThemeStyle(|t| &t.filename);
+    pub static frame: ThemeStyle = ThemeStyle(|t| &t.frame);
+    pub static success: ThemeStyle = ThemeStyle(|t| &t.success);
 }
 
 #[allow(non_upper_case_globals)]
@@ -294,12 +912,20 @@ pub mod text {
     /// Changes behavior if stdout is no tty.
     pub fn separator() -> String {
         if std::io::stdout().is_terminal() {
-            format!(" {} ", super::color::frame.apply_to("│"))
+            format!(
+                " {} ",
+                super::color::frame.apply_to(super::theme().frames.chars().vertical)
+            )
         } else {
             String::from('\t')
         }
     }
 
+    /// Arrow pointing from old to new names.
+    pub fn arrow() -> &'static str {
+        super::theme().frames.chars().arrow
+    }
+
     /// Helper to replace text in colored text
     pub struct ColoredTextReplacer {
         original: String,
@@ -346,3 +972,63 @@ pub mod text {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::theme::Frames;
+
+    #[test]
+    fn wrap_long_lines() {
+        let chars = Frames::Ascii.chars();
+        let line = format!(
+            " 1 | {} ",
+            console::Style::new()
+                .red()
+                .apply_to("https://example.com/abcdefghijklmnopqrstuvwxyz")
+        );
+        let lines = fit_line(&line, 30, &chars, LongLines::Wrap);
+        assert!(lines.iter().all(|l| console::measure_text_width(l) <= 30));
+        assert_eq!(
+            lines
+                .iter()
+                .map(|l| console::strip_ansi_codes(l).to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                " 1 | https://example.com/abcde",
+                "   | fghijklmnopqrstuvwxyz  ",
+            ]
+        );
+        assert_eq!(
+            console::strip_ansi_codes(&slice_columns(&line, 5, 10)),
+            "https"
+        );
+    }
+
+    #[test]
+    fn fit_into_tiny_widths() {
+        let chars = Frames::Ascii.chars();
+        let line = " 1 | https://example.com/a.pdf ";
+        for max_len in [0, 1, 2] {
+            for long_lines in [
+                LongLines::Wrap,
+                LongLines::EllipsisEnd,
+                LongLines::EllipsisMiddle,
+            ] {
+                let lines = fit_line(line, max_len, &chars, long_lines);
+                assert!(!lines.is_empty());
+                if long_lines == LongLines::Wrap {
+                    // Continuation lines are padded by a single space.
+                    let content: String = lines[1..]
+                        .iter()
+                        .map(|l| l.strip_suffix(' ').unwrap())
+                        .collect();
+                    assert_eq!(format!("{}{}", lines[0], content), line);
+                } else {
+                    assert_eq!(lines.len(), 1);
+                    assert!(console::measure_text_width(&lines[0]) <= max_len.max(1));
+                }
+            }
+        }
+    }
+}
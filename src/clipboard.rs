@@ -0,0 +1,60 @@
+//! Copy text to the system clipboard via the usual command line tools.
+
+use anyhow::{bail, Context, Result};
+use std::io::{ErrorKind, Write};
+use std::process::{Command, Stdio};
+
+/// Tools (with arguments) tried in order, the first one available is used.
+const TOOLS: &[(&str, &[&str])] = &[
+    ("wl-copy", &[]),
+    ("xclip", &["-selection", "clipboard"]),
+    ("xsel", &["--clipboard", "--input"]),
+    ("pbcopy", &[]),
+    ("clip.exe", &[]),
+];
+
+/// Copy the given text to the clipboard.
+pub fn copy(text: &str) -> Result<()> {
+    let wayland = std::env::var_os("WAYLAND_DISPLAY").is_some();
+    for (tool, args) in TOOLS.iter() {
+        if *tool == "wl-copy" && !wayland {
+            continue;
+        }
+        // xclip/xsel keep running in the background to serve the clipboard, so their output must
+        // not be captured.
+        let mut child = match Command::new(tool)
+            .args(*args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            Err(e) => return Err(e).with_context(|| format!("Could not run {}.", tool)),
+        };
+        child
+            .stdin
+            .take()
+            .with_context(|| format!("Could not pass text to {}.", tool))?
+            .write_all(text.as_bytes())?;
+        if !child.wait()?.success() {
+            bail!("{} failed to copy to clipboard.", tool);
+        }
+        log::debug!("Copied to clipboard via {}.", tool);
+        return Ok(());
+    }
+    bail!("No clipboard tool found (install wl-clipboard, xclip, xsel or pbcopy).");
+}
+
+/// Copy the given URLs (one per line) to the clipboard.
+///
+/// The URLs were already printed, so failing to copy them is only reported.
+pub fn copy_urls(urls: &[String]) {
+    if urls.is_empty() {
+        return;
+    }
+    if let Err(e) = copy(&urls.join("\n")) {
+        log::warn!("Could not copy URL to clipboard: {:#}", e);
+    }
+}
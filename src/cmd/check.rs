@@ -1,13 +1,19 @@
-use anyhow::{bail, Result};
+use anyhow::{Context, Result};
 use clap::Parser;
 use console::Style;
 use std::io::IsTerminal;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use crate::cfg::Config;
-use crate::cli::{color, draw_boxed};
+use crate::cli::{color, draw_boxed, WaitingSpinner};
 use crate::cmd::Command;
+use crate::file_listing::{Show, FORMAT_FIELDS, FORMAT_FIELDS_STATS};
 use crate::ssh::SshSession;
+use crate::template::Template;
+use crate::util::{self, ExitCodeError};
+
+/// Hash length used to compare content in `--deep` mode.
+const DEEP_HASH_LENGTH: u8 = 64;
 
 /// Check if a given local file is already present on the remote site.
 #[derive(Parser, Debug)]
@@ -16,18 +22,53 @@ pub struct Check {
     #[clap()]
     files: Vec<PathBuf>,
 
+    /// Also compare size and (full) hash of the remote candidates with the local files to
+    /// distinguish files that merely share the hash prefix from actually identical content.
+    ///
+    /// Prints one `identical`/`different`/`missing` line per file and exits with 1 if not all
+    /// files are identical.
+    #[clap(long, conflicts_with_all = &["format", "quiet", "url-only"])]
+    deep: bool,
+
     /// Show all details, can be set globally in config file.
     #[clap(long, short)]
     details: bool,
 
     /// Show no full urls but rather filenames only. Makes for more concise output.
-    #[clap(long, short)]
+    ///
+    /// Shorthand for `--show filename`.
+    #[clap(long, short, conflicts_with = "show")]
     filenames: bool,
 
+    /// Print one line per file according to the given template instead of a table, e.g.,
+    /// `--format '{index}\t{url}\t{size}\t{mtime}'`.
+    ///
+    /// Available fields: {index}, {rev_index}, {url}, {path}, {name}, {hash}, {size} (in bytes)
+    /// and {mtime}. Use `{{`/`}}` for literal braces.
+    #[clap(long, value_name = "template", conflicts_with_all = &["quiet", "url-only", "filenames", "show"])]
+    format: Option<String>,
+
+    /// Print the found files as JSON array (index, filename, url, path, hash, size, mtime and
+    /// expiration) for processing with e.g. `jq`.
+    #[clap(long, conflicts_with_all = &["format", "quiet", "url-only", "deep"])]
+    json: bool,
+
     /// If `details` is set to true in config, --no-details can be specified to suppress output.
     #[clap(long, short = 'D')]
     no_details: bool,
 
+    /// Machine mode for scripts: Print one `present`/`missing` line per file and exit with 0 if
+    /// all files are present, 1 if some are missing and 2 on error.
+    ///
+    /// Example: `asfa check --quiet foo.txt || asfa push foo.txt`
+    #[clap(long, conflicts_with_all = &["url-only", "details", "filenames"])]
+    quiet: bool,
+
+    /// How to present files: as URL, file name only, path relative to the base folder or
+    /// absolute path on the remote site.
+    #[clap(long, arg_enum, default_value = "url", value_name = "what")]
+    show: Show,
+
     /// Only list the remote URLs (useful for copying and scripting).
     #[clap(short, long = "url-only")]
     url_only: bool,
@@ -41,27 +82,210 @@ pub struct Check {
     with_size: bool,
 }
 
+impl Check {
+    /// Whether to only print URLs, requested explicitly or via `url_only` in the `output` config
+    /// section (unless a different presentation was requested).
+    fn url_only(&self, config: &Config) -> bool {
+        self.url_only
+            || (config.output.url_only
+                && self.show == Show::Url
+                && !(self.details || self.filenames || self.with_size || self.with_time))
+    }
+
+    /// Check if output and exit codes are meant to be consumed by scripts.
+    pub fn machine_mode(&self) -> bool {
+        self.quiet
+    }
+
+    fn run_machine_mode(&self, session: &SshSession, config: &Config) -> Result<()> {
+        let found = session.list_files()?.indices_by_hash(
+            self.files.iter().map(|pb| pb.to_string_lossy()),
+            session.host.prefix_length,
+        )?;
+
+        if !config.is_silent() {
+            for (file, idx) in self.files.iter().zip(found.iter()) {
+                println!(
+                    "{}\t{}",
+                    if idx.is_some() { "present" } else { "missing" },
+                    file.display()
+                );
+            }
+        }
+
+        let num_missing = found.iter().filter(|idx| idx.is_none()).count();
+        if num_missing == 0 {
+            Ok(())
+        } else {
+            Err(ExitCodeError::new(
+                1,
+                format!("{}/{} files missing.", num_missing, self.files.len()),
+            )
+            .into())
+        }
+    }
+
+    fn run_deep(&self, session: &SshSession, config: &Config) -> Result<()> {
+        let listing = session.list_files()?;
+        let found = listing.indices_by_hash(
+            self.files.iter().map(|pb| pb.to_string_lossy()),
+            session.host.prefix_length,
+        )?;
+
+        let candidates: Vec<(&PathBuf, &Path)> = self
+            .files
+            .iter()
+            .zip(found.iter())
+            .filter_map(|(file, idx)| Some((file, listing.path_of((*idx)?)?)))
+            .collect();
+        let remote: Vec<&Path> = candidates.iter().map(|(_, remote)| *remote).collect();
+
+        let spinner = (!config.is_silent() && std::io::stdout().is_terminal())
+            .then(|| WaitingSpinner::new("Comparing remote files..".to_string()));
+        let stats = session.stat(remote.iter().copied())?;
+        let mut hashes = Vec::with_capacity(remote.len());
+        for chunk in remote.chunks(session.hash_batch_size()?) {
+            hashes.extend(session.get_remote_hashes(chunk, DEEP_HASH_LENGTH)?);
+        }
+        if let Some(spinner) = spinner {
+            spinner.finish();
+        }
+
+        let mut identical = Vec::with_capacity(candidates.len());
+        for (((local, _), stat), hash) in candidates.iter().zip(stats.iter()).zip(hashes.iter()) {
+            let size_local = local
+                .metadata()
+                .with_context(|| format!("Could not get metadata of {}", local.display()))?
+                .len();
+            identical.push(
+                stat.size == Some(size_local) && util::get_hash(local, DEEP_HASH_LENGTH)? == *hash,
+            );
+        }
+
+        let mut lines = Vec::with_capacity(self.files.len());
+        let mut num_identical = 0;
+        let mut candidates = candidates.iter().zip(identical.iter()).peekable();
+        for file in self.files.iter() {
+            let (status, remote) = match candidates.peek() {
+                Some(((local, remote), identical)) if local == &file => {
+                    let remote = self
+                        .show
+                        .or_filenames(self.filenames)
+                        .render(session, remote)?;
+                    let status = if **identical {
+                        num_identical += 1;
+                        "identical"
+                    } else {
+                        "different"
+                    };
+                    candidates.next();
+                    (status, remote)
+                }
+                _ => ("missing", String::new()),
+            };
+            lines.push((status, file, remote));
+        }
+
+        if !config.is_silent() {
+            if std::io::stdout().is_terminal() {
+                let content: Vec<String> = lines
+                    .iter()
+                    .map(|(status, file, remote)| {
+                        let style = if *status == "identical" {
+                            &*color::success
+                        } else {
+                            &*color::failure
+                        };
+                        format!(
+                            " {} {} {} ",
+                            style.apply_to(status),
+                            file.display(),
+                            if remote.is_empty() {
+                                String::new()
+                            } else {
+                                format!("→ {}", remote)
+                            }
+                        )
+                    })
+                    .collect();
+                draw_boxed(
+                    format!(
+                        "{} remote files:",
+                        Style::new().bold().green().bright().apply_to("Compared")
+                    ),
+                    content.iter().map(|s| s.as_ref()),
+                    &color::frame,
+                )?;
+            } else {
+                for (status, file, remote) in lines.iter() {
+                    println!("{}\t{}\t{}", status, file.display(), remote);
+                }
+            }
+        }
+
+        if num_identical == self.files.len() {
+            Ok(())
+        } else {
+            Err(ExitCodeError::new(
+                1,
+                format!(
+                    "{}/{} files not identical on remote site.",
+                    self.files.len() - num_identical,
+                    self.files.len()
+                ),
+            )
+            .into())
+        }
+    }
+}
+
 impl Command for Check {
     fn run(&self, session: &SshSession, config: &Config) -> Result<()> {
-        let show_details = (self.details || config.details) && !self.no_details;
-
-        let found = session
-            .list_files()?
-            .by_hash(
-                self.files.iter().map(|pb| pb.to_string_lossy()),
-                session.host.prefix_length,
-                /* bail_when_missing = */ false,
-            )?
-            .with_stats(show_details || self.with_time || self.with_size)?;
-
-        if self.url_only {
+        if self.machine_mode() {
+            return self.run_machine_mode(session, config);
+        }
+        if self.deep {
+            return self.run_deep(session, config);
+        }
+
+        let template = self
+            .format
+            .as_deref()
+            .map(|f| Template::parse(f, FORMAT_FIELDS))
+            .transpose()?;
+        let template_needs_stats = template
+            .as_ref()
+            .map(|t| t.uses_any(FORMAT_FIELDS_STATS))
+            .unwrap_or(false);
+
+        let found = session.list_files()?.by_hash(
+            self.files.iter().map(|pb| pb.to_string_lossy()),
+            session.host.prefix_length,
+            /* bail_when_missing = */ false,
+        )?;
+        let show_details =
+            config.show_details_for(session.host, self.details, self.no_details, found.count());
+        let found = found.with_stats(
+            show_details || self.with_time || self.with_size || self.json || template_needs_stats,
+        )?;
+
+        if self.json {
+            if !config.is_silent() {
+                crate::cli::print_json(&found.to_json(session.host)?)?;
+            }
+        } else if let Some(template) = template.as_ref() {
+            if !config.is_silent() {
+                for line in found.format_template(session.host, template)? {
+                    println!("{}", line);
+                }
+            }
+        } else if self.url_only(config) {
             for (_, file, _) in found.iter() {
                 println!("{}", session.host.get_url(&format!("{}", file.display()))?);
             }
         } else if !config.is_silent() {
             let content = found.format_files(
-                Some(&session.host),
-                self.filenames,
+                self.show.or_filenames(self.filenames),
                 show_details || self.with_size,
                 show_details || self.with_time,
             )?;
@@ -87,11 +311,15 @@ impl Command for Check {
         if found.iter().count() == self.files.len() {
             Ok(())
         } else {
-            bail!(
-                "# of file expected/found differs: {}/{}",
-                self.files.len(),
-                found.iter().count()
-            );
+            Err(ExitCodeError::new(
+                1,
+                format!(
+                    "# of file expected/found differs: {}/{}",
+                    self.files.len(),
+                    found.iter().count()
+                ),
+            )
+            .into())
         }
     }
 }
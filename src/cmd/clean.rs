@@ -1,14 +1,18 @@
 use anyhow::{bail, Context, Result};
 use clap::{AppSettings, Parser};
 use dialoguer::{theme::ColorfulTheme, Confirm};
-use log::debug;
-use std::path::Path;
+use log::{debug, info};
+use std::collections::{HashMap, HashSet};
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
 
+use crate::at::At;
 use crate::cfg::Config;
-use crate::cli::color;
+use crate::cli::{self, color};
 use crate::cmd::Command;
-use crate::file_listing::FileListing;
+use crate::file_listing::{self, FileListing, Show};
 use crate::ssh::SshSession;
+use crate::tr;
 
 /// Clear already uploaded files.
 #[derive(Parser, Debug)]
@@ -22,6 +26,13 @@ pub struct Clean {
     #[clap(long, short)]
     details: bool,
 
+    /// Select empty files as well as files whose content does not match the hash in their URL
+    /// (e.g., left behind by interrupted uploads).
+    ///
+    /// NOTE: This computes the hash of all remote files and might take a while.
+    #[clap(long)]
+    empty: bool,
+
     /// Explicit file to delete
     #[clap(short, long = "file")]
     files: Vec<String>,
@@ -30,6 +41,30 @@ pub struct Clean {
     #[clap(long, short = 'F', value_name = "regex")]
     filter: Option<String>,
 
+    /// Do not confirm deletions, even for hosts with `require_confirmation: always`.
+    #[clap(long)]
+    force: bool,
+
+    /// Delete entries in the base folder that were not uploaded by asfa (see `list --foreign`),
+    /// selected by index, `--filter` (matching their path) or `--all`.
+    #[clap(
+        long,
+        conflicts_with_all = &[
+            "empty", "files", "last", "between", "select-newer", "select-older", "sort-size",
+            "sort-time"
+        ]
+    )]
+    foreign: bool,
+
+    /// Do not delete right away but schedule deletion after the given delay (e.g. `10m`) via
+    /// `at`, leaving time to cancel it.
+    #[clap(long, value_name = "delay", conflicts_with = "foreign")]
+    grace: Option<String>,
+
+    /// Also select pinned uploads (see `pin`) via `--all`, `--older` or `--between`.
+    #[clap(long)]
+    include_pinned: bool,
+
     /// Delete last
     #[clap(short = 'n', long)]
     last: Option<usize>,
@@ -38,7 +73,8 @@ pub struct Clean {
     #[clap()]
     indices: Vec<i64>,
 
-    /// Disable confirming deletions
+    /// Disable confirming deletions (unless the host sets `require_confirmation: always`, see
+    /// `--force`).
     #[clap(long = "no-confirm")]
     no_confirm: bool,
 
@@ -46,6 +82,15 @@ pub struct Clean {
     #[clap(long, short = 'D')]
     no_details: bool,
 
+    /// Select files whose age lies within the given window, e.g. `--between 2d..7d` selects files
+    /// older than two days but newer than a week. Either bound can be omitted.
+    #[clap(
+        long,
+        value_name = "min..max",
+        conflicts_with_all = &["select-newer", "select-older"]
+    )]
+    between: Option<String>,
+
     /// Select files newer than the given duration. Durations can be:seconds (sec, s), minutes
     /// (min, m), days (d), weeks (w), months (M) or years (y).
     #[clap(long = "newer")]
@@ -56,6 +101,11 @@ pub struct Clean {
     #[clap(long = "older")]
     select_older: Option<String>,
 
+    /// Overwrite the selected files with zeros before deleting them (via `shred` or `dd` on the
+    /// remote site), for storage where plain deletion is insufficient for sensitive material.
+    #[clap(long, conflicts_with_all = &["foreign", "grace", "recipient"])]
+    shred: bool,
+
     /// Sort by size (useful when specifying `--filter`/`--last`)
     #[clap(long, short = 'S')]
     sort_size: bool,
@@ -64,6 +114,20 @@ pub struct Clean {
     #[clap(long, short = 'T')]
     sort_time: bool,
 
+    /// Show a preview of each file's content (MIME type and first bytes) when confirming
+    /// deletions, e.g. to tell apart uploads with identical names.
+    #[clap(long)]
+    preview: bool,
+
+    /// Do not delete the selected files but revoke the URLs handed out to the given recipient(s)
+    /// via `push --recipient`. Use `--all` to revoke all of their URLs.
+    #[clap(
+        long,
+        value_name = "name",
+        conflicts_with_all = &["foreign", "empty", "grace"]
+    )]
+    recipient: Vec<String>,
+
     /// Reverse ordering (useful when specifying `--last` and `--sort-{size,time}`)
     #[clap(long, short)]
     reverse: bool,
@@ -75,16 +139,38 @@ impl Command for Clean {
 
         let files: Vec<&str> = self.files.iter().map(|s| s.as_str()).collect();
 
-        let show_details = (self.details || config.details) && !self.no_details;
+        let require_confirmation = session.host.require_confirmation;
+        let no_confirm = self.force || (self.no_confirm && !require_confirmation);
+        if !no_confirm && require_confirmation && !std::io::stdin().is_terminal() {
+            bail!(
+                "Host '{}' requires confirming deletions, specify --force to delete non-interactively.",
+                session.host.alias
+            );
+        }
+
+        if self.foreign {
+            return self.clean_foreign(session, config, no_confirm);
+        }
 
         let files_to_delete = session
             .list_files()?
             .with_all(self.all)
             .by_indices(&self.indices[..])?
             .by_filter(self.filter.as_deref())?
-            .with_all_if_none(self.select_newer.is_some() || self.select_older.is_some())
+            .by_broken(self.empty, session.host.prefix_length)?
+            .with_all_if_none(
+                self.select_newer.is_some()
+                    || self.select_older.is_some()
+                    || self.between.is_some(),
+            )
             .select_newer(self.select_newer.as_deref())?
             .select_older(self.select_older.as_deref())?
+            .select_between(self.between.as_deref())?
+            .without_pinned(
+                !self.include_pinned
+                    && self.recipient.is_empty()
+                    && (self.all || self.select_older.is_some() || self.between.is_some()),
+            )?
             .sort_by_size(self.sort_size)?
             .sort_by_time(self.sort_time)?
             .revert(self.reverse)
@@ -93,55 +179,343 @@ impl Command for Clean {
                 files.iter(),
                 session.host.prefix_length,
                 /* bail_when_missing = */ true,
-            )?
-            .with_stats(show_details && !self.no_confirm)?;
+            )?;
+        let show_details = config.show_details_for(
+            session.host,
+            self.details,
+            self.no_details,
+            files_to_delete.count(),
+        );
+        let files_to_delete = files_to_delete.with_stats(show_details && !no_confirm)?;
 
-        let do_delete = self.no_confirm || self.user_confirm_deletion(&files_to_delete)?;
+        if files_to_delete.count() == 0 {
+            crate::cli::report_empty(
+                config,
+                &if self.empty {
+                    tr!("clean.none-broken")
+                } else {
+                    tr!("clean.none-selected")
+                },
+            );
+            return Ok(());
+        }
 
-        let remove_file =
-            |file_to_delete: &Path| -> Result<()> {
-                if file_to_delete.components().count() != 2 {
-                    bail!("Invalid filename: {}", file_to_delete.display());
-                }
+        if !self.recipient.is_empty() {
+            return self.revoke_recipients(session, config, &files_to_delete, no_confirm);
+        }
 
-                session.remove_folder(file_to_delete.parent().with_context(|| {
-                    format!("File had not parent: {}", file_to_delete.display())
-                })?)?;
-                Ok(())
-            };
+        // Make sure scheduling is possible before asking for confirmation.
+        let grace = self
+            .grace
+            .as_deref()
+            .map(|delay| At::new(session, delay))
+            .transpose()?;
 
-        if do_delete {
-            for (_, file, _) in files_to_delete.iter() {
-                remove_file(&file)?
+        let do_delete =
+            no_confirm || self.user_confirm_deletion(session, &files_to_delete, grace.as_ref())?;
+
+        let upload_folder = |file_to_delete: &Path| -> Result<PathBuf> {
+            let layout = &session.host.layout;
+            if file_to_delete.components().count() != layout.depth() {
+                bail!("Invalid filename: {}", file_to_delete.display());
             }
+
+            let folder = layout
+                .upload_folder(file_to_delete)
+                .with_context(|| format!("File had not parent: {}", file_to_delete.display()))?;
+            Ok(folder.to_path_buf())
+        };
+
+        if !do_delete {
+            return Ok(());
+        }
+        if let Some(at) = grace {
+            return Self::schedule_deletion(session, &at, &files_to_delete);
         }
 
+        let folders = files_to_delete
+            .iter()
+            .map(|(_, file, _)| upload_folder(&file))
+            .collect::<Result<Vec<_>>>()?;
+        self.remove_folders(session, &folders)?;
+        let removed: Vec<_> = files_to_delete.iter().map(|(_, file, _)| file).collect();
+        session.audit(if self.shred { "clean --shred" } else { "clean" }, &removed);
+
         Ok(())
     }
 }
 
 impl Clean {
+    /// Remove the given upload folders, showing progress per folder and overall, and summarize
+    /// how many paths were removed.
+    fn remove_folders(&self, session: &SshSession, folders: &[PathBuf]) -> Result<()> {
+        let counts = session
+            .count_entries(&folders.iter().map(PathBuf::as_path).collect::<Vec<_>>())
+            .unwrap_or_else(|e| {
+                debug!("Not counting files to remove: {}", e);
+                None
+            });
+
+        let _multi = cli::multi_progress();
+        let overall = cli::progress_bar(folders.len() as u64);
+        overall.set_style(cli::style_progress_bar_count()?);
+        overall.set_message(format!("{} ", tr!("clean.removing")));
+
+        let mut total = 0;
+        for (i, folder) in folders.iter().enumerate() {
+            if self.shred {
+                session.shred_folder(folder)?;
+            }
+            let bar = match counts.as_ref() {
+                Some(counts) => {
+                    let bar = cli::progress_bar(counts[i]);
+                    bar.set_style(cli::style_progress_bar_count()?);
+                    bar
+                }
+                None => cli::spinner()?,
+            };
+            bar.set_message(format!("{} ", folder.display()));
+            total += session.remove_folder_reporting(folder, &mut |line| {
+                // Paths are shown by the progress bars, the full list is only of interest when
+                // debugging.
+                debug!("{}", line);
+                bar.inc(1);
+            })?;
+            bar.finish_and_clear();
+            overall.inc(1);
+        }
+        overall.finish_and_clear();
+        info!(
+            "{}",
+            tr!("clean.removed", paths = total, folders = folders.len())
+        );
+        Ok(())
+    }
+
+    /// Delete selected entries not created by asfa.
+    fn clean_foreign(&self, session: &SshSession, config: &Config, no_confirm: bool) -> Result<()> {
+        let entries = file_listing::select_foreign(
+            session.foreign_entries()?,
+            &self.indices,
+            self.filter.as_deref(),
+            self.all,
+        )?;
+        if entries.is_empty() {
+            crate::cli::report_empty(config, &tr!("clean.none-foreign"));
+            return Ok(());
+        }
+
+        let do_delete = no_confirm || {
+            crate::cli::draw_boxed(
+                tr!(
+                    "clean.header-foreign",
+                    delete = console::Style::new()
+                        .bold()
+                        .red()
+                        .bright()
+                        .apply_to(tr!("clean.delete"))
+                ),
+                file_listing::format_foreign(&entries)
+                    .iter()
+                    .map(|s| s.as_str()),
+                &color::frame,
+            )?;
+            Confirm::with_theme(&ColorfulTheme::default())
+                .with_prompt(tr!("clean.confirm-entries"))
+                .default(false)
+                .interact()?
+        };
+
+        if do_delete {
+            let mut removed = Vec::new();
+            for (_, path, _) in entries.iter() {
+                session.remove_foreign(path)?;
+                removed.push(path.as_path());
+            }
+            session.audit("clean --foreign", &removed);
+        }
+        Ok(())
+    }
+
+    /// Remove the links handed out to the given recipients for the selected files.
+    fn revoke_recipients(
+        &self,
+        session: &SshSession,
+        config: &Config,
+        files: &FileListing,
+        no_confirm: bool,
+    ) -> Result<()> {
+        let folders: HashMap<PathBuf, &Path> = files
+            .iter()
+            .filter_map(|(_, path, _)| path.parent().map(|folder| (folder.to_path_buf(), path)))
+            .collect();
+
+        let mut to_revoke = Vec::new();
+        // Links of removed uploads are forgotten.
+        let mut forgotten = HashSet::new();
+        for link in session.recipient_links()? {
+            match session.recipient_link_target(&link)? {
+                None => {
+                    forgotten.insert(link.token);
+                }
+                Some(target) => match folders.get(&target) {
+                    Some(file) if self.recipient.contains(&link.recipient) => {
+                        to_revoke.push((link, *file))
+                    }
+                    _ => {}
+                },
+            }
+        }
+        // Only drop the affected links, other processes might have added links meanwhile.
+        let forget = |dropped: &HashSet<String>| {
+            session.update_recipient_links(|links| {
+                links.retain(|l| !dropped.contains(&l.token));
+                Ok(())
+            })
+        };
+
+        if to_revoke.is_empty() {
+            crate::cli::report_empty(config, &tr!("clean.none-recipient"));
+            return forget(&forgotten);
+        }
+
+        let do_revoke = no_confirm || {
+            let lines: Vec<String> = to_revoke
+                .iter()
+                .map(|(link, file)| {
+                    format!(
+                        " {}{}{} ",
+                        link.recipient,
+                        crate::cli::text::separator(),
+                        file.display()
+                    )
+                })
+                .collect();
+            crate::cli::draw_boxed(
+                tr!(
+                    "clean.header-recipients",
+                    revoke = console::Style::new()
+                        .bold()
+                        .red()
+                        .bright()
+                        .apply_to(tr!("clean.revoke"))
+                ),
+                lines.iter().map(|s| s.as_str()),
+                &color::frame,
+            )?;
+            Confirm::with_theme(&ColorfulTheme::default())
+                .with_prompt(tr!("clean.confirm-recipients"))
+                .default(false)
+                .interact()?
+        };
+        if !do_revoke {
+            return Ok(());
+        }
+
+        let mut revoked = Vec::new();
+        for (link, _) in to_revoke.iter() {
+            session.remove_recipient_link(link)?;
+            forgotten.insert(link.token.clone());
+            revoked.push(link.path(session.host));
+        }
+        forget(&forgotten)?;
+        let revoked: Vec<&Path> = revoked.iter().map(|p| p.as_path()).collect();
+        session.audit("clean --recipient", &revoked);
+        Ok(())
+    }
+
+    /// Schedule deletion of the given files via `at` (or its fallbacks) and print how to cancel
+    /// it.
+    fn schedule_deletion(session: &SshSession, at: &At, files: &FileListing) -> Result<()> {
+        let mut ids = Vec::new();
+        let mut date = None;
+        for (_, file, _) in files.iter() {
+            let scheduled = at.expire(file, &[])?;
+            ids.extend(scheduled.id);
+            date = Some(scheduled.date);
+        }
+        if let Some(date) = date {
+            println!(
+                "{}",
+                tr!(
+                    "clean.scheduled",
+                    count = files.count(),
+                    date = color::expire.apply_to(date.to_rfc2822())
+                )
+            );
+        }
+        if ids.is_empty() {
+            println!("{}", tr!("clean.cancel-atq", host = session.host.alias));
+        } else {
+            println!(
+                "{}",
+                tr!(
+                    "clean.cancel-command",
+                    host = session.host.alias,
+                    command = at.cancel_command(&ids)
+                )
+            );
+        }
+        Ok(())
+    }
+
     /// Have the user confirm deletions
-    fn user_confirm_deletion(&self, files: &FileListing) -> Result<bool> {
+    fn user_confirm_deletion(
+        &self,
+        session: &SshSession,
+        files: &FileListing,
+        grace: Option<&At>,
+    ) -> Result<bool> {
         let with_stats = files.has_stats();
         // If we have stats, print only the filename to shorten the line
-        let formatted_files = files.format_files(None, with_stats, with_stats, with_stats)?;
+        let show = if with_stats {
+            Show::Filename
+        } else {
+            Show::Path
+        };
+        let formatted_files = files.format_files(show, with_stats, with_stats)?;
+        let formatted_files = if self.preview {
+            let paths: Vec<&Path> = files.iter().map(|(_, path, _)| path).collect();
+            formatted_files
+                .into_iter()
+                .zip(session.preview(&paths)?)
+                .map(|(line, preview)| {
+                    format!(
+                        "{}{}{} ",
+                        line,
+                        crate::cli::text::separator().trim_start(),
+                        console::Style::new().dim().apply_to(preview)
+                    )
+                })
+                .collect()
+        } else {
+            formatted_files
+        };
 
         crate::cli::draw_boxed(
-            &format!(
-                "Will {delete} the following files:",
+            tr!(
+                "clean.header-files",
                 delete = console::Style::new()
                     .bold()
                     .red()
                     .bright()
-                    .apply_to("delete")
-            )
-            .as_str(),
+                    .apply_to(if self.shred {
+                        tr!("clean.shred")
+                    } else {
+                        tr!("clean.delete")
+                    }),
+                grace = grace
+                    .map(|at| tr!(
+                        "clean.header-grace",
+                        delay = humantime::format_duration(at.duration())
+                    ))
+                    .unwrap_or_default()
+            ),
             formatted_files.iter().map(|s| s.as_str()),
             &color::frame,
         )?;
         Ok(Confirm::with_theme(&ColorfulTheme::default())
-            .with_prompt("Delete files?")
+            .with_prompt(tr!("clean.confirm-files"))
             .default(false)
             .interact()?)
     }
@@ -0,0 +1,33 @@
+use anyhow::Result;
+use clap::Parser;
+
+use crate::cache::Cache;
+use crate::cfg::{Config, Host};
+use crate::cmd::Command;
+use crate::ssh::SshSession;
+
+/// Print index and name of all files known from the local cache (used by shell completions).
+///
+/// Output is one tab-separated line per file. Does not connect to the remote site, so the
+/// candidates are as recent as the last time asfa listed, uploaded or removed files.
+#[derive(Parser, Debug)]
+#[clap(hide = true)]
+pub struct CompleteRemote {}
+
+impl CompleteRemote {
+    /// Print candidates from the local cache, does not need the remote site.
+    pub fn print(&self, host: &Host) -> Result<()> {
+        let cache = Cache::load(&host.alias)?;
+        for (idx, file) in cache.files().iter().enumerate() {
+            let name = file.file_name().unwrap_or(file.as_os_str());
+            println!("{}\t{}", idx, name.to_string_lossy());
+        }
+        Ok(())
+    }
+}
+
+impl Command for CompleteRemote {
+    fn run(&self, session: &SshSession, _config: &Config) -> Result<()> {
+        self.print(session.host)
+    }
+}
@@ -0,0 +1,232 @@
+use anyhow::{Context, Result};
+use clap::{crate_version, CommandFactory, Parser};
+use itertools::Itertools;
+
+use crate::cfg::Config;
+use crate::cli::{options_taking_values, Opts};
+use crate::cmd::shell_init::Shell;
+use crate::cmd::Command;
+use crate::ssh::SshSession;
+
+/// Global options that are passed on to `__complete-remote` because they select the cache.
+const FORWARDED: &[&str] = &["-H", "--host", "-c", "--config"];
+
+/// Positional arguments that select remote files by index.
+const REMOTE_ARGS: &[&str] = &["indices", "input"];
+
+/// Replaces the generated `complete -F _asfa` registration.
+const HOOK_BASH: &str = r#"
+_asfa_remote() {
+    _asfa "$@"
+    local i cur prev cmd="" global=()
+    cur="${COMP_WORDS[COMP_CWORD]}"
+    prev="${COMP_WORDS[COMP_CWORD-1]}"
+    for (( i = 1; i < COMP_CWORD; i++ )); do
+        case "${COMP_WORDS[i]}" in
+            __FORWARDED__)
+                global+=("${COMP_WORDS[i]}" "${COMP_WORDS[i+1]}")
+                (( i++ ))
+                ;;
+            __GLOBAL_VALUES__)
+                (( i++ ))
+                ;;
+            -*)
+                ;;
+            *)
+                cmd="${COMP_WORDS[i]}"
+                break
+                ;;
+        esac
+    done
+    case "${cmd}" in
+        __COMMANDS__) ;;
+        *) return 0 ;;
+    esac
+    case "${prev}" in
+        __VALUES__) return 0 ;;
+    esac
+    [[ "${cur}" == -* ]] && return 0
+    COMPREPLY=( $(compgen -W "$(command asfa "${global[@]}" __complete-remote 2>/dev/null | cut -f1)" -- "${cur}") )
+}
+
+complete -F _asfa_remote -o bashdefault -o default asfa
+"#;
+
+/// Wraps the generated `_asfa` (renamed to `_asfa_generated`) so that autoloading keeps working.
+const HOOK_ZSH: &str = r#"
+_asfa() {
+    local i cmd prev="${words[CURRENT-1]}"
+    local -a global remote
+    for (( i = 2; i < CURRENT; i++ )); do
+        case "${words[i]}" in
+            __FORWARDED__)
+                global+=("${words[i]}" "${words[i+1]}")
+                (( i++ ))
+                ;;
+            __GLOBAL_VALUES__)
+                (( i++ ))
+                ;;
+            -*)
+                ;;
+            *)
+                cmd="${words[i]}"
+                break
+                ;;
+        esac
+    done
+    case "${cmd}" in
+        __COMMANDS__)
+            case "${prev}" in
+                __VALUES__) ;;
+                *)
+                    if [[ "${PREFIX}" != -* ]]; then
+                        remote=(${${(f)"$(command asfa "${global[@]}" __complete-remote 2>/dev/null)"}/$'\t'/:})
+                        _describe -V -t remote-files 'remote files' remote
+                    fi
+                    ;;
+            esac
+            ;;
+    esac
+    _asfa_generated "$@"
+}
+
+_asfa "$@"
+"#;
+
+const HOOK_FISH: &str = r#"
+function __asfa_remote_files
+    set -l tokens (commandline -opc)
+    set -l global
+    for i in (seq 2 (count $tokens))
+        if contains -- $tokens[$i] __FORWARDED__
+            set -a global $tokens[$i] $tokens[(math $i + 1)]
+        end
+    end
+    command asfa $global __complete-remote 2>/dev/null
+end
+
+complete -c asfa -n "__fish_seen_subcommand_from __COMMANDS__; and not __fish_prev_arg_in __VALUES__" -a "(__asfa_remote_files)"
+"#;
+
+/// Print shell completions that also offer remote files.
+///
+/// Indices of uploaded files are completed (with their names) from the local cache, i.e., as of
+/// the last time asfa listed, uploaded or removed files on the selected host.
+///
+/// Installation: bash: `source <(asfa completions bash)` in `~/.bashrc`, zsh:
+/// `asfa completions zsh > "${fpath[1]}/_asfa"`, fish:
+/// `asfa completions fish > ~/.config/fish/completions/asfa.fish`
+#[derive(Parser, Debug)]
+pub struct Completions {
+    /// Shell to emit completions for.
+    #[clap(arg_enum)]
+    shell: Shell,
+}
+
+impl Completions {
+    /// Print the completions, does not need the remote site.
+    pub fn print(&self) -> Result<()> {
+        print!("{}", self.script()?);
+        Ok(())
+    }
+
+    fn script(&self) -> Result<String> {
+        let full = Opts::command();
+        // Hidden helpers (e.g. `__complete-remote`) should not be offered.
+        let mut command = clap::Command::new("asfa")
+            .version(crate_version!())
+            .args(
+                full.get_arguments()
+                    .filter(|a| !["help", "version"].contains(&a.get_id()))
+                    .cloned(),
+            )
+            .subcommands(
+                full.get_subcommands()
+                    .filter(|sc| !sc.is_hide_set())
+                    .cloned(),
+            );
+
+        let mut generated = vec![];
+        let target = match self.shell {
+            Shell::Bash => clap_complete::Shell::Bash,
+            Shell::Zsh => clap_complete::Shell::Zsh,
+            Shell::Fish => clap_complete::Shell::Fish,
+        };
+        clap_complete::generate(target, &mut command, "asfa", &mut generated);
+        let generated = String::from_utf8(generated)?;
+
+        let (script, separator) = match self.shell {
+            Shell::Bash => (generated + HOOK_BASH, "|"),
+            Shell::Zsh => {
+                let generated = generated
+                    .strip_suffix("_asfa \"$@\"\n")
+                    .context("Unexpected completion script generated for zsh.")?
+                    .replacen("\n_asfa() {", "\n_asfa_generated() {", 1);
+                (generated + HOOK_ZSH, "|")
+            }
+            Shell::Fish => (generated + HOOK_FISH, " "),
+        };
+
+        let commands = remote_commands(&full);
+        let remote: Vec<&clap::Command> = full
+            .get_subcommands()
+            .filter(|sc| commands.iter().any(|c| c == sc.get_name()))
+            .collect();
+        let values: Vec<String> = remote
+            .iter()
+            .flat_map(|sc| options_taking_values(sc))
+            .chain(options_taking_values(&full))
+            .unique()
+            .collect();
+        let global_values: Vec<String> = options_taking_values(&full)
+            .into_iter()
+            .filter(|o| !FORWARDED.contains(&o.as_str()))
+            .collect();
+
+        Ok(script
+            .replace("__FORWARDED__", &FORWARDED.join(separator))
+            .replace("__GLOBAL_VALUES__", &global_values.join(separator))
+            .replace("__COMMANDS__", &commands.join(separator))
+            .replace("__VALUES__", &values.join(separator)))
+    }
+}
+
+/// Names (and aliases) of all subcommands that select remote files by index.
+fn remote_commands(command: &clap::Command) -> Vec<String> {
+    command
+        .get_subcommands()
+        .filter(|sc| !sc.is_hide_set())
+        .filter(|sc| {
+            sc.get_positionals()
+                .any(|a| REMOTE_ARGS.contains(&a.get_id()))
+        })
+        .flat_map(|sc| std::iter::once(sc.get_name()).chain(sc.get_all_aliases()))
+        .map(String::from)
+        .collect()
+}
+
+impl Command for Completions {
+    fn run(&self, _session: &SshSession, _config: &Config) -> Result<()> {
+        self.print()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remote_commands_select_indices() {
+        let commands = remote_commands(&Opts::command());
+        for name in ["clean", "rm", "list", "ls", "pin", "rename", "verify"] {
+            assert!(commands.iter().any(|c| c == name), "missing {}", name);
+        }
+        assert!(!commands.iter().any(|c| c == "push"));
+
+        for shell in [Shell::Bash, Shell::Zsh, Shell::Fish] {
+            let script = Completions { shell }.script().unwrap();
+            assert!(script.contains("__complete-remote 2>/dev/null"));
+            assert!(!script.contains("__COMMANDS__"));
+        }
+    }
+}
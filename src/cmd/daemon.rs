@@ -0,0 +1,20 @@
+use anyhow::Result;
+use clap::Parser;
+
+use crate::cfg::Config;
+use crate::cmd::Command;
+use crate::daemon;
+use crate::ssh::SshSession;
+
+/// Keep the session open and run commands of other invocations.
+///
+/// As long as the daemon is running, all invocations for the same host are handed to it and
+/// skip connecting and authenticating. Use `--no-daemon` to bypass a running daemon.
+#[derive(Parser, Debug)]
+pub struct Daemon {}
+
+impl Command for Daemon {
+    fn run(&self, session: &SshSession, _config: &Config) -> Result<()> {
+        daemon::serve(session)
+    }
+}
@@ -0,0 +1,168 @@
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+use itertools::Itertools;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::at::At;
+use crate::cfg::Config;
+use crate::cli::color;
+use crate::cmd::Command;
+use crate::ssh::SshSession;
+use crate::util;
+
+/// Diagnose the configuration of the remote site.
+///
+/// Checks that the base folder is writable and that the configured `url` actually serves
+/// uploaded files: A small canary file is uploaded, fetched via HTTP(S), compared byte by byte
+/// and removed again. This catches typos in `url` as well as webserver misconfigurations.
+#[derive(Parser, Debug)]
+pub struct Doctor {
+    /// Do not fetch the canary file via HTTP(S).
+    #[clap(long)]
+    no_http: bool,
+}
+
+impl Command for Doctor {
+    fn run(&self, session: &SshSession, config: &Config) -> Result<()> {
+        let host = session.host;
+        let mut num_failed = 0;
+        let mut report = |check: &str, result: Result<String>| {
+            let ok = result.is_ok();
+            if !config.is_silent() {
+                match result {
+                    Ok(detail) => {
+                        println!("{} {}: {}", color::success.apply_to("✓"), check, detail)
+                    }
+                    Err(e) => println!(
+                        "{} {}: {:#}",
+                        color::failure.apply_to("✗"),
+                        check,
+                        color::failure.apply_to(e)
+                    ),
+                }
+            }
+            if !ok {
+                num_failed += 1;
+            }
+            ok
+        };
+
+        report(
+            "Connection",
+            Ok(format!("authenticated as {}", session.get_username())),
+        );
+
+        // Refresh cached capabilities, e.g. after tools were installed on the remote site.
+        report(
+            "Remote tools",
+            if !host.exec {
+                Ok("none, remote commands are disabled (`exec: false`)".to_string())
+            } else {
+                session.probe_capabilities().map(|c| {
+                    format!(
+                        "{}{}: {}",
+                        c.platform,
+                        if c.gnu {
+                            " (GNU)"
+                        } else if c.busybox {
+                            " (busybox, listing via SFTP)"
+                        } else {
+                            ""
+                        },
+                        c.tools.iter().join(", ")
+                    )
+                })
+            },
+        );
+
+        let writable = report(
+            "Base folder",
+            if !host.exec {
+                // Writability is checked by uploading the canary.
+                match session.base_folder_exists() {
+                    Ok(true) => Ok(format!("{} exists", host.folder.display())),
+                    Ok(false) => Err(anyhow::anyhow!("{} is no folder", host.folder.display())),
+                    Err(e) => Err(e),
+                }
+            } else {
+                session
+                    .exec_remote(&format!(
+                        "test -d '{folder}' -a -w '{folder}'",
+                        folder = host.folder.display()
+                    ))?
+                    .expect_with(|_| format!("{} is no writable folder", host.folder.display()))
+                    .map(|_| format!("{} is writable", host.folder.display()))
+            },
+        );
+
+        if let Some(expire) = host.expire.as_ref() {
+            report(
+                "Expiration",
+                At::new(session, expire)
+                    .map(|at| format!("`{}` available to expire after {}", at.tool(), expire)),
+            );
+        }
+
+        if writable && !host.read_only {
+            let content = format!(
+                "asfa canary {}\n",
+                SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos()
+            );
+            let hash = util::get_hash_from(content.as_bytes(), host.prefix_length)?;
+            let canary = PathBuf::from(&hash).join("asfa-canary.txt");
+
+            let uploaded = report(
+                "Upload",
+                self.upload_canary(session, &canary, &content)
+                    .map(|_| format!("{} uploaded", canary.display())),
+            );
+
+            if uploaded && !self.no_http {
+                report("URL", self.fetch_canary(session, &canary, &content));
+            }
+
+            session.remove_folder(Path::new(&hash))?;
+        }
+
+        if num_failed > 0 {
+            bail!("{} checks failed.", num_failed);
+        }
+        Ok(())
+    }
+}
+
+impl Doctor {
+    fn upload_canary(&self, session: &SshSession, canary: &Path, content: &str) -> Result<()> {
+        let group_inherited = session.make_upload_folder(canary.parent().unwrap())?;
+        session.upload_reader(content.as_bytes(), content.len() as u64, canary, None)?;
+        if let (false, Some(group)) = (group_inherited, &session.host.group) {
+            session.adjust_group(canary.parent().unwrap(), group)?;
+        }
+        Ok(())
+    }
+
+    /// Fetch the canary via HTTP(S) and compare it to what was uploaded.
+    fn fetch_canary(&self, session: &SshSession, canary: &Path, content: &str) -> Result<String> {
+        let url = session.host.get_url(&format!("{}", canary.display()))?;
+        let response =
+            reqwest::blocking::get(&url).with_context(|| format!("Could not fetch {}", url))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            bail!(
+                "{} returned {} (check `url` and `folder` in config as well as webserver setup)",
+                url,
+                status
+            );
+        }
+        let fetched = response.bytes()?;
+        if fetched != content.as_bytes() {
+            bail!(
+                "{} served different content than uploaded (does `url` point to `folder`?)",
+                url
+            );
+        }
+        Ok(format!("{} serves uploaded files", session.host.url))
+    }
+}
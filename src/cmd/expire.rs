@@ -0,0 +1,258 @@
+use anyhow::{bail, Context, Result};
+use chrono::Local;
+use clap::{AppSettings, Parser};
+use std::io::IsTerminal;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::at::{self, At};
+use crate::cache::{Expiration, Expirations};
+use crate::cfg::{Config, Host};
+use crate::cli::{color, draw_boxed, text};
+use crate::cmd::Command;
+use crate::file_listing::FileListing;
+use crate::ssh::SshSession;
+
+/// Window of `--upcoming` unless `--within` or `expire_reminder` is given.
+const DEFAULT_UPCOMING_WINDOW: Duration = Duration::from_secs(24 * 3600);
+
+/// Inspect or change expiration of uploaded files.
+#[derive(Parser, Debug)]
+#[clap(global_setting=AppSettings::AllowNegativeNumbers)]
+pub struct Expire {
+    /// List uploads expiring soon (see `--within`), as recorded locally when pushing them. Does
+    /// not connect to the remote site.
+    #[clap(long)]
+    pub upcoming: bool,
+
+    /// Window for `--upcoming`, e.g. `3days`. Defaults to `expire_reminder` from config or a day.
+    #[clap(long, value_name = "duration", requires = "upcoming")]
+    within: Option<String>,
+
+    /// Postpone the pending expiration of the selected uploads by the given duration, e.g. `7d`.
+    #[clap(long, value_name = "duration", conflicts_with = "upcoming")]
+    extend: Option<String>,
+
+    /// Expire the selected uploads after the given delay (see `push --expire`), replacing any
+    /// pending expiration.
+    #[clap(long, value_name = "delay", conflicts_with_all = &["upcoming", "extend"])]
+    set: Option<String>,
+
+    /// Cancel the pending expiration of the selected uploads.
+    #[clap(long, alias = "remove", conflicts_with_all = &["upcoming", "extend", "set"])]
+    cancel: bool,
+
+    /// Explicit local file to select (by hash).
+    #[clap(short, long = "file")]
+    files: Vec<String>,
+
+    /// Filter filenames by regex. See <https://docs.rs/regex/latest/regex/#syntax>
+    #[clap(long, short = 'F', value_name = "regex")]
+    filter: Option<String>,
+
+    /// Indices of files to select as returned by `list` command.
+    #[clap()]
+    indices: Vec<i64>,
+}
+
+impl Expire {
+    /// List upcoming expirations from the local journal without any network access.
+    pub fn run_upcoming(&self, host: &Host, config: &Config) -> Result<()> {
+        let within = match self.within.as_deref() {
+            Some(within) => humantime::parse_duration(within)
+                .with_context(|| format!("Invalid duration for --within: {}", within))?,
+            None => config.expire_reminder.unwrap_or(DEFAULT_UPCOMING_WINDOW),
+        };
+        let until = Local::now() + chrono::Duration::from_std(within)?;
+
+        let expirations = Expirations::load(&host.alias)?;
+        let upcoming: Vec<_> = expirations
+            .pending()
+            .iter()
+            .filter(|e| e.date <= until)
+            .collect();
+
+        if upcoming.is_empty() {
+            crate::cli::report_empty(
+                config,
+                &format!(
+                    "No uploads expiring within {}.",
+                    humantime::format_duration(within)
+                ),
+            );
+            return Ok(());
+        }
+        if config.is_silent() {
+            return Ok(());
+        }
+
+        if std::io::stdout().is_terminal() {
+            let content: Vec<String> = upcoming
+                .iter()
+                .map(|e| {
+                    format!(
+                        " {}{}{} ",
+                        color::expire.apply_to(e.date.format("%Y-%m-%d %H:%M")),
+                        text::separator(),
+                        e.url
+                    )
+                })
+                .collect();
+            draw_boxed(
+                format!("Expiring within {}:", humantime::format_duration(within)),
+                content.iter().map(|s| s.as_str()),
+                &color::frame,
+            )?;
+        } else {
+            for e in upcoming {
+                println!("{}\t{}", e.date.to_rfc3339(), e.url);
+            }
+        }
+        Ok(())
+    }
+
+    /// Select uploads via indices, `--file` or `--filter`.
+    fn select<'a>(&self, session: &'a SshSession) -> Result<FileListing<'a>> {
+        if self.indices.is_empty() && self.files.is_empty() && self.filter.is_none() {
+            bail!("No uploads selected, specify indices, --file or --filter.");
+        }
+        session
+            .list_files()?
+            .by_indices(&self.indices[..])?
+            .by_filter(self.filter.as_deref())?
+            .by_hash(
+                self.files.iter(),
+                session.host.prefix_length,
+                /* bail_when_missing = */ true,
+            )
+    }
+
+    /// Postpone the pending expiration jobs of the selected uploads, keeping their scripts.
+    fn extend(&self, session: &SshSession, config: &Config, by: &str) -> Result<()> {
+        let by = humantime::parse_duration(by)
+            .with_context(|| format!("Invalid duration for --extend: {}", by))?;
+        let selected = self.select(session)?;
+
+        let jobs = at::list_jobs(session)?;
+        let mut extended: Vec<&Path> = Vec::new();
+        for (_, path, _) in selected.iter() {
+            let job = match jobs.iter().find(|job| job.path == path) {
+                Some(job) => job,
+                None => {
+                    log::warn!("No pending expiration for: {}", path.display());
+                    continue;
+                }
+            };
+            let date = at::postpone_job(session, job, by)?;
+            Expirations::postpone(&session.host.alias, path, chrono::Duration::from_std(by)?);
+            extended.push(path);
+
+            if !config.is_silent() {
+                println!(
+                    "Expiring {} at: {}",
+                    color::filename.apply_to(path.display()),
+                    color::expire.apply_to(date.format("%Y-%m-%d %H:%M"))
+                );
+            }
+        }
+        session.audit("extend", &extended);
+        Ok(())
+    }
+
+    /// Schedule expiration of the selected uploads, replacing pending expiration jobs.
+    ///
+    /// Pinned uploads are skipped, they never expire.
+    fn set(&self, session: &SshSession, config: &Config, delay: &str) -> Result<()> {
+        let at = At::new(session, delay)?;
+        let selected = self.select(session)?;
+        let pins = session.pins()?;
+
+        let jobs = at::list_jobs(session)?;
+        let mut set: Vec<&Path> = Vec::new();
+        for (_, path, _) in selected.iter() {
+            let pinned = session
+                .host
+                .layout
+                .upload_folder(path)
+                .map(|folder| pins.contains(folder))
+                .unwrap_or(false);
+            if pinned {
+                log::warn!(
+                    "Skipping pinned upload (unpin it first): {}",
+                    path.display()
+                );
+                continue;
+            }
+            // Schedule first so that the upload never ends up without expiration if this fails.
+            let scheduled = at.expire(path, &[])?;
+            for job in jobs.iter().filter(|job| job.path == path) {
+                at::remove_job(session, job)?;
+            }
+            set.push(path);
+            Expirations::record(
+                &session.host.alias,
+                Expiration {
+                    date: scheduled.date,
+                    path: path.to_path_buf(),
+                    url: session.host.get_url(&format!("{}", path.display()))?,
+                },
+            );
+
+            if !config.is_silent() {
+                println!(
+                    "Expiring {} at: {}",
+                    color::filename.apply_to(path.display()),
+                    color::expire.apply_to(scheduled.date.format("%Y-%m-%d %H:%M"))
+                );
+            }
+        }
+        session.audit("set-expiration", &set);
+        Ok(())
+    }
+
+    /// Remove the pending expiration jobs of the selected uploads.
+    fn cancel(&self, session: &SshSession, config: &Config) -> Result<()> {
+        let selected = self.select(session)?;
+
+        let jobs = at::list_jobs(session)?;
+        let mut cancelled: Vec<&Path> = Vec::new();
+        for (_, path, _) in selected.iter() {
+            let mut pending = jobs.iter().filter(|job| job.path == path).peekable();
+            if pending.peek().is_none() {
+                log::warn!("No pending expiration for: {}", path.display());
+            }
+            for job in pending {
+                at::remove_job(session, job)?;
+            }
+            Expirations::forget_folder(&session.host.alias, path);
+            cancelled.push(path);
+
+            if !config.is_silent() {
+                println!(
+                    "Cancelled expiration of {}",
+                    color::filename.apply_to(path.display())
+                );
+            }
+        }
+        session.audit("cancel-expiration", &cancelled);
+        Ok(())
+    }
+}
+
+impl Command for Expire {
+    fn run(&self, session: &SshSession, config: &Config) -> Result<()> {
+        if self.upcoming {
+            return self.run_upcoming(session.host, config);
+        }
+        if let Some(by) = self.extend.as_deref() {
+            return self.extend(session, config, by);
+        }
+        if let Some(delay) = self.set.as_deref() {
+            return self.set(session, config, delay);
+        }
+        if self.cancel {
+            return self.cancel(session, config);
+        }
+        bail!("Nothing to do, specify --upcoming, --extend, --set or --cancel.");
+    }
+}
@@ -0,0 +1,71 @@
+use anyhow::{Context, Result};
+use clap::Parser;
+use std::path::{Path, PathBuf};
+
+use crate::cfg::Config;
+use crate::cli::WaitingSpinner;
+use crate::cmd::Command;
+use crate::manifest::{self, Entry, Manifest};
+use crate::share_code;
+use crate::ssh::SshSession;
+
+/// Export sizes and full hashes of all remote files to a manifest.
+///
+/// The manifest can later be compared against via `verify --against` to detect changes (e.g.,
+/// bit rot or tampering) independently of the hash prefixes in URLs.
+#[derive(Parser, Debug)]
+pub struct ExportManifest {
+    /// File to write the manifest to (JSON), `-` for stdout.
+    #[clap()]
+    output: PathBuf,
+}
+
+impl Command for ExportManifest {
+    fn run(&self, session: &SshSession, config: &Config) -> Result<()> {
+        let spinner = (!config.is_silent() && self.output != Path::new("-"))
+            .then(|| WaitingSpinner::new("Hashing remote files..".to_string()));
+
+        let listing = session.list_files()?.with_all(true).with_stats(true)?;
+        let entries: Vec<_> = listing.iter().collect();
+        let paths: Vec<&Path> = entries.iter().map(|(_, path, _)| *path).collect();
+
+        let mut hashes = Vec::with_capacity(paths.len());
+        let chunk_size = session.hash_batch_size()?;
+        for (idx, chunk) in paths.chunks(chunk_size).enumerate() {
+            if let Some(spinner) = spinner.as_ref() {
+                spinner.set_message(format!(
+                    "Hashing remote files.. {}/{}",
+                    idx * chunk_size,
+                    paths.len()
+                ))?;
+            }
+            hashes.extend(session.get_remote_hashes(chunk, manifest::HASH_LENGTH)?);
+        }
+
+        let files = entries
+            .iter()
+            .zip(hashes)
+            .map(|((_, path, stat), hash)| {
+                let stat = stat.with_context(|| format!("No stats for {}", path.display()))?;
+                Ok(Entry {
+                    path: path.to_path_buf(),
+                    size: stat.size.context("No file size defined!")?,
+                    mtime: stat.mtime.context("No modification time defined!")?,
+                    hash,
+                    code: share_code::of_file(path),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        if let Some(spinner) = spinner {
+            spinner.finish();
+        }
+
+        Manifest {
+            host: session.host.alias.clone(),
+            created: chrono::Local::now().to_rfc3339(),
+            files,
+        }
+        .store(&self.output)
+    }
+}
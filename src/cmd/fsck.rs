@@ -0,0 +1,327 @@
+use anyhow::{bail, Result};
+use clap::Parser;
+use console::Style;
+use dialoguer::{theme::ColorfulTheme, Confirm};
+use log::warn;
+use std::collections::HashSet;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+
+use crate::at;
+use crate::cfg::Config;
+use crate::cli::{color, draw_boxed, WaitingSpinner};
+use crate::cmd::Command;
+use crate::layout::Layout;
+use crate::ssh::SshSession;
+use crate::tr;
+use crate::util;
+
+/// Check the remote site for problems and repair them.
+///
+/// Looks for files whose content does not match their hash, empty files, orphaned folders
+/// without any uploaded file and pending expiration jobs for files that no longer exist. Each
+/// category of problems is repaired after confirmation (or right away with `--fix`).
+#[derive(Parser, Debug)]
+pub struct Fsck {
+    /// Repair all problems without asking for confirmation (unless the host sets
+    /// `require_confirmation: always`, see `--force`).
+    #[clap(long)]
+    fix: bool,
+
+    /// Repair all problems without asking for confirmation, even for hosts with
+    /// `require_confirmation: always`.
+    #[clap(long)]
+    force: bool,
+
+    /// Skip verifying the hashes of all remote files (which might take a while).
+    #[clap(long)]
+    no_verify: bool,
+
+    /// Also remove pinned upload folders (see `pin`), which are skipped otherwise.
+    #[clap(long)]
+    include_pinned: bool,
+}
+
+/// A category of problems found on the remote site.
+struct Problems {
+    /// Description of the category.
+    title: &'static str,
+
+    /// What happens when fixing the problems.
+    fix: Fix,
+
+    /// One entry per problem.
+    entries: Vec<Problem>,
+}
+
+enum Fix {
+    Remove,
+    Cancel,
+}
+
+enum Problem {
+    /// Folder (relative to base folder) to remove.
+    Folder(PathBuf),
+
+    /// Expiration job to remove.
+    Job(at::ExpirationJob),
+}
+
+impl Problem {
+    fn describe(&self) -> String {
+        match self {
+            Problem::Folder(folder) => folder.display().to_string(),
+            Problem::Job(job) => format!("job {}: {}", job.id, job.path.display()),
+        }
+    }
+
+    /// Affected path (relative to base folder).
+    fn path(&self) -> &Path {
+        match self {
+            Problem::Folder(folder) => folder,
+            Problem::Job(job) => &job.path,
+        }
+    }
+
+    fn fix(&self, session: &SshSession) -> Result<()> {
+        match self {
+            Problem::Folder(folder) => session.remove_folder(folder),
+            Problem::Job(job) => at::remove_job(session, job),
+        }
+    }
+}
+
+impl Command for Fsck {
+    fn run(&self, session: &SshSession, config: &Config) -> Result<()> {
+        let spinner = (!config.is_silent()).then(|| WaitingSpinner::new("Checking..".to_string()));
+
+        let mut listing = session.list_files()?;
+        let all_files: Vec<PathBuf> = (0..listing.num_files)
+            .filter_map(|idx| listing.path_of(idx).map(|p| p.to_path_buf()))
+            .collect();
+        let folder_of = |idx: &usize| {
+            Problem::Folder(
+                session
+                    .host
+                    .layout
+                    .upload_folder(&all_files[*idx])
+                    .map(|p| p.to_path_buf())
+                    .unwrap_or_default(),
+            )
+        };
+
+        let mut categories = Vec::new();
+
+        // Empty files do not match their hash either, but are only reported as empty.
+        let empty = listing.empty_indices()?;
+        if !self.no_verify {
+            if let Some(spinner) = spinner.as_ref() {
+                spinner.set_message("Verifying hashes..".to_string())?;
+            }
+            categories.push(Problems {
+                title: "Files not matching their hash",
+                fix: Fix::Remove,
+                entries: listing
+                    .mismatched_indices(session.host.prefix_length)?
+                    .iter()
+                    .filter(|idx| !empty.contains(idx))
+                    .map(folder_of)
+                    .collect(),
+            });
+        }
+
+        categories.push(Problems {
+            title: "Empty files",
+            fix: Fix::Remove,
+            entries: empty.iter().map(folder_of).collect(),
+        });
+
+        if let Some(spinner) = spinner.as_ref() {
+            spinner.set_message("Looking for orphaned folders..".to_string())?;
+        }
+        categories.push(Problems {
+            title: "Orphaned folders",
+            fix: Fix::Remove,
+            entries: orphaned_folders(
+                &all_files,
+                session.all_folders()?,
+                &session.host.layout,
+                &session.host.links_folder,
+            )
+            .into_iter()
+            .map(Problem::Folder)
+            .collect(),
+        });
+
+        if let Some(spinner) = spinner.as_ref() {
+            spinner.set_message("Looking for dangling expiration jobs..".to_string())?;
+        }
+        let existing: HashSet<&PathBuf> = all_files.iter().collect();
+        categories.push(Problems {
+            title: "Expiration jobs for missing files",
+            fix: Fix::Cancel,
+            entries: at::list_jobs(session)?
+                .into_iter()
+                .filter(|job| !existing.contains(&job.path))
+                .map(Problem::Job)
+                .collect(),
+        });
+
+        if let Some(spinner) = spinner {
+            spinner.finish();
+        }
+
+        if !self.include_pinned {
+            let pinned = skip_pinned(&mut categories, &session.pins()?);
+            if pinned > 0 {
+                warn!(
+                    "Skipping {} pinned folders (see `pin`), use --include-pinned to remove them.",
+                    pinned
+                );
+            }
+        }
+
+        let fix = self.force || (self.fix && !session.host.require_confirmation);
+        let mut num_unfixed = 0;
+        for category in categories.iter().filter(|c| !c.entries.is_empty()) {
+            if !config.is_silent() {
+                category.show()?;
+            }
+            if fix || category.confirm()? {
+                for problem in category.entries.iter() {
+                    problem.fix(session)?;
+                }
+                let fixed: Vec<_> = category.entries.iter().map(Problem::path).collect();
+                session.audit(&format!("fsck: {}", category.title), &fixed);
+            } else {
+                num_unfixed += category.entries.len();
+            }
+        }
+
+        if categories.iter().all(|c| c.entries.is_empty()) && !config.is_silent() {
+            println!("No problems found.");
+        }
+        if num_unfixed > 0 {
+            bail!("{} problems left unfixed.", num_unfixed);
+        }
+        Ok(())
+    }
+}
+
+impl Problems {
+    fn show(&self) -> Result<()> {
+        if std::io::stdout().is_terminal() {
+            let content: Vec<String> = self
+                .entries
+                .iter()
+                .map(|p| format!(" {} ", p.describe()))
+                .collect();
+            draw_boxed(
+                format!(
+                    "{}:",
+                    Style::new().bold().red().bright().apply_to(self.title)
+                ),
+                content.iter().map(|s| s.as_str()),
+                &color::frame,
+            )
+        } else {
+            for problem in self.entries.iter() {
+                println!("{}\t{}", self.title, problem.describe());
+            }
+            Ok(())
+        }
+    }
+
+    fn confirm(&self) -> Result<bool> {
+        if !std::io::stdin().is_terminal() {
+            return Ok(false);
+        }
+        Ok(Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt(match self.fix {
+                Fix::Remove => tr!("fsck.confirm-remove", count = self.entries.len()),
+                Fix::Cancel => tr!("fsck.confirm-cancel", count = self.entries.len()),
+            })
+            .default(false)
+            .interact()?)
+    }
+}
+
+/// Drop all pinned folders from the problems to remove and return how many were dropped.
+fn skip_pinned(categories: &mut [Problems], pins: &HashSet<PathBuf>) -> usize {
+    let mut skipped = 0;
+    for category in categories.iter_mut() {
+        if let Fix::Remove = category.fix {
+            let before = category.entries.len();
+            category.entries.retain(|p| !pins.contains(p.path()));
+            skipped += before - category.entries.len();
+        }
+    }
+    skipped
+}
+
+/// Hash folders (relative to the base folder) that do not contain any of the listed files.
+///
+/// Folders not created by asfa are listed via `list --foreign` instead.
+fn orphaned_folders(
+    all_files: &[PathBuf],
+    folders: Vec<PathBuf>,
+    layout: &Layout,
+    links: &Path,
+) -> Vec<PathBuf> {
+    let used: HashSet<&Path> = all_files
+        .iter()
+        .filter_map(|f| layout.upload_folder(f))
+        .collect();
+    let links_folder = links.components().next();
+    folders
+        .into_iter()
+        .filter(|f| !used.contains(f.as_path()))
+        .filter(|f| util::is_hash_folder(&f.to_string_lossy()))
+        .filter(|f| links_folder.is_none() || f.components().next() != links_folder)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pinned_folders_are_skipped() {
+        let pinned = PathBuf::from("V66lLtli0Ei4hw3tNkCTXOcweBrneNjt");
+        let other = PathBuf::from("Zr4kdvL2r0eTl6lHKwBPcWGBTJOoNuRM");
+        let mut categories = vec![Problems {
+            title: "Empty files",
+            fix: Fix::Remove,
+            entries: vec![
+                Problem::Folder(pinned.clone()),
+                Problem::Folder(other.clone()),
+            ],
+        }];
+        assert_eq!(skip_pinned(&mut categories, &HashSet::from([pinned])), 1);
+        let remaining: Vec<_> = categories[0].entries.iter().map(Problem::path).collect();
+        assert_eq!(remaining, vec![other.as_path()]);
+    }
+
+    #[test]
+    fn recursive_uploads_are_not_orphaned() {
+        let dir = Path::new("example-config");
+        let files = crate::bundle::files_recursively(dir).unwrap();
+        let manifest = crate::bundle::manifest(&files, 32).unwrap();
+        let hash = util::get_hash_from(manifest.as_bytes(), 32).unwrap();
+        let layout = Layout::default();
+
+        // Only the manifest is listed for a recursive upload, the uploaded files are nested.
+        let index = Path::new(&hash).join("example-config.files");
+        assert!(layout.matches(&index));
+        let orphaned = PathBuf::from("V66lLtli0Ei4hw3tNkCTXOcweBrneNjt");
+        assert_eq!(
+            orphaned_folders(
+                &[index],
+                vec![PathBuf::from(&hash), orphaned.clone()],
+                &layout,
+                Path::new("")
+            ),
+            vec![orphaned]
+        );
+    }
+}
@@ -0,0 +1,356 @@
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Local};
+use clap::Parser;
+use console::Style;
+use std::fs::{create_dir_all, read_to_string, write};
+use std::io::IsTerminal;
+
+use crate::cache;
+use crate::cfg::{Config, Host};
+use crate::cli::{color, draw_boxed};
+use crate::cmd::Command;
+use crate::ssh::SshSession;
+use crate::util;
+
+/// List configured hosts and what their remote sites support.
+///
+/// Shows the results of the last probe of each host (cached locally), so that the listing does
+/// not need any network access. With `--probe`, connects to every configured host and records
+/// the authentication method that worked, whether `at` and `sha512` are available, free disk
+/// space and whether the base folder is writable.
+#[derive(Parser, Debug)]
+pub struct Hosts {
+    /// Connect to each host and update the cached capabilities.
+    #[clap(long)]
+    probe: bool,
+}
+
+/// Result of probing a single host.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Probe {
+    probed: DateTime<Local>,
+    /// Error message if the host could not be reached.
+    error: Option<String>,
+    auth: Option<String>,
+    at: bool,
+    sha512: bool,
+    /// Free space in the base folder in bytes, if known.
+    free: Option<u64>,
+    /// Whether the base folder is writable, if known (not checked without remote commands).
+    writable: Option<bool>,
+}
+
+impl Command for Hosts {
+    fn run(&self, _session: &SshSession, config: &Config) -> Result<()> {
+        self.run_local(config)
+    }
+}
+
+impl Hosts {
+    /// List (and probe) all hosts, independent of any selected host.
+    pub fn run_local(&self, config: &Config) -> Result<()> {
+        let hosts: Vec<&Host> = config.hosts().collect();
+        if hosts.is_empty() {
+            bail!("No hosts configured.");
+        }
+        let probes = hosts
+            .iter()
+            .map(|host| {
+                if self.probe {
+                    let probe = Probe::run(host);
+                    probe.store(&host.alias);
+                    Ok(Some(probe))
+                } else {
+                    Probe::load(&host.alias)
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        if config.is_silent() {
+            return Ok(());
+        }
+        let rows: Vec<Vec<String>> = hosts
+            .iter()
+            .zip(probes.iter())
+            .map(|(host, probe)| row(host, probe.as_ref()))
+            .collect();
+        if std::io::stdout().is_terminal() {
+            let header = ["host", "auth", "at", "sha512", "free", "write", "probed"];
+            let widths: Vec<usize> = (0..header.len())
+                .map(|i| {
+                    rows.iter()
+                        .map(|row| row[i].chars().count())
+                        .chain([header[i].len()])
+                        .max()
+                        .unwrap_or(0)
+                })
+                .collect();
+            let format_row = |row: &[&str]| {
+                let mut line: String = row
+                    .iter()
+                    .zip(widths.iter())
+                    .map(|(cell, width)| format!(" {:<width$}", cell, width = width))
+                    .collect();
+                line.push(' ');
+                line
+            };
+            let content: Vec<String> = [format_row(&header)]
+                .into_iter()
+                .chain(
+                    rows.iter()
+                        .map(|row| format_row(&row.iter().map(String::as_str).collect::<Vec<_>>())),
+                )
+                .collect();
+            draw_boxed(
+                format!(
+                    "{} configured:",
+                    Style::new().bold().green().bright().apply_to("Hosts")
+                ),
+                content.iter().map(|s| s.as_str()),
+                &color::frame,
+            )?;
+        } else {
+            for row in rows.iter() {
+                println!("{}", row.join("\t"));
+            }
+        }
+        let failed = probes
+            .iter()
+            .flatten()
+            .filter(|probe| probe.error.is_some())
+            .count();
+        if self.probe && failed > 0 {
+            bail!("{} host(s) could not be probed.", failed);
+        }
+        Ok(())
+    }
+}
+
+/// Cells describing the given host, `?` for everything unknown.
+fn row(host: &Host, probe: Option<&Probe>) -> Vec<String> {
+    let unknown = || "?".to_string();
+    let yes_no = |value: bool| if value { "yes" } else { "no" }.to_string();
+    let mut row = vec![host.alias.clone()];
+    match probe {
+        None => row.extend([
+            unknown(),
+            unknown(),
+            unknown(),
+            unknown(),
+            unknown(),
+            unknown(),
+        ]),
+        Some(probe) => {
+            if let Some(error) = probe.error.as_ref() {
+                row.extend([
+                    format!("unreachable: {}", error),
+                    unknown(),
+                    unknown(),
+                    unknown(),
+                    unknown(),
+                ]);
+            } else {
+                row.extend([
+                    probe.auth.clone().unwrap_or_else(unknown),
+                    yes_no(probe.at),
+                    yes_no(probe.sha512),
+                    probe.free.map(util::format_size).unwrap_or_else(unknown),
+                    probe.writable.map(yes_no).unwrap_or_else(unknown),
+                ]);
+            }
+            row.push(probe.probed.format("%Y-%m-%d %H:%M").to_string());
+        }
+    }
+    row
+}
+
+impl Probe {
+    /// Connect to the given host and check what it supports, recording connection failures.
+    fn run(host: &Host) -> Self {
+        let probed = Local::now();
+        match Self::check(host, probed) {
+            Ok(probe) => probe,
+            Err(e) => {
+                log::debug!("Could not probe {}: {:?}", host.alias, e);
+                Self {
+                    probed,
+                    error: Some(e.to_string()),
+                    auth: None,
+                    at: false,
+                    sha512: false,
+                    free: None,
+                    writable: None,
+                }
+            }
+        }
+    }
+
+    fn check(host: &Host, probed: DateTime<Local>) -> Result<Self> {
+        let session = SshSession::connect(host)?;
+        let auth = session.auth_method().map(str::to_string);
+        if !host.exec {
+            return Ok(Self {
+                probed,
+                error: None,
+                auth,
+                at: false,
+                sha512: false,
+                free: None,
+                writable: None,
+            });
+        }
+        let capabilities = session.probe_capabilities()?;
+        let folder = host.folder.display();
+        let free = session
+            .exec_remote(&format!("df -Pk '{}'", folder))?
+            .expect("Could not determine free disk space.")
+            .ok()
+            .and_then(|output| parse_df(output.stdout()));
+        let writable = session
+            .exec_remote(&format!("test -d '{0}' -a -w '{0}'", folder))?
+            .exit_status()
+            == 0;
+        Ok(Self {
+            probed,
+            error: None,
+            auth,
+            at: capabilities.has("at"),
+            sha512: capabilities.hasher(64).is_ok(),
+            free,
+            writable: Some(writable),
+        })
+    }
+
+    /// Load the result of the last probe of the given host, if any.
+    fn load(alias: &str) -> Result<Option<Self>> {
+        let path = cache::cache_dir()?.join(format!("{}.probe", alias));
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = read_to_string(&path)
+            .with_context(|| format!("Could not read cache: {}", path.display()))?;
+        Ok(Some(Self::parse(&content).with_context(|| {
+            format!("Invalid cache: {}", path.display())
+        })?))
+    }
+
+    /// Cache the result for the given host.
+    ///
+    /// Failing to store is never fatal, it is only reported.
+    fn store(&self, alias: &str) {
+        let stored = cache::cache_dir().and_then(|dir| {
+            create_dir_all(&dir)?;
+            Ok(write(
+                dir.join(format!("{}.probe", alias)),
+                self.serialize(),
+            )?)
+        });
+        if let Err(e) = stored {
+            log::debug!("Could not cache probe: {}", e);
+        }
+    }
+
+    fn serialize(&self) -> String {
+        let mut content = format!("probed {}\n", self.probed.to_rfc3339());
+        if let Some(error) = self.error.as_ref() {
+            content.push_str(&format!("error {}\n", error.replace('\n', " ")));
+        }
+        if let Some(auth) = self.auth.as_ref() {
+            content.push_str(&format!("auth {}\n", auth));
+        }
+        if self.at {
+            content.push_str("at\n");
+        }
+        if self.sha512 {
+            content.push_str("sha512\n");
+        }
+        if let Some(free) = self.free {
+            content.push_str(&format!("free {}\n", free));
+        }
+        if let Some(writable) = self.writable {
+            content.push_str(&format!("writable {}\n", writable));
+        }
+        content
+    }
+
+    fn parse(content: &str) -> Result<Self> {
+        let mut probed = None;
+        let mut probe = Self {
+            probed: Local::now(),
+            error: None,
+            auth: None,
+            at: false,
+            sha512: false,
+            free: None,
+            writable: None,
+        };
+        for line in content.lines() {
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            match key {
+                "probed" => probed = Some(DateTime::parse_from_rfc3339(value)?.into()),
+                "error" => probe.error = Some(value.to_string()),
+                "auth" => probe.auth = Some(value.to_string()),
+                "at" => probe.at = true,
+                "sha512" => probe.sha512 = true,
+                "free" => probe.free = Some(value.parse()?),
+                "writable" => probe.writable = Some(value.parse()?),
+                _ => bail!("Unknown entry: {}", line),
+            }
+        }
+        probe.probed = probed.context("Time of probe missing.")?;
+        Ok(probe)
+    }
+}
+
+/// Available bytes reported by `df -Pk` (POSIX output format, 1024-byte blocks).
+fn parse_df(output: &str) -> Option<u64> {
+    let available: u64 = output
+        .lines()
+        .nth(1)?
+        .split_whitespace()
+        .nth(3)?
+        .parse()
+        .ok()?;
+    Some(available * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn probe_cache_roundtrip() {
+        let probe = Probe {
+            probed: DateTime::parse_from_rfc3339("2024-03-01T12:00:00+01:00")
+                .unwrap()
+                .into(),
+            error: None,
+            auth: Some("publickey (agent)".to_string()),
+            at: true,
+            sha512: false,
+            free: Some(1024 * 1024),
+            writable: Some(true),
+        };
+        assert_eq!(Probe::parse(&probe.serialize()).unwrap(), probe);
+
+        let unreachable = Probe {
+            error: Some("Connection refused".to_string()),
+            auth: None,
+            at: false,
+            free: None,
+            writable: None,
+            ..probe
+        };
+        assert_eq!(Probe::parse(&unreachable.serialize()).unwrap(), unreachable);
+        assert!(Probe::parse("at\n").is_err());
+
+        assert_eq!(
+            parse_df(
+                "Filesystem 1024-blocks Used Available Capacity Mounted on\n\
+                 /dev/sda1 1000 600 400 60% /\n"
+            ),
+            Some(400 * 1024)
+        );
+        assert_eq!(parse_df(""), None);
+    }
+}
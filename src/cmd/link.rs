@@ -0,0 +1,41 @@
+use anyhow::Result;
+use clap::{AppSettings, Parser};
+
+use crate::cfg::Config;
+use crate::cmd::Command;
+use crate::ssh::SshSession;
+
+/// Create a named symlink to an already uploaded file.
+///
+/// The symlink is placed in the `links_folder` configured for the host (defaults to the base
+/// folder) and is removed once its target gets cleaned.
+#[derive(Parser, Debug)]
+#[clap(global_setting=AppSettings::AllowNegativeNumbers)]
+pub struct Link {
+    /// Specify index of remote file or local file to compute hash from.
+    #[clap()]
+    input: String,
+
+    /// Name of the symlink to create (an existing symlink with the same name is replaced).
+    #[clap()]
+    linkname: String,
+}
+
+impl Command for Link {
+    fn run(&self, session: &SshSession, config: &Config) -> Result<()> {
+        let host = &session.host;
+
+        let selected = session
+            .list_files()?
+            .by_index_or_file(&self.input, host.prefix_length)?;
+        let (_, target, _) = selected.iter().next().unwrap();
+
+        let link = host.links_folder.join(&self.linkname);
+        session.symlink(target, &link)?;
+
+        if !config.is_silent() {
+            println!("{}", host.get_url(&format!("{}", link.display()))?);
+        }
+        Ok(())
+    }
+}
@@ -3,32 +3,73 @@ use clap::{AppSettings, Parser};
 use console::Style;
 use std::io::IsTerminal;
 
-use crate::cfg::Config;
+use crate::cfg::{Config, Host};
 use crate::cli::color;
 use crate::cli::draw_boxed;
+use crate::clipboard;
 use crate::cmd::Command;
+use crate::file_listing::{self, FileListing, Show, FORMAT_FIELDS, FORMAT_FIELDS_STATS};
+use crate::http;
+use crate::qr;
 use crate::ssh::SshSession;
+use crate::template::Template;
+use crate::util;
 
 /// List uploaded files and their URLs.
 #[derive(Parser, Debug)]
 #[clap(global_setting=AppSettings::AllowNegativeNumbers)]
 pub struct List {
-    /// Show all details, can be set globally in config file.
+    /// Copy the URLs of all listed files to the clipboard, can be set globally in config file.
+    #[clap(long, short = 'b')]
+    clipboard: bool,
+
+    /// Show all details (including pending expirations), can be set globally in config file.
     #[clap(long, short)]
     details: bool,
 
+    /// Show disk usage (allocated blocks) instead of apparent file sizes as well as the total
+    /// usage, which differs for sparse files. Sorting by size (`-S`) uses disk usage as well.
+    #[clap(long)]
+    du: bool,
+
     /// Show no full urls but rather filenames only. Makes for more concise output.
-    #[clap(long, short)]
+    ///
+    /// Shorthand for `--show filename`.
+    #[clap(long, short, conflicts_with = "show")]
     filenames: bool,
 
     /// Filter filenames by regex. See <https://docs.rs/regex/latest/regex/#syntax>
     #[clap(long, short = 'F', value_name = "regex")]
     filter: Option<String>,
 
+    /// Print one line per file according to the given template instead of a table, e.g.,
+    /// `--format '{index}\t{url}\t{size}\t{mtime}'`.
+    ///
+    /// Available fields: {index}, {rev_index}, {url}, {path}, {name}, {hash}, {size} (in bytes)
+    /// and {mtime}. Use `{{`/`}}` for literal braces.
+    #[clap(long, value_name = "template", conflicts_with_all = &["print-indices", "url-only", "filenames", "show"])]
+    format: Option<String>,
+
     /// Only list first `N` entries.
     #[clap(short = 'N', long, conflicts_with = "last")]
     first: Option<usize>,
 
+    /// List entries in the base folder that were not uploaded by asfa (e.g., files copied there
+    /// manually), which are skipped otherwise. Indices and `--filter` refer to these entries.
+    #[clap(
+        long,
+        conflicts_with_all = &["format", "url-only", "verify-urls", "du", "print-indices"]
+    )]
+    foreign: bool,
+
+    /// Print the listed files as JSON array (index, filename, url, path, hash, size, mtime and
+    /// expiration) for processing with e.g. `jq`.
+    #[clap(
+        long,
+        conflicts_with_all = &["format", "foreign", "print-indices", "url-only", "verify-urls"]
+    )]
+    json: bool,
+
     /// Specify indices of files to list (if none given, list all).
     #[clap()]
     indices: Vec<i64>,
@@ -37,6 +78,10 @@ pub struct List {
     #[clap(short = 'n', long, conflicts_with = "first")]
     last: Option<usize>,
 
+    /// If `clipboard` is set to true in config, --no-clipboard can be specified to not copy URLs.
+    #[clap(long, conflicts_with = "clipboard")]
+    no_clipboard: bool,
+
     /// If `details` is set to true in config, --no-details can be specified to suppress output.
     #[clap(long, short = 'D')]
     no_details: bool,
@@ -47,10 +92,24 @@ pub struct List {
     #[clap(long = "indices", short = 'i', conflicts_with = "url-only")]
     print_indices: bool,
 
+    /// Render the URL of each listed file as QR code (below the listing), e.g. to open it on a
+    /// phone.
+    #[clap(long, conflicts_with_all = &["format", "json", "print-indices"])]
+    qr: bool,
+
     /// Reverse listing.
     #[clap(long, short)]
     reverse: bool,
 
+    /// Select files whose age lies within the given window, e.g. `--between 2d..7d` selects files
+    /// older than two days but newer than a week. Either bound can be omitted.
+    #[clap(
+        long,
+        value_name = "min..max",
+        conflicts_with_all = &["select-newer", "select-older"]
+    )]
+    between: Option<String>,
+
     /// Select files newer than the given duration. Durations can be:seconds (sec, s), minutes
     /// (min, m), days (d), weeks (w), months (M) or years (y).
     #[clap(long = "newer")]
@@ -69,10 +128,20 @@ pub struct List {
     #[clap(long, short = 'T')]
     sort_time: bool,
 
+    /// How to present files: as URL, file name only, path relative to the base folder or
+    /// absolute path on the remote site.
+    #[clap(long, arg_enum, default_value = "url", value_name = "what")]
+    show: Show,
+
     /// Only list the remote URLs (useful for copying and scripting).
     #[clap(short, long = "url-only", conflicts_with = "indices")]
     url_only: bool,
 
+    /// Send a HEAD-request to each listed URL and mark entries the webserver does not serve
+    /// (e.g., 403/404).
+    #[clap(long, conflicts_with_all = &["print-indices", "url-only", "format"])]
+    verify_urls: bool,
+
     /// Print remote modification time
     #[clap(long, short = 't')]
     with_time: bool,
@@ -82,11 +151,133 @@ pub struct List {
     with_size: bool,
 }
 
+impl List {
+    /// Whether to only print URLs, requested explicitly or via `url_only` in the `output` config
+    /// section (unless a different presentation was requested).
+    fn url_only(&self, config: &Config) -> bool {
+        self.url_only
+            || (config.output.url_only
+                && self.show == Show::Url
+                && !(self.details
+                    || self.du
+                    || self.filenames
+                    || self.print_indices
+                    || self.verify_urls
+                    || self.with_size
+                    || self.with_time))
+    }
+
+    /// List entries not created by asfa.
+    fn list_foreign(&self, session: &SshSession, config: &Config) -> Result<()> {
+        let all = self.indices.is_empty() && self.filter.is_none();
+        let entries = file_listing::select_foreign(
+            session.foreign_entries()?,
+            &self.indices,
+            self.filter.as_deref(),
+            all,
+        )?;
+        let content = file_listing::format_foreign(&entries);
+
+        if content.is_empty() {
+            crate::cli::report_empty(config, "No foreign entries found.");
+        } else if !std::io::stdout().is_terminal() {
+            for line in content {
+                println!("{}", line);
+            }
+        } else {
+            draw_boxed(
+                format!(
+                    "{listing} entries not uploaded by asfa:",
+                    listing = Style::new().bold().yellow().bright().apply_to("Listing")
+                ),
+                content.iter().map(|s| s.as_ref()),
+                &color::frame,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Summary line with the total disk usage of all listed files.
+    fn total_usage(&self, listing: &FileListing) -> String {
+        let total: u64 = listing
+            .iter()
+            .filter_map(|(_, _, stat)| stat.and_then(|s| s.size))
+            .sum();
+        format!(
+            " {} {} ",
+            Style::new().bold().apply_to("Total disk usage:"),
+            util::format_size(total)
+        )
+    }
+
+    /// Check the URL of every listed file and mark those not served by the webserver.
+    fn mark_dead_urls(
+        &self,
+        host: &Host,
+        listing: &FileListing,
+        content: Vec<String>,
+    ) -> Result<Vec<String>> {
+        let urls = listing
+            .iter()
+            .map(|(_, file, _)| host.get_url(&format!("{}", file.display())))
+            .collect::<Result<Vec<_>>>()?;
+        let statuses = http::head_all(&urls);
+
+        let num_dead = statuses
+            .iter()
+            .filter(|s| !matches!(s, Ok(status) if status.is_success()))
+            .count();
+        if num_dead > 0 {
+            log::warn!("{}/{} URLs are not served.", num_dead, urls.len());
+        }
+
+        let is_terminal = std::io::stdout().is_terminal();
+        Ok(content
+            .into_iter()
+            .zip(statuses)
+            .map(|(line, status)| {
+                let dead = match status {
+                    Ok(status) if status.is_success() => None,
+                    Ok(status) => Some(status.as_u16().to_string()),
+                    Err(e) => {
+                        log::debug!("Request failed: {}", e);
+                        Some("error".to_string())
+                    }
+                };
+                match (is_terminal, dead) {
+                    (true, None) => line,
+                    (true, Some(dead)) => format!(
+                        "{}{} ",
+                        line,
+                        color::failure.apply_to(format!("✗ {}", dead))
+                    ),
+                    (false, dead) => format!("{}\t{}", line, dead.as_deref().unwrap_or("ok")),
+                }
+            })
+            .collect())
+    }
+}
+
 impl Command for List {
     fn run(&self, session: &SshSession, config: &Config) -> Result<()> {
         let host = &session.host;
 
-        let show_details = (self.details || config.details) && !self.no_details;
+        if self.foreign {
+            if !config.is_silent() {
+                self.list_foreign(session, config)?;
+            }
+            return Ok(());
+        }
+
+        let template = self
+            .format
+            .as_deref()
+            .map(|f| Template::parse(f, FORMAT_FIELDS))
+            .transpose()?;
+        let template_needs_stats = template
+            .as_ref()
+            .map(|t| t.uses_any(FORMAT_FIELDS_STATS))
+            .unwrap_or(false);
 
         let to_list = session
             .list_files()?
@@ -95,17 +286,46 @@ impl Command for List {
             .with_all_if_none(self.filter.is_none())
             .select_newer(self.select_newer.as_deref())?
             .select_older(self.select_older.as_deref())?
+            .select_between(self.between.as_deref())?
+            .with_disk_usage(self.du)?
             .sort_by_size(self.sort_size)?
             .sort_by_time(self.sort_time)?
             .first(self.first)
             .last(self.last)
-            .revert(self.reverse)
-            .with_stats(show_details || self.with_time || self.with_size)?;
+            .revert(self.reverse);
+        let show_details =
+            config.show_details_for(host, self.details, self.no_details, to_list.count());
+        let to_list = to_list.with_stats(
+            show_details
+                || self.with_time
+                || self.with_size
+                || self.du
+                || self.json
+                || template_needs_stats,
+        )?;
+
+        if config.copy_to_clipboard(self.clipboard, self.no_clipboard) {
+            let urls = to_list
+                .iter()
+                .map(|(_, file, _)| host.get_url(&format!("{}", file.display())))
+                .collect::<Result<Vec<_>>>()?;
+            clipboard::copy_urls(&urls);
+        }
 
         if !config.is_silent() {
-            if self.url_only {
+            if self.json {
+                crate::cli::print_json(&to_list.to_json(host)?)?;
+            } else if let Some(template) = template.as_ref() {
+                for line in to_list.format_template(host, template)? {
+                    println!("{}", line);
+                }
+            } else if self.url_only(config) {
                 for (_, file, _) in to_list.iter() {
-                    println!("{}", host.get_url(&format!("{}", file.display()))?);
+                    let url = host.get_url(&format!("{}", file.display()))?;
+                    println!("{}", url);
+                    if self.qr {
+                        println!("{}", qr::render(&url)?);
+                    }
                 }
             } else if self.print_indices {
                 for idx in to_list.indices {
@@ -113,26 +333,31 @@ impl Command for List {
                 }
                 println!();
             } else {
+                let to_list = to_list.with_expirations(show_details)?;
                 let content = to_list.format_files(
-                    Some(&session.host),
-                    self.filenames,
-                    show_details || self.with_size,
+                    self.show.or_filenames(self.filenames),
+                    show_details || self.with_size || self.du,
                     show_details || self.with_time,
                 )?;
+                let content = if self.verify_urls {
+                    self.mark_dead_urls(host, &to_list, content)?
+                } else {
+                    content
+                };
 
-                let content = if content.is_empty() {
-                    vec![format!(
-                        "{}(There are no remote files to show.)",
-                        if std::io::stdout().is_terminal() {
-                            " "
-                        } else {
-                            ""
-                        }
-                    )]
+                let content = if self.du && !content.is_empty() && std::io::stdout().is_terminal() {
+                    let mut content = content;
+                    content.push(self.total_usage(&to_list));
+                    content
                 } else {
                     content
                 };
 
+                if content.is_empty() {
+                    crate::cli::report_empty(config, "There are no remote files to show.");
+                    return Ok(());
+                }
+
                 // Only print fancy boxes if we are attached to a TTY -> otherwise, just dump data in
                 // parseable format
                 if std::io::stdout().is_terminal() {
@@ -149,8 +374,31 @@ impl Command for List {
                         println!("{}", line);
                     }
                 }
+
+                if self.qr {
+                    for (idx, file, _) in to_list.iter() {
+                        let url = host.get_url(&format!("{}", file.display()))?;
+                        println!("{} {}\n{}", idx, url, qr::render(&url)?);
+                    }
+                }
             }
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn select_indices_with_output_options() {
+        let list = List::try_parse_from(["list", "--format", "{url}", "3"]).unwrap();
+        assert_eq!(list.indices, vec![3]);
+        assert!(List::try_parse_from(["list", "--format", "{url}", "-i"]).is_err());
+
+        let list = List::try_parse_from(["list", "--verify-urls", "3"]).unwrap();
+        assert_eq!(list.indices, vec![3]);
+        assert!(List::try_parse_from(["list", "--verify-urls", "-i"]).is_err());
+    }
+}
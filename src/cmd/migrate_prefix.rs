@@ -0,0 +1,191 @@
+use anyhow::{bail, Result};
+use clap::Parser;
+use console::Style;
+use dialoguer::{theme::ColorfulTheme, Confirm};
+use log::{debug, warn};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+
+use crate::at;
+use crate::cfg::Config;
+use crate::cli::{color, draw_boxed, text, WaitingSpinner};
+use crate::cmd::Command;
+use crate::ssh::SshSession;
+use crate::tr;
+use crate::util;
+
+/// Rename folders of files uploaded with a different prefix length.
+///
+/// After changing `prefix_length`, previously uploaded files still reside in folders named after
+/// a hash of the old length. This command moves them to folders matching the current
+/// `prefix_length` (updating named symlinks) so that URLs are consistent again. Only folders
+/// whose name is the hash of their content are moved.
+#[derive(Parser, Debug)]
+pub struct MigratePrefix {
+    /// Only print which folders would be renamed.
+    #[clap(long)]
+    dry_run: bool,
+
+    /// Disable confirming the renaming.
+    #[clap(long = "no-confirm")]
+    no_confirm: bool,
+}
+
+impl Command for MigratePrefix {
+    fn run(&self, session: &SshSession, config: &Config) -> Result<()> {
+        session.host.layout.require_default(&session.host.alias)?;
+        let prefix_length = session.host.prefix_length;
+        let spinner = (!config.is_silent()).then(|| WaitingSpinner::new("Hashing..".to_string()));
+
+        let listing = session.list_files()?;
+        let links_folder = session.host.links_folder.components().next();
+        let mut seen = HashSet::new();
+        // Only folders named like hashes of another length are candidates, anything else (e.g.
+        // links or folders created manually) is left alone.
+        let candidates: Vec<&Path> = (0..listing.num_files)
+            .filter_map(|idx| listing.path_of(idx))
+            .filter(|path| {
+                path.parent()
+                    .map(|folder| {
+                        let name = folder.to_string_lossy();
+                        util::is_hash_folder(&name)
+                            && name.len() != prefix_length as usize
+                            && (links_folder.is_none()
+                                || folder.components().next() != links_folder)
+                            && seen.insert(folder.to_path_buf())
+                    })
+                    .unwrap_or(false)
+            })
+            .collect();
+
+        // Hashes of all lengths are prefixes of the full hash of the same hasher (SHA-256 up to
+        // a length of 32, SHA-512 above), so each file needs to be hashed at most twice.
+        let full_length = |length: usize| if length <= 32 { 32 } else { 64 };
+        let mut full_hashes: HashMap<usize, Vec<String>> = HashMap::new();
+        for length in candidates
+            .iter()
+            .filter_map(|path| Some(path.parent()?.as_os_str().len()))
+            .chain([prefix_length as usize])
+            .map(full_length)
+        {
+            if let Entry::Vacant(entry) = full_hashes.entry(length) {
+                let mut hashes = Vec::with_capacity(candidates.len());
+                for chunk in candidates.chunks(session.hash_batch_size()?) {
+                    hashes.extend(session.get_remote_hashes(chunk, length as u8)?);
+                }
+                entry.insert(hashes);
+            }
+        }
+        if let Some(spinner) = spinner {
+            spinner.finish();
+        }
+        let hash = |idx: usize, length: usize| -> &str {
+            &full_hashes[&full_length(length)][idx][..length]
+        };
+
+        // Folders whose content does not match their name are no legacy uploads (e.g. extracted
+        // archives or static sites), their URLs have to stay as they are.
+        let moves: Vec<(PathBuf, PathBuf)> = candidates
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, path)| {
+                let folder = path.parent()?;
+                let name = folder.to_string_lossy();
+                if hash(idx, name.len()) != name {
+                    debug!(
+                        "Skipping {}: content does not match hash.",
+                        folder.display()
+                    );
+                    return None;
+                }
+                Some((
+                    folder.to_path_buf(),
+                    PathBuf::from(hash(idx, prefix_length as usize)),
+                ))
+            })
+            .collect();
+
+        if moves.is_empty() {
+            if !config.is_silent() {
+                println!("All folders match prefix length {}.", prefix_length);
+            }
+            return Ok(());
+        }
+
+        if !config.is_silent() {
+            self.show(&moves)?;
+        }
+        if self.dry_run {
+            return Ok(());
+        }
+        if !self.no_confirm && !self.confirm(moves.len())? {
+            bail!("Aborted.");
+        }
+
+        let jobs = at::list_jobs(session)?;
+        let mut existing: HashSet<PathBuf> = session.all_folders()?.into_iter().collect();
+        for (old, new) in moves.iter() {
+            if !existing.insert(new.clone()) {
+                warn!(
+                    "Skipping {}: {} already exists (uploaded twice?).",
+                    old.display(),
+                    new.display()
+                );
+                continue;
+            }
+            session.move_folder(old, new)?;
+            session.audit("migrate-prefix", &[old, new]);
+            if jobs.iter().any(|job| job.path.starts_with(old)) {
+                warn!(
+                    "Pending expiration job still refers to {}, it will not expire.",
+                    old.display()
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+impl MigratePrefix {
+    fn show(&self, moves: &[(PathBuf, PathBuf)]) -> Result<()> {
+        if std::io::stdout().is_terminal() {
+            let content: Vec<String> = moves
+                .iter()
+                .map(|(old, new)| {
+                    format!(
+                        " {} {} {} ",
+                        Style::new().red().bright().apply_to(old.display()),
+                        text::arrow(),
+                        new.display()
+                    )
+                })
+                .collect();
+            draw_boxed(
+                tr!(
+                    "migrate-prefix.header",
+                    rename = Style::new()
+                        .bold()
+                        .green()
+                        .bright()
+                        .apply_to(tr!("migrate-prefix.rename"))
+                ),
+                content.iter().map(|s| s.as_str()),
+                &color::frame,
+            )
+        } else {
+            for (old, new) in moves.iter() {
+                println!("{}\t{}", old.display(), new.display());
+            }
+            Ok(())
+        }
+    }
+
+    fn confirm(&self, num_folders: usize) -> Result<bool> {
+        Ok(Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt(tr!("migrate-prefix.confirm", count = num_folders))
+            .default(false)
+            .interact()?)
+    }
+}
@@ -5,16 +5,50 @@ use anyhow::Result;
 
 mod check;
 mod clean;
+mod complete_remote;
+mod completions;
+mod daemon;
+mod doctor;
+mod expire;
+mod export_manifest;
+mod fsck;
+mod hosts;
+mod link;
 mod list;
+mod migrate_prefix;
+mod pin;
+mod ping;
 mod push;
+mod quickshare;
 mod rename;
+mod resolve;
+mod shell_init;
+mod stats;
+mod status;
 mod verify;
 
 pub use check::Check;
 pub use clean::Clean;
+pub use complete_remote::CompleteRemote;
+pub use completions::Completions;
+pub use daemon::Daemon;
+pub use doctor::Doctor;
+pub use expire::Expire;
+pub use export_manifest::ExportManifest;
+pub use fsck::Fsck;
+pub use hosts::Hosts;
+pub use link::Link;
 pub use list::List;
+pub use migrate_prefix::MigratePrefix;
+pub use pin::Pin;
+pub use ping::Ping;
 pub use push::Push;
+pub use quickshare::QuickShare;
 pub use rename::Rename;
+pub use resolve::Resolve;
+pub use shell_init::ShellInit;
+pub use stats::Stats;
+pub use status::Status;
 pub use verify::Verify;
 
 pub trait Command {
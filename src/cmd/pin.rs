@@ -0,0 +1,134 @@
+use anyhow::{Context, Result};
+use clap::{AppSettings, Parser};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use crate::at;
+use crate::cfg::Config;
+use crate::cli::{color, draw_boxed};
+use crate::cmd::Command;
+use crate::ssh::SshSession;
+
+/// Pin uploads to protect them from deletion and expiration.
+///
+/// Pinned uploads are skipped when selecting files via `clean --all`, `--older` or `--between`
+/// (unless `--include-pinned` is given) and pending expiration jobs for them are cancelled.
+/// Without selection, list all pinned uploads.
+#[derive(Parser, Debug)]
+#[clap(global_setting=AppSettings::AllowNegativeNumbers)]
+pub struct Pin {
+    /// Explicit local file to pin (by hash).
+    #[clap(short, long = "file")]
+    files: Vec<String>,
+
+    /// Filter filenames by regex. See <https://docs.rs/regex/latest/regex/#syntax>
+    #[clap(long, short = 'F', value_name = "regex")]
+    filter: Option<String>,
+
+    /// Indices of files to pin as returned by `list` command.
+    #[clap()]
+    indices: Vec<i64>,
+
+    /// Unpin the selected uploads instead.
+    #[clap(long)]
+    remove: bool,
+}
+
+impl Command for Pin {
+    fn run(&self, session: &SshSession, config: &Config) -> Result<()> {
+        let mut pins = session.pins()?;
+
+        if self.indices.is_empty() && self.files.is_empty() && self.filter.is_none() {
+            return self.list_pinned(session, config, &pins);
+        }
+
+        let selected = session
+            .list_files()?
+            .by_indices(&self.indices[..])?
+            .by_filter(self.filter.as_deref())?
+            .by_hash(
+                self.files.iter(),
+                session.host.prefix_length,
+                /* bail_when_missing = */ true,
+            )?;
+
+        let layout = &session.host.layout;
+        let mut changed: Vec<&Path> = Vec::new();
+        for (_, path, _) in selected.iter() {
+            let folder = layout
+                .upload_folder(path)
+                .with_context(|| format!("Invalid remote file: {}", path.display()))?;
+            let is_change = if self.remove {
+                pins.remove(folder)
+            } else {
+                pins.insert(folder.to_path_buf())
+            };
+            if is_change {
+                changed.push(path);
+            }
+        }
+        session.set_pins(&pins)?;
+
+        if !self.remove {
+            self.cancel_expiration(session, &changed)?;
+        }
+        session.audit(if self.remove { "unpin" } else { "pin" }, &changed);
+
+        if !config.is_silent() {
+            for path in changed.iter() {
+                println!(
+                    "{} {}",
+                    if self.remove { "Unpinned:" } else { "Pinned:" },
+                    color::filename.apply_to(path.display())
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Pin {
+    /// Cancel pending expiration jobs for the given (newly pinned) files.
+    fn cancel_expiration(&self, session: &SshSession, pinned: &[&Path]) -> Result<()> {
+        if pinned.is_empty() {
+            return Ok(());
+        }
+        for job in at::list_jobs(session)? {
+            if pinned.contains(&job.path.as_path()) {
+                at::remove_job(session, &job)?;
+                log::info!("Cancelled expiration of {}", job.path.display());
+            }
+        }
+        Ok(())
+    }
+
+    fn list_pinned(
+        &self,
+        session: &SshSession,
+        config: &Config,
+        pins: &HashSet<PathBuf>,
+    ) -> Result<()> {
+        let listing = session.list_files()?;
+        let layout = &session.host.layout;
+        let pinned: Vec<String> = (0..listing.num_files)
+            .filter_map(|idx| listing.path_of(idx).map(|path| (idx, path)))
+            .filter(|(_, path)| {
+                layout
+                    .upload_folder(path)
+                    .map(|folder| pins.contains(folder))
+                    .unwrap_or(false)
+            })
+            .map(|(idx, path)| format!(" {} {} ", idx, color::filename.apply_to(path.display())))
+            .collect();
+
+        if pinned.is_empty() {
+            crate::cli::report_empty(config, "No pinned uploads.");
+            return Ok(());
+        }
+        draw_boxed(
+            "Pinned uploads:",
+            pinned.iter().map(|s| s.as_str()),
+            &color::frame,
+        )
+    }
+}
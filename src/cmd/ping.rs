@@ -0,0 +1,56 @@
+use anyhow::{bail, Result};
+use clap::Parser;
+use std::time::{Duration, Instant};
+
+use crate::cfg::Config;
+use crate::cmd::Command;
+use crate::ssh::SshSession;
+
+/// Check that the remote site is reachable and measure round-trip times.
+///
+/// Connects, authenticates and runs `true` remotely. Prints a single line with the time it took
+/// to connect, the authentication method used and the round-trip times of the remote command,
+/// e.g. for scripts before starting large batches. Fails if the remote site cannot be reached.
+#[derive(Parser, Debug)]
+pub struct Ping {
+    /// Number of round trips to measure.
+    #[clap(short = 'n', long, value_name = "N", default_value = "3")]
+    count: usize,
+}
+
+impl Command for Ping {
+    fn run(&self, session: &SshSession, config: &Config) -> Result<()> {
+        if self.count == 0 {
+            bail!("Need to measure at least one round trip.");
+        }
+        let mut round_trips = Vec::with_capacity(self.count);
+        for _ in 0..self.count {
+            let start = Instant::now();
+            if session.host.exec {
+                session
+                    .exec_remote("true")?
+                    .expect("Could not run remote command.")?;
+            } else {
+                // Hosts without remote commands are only reachable via SFTP.
+                session.base_folder_exists()?;
+            }
+            round_trips.push(start.elapsed());
+        }
+
+        if !config.is_silent() {
+            let min = round_trips.iter().min().copied().unwrap_or_default();
+            let max = round_trips.iter().max().copied().unwrap_or_default();
+            let avg = round_trips.iter().sum::<Duration>() / round_trips.len() as u32;
+            println!(
+                "{}: connected in {} ms via {}, round trip min/avg/max {}/{}/{} ms",
+                session.host.alias,
+                session.connect_duration().as_millis(),
+                session.auth_method().unwrap_or("unknown method"),
+                min.as_millis(),
+                avg.as_millis(),
+                max.as_millis()
+            );
+        }
+        Ok(())
+    }
+}
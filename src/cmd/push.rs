@@ -1,22 +1,48 @@
 use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Local};
 use clap::Parser;
-use log::debug;
+use log::{debug, warn};
+use std::fs::read_dir;
 use std::io::IsTerminal;
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
 use std::string::String;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, UNIX_EPOCH};
 
-use crate::at::At;
-use crate::cfg::Config;
+use crate::at::{self, At};
+use crate::bundle::{self, ArchiveFormat, Bundle};
+use crate::cache::{self, PushJournal, ShortUrls, Throughput};
+use crate::cfg::{Config, DirectoryMode, VerifyStrategy};
 use crate::cli::color;
-use crate::cli::WaitingSpinner;
+use crate::cli::{progress_header, WaitingSpinner};
+use crate::clipboard;
 use crate::cmd::Command;
-use crate::ssh::SshSession;
-use crate::util::get_hash;
+use crate::compress::Compression;
+use crate::file_listing::FileListing;
+use crate::metrics;
+use crate::notify;
+use crate::parallel;
+use crate::qr;
+use crate::share_code;
+use crate::spool::{Spool, TempFile};
+use crate::ssh::{RecipientLink, SshSession};
+use crate::tr;
+use crate::util::{self, get_hash};
 
 /// Upload new files.
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Default)]
 pub struct Push {
+    /// Upload directories as archive of the given format (`tar.gz` or `zip`) named after the
+    /// directory, regardless of `push_directories`.
+    #[clap(
+        long,
+        arg_enum,
+        value_name = "format",
+        conflicts_with_all = &["recursive", "bundle", "site"]
+    )]
+    archive: Option<ArchiveFormat>,
+
     /// Alias/file name on the remote site.
     ///
     /// If you specify multiple files to upload you can either specify no aliases or as many
@@ -24,23 +50,139 @@ pub struct Push {
     #[clap(short, long)]
     alias: Vec<String>,
 
+    /// Transliterate non-ASCII characters in aliases/file names on the remote site to ASCII.
+    ///
+    /// Example: `--ascii` causes `Übersicht 東京.pdf` to be uploaded as `Ubersicht Dong Jing .pdf`.
+    #[clap(long)]
+    ascii: bool,
+
+    /// Bundle all given files and folders into a single tar.gz-archive with the given name.
+    ///
+    /// The archive is streamed directly to the remote site without creating a local temporary
+    /// file. Since it is created deterministically, bundling the same files twice results in the
+    /// same URL.
+    #[clap(
+        short,
+        long,
+        value_name = "name.tar.gz",
+        conflicts_with_all = &["alias", "prefix", "suffix", "if-absent", "if-newer"]
+    )]
+    bundle: Option<String>,
+
+    /// Additionally print a short code (e.g. `k3f9-x2ab`) for each upload that can be turned
+    /// back into the URL via `asfa resolve`, e.g. when dictating links over the phone.
+    #[clap(long)]
+    code: bool,
+
+    /// Copy the printed URL(s) to the clipboard (via `wl-copy`, `xclip`, `xsel` or `pbcopy`), can
+    /// be set globally in config file.
+    #[clap(long, alias = "copy")]
+    clipboard: bool,
+
+    /// Compress files with the given format (`gzip` or `zstd`) before uploading them, appending
+    /// `.gz`/`.zst` to their names, e.g. for logs or JSON dumps.
+    ///
+    /// Compressed files are stored under the hash of the uncompressed file, so that `check` and
+    /// `--if-absent` still find them. The transfer is verified against the hash of the compressed
+    /// data, which is not recorded, so `verify` later decompresses them on the remote site and
+    /// compares the hash of their content.
+    #[clap(
+        long,
+        arg_enum,
+        value_name = "format",
+        conflicts_with_all = &["bundle", "site", "recursive", "extract", "resume", "stdin"]
+    )]
+    compress: Option<Compression>,
+
+    /// Continue an interrupted push of several files to the host, uploading only those that have
+    /// not been uploaded completely.
+    ///
+    /// Files are taken from a journal kept locally during every multi-file push. Files whose hash
+    /// is present on the remote site with the same size are skipped.
+    #[clap(
+        long = "continue",
+        conflicts_with_all = &["files", "alias", "bundle", "site", "prefix", "suffix", "if-newer"]
+    )]
+    resume: bool,
+
+    /// Remove local files once they have been uploaded and verified via hash (regardless of
+    /// `verify_strategy`), e.g. to move screenshots to the remote site.
+    ///
+    /// Files that are not uploaded because they are already present (see `--if-absent` and
+    /// `--if-newer`) are kept.
+    #[clap(long, conflicts_with_all = &["bundle", "dry-run"])]
+    delete_after_upload: bool,
+
+    /// Only print what would be uploaded (and how long it is estimated to take) without
+    /// uploading anything.
+    #[clap(long)]
+    dry_run: bool,
+
     /// Expire the uploaded file after the given amount of time via `at`-scheduled remote job.
     ///
     /// Select files newer than the given duration. Durations can be: seconds (sec, s), minutes
     /// (min, m), days (d), weeks (w), months (M) or years (y).
     ///
+    /// Alternatively, a calendar phrase expires the file at the end of the given day: `today`,
+    /// `tomorrow`, `next monday`, `end of week`, `end of month`, `end of year` or a date such as
+    /// `2024-12-24`.
+    ///
     /// Mininum time till expiration is a minute.
     ///
-    /// Any setting specified via command line overwrites settings from config files.
+    /// Any setting specified via command line overwrites settings from config files (`expire`
+    /// and `expire_by_type`).
     ///
     /// A globally set expiration setting can overwritten by specifying "none".
     #[clap(short, long)]
     expire: Option<String>,
 
-    /// File(s) to upload.
+    /// Extract uploaded archives (tar or zip) on the remote site into a folder named after the
+    /// archive (next to the archive itself) and print the URL of the contained `index.html`.
+    ///
+    /// Useful for sharing static sites. Requires `tar` (or `unzip` for zip-archives) to be
+    /// available on the remote site.
+    #[clap(short = 'x', long)]
+    extract: bool,
+
+    /// File(s) to upload, `-` uploads data read from stdin under the name given via `--alias`.
+    ///
+    /// Directories are archived (see `--archive`) or uploaded recursively (see `--recursive`)
+    /// depending on `push_directories` (default: `tar.gz`).
+    ///
+    /// Example: `tar cz dir | asfa push - --alias backup.tar.gz`
     #[clap()]
     files: Vec<PathBuf>,
 
+    /// Upload up to the given number of files concurrently (over separate channels of the same
+    /// connection), bounded by `max_parallel_transfers`.
+    #[clap(short, long, value_name = "N", default_value = "1")]
+    jobs: usize,
+
+    /// Only upload files whose hash is not yet present on the remote site. For files that are
+    /// already present, the existing URL is printed instead.
+    #[clap(long)]
+    if_absent: bool,
+
+    /// Only upload files if they were modified locally after the remote file with the same
+    /// alias/file name was uploaded. Outdated remote versions are removed after the upload.
+    /// If the remote file is up to date, its URL is printed instead.
+    ///
+    /// Useful for mutable artifacts that are re-shared under a stable name.
+    #[clap(long)]
+    if_newer: bool,
+
+    /// After uploading, create (or update) a symlink with the given name in the remote links
+    /// folder (defaults to the base folder) that points to the uploaded file. This provides a
+    /// stable URL that always refers to the most recently pushed version.
+    ///
+    /// Example: `--latest-link latest.pdf` makes the upload reachable under `<url>/latest.pdf`.
+    #[clap(long, value_name = "name")]
+    latest_link: Option<String>,
+
+    /// File name on the remote site for data read via `--stdin`.
+    #[clap(long, value_name = "name", requires = "stdin")]
+    name: Option<String>,
+
     /// Limit upload speed (in Mbit/s). Please note that the upload speed will be shown in
     /// {M,K}Bytes/s, but most internet providers specify upload speeds in Mbits/s. This option
     /// makes it easier to specify what portion of your available upload speed to use.
@@ -62,6 +204,14 @@ pub struct Push {
     )]
     limit_kbytes: Option<f64>,
 
+    /// If `clipboard` is set to true in config, --no-clipboard can be specified to not copy URLs.
+    #[clap(long, conflicts_with = "clipboard")]
+    no_clipboard: bool,
+
+    /// Do not verify the uploaded file, regardless of `verify_strategy`.
+    #[clap(long, conflicts_with_all = &["verify", "delete-after-upload"])]
+    no_verify: bool,
+
     /// Upload all files with the given prefix prepended.
     /// This is especially useful to give a bunch of files with generic names (e.g., plots) more
     /// context.
@@ -70,6 +220,47 @@ pub struct Push {
     #[clap(short, long, conflicts_with = "alias")]
     prefix: Option<String>,
 
+    /// Render each printed URL as QR code below it, e.g. to open the upload on a phone.
+    #[clap(long)]
+    qr: bool,
+
+    /// Hand out a separate URL to each given recipient (printed as `<recipient><TAB><url>`).
+    ///
+    /// Each recipient's URL points to a link named by a random token, so the webserver's access
+    /// logs reveal whose URL was used. Revoke single recipients via `clean --recipient`.
+    #[clap(
+        long,
+        value_name = "name",
+        conflicts_with_all = &["site", "extract", "recursive", "dry-run"]
+    )]
+    recipient: Vec<String>,
+
+    /// Upload directories by uploading all contained files (preserving their relative paths)
+    /// below a single hash folder, regardless of `push_directories`.
+    #[clap(long, conflicts_with_all = &["bundle", "site", "extract"])]
+    recursive: bool,
+
+    /// Print a shortened URL obtained from the configured `shortener` instead of the full URL.
+    ///
+    /// Both URLs are recorded locally so that pushing the same file again reuses the short URL.
+    #[clap(long)]
+    short: bool,
+
+    /// Upload the given folder as static site (preserving its structure) and print the URL of its
+    /// `index.html` (or of the folder itself if there is none).
+    ///
+    /// The folder is streamed as tar.gz-archive and extracted on the remote site (see `--bundle`
+    /// and `--extract`), so previews of e.g. generated documentation are a single command.
+    #[clap(
+        long,
+        value_name = "folder",
+        conflicts_with_all = &[
+            "files", "alias", "bundle", "prefix", "suffix", "if-absent", "if-newer", "latest-link",
+            "delete-after-upload"
+        ]
+    )]
+    site: Option<PathBuf>,
+
     /// Upload all files with the given suffix appended while not altering the file extension.
     /// This is especially useful to give a bunch of files with generic names (e.g., plots) more
     /// context.
@@ -79,81 +270,482 @@ pub struct Push {
     /// Example: `--suffix _bar` causes `foo.png` to be uploaded as `foo_bar.png`.
     #[clap(short, long, conflicts_with = "alias")]
     suffix: Option<String>,
+
+    /// Upload data read from stdin (e.g. an editor buffer) as a single file named via `--name`.
+    ///
+    /// Only the URL is printed on stdout, everything else goes to stderr, so that e.g.
+    /// `asfa push --stdin --name note.md --expire 1d --clipboard` can be used from editors (see also
+    /// `quickshare`).
+    #[clap(
+        long,
+        requires = "name",
+        conflicts_with_all = &["files", "alias", "bundle", "site", "resume", "prefix", "suffix", "if-absent", "if-newer", "delete-after-upload", "extract"]
+    )]
+    stdin: bool,
+
+    /// Which of the host's `url_variants` to print (`all` prints every variant, `default` the
+    /// main `url`). Defaults to the host's `url_variant`.
+    #[clap(long, value_name = "name")]
+    url_variant: Option<String>,
+
+    /// Hash the uploaded file on the remote site to verify it, regardless of `verify_strategy`.
+    #[clap(long)]
+    verify: bool,
+
+    /// URLs printed so far, copied to the clipboard once done if requested.
+    #[clap(skip)]
+    printed: Mutex<Vec<String>>,
+
+    /// Serializes updates of the host's recipient links when uploading concurrently.
+    #[clap(skip)]
+    recipients_lock: Mutex<()>,
 }
 
-impl Push {
+/// Local data to upload.
+enum Source<'a> {
+    /// A single local file.
+    File(&'a Path),
+
+    /// Several local files and folders archived on the fly.
+    Bundle(&'a Bundle),
+
+    /// Data read from stdin, spooled to a local temporary file.
+    Stdin(&'a Spool),
+
+    /// A single local file, compressed to a local temporary file (see `--compress`).
+    Compressed(&'a Path, &'a Spool),
+}
+
+impl<'a> Source<'a> {
+    fn hash(&self, prefix_length: u8) -> Result<String> {
+        match self {
+            Source::File(path) | Source::Compressed(path, _) => get_hash(path, prefix_length)
+                .with_context(|| format!("Could not read {} to compute hash.", path.display())),
+            Source::Bundle(bundle) => Ok(bundle.hash().to_string()),
+            Source::Stdin(spool) => Ok(spool.hash().to_string()),
+        }
+    }
+
+    /// Hash of the uploaded bytes, if it differs from `hash` (which names the folder).
+    fn transferred_hash(&self) -> Option<&str> {
+        match self {
+            Source::Compressed(_, spool) => Some(spool.hash()),
+            _ => None,
+        }
+    }
+
+    fn size(&self) -> Result<u64> {
+        match self {
+            Source::File(path) => Ok(path
+                .metadata()
+                .with_context(|| format!("Could not get metadata of {}", path.display()))?
+                .len()),
+            Source::Bundle(bundle) => Ok(bundle.size()),
+            Source::Stdin(spool) | Source::Compressed(_, spool) => Ok(spool.size()),
+        }
+    }
+
+    /// Upload to the given target and return the hash of the uploaded bytes.
     fn upload(
+        &self,
+        session: &SshSession,
+        target: &Path,
+        limit_speed_bytes_per_second: Option<usize>,
+    ) -> Result<String> {
+        match self {
+            Source::File(path) => session.upload_file(path, target, limit_speed_bytes_per_second),
+            Source::Bundle(bundle) => session.upload_reader(
+                bundle.reader(),
+                bundle.size(),
+                target,
+                limit_speed_bytes_per_second,
+            ),
+            Source::Stdin(spool) | Source::Compressed(_, spool) => {
+                session.upload_file(spool.path(), target, limit_speed_bytes_per_second)
+            }
+        }
+    }
+}
+
+impl<'a> std::fmt::Display for Source<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Source::File(path) | Source::Compressed(path, _) => write!(f, "{}", path.display()),
+            Source::Bundle(_) => write!(f, "bundle"),
+            Source::Stdin(_) => write!(f, "stdin"),
+        }
+    }
+}
+
+/// Split the name of an archive into the name without archive extension and the tool used to
+/// extract it. Returns None if the file is no supported archive.
+fn split_archive_name(name: &str) -> Option<(&str, &'static str)> {
+    const TAR_EXTENSIONS: [&str; 9] = [
+        ".tar.gz", ".tgz", ".tar.bz2", ".tbz2", ".tar.xz", ".txz", ".tar.zst", ".tzst", ".tar",
+    ];
+    let split = match name.strip_suffix(".zip") {
+        Some(stem) => Some((stem, "unzip")),
+        None => TAR_EXTENSIONS
+            .iter()
+            .find_map(|ext| name.strip_suffix(ext))
+            .map(|stem| (stem, "tar")),
+    };
+    split.filter(|(stem, _)| !stem.is_empty())
+}
+
+/// Transliterate the given file name to ASCII, never introducing path separators.
+fn to_ascii(name: &str) -> String {
+    deunicode::deunicode(name).replace('/', "_")
+}
+
+/// Remote copies of a file that is to be uploaded under a given alias.
+enum RemoteCopies<'a> {
+    /// There is a remote copy that is at least as recent as the local file.
+    UpToDate(&'a Path),
+
+    /// All remote copies (if any) are older than the local file.
+    Outdated(Vec<&'a Path>),
+}
+
+impl Push {
+    /// How to verify uploaded files.
+    fn verify_strategy(&self, session: &SshSession) -> VerifyStrategy {
+        if self.verify || self.delete_after_upload {
+            VerifyStrategy::Hash
+        } else if self.no_verify {
+            VerifyStrategy::Off
+        } else {
+            session.host.verify_strategy
+        }
+    }
+
+    /// Find remote files uploaded under the given alias and compare their modification time with
+    /// the local file.
+    fn remote_copies<'a>(
+        &self,
+        listing: &'a FileListing,
+        to_upload: &Path,
+        alias: &str,
+    ) -> Result<RemoteCopies<'a>> {
+        let mtime_local = std::fs::metadata(to_upload)
+            .and_then(|m| m.modified())
+            .with_context(|| format!("Could not get mtime of {}", to_upload.display()))?
+            .duration_since(UNIX_EPOCH)?
+            .as_secs();
+
+        let copies: Vec<_> = listing
+            .iter()
+            .filter(|(_, path, _)| path.file_name().map(|n| n == alias).unwrap_or(false))
+            .collect();
+
+        for (_, path, stat) in copies.iter() {
+            let mtime_remote = stat
+                .and_then(|s| s.mtime)
+                .with_context(|| format!("No mtime for remote file {}", path.display()))?;
+            if mtime_remote >= mtime_local {
+                return Ok(RemoteCopies::UpToDate(path));
+            }
+        }
+        Ok(RemoteCopies::Outdated(
+            copies.into_iter().map(|(_, path, _)| path).collect(),
+        ))
+    }
+
+    /// Only keep files present on the remote site whose remote copy has the same size as the local
+    /// file.
+    fn completely_uploaded(
+        &self,
+        listing: &FileListing,
+        files: &[PathBuf],
+        present: Vec<Option<usize>>,
+    ) -> Result<Vec<Option<usize>>> {
+        files
+            .iter()
+            .zip(present)
+            .map(|(file, present)| {
+                let idx = match present {
+                    Some(idx) => idx,
+                    None => return Ok(None),
+                };
+                let size_local = file
+                    .metadata()
+                    .with_context(|| format!("Could not get metadata of {}", file.display()))?
+                    .len();
+                let size_remote = listing
+                    .iter()
+                    .find(|(i, _, _)| *i == idx)
+                    .and_then(|(_, _, stat)| stat.and_then(|s| s.size));
+                Ok((size_remote == Some(size_local)).then_some(idx))
+            })
+            .collect()
+    }
+
+    /// Create links for the recipients given via `--recipient` (if any) to the given remote file
+    /// and print their URLs.
+    fn link_recipients(&self, session: &SshSession, remote: &Path) -> Result<()> {
+        if self.recipient.is_empty() {
+            return Ok(());
+        }
+        let folder = remote
+            .parent()
+            .with_context(|| format!("File had no parent: {}", remote.display()))?;
+        let name = remote
+            .file_name()
+            .with_context(|| format!("Invalid remote file: {}", remote.display()))?;
+        // The trailing slash makes sure the links are removed along with the upload.
+        let target = PathBuf::from(format!("{}/", folder.display()));
+
+        let _lock = self.recipients_lock.lock().unwrap();
+        session.update_recipient_links(|links| {
+            for recipient in self.recipient.iter() {
+                let link = RecipientLink {
+                    token: util::random_token(session.host.prefix_length),
+                    recipient: recipient.clone(),
+                };
+                session.symlink(&target, &link.path(session.host))?;
+                for url in session.host.get_urls(
+                    &format!("{}", link.path(session.host).join(name).display()),
+                    self.url_variant.as_deref(),
+                )? {
+                    println!("{}\t{}", recipient, url);
+                }
+                links.push(link);
+            }
+            Ok(())
+        })
+    }
+
+    /// Point the link given via `--latest-link` (if any) to the given remote file.
+    fn update_latest_link(&self, session: &SshSession, remote: &Path) -> Result<()> {
+        if let (Some(link), false) = (self.latest_link.as_deref(), self.dry_run) {
+            let link = session.host.links_folder.join(link);
+            session.symlink(remote, &link)?;
+            for url in session
+                .host
+                .get_urls(&format!("{}", link.display()), self.url_variant.as_deref())?
+            {
+                println!("{}", url);
+            }
+        }
+        Ok(())
+    }
+
+    /// Print the URL of an already existing remote file instead of uploading.
+    fn skip_upload(&self, session: &SshSession, to_upload: &Path, existing: &Path) -> Result<()> {
+        debug!(
+            "Skipping upload of {}, already present: {}",
+            to_upload.display(),
+            existing.display()
+        );
+        self.print_url(session, existing)?;
+        Ok(())
+    }
+
+    /// Push a single file, honoring `--if-absent` and `--if-newer`.
+    ///
+    /// Returns the remote path (relative to base folder) of either the uploaded or the already
+    /// present file.
+    fn push_file(
         &self,
         session: &SshSession,
         config: &Config,
+        listing: Option<&FileListing>,
+        present: Option<usize>,
         to_upload: &Path,
+        alias: &str,
+    ) -> Result<PathBuf> {
+        if to_upload.is_dir() {
+            return self.push_directory(session, config, to_upload, alias);
+        }
+        if let Some(existing) = present.and_then(|idx| listing.and_then(|l| l.path_of(idx))) {
+            self.skip_upload(session, to_upload, existing)?;
+            return Ok(existing.to_path_buf());
+        }
+        let alias = match self.compress {
+            Some(compression) => format!("{}{}", alias, compression.extension()),
+            None => alias.to_string(),
+        };
+
+        let outdated = match listing {
+            Some(listing) if self.if_newer => {
+                match self.remote_copies(listing, to_upload, &alias)? {
+                    RemoteCopies::UpToDate(existing) => {
+                        self.skip_upload(session, to_upload, existing)?;
+                        return Ok(existing.to_path_buf());
+                    }
+                    RemoteCopies::Outdated(outdated) => outdated,
+                }
+            }
+            _ => vec![],
+        };
+
+        let spool = self
+            .compress
+            .map(|compression| compression.compress(to_upload, session.host.prefix_length))
+            .transpose()?;
+        let source = match spool.as_ref() {
+            Some(spool) => Source::Compressed(to_upload, spool),
+            None => Source::File(to_upload),
+        };
+        let target = self.upload(session, config, &source, &alias)?;
+
+        for old in outdated.into_iter().filter(|_| !self.dry_run) {
+            let old_folder = old
+                .parent()
+                .with_context(|| format!("File had no parent: {}", old.display()))?;
+            if Some(old_folder) != target.parent() {
+                debug!("Removing outdated version: {}", old.display());
+                session.remove_folder(old_folder)?;
+            }
+        }
+
+        if self.delete_after_upload {
+            debug!("Removing local file: {}", to_upload.display());
+            std::fs::remove_file(to_upload)
+                .with_context(|| format!("Could not remove {}", to_upload.display()))?;
+        }
+        Ok(target)
+    }
+
+    /// Upload the given source and return the remote path (relative to base folder).
+    fn upload(
+        &self,
+        session: &SshSession,
+        config: &Config,
+        to_upload: &Source,
         target_name: &str,
-    ) -> Result<()> {
+    ) -> Result<PathBuf> {
+        let start = Instant::now();
         let mut target = PathBuf::new();
         let prefix_length = session.host.prefix_length;
-        let hash = get_hash(to_upload, prefix_length)
-            .with_context(|| format!("Could not read {} to compute hash.", to_upload.display()))?;
+        let hash = to_upload.hash(prefix_length)?;
+        let transferred_hash = to_upload
+            .transferred_hash()
+            .map(str::to_string)
+            .unwrap_or_else(|| hash.clone());
 
-        let expirer = if let Some(delay) = self
-            .expire
-            .as_ref()
-            .or_else(|| session.host.expire.as_ref())
-        {
-            // Allow for explicit disabling term that overwrites a possibly set default
-            if ["no", "none", "disabled", "false"].contains(&delay.as_str()) {
-                None
-            } else {
-                Some(At::new(session, &delay)?)
-            }
-        } else {
-            None
-        };
+        let expirer = self.expirer(session, target_name)?;
 
         target.push(&hash);
         let folder = target.clone();
-        session.make_folder(&folder)?;
-
         target.push(target_name);
 
+        let size = to_upload.size()?;
+        let limit = self.limit();
+        // Recorded throughput only reflects unlimited uploads, limited uploads are estimated from
+        // the limit itself.
+        let current_limit = limit.or_else(|| session.host.limit_schedule.current_limit());
+        let unlimited = current_limit.is_none();
+        let estimate = match current_limit {
+            Some(limit) => Some(Duration::from_secs(size / limit.max(1) as u64)),
+            None => Throughput::load(&session.host.alias)?.estimate(size),
+        };
+        // Printed on stderr, stdout only carries the URLs.
+        if let (Some(estimate), false) = (estimate, config.is_silent()) {
+            progress_header(&format!(
+                "[{}] Estimated upload duration: {}",
+                to_upload,
+                humantime::format_duration(Duration::from_secs(estimate.as_secs()))
+            ));
+        }
+
+        if self.dry_run {
+            println!(
+                "{} → {}",
+                to_upload,
+                session.host.get_url(&format!("{}", target.display()))?
+            );
+            return Ok(target);
+        }
+
+        let group_inherited = session.make_upload_folder(&folder)?;
+
+        // The URL only depends on the hash, so it can already be shared during the upload. When
+        // shortening, the short URL is recorded and reused when printing it afterwards.
+        if !self.extract && self.site.is_none() && !config.is_silent() {
+            for url in session.host.get_urls(
+                &format!("{}", target.display()),
+                self.url_variant.as_deref(),
+            )? {
+                let url = if self.short {
+                    self.shorten(session, url)?
+                } else {
+                    url
+                };
+                progress_header(&format!("{} {}", color::frame.apply_to("→"), url));
+            }
+        }
+
         // TODO: Maybe check if file exists already.
-        session.upload_file(
-            &to_upload,
-            &target,
-            self.limit_mbits
-                .map(|f| {
-                    (f * 1024.0 /* mega */ * 1024.0/* kilo */ / 8.0/* bit -> bytes */) as usize
-                })
-                .or_else(|| {
-                    self.limit_kbytes.map(|f| {
-                        (f * 1024.0/* kilo */) as usize
-                    })
-                }),
-        )?;
+        let start_transfer = Instant::now();
+        let uploaded_hash = to_upload.upload(session, &target, limit)?;
+        if unlimited {
+            Throughput::record(&session.host.alias, size, start_transfer.elapsed());
+        }
 
-        if config.verify_via_hash {
-            debug!("Verifying upload..");
-            let spinner = WaitingSpinner::new("Verifying upload..".to_string());
+        // The bytes sent have to match the hash already included in the URL, otherwise the local
+        // file changed or could not be read correctly during the upload.
+        if uploaded_hash != transferred_hash {
+            session.remove_folder(&folder)?;
+            bail!(
+                "[{}] Uploaded data does not match local hash (file modified during upload?): \
+                local={} uploaded={}",
+                to_upload,
+                transferred_hash,
+                uploaded_hash
+            );
+        }
 
-            let remote_hash = session.get_remote_hash(&target, prefix_length)?;
-            if hash != remote_hash {
-                session.remove_folder(&folder)?;
-                bail!(
-                    "[{}] Hashes differ: local={} remote={}",
-                    to_upload.display(),
-                    hash,
-                    remote_hash
-                );
+        match self.verify_strategy(session) {
+            // Hashing the uploaded file needs remote commands.
+            VerifyStrategy::Hash if session.host.exec => {
+                debug!("Verifying upload..");
+                let spinner = WaitingSpinner::new("Verifying upload..".to_string());
+
+                let remote_hash = session.get_remote_hash(&target, prefix_length)?;
+                if transferred_hash != remote_hash {
+                    session.remove_folder(&folder)?;
+                    bail!(
+                        "[{}] Hashes differ: local={} remote={}",
+                        to_upload,
+                        transferred_hash,
+                        remote_hash
+                    );
+                }
+                spinner.finish();
+                debug!("Done");
+            }
+            VerifyStrategy::Size => {
+                let remote_size = session.stat_single(&target)?.size.unwrap_or_default();
+                if size != remote_size {
+                    session.remove_folder(&folder)?;
+                    bail!(
+                        "[{}] Sizes differ: local={} remote={}",
+                        to_upload,
+                        size,
+                        remote_size
+                    );
+                }
             }
-            spinner.finish();
-            debug!("Done");
+            VerifyStrategy::Hash | VerifyStrategy::Off => {}
         }
 
-        if let Some(group) = &session.host.group {
-            session.adjust_group(&folder, &group)?;
+        let (extracted, url_path) = if self.extract || self.site.is_some() {
+            let (extracted, url_path) = self.extract_remote(session, &target)?;
+            (vec![extracted], url_path)
+        } else {
+            (vec![], format!("{}/{}", &hash, &target_name))
+        };
+
+        if let (false, Some(group)) = (group_inherited, &session.host.group) {
+            // The upload itself succeeded, so only report that the webserver might not serve it.
+            if let Err(e) = session.adjust_group(&folder, group) {
+                warn!("{} The webserver might not be able to serve the upload.", e);
+            }
         };
 
         let expiration_date = if let Some(expirer) = expirer {
-            Some(expirer.expire(&target)?)
+            Some(expirer.expire(&target, &extracted)?.date)
         } else {
             None
         };
@@ -167,17 +759,360 @@ impl Push {
                 date = color::expire.apply_to(expiration_date.to_rfc2822())
             );
         }
-        println!(
-            "{}",
+        let url = self.print_url(session, Path::new(&url_path))?;
+        cache::update(&session.host.alias, |c| c.add(&target));
+        metrics::uploaded(size);
+
+        if let Some(date) = expiration_date {
+            cache::Expirations::record(
+                &session.host.alias,
+                cache::Expiration {
+                    date,
+                    path: target.clone(),
+                    url: url.clone(),
+                },
+            );
+            if let Some(before) = config.expire_reminder {
+                self.remind_expiration(date, before, &url);
+            }
+        }
+
+        if let Some(notify_after) = config.notify_after {
+            if start.elapsed() > notify_after {
+                notify::notify(&format!("Uploaded {}", to_upload), &url);
+            }
+        }
+
+        Ok(target)
+    }
+
+    /// Expiration to apply to an upload with the given name: `--expire`, `expire_by_type` or
+    /// `expire` from config.
+    fn expirer<'a>(&self, session: &'a SshSession, target_name: &str) -> Result<Option<At<'a>>> {
+        if let Some(delay) = self.expire.as_deref().or_else(|| {
             session
                 .host
-                .get_url(&format!("{}/{}", &hash, &target_name))?,
+                .expire_by_type
+                .expire_for(Path::new(target_name))
+                .or(session.host.expire.as_deref())
+        }) {
+            // Allow for explicit disabling term that overwrites a possibly set default
+            if at::DISABLED.contains(&delay) {
+                Ok(None)
+            } else {
+                Ok(Some(At::new(session, delay)?))
+            }
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Upload speed limit (in bytes/s) given via `--limit-mbits` or `--limit-kbytes`.
+    fn limit(&self) -> Option<usize> {
+        self.limit_mbits
+            .map(|f| (f * 1024.0 /* mega */ * 1024.0/* kilo */ / 8.0/* bit -> bytes */) as usize)
+            .or_else(|| {
+                self.limit_kbytes.map(|f| {
+                    (f * 1024.0/* kilo */) as usize
+                })
+            })
+    }
+
+    /// How to upload directories.
+    fn directory_mode(&self, session: &SshSession) -> DirectoryMode {
+        if let Some(format) = self.archive {
+            DirectoryMode::Archive(format)
+        } else if self.recursive {
+            DirectoryMode::Recursive
+        } else {
+            session.host.push_directories
+        }
+    }
+
+    /// Push a local directory, either as archive or recursively.
+    ///
+    /// Returns the remote path (relative to base folder) of the archive or folder.
+    fn push_directory(
+        &self,
+        session: &SshSession,
+        config: &Config,
+        dir: &Path,
+        alias: &str,
+    ) -> Result<PathBuf> {
+        // Make sure the archive contains the folder by name even for e.g. `.`.
+        let dir = dir
+            .canonicalize()
+            .with_context(|| format!("Could not resolve {}", dir.display()))?;
+        match self.directory_mode(session) {
+            DirectoryMode::Archive(ArchiveFormat::TarGz) => {
+                let bundle = Bundle::new(&[dir], session.host.prefix_length)?;
+                let name = format!("{}{}", alias, ArchiveFormat::TarGz.extension());
+                self.upload(session, config, &Source::Bundle(&bundle), &name)
+            }
+            DirectoryMode::Archive(ArchiveFormat::Zip) => {
+                let name = format!("{}{}", alias, ArchiveFormat::Zip.extension());
+                let (archive, file) = TempFile::create()?;
+                bundle::write_zip(&[dir], file)
+                    .with_context(|| format!("Could not create archive: {}", name))?;
+                self.upload(session, config, &Source::File(archive.path()), &name)
+            }
+            DirectoryMode::Recursive => self.upload_recursively(session, config, &dir, alias),
+        }
+    }
+
+    /// Upload all files in the given directory below a single hash folder (preserving their
+    /// relative paths) and return the remote folder (relative to base folder).
+    ///
+    /// The hash folder is named after the manifest of all files (see `bundle::manifest`), which
+    /// is uploaded last as `<hash>/<alias>.files` and represents the upload in listings.
+    fn upload_recursively(
+        &self,
+        session: &SshSession,
+        config: &Config,
+        dir: &Path,
+        alias: &str,
+    ) -> Result<PathBuf> {
+        let prefix_length = session.host.prefix_length;
+        let files = bundle::files_recursively(dir)?;
+        if files.is_empty() {
+            bail!("Directory to upload is empty: {}", dir.display());
+        }
+        let manifest = bundle::manifest(&files, prefix_length)?;
+        let hash = util::get_hash_from(manifest.as_bytes(), prefix_length)?;
+        let folder = PathBuf::from(&hash);
+        let root = folder.join(alias);
+        let index = folder.join(format!("{}.files", alias));
+        let url_path = format!("{}/", root.display());
+
+        let expirer = self.expirer(session, alias)?;
+
+        if self.dry_run {
+            println!("{} → {}", dir.display(), session.host.get_url(&url_path)?);
+            return Ok(root);
+        }
+
+        let group_inherited = session.make_upload_folder(&folder)?;
+        let limit = self.limit();
+        for (local, relative) in files.iter() {
+            let target = root.join(relative);
+            if let Some(parent) = target.parent() {
+                session.make_folder(parent)?;
+            }
+            let uploaded_hash = session.upload_file(local, &target, limit)?;
+            let local_hash = get_hash(local, prefix_length)?;
+            if uploaded_hash != local_hash {
+                session.remove_folder(&folder)?;
+                bail!(
+                    "[{}] Uploaded data does not match local hash (file modified during \
+                    upload?): local={} uploaded={}",
+                    local.display(),
+                    local_hash,
+                    uploaded_hash
+                );
+            }
+            metrics::uploaded(local.metadata()?.len());
+        }
+        session.upload_reader(manifest.as_bytes(), manifest.len() as u64, &index, limit)?;
+        cache::update(&session.host.alias, |c| c.add(&index));
+
+        if let (false, Some(group)) = (group_inherited, &session.host.group) {
+            if let Err(e) = session.adjust_group(&folder, group) {
+                warn!("{} The webserver might not be able to serve the upload.", e);
+            }
+        };
+
+        let expiration_date = match expirer {
+            Some(expirer) => Some(expirer.expire(&index, std::slice::from_ref(&root))?.date),
+            None => None,
+        };
+        let url = self.print_url(session, Path::new(&url_path))?;
+        if let Some(date) = expiration_date {
+            cache::Expirations::record(
+                &session.host.alias,
+                cache::Expiration {
+                    date,
+                    path: index,
+                    url: url.clone(),
+                },
+            );
+            if let Some(before) = config.expire_reminder {
+                self.remind_expiration(date, before, &url);
+            }
+        }
+        Ok(root)
+    }
+
+    /// Show a desktop notification the given duration before the upload with the given URL
+    /// expires on `date`.
+    ///
+    /// Failing to schedule the notification is never fatal, it is only reported.
+    fn remind_expiration(&self, date: DateTime<Local>, before: Duration, url: &str) {
+        let remind_at =
+            date - chrono::Duration::from_std(before).unwrap_or_else(|_| chrono::Duration::zero());
+        if remind_at <= Local::now() {
+            debug!("Expiration is too close for a reminder.");
+            return;
+        }
+        let summary = format!(
+            "Expiring in {}",
+            humantime::format_duration(Duration::from_secs(before.as_secs()))
         );
+        if let Err(e) = notify::schedule(remind_at, &summary, url) {
+            warn!(
+                "Could not schedule expiration reminder (see `asfa expire --upcoming`): {:#}",
+                e
+            );
+        }
+    }
+
+    /// Print the URL(s) of the given remote file in the selected variant(s) (shortened and
+    /// followed by its QR code and share code if requested) and return the first printed URL.
+    fn print_url(&self, session: &SshSession, remote: &Path) -> Result<String> {
+        let mut printed = Vec::new();
+        for url in session.host.get_urls(
+            &format!("{}", remote.display()),
+            self.url_variant.as_deref(),
+        )? {
+            let url = if self.short {
+                self.shorten(session, url)?
+            } else {
+                url
+            };
+            printed.push(url);
+        }
+        self.write_urls(&mut io::stdout().lock(), &printed, remote)?;
+        self.printed.lock().unwrap().extend(printed.iter().cloned());
+        Ok(printed.swap_remove(0))
+    }
+
+    /// Write the given URLs of the given remote file (followed by their QR codes and the share
+    /// code if requested), this is all that is printed on stdout for each upload.
+    fn write_urls(&self, out: &mut dyn Write, urls: &[String], remote: &Path) -> Result<()> {
+        for url in urls.iter() {
+            writeln!(out, "{}", url)?;
+            if self.qr {
+                writeln!(out, "{}", qr::render(url)?)?;
+            }
+        }
+        if self.code {
+            if let Some(code) = share_code::of_file(remote) {
+                writeln!(out, "{}", code)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Shorten the given URL via the configured shortener (or reuse a previously shortened URL).
+    fn shorten(&self, session: &SshSession, url: String) -> Result<String> {
+        let shortener = session
+            .host
+            .shortener
+            .as_ref()
+            .context("No 'shortener' configured.")?;
+        if let Some(short) = ShortUrls::load(&session.host.alias)?.get(&url) {
+            debug!("Reusing short URL for {}", url);
+            return Ok(short.to_string());
+        }
+        let short = shortener.shorten(&url)?;
+        debug!("{} → {}", short, url);
+        ShortUrls::record(&session.host.alias, &url, &short);
+        Ok(short)
+    }
+
+    /// Upload the contents of the given folder as static site.
+    fn push_site(&self, session: &SshSession, config: &Config, site: &Path) -> Result<()> {
+        if !site.is_dir() {
+            bail!("Site to upload is no folder: {}", site.display());
+        }
+        if !site.join("index.html").is_file() {
+            warn!("{} contains no index.html.", site.display());
+        }
+        let name = site
+            .canonicalize()?
+            .file_name()
+            .map(|n| format!("{}.tar.gz", n.to_string_lossy()))
+            .with_context(|| format!("{} has no filename.", site.display()))?;
+        let name = if self.ascii { to_ascii(&name) } else { name };
 
+        // Bundle the contents of the folder so that they end up at the root of the extracted site.
+        let mut contents = read_dir(site)?
+            .map(|e| e.map(|e| e.path()))
+            .collect::<io::Result<Vec<_>>>()?;
+        contents.sort();
+        if contents.is_empty() {
+            bail!("Site to upload is empty: {}", site.display());
+        }
+        let bundle = Bundle::new(&contents, session.host.prefix_length)?;
+        self.upload(session, config, &Source::Bundle(&bundle), &name)?;
         Ok(())
     }
 
+    /// Extract the given remote archive (relative to base folder) into a sibling folder named
+    /// after the archive.
+    ///
+    /// Returns the folder the archive was extracted to as well as the path to print (pointing to
+    /// `index.html` if present), both relative to the base folder.
+    fn extract_remote(&self, session: &SshSession, archive: &Path) -> Result<(PathBuf, String)> {
+        let name = archive
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .with_context(|| format!("Invalid archive name: {}", archive.display()))?;
+        let (stem, extractor) = split_archive_name(&name)
+            .with_context(|| format!("Cannot extract {}: Unknown archive type.", name))?;
+
+        let extracted = archive.with_file_name(stem);
+        let extracted_full = session.prepend_base_folder(&extracted);
+        let archive_full = session.prepend_base_folder(archive);
+
+        debug!("Extracting remote archive to: {}", extracted_full.display());
+        let spinner = WaitingSpinner::new("Extracting archive..".to_string());
+        let dir = util::shell_quote(&extracted_full.to_string_lossy());
+        let archive_quoted = util::shell_quote(&archive_full.to_string_lossy());
+        let cmd = match extractor {
+            "unzip" => format!(
+                "mkdir -p {dir} && unzip -q -o {archive} -d {dir}",
+                dir = dir,
+                archive = archive_quoted
+            ),
+            _ => format!(
+                "mkdir -p {dir} && tar -xf {archive} -C {dir}",
+                dir = dir,
+                archive = archive_quoted
+            ),
+        };
+        session.exec_remote(&cmd)?.expect_with(|rc| match rc {
+            127 => format!("{} not found on remote site.", extractor),
+            _ => format!("Could not extract {}.", name),
+        })?;
+        spinner.finish();
+
+        let index = extracted_full.join("index.html");
+        let has_index = session
+            .exec_remote(&format!(
+                "[ -f {} ]",
+                util::shell_quote(&index.to_string_lossy())
+            ))?
+            .exit_status()
+            == 0;
+        let url_path = if has_index {
+            format!("{}/index.html", extracted.display())
+        } else {
+            format!("{}/", extracted.display())
+        };
+        Ok((extracted, url_path))
+    }
+
     fn transform_filename(&self, file: &Path) -> Result<String> {
+        // Directories such as `.` are named after the folder they resolve to.
+        let resolved;
+        let file = if file.is_dir() {
+            resolved = file
+                .canonicalize()
+                .with_context(|| format!("Could not resolve {}", file.display()))?;
+            &resolved
+        } else {
+            file
+        };
         let stem = file
             .file_stem()
             .with_context(|| format!("{} has no filename.", file.display()))?
@@ -199,12 +1134,137 @@ impl Push {
 
 impl Command for Push {
     fn run(&self, session: &SshSession, config: &Config) -> Result<()> {
-        let (files, aliases) = {
+        self.push(session, config)?;
+        if config.copy_to_clipboard(self.clipboard, self.no_clipboard) && !self.dry_run {
+            clipboard::copy_urls(&self.printed.lock().unwrap());
+        }
+        Ok(())
+    }
+}
+
+impl Push {
+    /// Push data read from stdin (with the given expiration, if any) and copy the URL to the
+    /// clipboard, see `quickshare`.
+    pub fn stdin(name: String, expire: Option<String>) -> Self {
+        Self {
+            clipboard: true,
+            expire,
+            name: Some(name),
+            stdin: true,
+            ..Self::default()
+        }
+    }
+
+    /// Local files to upload.
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    /// Remote file name of data to read from stdin (via `--stdin` or `-`), if any.
+    fn stdin_name(&self) -> Result<Option<&str>> {
+        if self.stdin {
+            return Ok(Some(
+                self.name.as_deref().context("--stdin requires --name.")?,
+            ));
+        }
+        // clap cannot tell apart `--alias <alias> -` from two aliases (see `push`).
+        let name = match (&self.files[..], &self.alias[..]) {
+            ([file], [alias]) if file.as_os_str() == "-" => alias,
+            ([], [alias, file]) if file == "-" => alias,
+            (files, _) if !files.iter().any(|f| f.as_os_str() == "-") => return Ok(None),
+            ([_], _) => bail!(
+                "Specify the file name on the remote site via --alias when uploading from stdin \
+                (`-`)."
+            ),
+            _ => bail!("Data read from stdin (`-`) can only be uploaded on its own."),
+        };
+        if self.bundle.is_some() || self.site.is_some() {
+            bail!("Data read from stdin (`-`) can only be uploaded on its own.");
+        }
+        if self.resume
+            || self.if_absent
+            || self.if_newer
+            || self.delete_after_upload
+            || self.compress.is_some()
+        {
+            bail!(
+                "--resume, --if-absent, --if-newer, --delete-after-upload and --compress need a \
+                local file, not data read from stdin (`-`)."
+            );
+        }
+        Ok(Some(name))
+    }
+
+    fn push(&self, session: &SshSession, config: &Config) -> Result<()> {
+        session.host.layout.require_default(&session.host.alias)?;
+        // Recipients are stored tab-separated, one per line.
+        if let Some(recipient) = self
+            .recipient
+            .iter()
+            .find(|r| r.contains(['\t', '\n', '\r']))
+        {
+            bail!(
+                "Invalid recipient (contains tabs or line breaks): {:?}",
+                recipient
+            );
+        }
+        if self.short && session.host.shortener.is_none() {
+            bail!("--short requires a 'shortener' to be configured.");
+        }
+        // Fail early on unknown URL variants.
+        session.host.url_prefixes(self.url_variant.as_deref())?;
+        if self.delete_after_upload && !session.host.exec {
+            bail!(
+                "--delete-after-upload requires verifying uploads via remote commands, which \
+                host '{}' does not allow (`exec: false`).",
+                session.host.alias
+            );
+        }
+        if self.verify && !session.host.exec {
+            bail!(
+                "--verify requires remote commands, which host '{}' does not allow (`exec: false`).",
+                session.host.alias
+            );
+        }
+
+        if let Some(site) = self.site.as_deref() {
+            return self.push_site(session, config, site);
+        }
+
+        if let Some(name) = self.stdin_name()? {
+            let name = if self.ascii {
+                to_ascii(name)
+            } else {
+                name.to_string()
+            };
+            let spool = Spool::new(io::stdin().lock(), session.host.prefix_length)
+                .context("Could not read from stdin.")?;
+            let remote = self.upload(session, config, &Source::Stdin(&spool), &name)?;
+            self.link_recipients(session, &remote)?;
+            return self.update_latest_link(session, &remote);
+        }
+
+        if let Some(name) = self.bundle.as_deref() {
+            let bundle = Bundle::new(&self.files, session.host.prefix_length)?;
+            let remote = self.upload(session, config, &Source::Bundle(&bundle), name)?;
+            self.link_recipients(session, &remote)?;
+            return self.update_latest_link(session, &remote);
+        }
+
+        let (files, aliases) = if self.resume {
+            match PushJournal::pending(&session.host.alias)? {
+                Some(pending) if !pending.is_empty() => pending.into_iter().unzip(),
+                _ => bail!(
+                    "No interrupted push to continue for host '{}'.",
+                    session.host.alias
+                ),
+            }
+        } else {
             let mut aliases: Vec<String> = vec![];
             let mut files: Vec<PathBuf> = vec![];
 
             if self.files.is_empty() && self.alias.is_empty() {
-                bail!("No files to upload specified.");
+                bail!(tr!("push.no-files"));
             } else if self.files.is_empty() && !self.alias.is_empty() {
                 if self.alias.len() == 2 {
                     // The other specified `asfa push --alias <alias> <file>`, clap is not able to
@@ -228,6 +1288,9 @@ impl Command for Push {
                 aliases = self.alias.clone();
                 files = self.files.clone();
             }
+            if self.ascii {
+                aliases = aliases.iter().map(|a| to_ascii(a)).collect();
+            }
             (files, aliases)
         };
 
@@ -238,10 +1301,149 @@ impl Command for Push {
             debug!("Limiting upload to {} kByte/s", limit);
         }
 
-        for (to_upload, alias) in files.iter().zip(aliases.iter()) {
-            self.upload(session, config, to_upload, alias)?;
+        let listing = if self.if_absent || self.if_newer || self.resume {
+            Some(
+                session
+                    .list_files()?
+                    .with_all(self.if_newer || self.resume)
+                    .with_stats(self.if_newer || self.resume)?,
+            )
+        } else {
+            None
+        };
+        if (self.if_newer || self.delete_after_upload || self.compress.is_some())
+            && files.iter().any(|f| f.is_dir())
+        {
+            bail!(
+                "--if-newer, --delete-after-upload and --compress cannot be used to upload \
+                directories."
+            );
+        }
+
+        let present = match listing.as_ref() {
+            Some(listing) if self.if_absent || self.resume => {
+                // Directories are always uploaded.
+                let regular: Vec<PathBuf> = files.iter().filter(|f| !f.is_dir()).cloned().collect();
+                let present = listing.indices_by_hash(
+                    regular.iter().map(|f| f.to_string_lossy()),
+                    session.host.prefix_length,
+                )?;
+                let mut present = if self.resume {
+                    // The upload of a file might have been interrupted halfway.
+                    self.completely_uploaded(listing, &regular, present)?
+                } else {
+                    present
+                }
+                .into_iter();
+                files
+                    .iter()
+                    .map(|f| {
+                        if f.is_dir() {
+                            None
+                        } else {
+                            present.next().flatten()
+                        }
+                    })
+                    .collect()
+            }
+            _ => vec![None; files.len()],
+        };
+
+        if self.latest_link.is_some() && files.len() != 1 {
+            bail!("--latest-link can only be used when uploading a single file.");
+        }
+
+        // Keep track of multi-file pushes so that they can be continued if interrupted.
+        let journal = (files.len() > 1 || self.resume) && !self.dry_run;
+        let files: Vec<PathBuf> = if journal {
+            files
+                .into_iter()
+                .map(|f| f.canonicalize().unwrap_or(f))
+                .collect()
+        } else {
+            files
+        };
+        if journal && !self.resume {
+            PushJournal::start(
+                &session.host.alias,
+                &files
+                    .iter()
+                    .cloned()
+                    .zip(aliases.iter().cloned())
+                    .collect::<Vec<_>>(),
+            );
+        }
+
+        let to_push: Vec<_> = files.iter().zip(aliases.iter()).zip(present).collect();
+        let push_one = |((to_upload, alias), present): &((&PathBuf, &String), Option<usize>)| {
+            let remote = self.push_file(
+                session,
+                config,
+                listing.as_ref(),
+                *present,
+                to_upload,
+                alias,
+            )?;
+            if journal {
+                PushJournal::done(&session.host.alias, to_upload);
+            }
+            self.link_recipients(session, &remote)?;
+            self.update_latest_link(session, &remote)
+        };
+
+        if self.jobs > 1 && to_push.len() > 1 {
+            let _multi = crate::cli::multi_progress();
+            let results = parallel::map(&to_push, self.jobs, push_one);
+            let num_failed = to_push
+                .iter()
+                .zip(results)
+                .filter_map(|(((to_upload, _), _), result)| {
+                    result
+                        .map_err(|e| log::error!("[{}] {:#}", to_upload.display(), e))
+                        .err()
+                })
+                .count();
+            if num_failed > 0 {
+                bail!(tr!(
+                    "push.failed",
+                    failed = num_failed,
+                    total = to_push.len()
+                ));
+            }
+        } else {
+            for item in to_push.iter() {
+                push_one(item)?;
+            }
         }
 
+        if journal {
+            PushJournal::finish(&session.host.alias);
+        }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quickshare_prints_only_the_url() {
+        let push = Push::stdin("note.md".to_string(), Some("1d".to_string()));
+        let url = "https://example.com/Zm9vYmFy/note.md".to_string();
+        let mut out = Vec::new();
+        push.write_urls(&mut out, &[url.clone()], Path::new("Zm9vYmFy/note.md"))
+            .unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), format!("{}\n", url));
+    }
+
+    #[test]
+    fn alias_of_current_directory() {
+        let push = Push::parse_from(["push", "--prefix", "p-", "."]);
+        let cwd = std::env::current_dir().unwrap();
+        assert_eq!(
+            push.transform_filename(Path::new(".")).unwrap(),
+            format!("p-{}", cwd.file_name().unwrap().to_string_lossy())
+        );
+    }
+}
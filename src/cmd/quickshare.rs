@@ -0,0 +1,29 @@
+use anyhow::Result;
+use clap::Parser;
+
+use crate::cfg::Config;
+use crate::cmd::{Command, Push};
+use crate::ssh::SshSession;
+
+/// Share stdin (e.g. an editor buffer) and copy its URL to the clipboard.
+///
+/// Shorthand for `push --stdin --name <name> --clipboard`: Only the URL is printed on stdout,
+/// everything else goes to stderr.
+///
+/// Example (vim): `:w !asfa quickshare --name note.md --expire 1d`
+#[derive(Parser, Debug)]
+pub struct QuickShare {
+    /// File name on the remote site.
+    #[clap(long, default_value = "snippet.txt")]
+    name: String,
+
+    /// Expire the upload after the given amount of time (see `push --expire`).
+    #[clap(short, long)]
+    expire: Option<String>,
+}
+
+impl Command for QuickShare {
+    fn run(&self, session: &SshSession, config: &Config) -> Result<()> {
+        Push::stdin(self.name.clone(), self.expire.clone()).run(session, config)
+    }
+}
@@ -1,19 +1,32 @@
-use anyhow::{bail, Context, Result};
+use anyhow::{Context, Result};
 use clap::{AppSettings, Parser};
 use console::Style;
+use log::warn;
 use std::io::IsTerminal;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
+use crate::at;
+use crate::cache;
 use crate::cfg::Config;
 use crate::cli::color;
 use crate::cli::draw_boxed;
+use crate::cli::text;
+use crate::clipboard;
 use crate::cmd::Command;
 use crate::ssh::SshSession;
+use crate::util;
 
 /// Rename an already uploaded file
+///
+/// With `--regenerate-hash`, the file is moved to the folder matching the hash of its current
+/// content instead (e.g., after its content was replaced on the remote site).
 #[derive(Parser, Debug)]
 #[clap(global_setting=AppSettings::AllowNegativeNumbers)]
 pub struct Rename {
+    /// Copy the new URL to the clipboard, can be set globally in config file.
+    #[clap(long, short = 'b')]
+    clipboard: bool,
+
     /// Show all details, can be set globally in config file.
     #[clap(long, short)]
     details: bool,
@@ -23,106 +36,97 @@ pub struct Rename {
     input: String,
 
     /// New name to rename file
-    #[clap()]
-    filename: PathBuf,
+    #[clap(required_unless_present = "regenerate-hash")]
+    filename: Option<PathBuf>,
 
     /// If `details` is set to true in config, --no-details can be specified to suppress output.
     #[clap(long, short = 'D')]
     no_details: bool,
-}
 
-enum IndexOrFile<'a> {
-    Index(i64),
-    Filename(&'a str),
-}
+    /// If `clipboard` is set to true in config, --no-clipboard can be specified to not copy URLs.
+    #[clap(long, conflicts_with = "clipboard")]
+    no_clipboard: bool,
 
-impl Rename {
-    fn parse_input(&self) -> IndexOrFile {
-        use IndexOrFile::*;
-        match self.input.parse::<i64>() {
-            Ok(idx) => Index(idx),
-            Err(_) => Filename(&self.input),
-        }
-    }
+    /// Move the file (and everything next to it) into the folder matching the hash of its
+    /// current content so that `verify` succeeds again. Named symlinks are updated accordingly.
+    #[clap(long)]
+    regenerate_hash: bool,
 }
 
 impl Command for Rename {
     fn run(&self, session: &SshSession, config: &Config) -> Result<()> {
-        use IndexOrFile::*;
         let host = &session.host;
 
-        let (input_indices, input_filenames) = {
-            let mut indices = Vec::new();
-            let mut filenames = Vec::new();
-
-            match self.parse_input() {
-                Index(idx) => {
-                    indices.push(idx);
-                }
-                Filename(name) => {
-                    filenames.push(name);
-                }
-            }
-
-            (indices, filenames)
-        };
-
         let remote_selected = session
             .list_files()?
-            .by_hash(
-                &input_filenames,
-                session.host.prefix_length,
-                /* bail_when_missing = */ false,
-            )?
-            .by_indices(&input_indices)?;
-
-        if remote_selected.count() == 0 {
-            match self.parse_input() {
-                Index(idx) => {
-                    bail!("Invalid remote index specified: {}", idx);
-                }
-                Filename(name) => {
-                    bail!("File not uploaded to remote site: {}", name);
-                }
-            }
-        } else if remote_selected.count() > 1 {
-            bail!(
-                "Found {} matching remote files, this should never happen.",
-                remote_selected.count()
-            );
-        }
+            .by_index_or_file(&self.input, session.host.prefix_length)?;
 
         let (_, old_path_relative, _) = remote_selected.iter().next().unwrap();
 
-        let hash = old_path_relative
+        let hash_old = old_path_relative
             .parent()
             .with_context(|| "Could not determine remote hash.")?;
+        let filename = match &self.filename {
+            Some(filename) => filename.as_path(),
+            None => Path::new(
+                old_path_relative
+                    .file_name()
+                    .with_context(|| "Invalid remote file name")?,
+            ),
+        };
+
+        let hash = if self.regenerate_hash {
+            let hash_new = PathBuf::from(
+                session.get_remote_hash(old_path_relative, session.host.prefix_length)?,
+            );
+            if hash_new != hash_old {
+                self.move_folder(session, hash_old, &hash_new)?;
+            }
+            hash_new
+        } else {
+            hash_old.to_path_buf()
+        };
+        let moved_path_relative = hash.join(
+            old_path_relative
+                .file_name()
+                .with_context(|| "Invalid remote file name")?,
+        );
 
         let path_old = {
             let mut path = host.folder.clone();
-            path.push(old_path_relative);
+            path.push(&moved_path_relative);
             path
         };
         let path_new = {
             let mut path = host.folder.clone();
             path.push(&hash);
-            path.push(&self.filename);
+            path.push(filename);
             path
         };
 
-        session.exec_remote(&format!(
-            "mv '{}' '{}'",
-            path_old.display().to_string().replace("'", ""),
-            path_new.display().to_string().replace("'", "")
-        ))?;
+        if path_old != path_new {
+            session
+                .exec_remote(&format!(
+                    "mv {} {}",
+                    util::shell_quote(&path_old.to_string_lossy()),
+                    util::shell_quote(&path_new.to_string_lossy())
+                ))?
+                .expect_with(|_| format!("Could not rename to {}", filename.display()))?;
+        }
+        let path_new_relative = hash.join(filename);
+        session.audit("rename", &[old_path_relative, &path_new_relative]);
+        cache::update(&host.alias, |c| {
+            c.rename(&moved_path_relative, &path_new_relative)
+        });
 
-        let url_new = host.get_url(&format!("{}/{}", hash.display(), &self.filename.display()))?;
+        let url_new = host.get_url(&format!("{}", path_new_relative.display()))?;
         if !config.is_silent() {
             // Only print fancy boxes if we are attached to a TTY -> otherwise, just dump data in
             // parseable format
             if std::io::stdout().is_terminal() {
                 let content = vec![format!(
-                    " {old} → {new} ",
+                    " {old} {arrow} {new} ",
+                    arrow = text::arrow(),
                     old = Style::new().red().bright().apply_to(
                         old_path_relative
                             .file_name()
@@ -145,6 +149,26 @@ impl Command for Rename {
                 println!("{}", &url_new);
             }
         }
+        if config.copy_to_clipboard(self.clipboard, self.no_clipboard) {
+            clipboard::copy_urls(&[url_new]);
+        }
+        Ok(())
+    }
+}
+
+impl Rename {
+    /// Move the given folder (relative to base folder) to its new name.
+    fn move_folder(&self, session: &SshSession, old: &Path, new: &Path) -> Result<()> {
+        session.move_folder(old, new)?;
+        if at::list_jobs(session)?
+            .iter()
+            .any(|job| job.path.starts_with(old))
+        {
+            warn!(
+                "Pending expiration job still refers to {}, it will not expire.",
+                old.display()
+            );
+        }
         Ok(())
     }
 }
@@ -0,0 +1,52 @@
+use anyhow::{bail, Result};
+use clap::Parser;
+use log::warn;
+use std::path::Path;
+
+use crate::cfg::Config;
+use crate::cmd::Command;
+use crate::share_code;
+use crate::ssh::SshSession;
+
+/// Print the URLs of uploads identified by short codes (see `push --code`).
+///
+/// Codes are case-insensitive, separators and commonly confused characters (O/0, I/L/1) are
+/// ignored.
+#[derive(Parser, Debug)]
+pub struct Resolve {
+    /// Code(s) to resolve.
+    #[clap(required = true)]
+    codes: Vec<String>,
+}
+
+impl Command for Resolve {
+    fn run(&self, session: &SshSession, _config: &Config) -> Result<()> {
+        let listing = session.list_files()?.with_all(true);
+        let uploads: Vec<(String, &Path)> = (0..listing.num_files)
+            .filter_map(|idx| listing.path_of(idx))
+            .filter_map(|path| Some((share_code::of_file(path)?, path)))
+            .collect();
+
+        let mut num_unknown = 0;
+        for code in self.codes.iter() {
+            let normalized = share_code::normalize(code);
+            // Extracted archives contain many files, only the topmost one is the upload itself.
+            match uploads
+                .iter()
+                .filter(|(c, _)| *c == normalized)
+                .map(|(_, path)| *path)
+                .min_by_key(|path| path.components().count())
+            {
+                Some(path) => println!("{}", session.host.get_url(&format!("{}", path.display()))?),
+                None => {
+                    warn!("Unknown code: {}", code);
+                    num_unknown += 1;
+                }
+            }
+        }
+        if num_unknown > 0 {
+            bail!("{} code(s) could not be resolved.", num_unknown);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,135 @@
+use anyhow::{bail, Result};
+use clap::Parser;
+
+use crate::cfg::Config;
+use crate::cmd::Command;
+use crate::ssh::SshSession;
+
+/// Clipboard helper shared by bash and zsh: `__asfa_clip copy|paste`.
+const CLIP_POSIX: &str = r#"__asfa_clip() {
+    if [ -n "$WAYLAND_DISPLAY" ] && command -v wl-copy >/dev/null 2>&1; then
+        if [ "$1" = copy ]; then wl-copy; else wl-paste --no-newline; fi
+    elif command -v xclip >/dev/null 2>&1; then
+        if [ "$1" = copy ]; then xclip -selection clipboard; else xclip -selection clipboard -o; fi
+    elif command -v pbcopy >/dev/null 2>&1; then
+        if [ "$1" = copy ]; then pbcopy; else pbpaste; fi
+    else
+        return 1
+    fi
+}
+"#;
+
+/// Function for bash and zsh, `__NAME__` is replaced with the chosen name.
+const SHARE_POSIX: &str = r#"
+__NAME__() {
+    local file urls
+    if [ $# -eq 0 ]; then
+        file="$(__asfa_clip paste)"
+        if [ -z "$file" ]; then
+            echo "Nothing to share: specify files or copy a path to the clipboard." >&2
+            return 1
+        fi
+        set -- "$file"
+    fi
+    urls="$(command asfa push "$@")" || return
+    printf '%s\n' "$urls"
+    printf '%s' "$urls" | __asfa_clip copy || echo "No clipboard tool found." >&2
+    if command -v notify-send >/dev/null 2>&1; then
+        notify-send --app-name=asfa "Shared via asfa" "$urls"
+    fi
+}
+"#;
+
+/// Widget sharing the most recently killed text (e.g. a path cut via `Ctrl-W`).
+const WIDGET_ZSH: &str = r#"
+__NAME__-widget() {
+    zle -I
+    __NAME__ "${CUTBUFFER%%[[:space:]]}"
+}
+zle -N __NAME__-widget
+"#;
+
+const SHARE_FISH: &str = r#"function __asfa_clip
+    if set -q WAYLAND_DISPLAY; and command -q wl-copy
+        if test "$argv[1]" = copy; wl-copy; else; wl-paste --no-newline; end
+    else if command -q xclip
+        if test "$argv[1]" = copy; xclip -selection clipboard; else; xclip -selection clipboard -o; end
+    else if command -q pbcopy
+        if test "$argv[1]" = copy; pbcopy; else; pbpaste; end
+    else
+        return 1
+    end
+end
+
+function __NAME__ --description 'Push files via asfa, copy the URLs and notify'
+    if test (count $argv) -eq 0
+        set -l file (__asfa_clip paste)
+        if test -z "$file"
+            echo "Nothing to share: specify files or copy a path to the clipboard." >&2
+            return 1
+        end
+        set argv $file
+    end
+    set -l urls (command asfa push $argv); or return
+    printf '%s\n' $urls
+    printf '%s\n' $urls | __asfa_clip copy; or echo "No clipboard tool found." >&2
+    if command -q notify-send
+        notify-send --app-name=asfa "Shared via asfa" (string join \n -- $urls)
+    end
+end
+"#;
+
+/// Print a shell function sharing files via `push` and copying their URLs.
+///
+/// Add e.g. `eval "$(asfa shell-init bash)"` to your shell's rc-file (fish:
+/// `asfa shell-init fish | source`). The function passes its arguments on to `push`; without
+/// arguments it shares the path in the clipboard. zsh additionally gets a widget `<name>-widget`
+/// sharing the most recently killed text, bind it via e.g. `bindkey '^Xs' share-widget`.
+#[derive(Parser, Debug)]
+pub struct ShellInit {
+    /// Shell to emit the function for.
+    #[clap(arg_enum)]
+    shell: Shell,
+
+    /// Name of the emitted function.
+    #[clap(long, default_value = "share")]
+    name: String,
+}
+
+#[derive(clap::ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl ShellInit {
+    /// Print the function, does not need the remote site.
+    pub fn print(&self) -> Result<()> {
+        if self.name.is_empty()
+            || !self
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            bail!("Invalid function name: {}", self.name);
+        }
+        print!("{}", self.script());
+        Ok(())
+    }
+
+    fn script(&self) -> String {
+        let script = match self.shell {
+            Shell::Bash => format!("{}{}", CLIP_POSIX, SHARE_POSIX),
+            Shell::Zsh => format!("{}{}{}", CLIP_POSIX, SHARE_POSIX, WIDGET_ZSH),
+            Shell::Fish => SHARE_FISH.to_string(),
+        };
+        script.replace("__NAME__", &self.name)
+    }
+}
+
+impl Command for ShellInit {
+    fn run(&self, _session: &SshSession, _config: &Config) -> Result<()> {
+        self.print()
+    }
+}
@@ -0,0 +1,94 @@
+use anyhow::{Context, Result};
+use chrono::{Local, LocalResult, TimeZone};
+use clap::Parser;
+use console::Style;
+use std::collections::BTreeMap;
+use std::io::IsTerminal;
+
+use crate::cfg::Config;
+use crate::cli::{color, draw_boxed};
+use crate::cmd::Command;
+use crate::ssh::SshSession;
+use crate::util;
+
+/// Show disk usage of uploaded files, broken down by month of upload.
+///
+/// Useful to decide which period's uploads to target with `clean --older`.
+#[derive(Parser, Debug)]
+pub struct Stats {}
+
+/// Width of the histogram bars (in characters).
+const BAR_WIDTH: u64 = 20;
+
+/// Number of files and bytes.
+#[derive(Default)]
+struct Usage {
+    count: usize,
+    bytes: u64,
+}
+
+impl Command for Stats {
+    fn run(&self, session: &SshSession, config: &Config) -> Result<()> {
+        let listing = session.list_files()?.with_all(true).with_stats(true)?;
+
+        let mut total = Usage::default();
+        let mut by_month: BTreeMap<String, Usage> = BTreeMap::new();
+        for (_, path, stat) in listing.iter() {
+            let stat = stat.with_context(|| format!("No stats for {}", path.display()))?;
+            let size = stat.size.context("No file size defined!")?;
+            let month =
+                match Local.timestamp_opt(stat.mtime.context("File has no mtime.")? as i64, 0) {
+                    LocalResult::Single(mtime) => mtime.format("%Y-%m").to_string(),
+                    _ => "unknown".to_string(),
+                };
+            for usage in [by_month.entry(month).or_default(), &mut total] {
+                usage.count += 1;
+                usage.bytes += size;
+            }
+        }
+
+        if config.is_silent() {
+            return Ok(());
+        }
+        if std::io::stdout().is_terminal() {
+            let max_bytes = by_month.values().map(|u| u.bytes).max().unwrap_or(0).max(1);
+            let count_width = total.count.to_string().len();
+            let mut content: Vec<String> = by_month
+                .iter()
+                .map(|(month, usage)| {
+                    let bar_width = (usage.bytes * BAR_WIDTH / max_bytes).max(1) as usize;
+                    format!(
+                        " {month} {count:>width$} files {size} {bar}{pad} ",
+                        month = month,
+                        count = usage.count,
+                        width = count_width,
+                        size = util::format_size(usage.bytes),
+                        bar = color::dot.apply_to("█".repeat(bar_width)),
+                        pad = " ".repeat(BAR_WIDTH as usize - bar_width),
+                    )
+                })
+                .collect();
+            content.push(format!(
+                " {total:<7} {count:>width$} files {size} ",
+                total = "total",
+                count = total.count,
+                width = count_width,
+                size = util::format_size(total.bytes),
+            ));
+            draw_boxed(
+                format!(
+                    "{} by month:",
+                    Style::new().bold().green().bright().apply_to("Usage")
+                ),
+                content.iter().map(|s| s.as_str()),
+                &color::frame,
+            )?;
+        } else {
+            for (month, usage) in by_month.iter() {
+                println!("{}\t{}\t{}", month, usage.count, usage.bytes);
+            }
+            println!("total\t{}\t{}", total.count, total.bytes);
+        }
+        Ok(())
+    }
+}
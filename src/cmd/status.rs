@@ -0,0 +1,53 @@
+use anyhow::Result;
+use clap::Parser;
+use std::path::PathBuf;
+
+use crate::cache::Cache;
+use crate::cfg::{Config, Host};
+use crate::cmd::Command;
+use crate::ssh::SshSession;
+
+/// Print the number of uploaded files and the URL of the most recent upload.
+///
+/// Output is a single tab-separated line, suitable for embedding in a shell prompt.
+#[derive(Parser, Debug)]
+pub struct Status {
+    /// Do not connect to the remote site but report the state known locally from the last
+    /// time asfa listed, uploaded or removed files.
+    #[clap(long)]
+    pub cached: bool,
+}
+
+impl Status {
+    /// Report status from the local cache without any network access.
+    pub fn run_cached(&self, host: &Host, config: &Config) -> Result<()> {
+        let cache = Cache::load(&host.alias)?;
+        self.print(host, config, cache.files())
+    }
+
+    fn print(&self, host: &Host, config: &Config, files: &[PathBuf]) -> Result<()> {
+        if config.is_silent() {
+            return Ok(());
+        }
+        match files.last() {
+            Some(latest) => println!(
+                "{}\t{}",
+                files.len(),
+                host.get_url(&format!("{}", latest.display()))?
+            ),
+            None => println!("0"),
+        }
+        Ok(())
+    }
+}
+
+impl Command for Status {
+    fn run(&self, session: &SshSession, config: &Config) -> Result<()> {
+        if self.cached {
+            return self.run_cached(session.host, config);
+        }
+        // Listing the files also refreshes the local cache.
+        let files = session.all_files()?;
+        self.print(session.host, config, &files)
+    }
+}
@@ -1,15 +1,49 @@
 use anyhow::{bail, Result};
 use clap::Parser;
-use log::debug;
+use console::Style;
+use itertools::Itertools;
+use log::{debug, info, warn};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
 
 use crate::cfg::Config;
-use crate::cli::{color, WaitingSpinner};
+use crate::cli::{color, draw_boxed, WaitingSpinner};
 use crate::cmd::Command;
+use crate::compress;
+use crate::file_listing::{FileListing, JsonEntry, Show, FORMAT_FIELDS, FORMAT_FIELDS_STATS};
+use crate::manifest::{self, Entry, Manifest};
 use crate::ssh::SshSession;
+use crate::template::Template;
+use crate::tr;
+
+/// A verified file as printed via `--json`.
+#[derive(Serialize)]
+struct VerifiedEntry {
+    #[serde(flatten)]
+    entry: JsonEntry,
+
+    /// `verified` or `failed`.
+    status: &'static str,
+
+    /// Hash of the remote file.
+    actual: String,
+}
 
 /// Verify already uploaded files.
 #[derive(Parser, Debug)]
 pub struct Verify {
+    /// Compare size and full hash of the selected files against a manifest created via
+    /// `export-manifest` instead of the hash in their URL. Reports changed and missing files.
+    #[clap(long, value_name = "manifest.json", conflicts_with_all = &["format", "failed-indices"])]
+    against: Option<PathBuf>,
+
+    /// Only print the indices of files that failed to verify (separated by spaces) instead of
+    /// progress messages and summary, e.g. for `asfa clean $(asfa verify --failed-indices)`.
+    #[clap(long, conflicts_with = "format")]
+    failed_indices: bool,
+
     /// Explicit file to verify
     #[clap(short, long = "file")]
     files: Vec<String>,
@@ -26,6 +60,15 @@ pub struct Verify {
     #[clap()]
     indices: Vec<i64>,
 
+    /// Select files whose age lies within the given window, e.g. `--between 2d..7d` selects files
+    /// older than two days but newer than a week. Either bound can be omitted.
+    #[clap(
+        long,
+        value_name = "min..max",
+        conflicts_with_all = &["select-newer", "select-older"]
+    )]
+    between: Option<String>,
+
     /// Select files newer than the given duration. Durations can be:seconds (sec, s), minutes
     /// (min, m), days (d), weeks (w), months (M) or years (y).
     #[clap(long = "newer")]
@@ -36,6 +79,19 @@ pub struct Verify {
     #[clap(long = "older")]
     select_older: Option<String>,
 
+    /// Print the verified files as JSON array (index, filename, url, path, hash, size, mtime,
+    /// expiration as well as status and actual remote hash) instead of progress messages.
+    #[clap(long, conflicts_with_all = &["against", "failed-indices", "format"])]
+    json: bool,
+
+    /// Print one line per verified file according to the given template instead of progress
+    /// messages, e.g., `--format '{status}\t{url}'`.
+    ///
+    /// Available fields: {status} (`verified`/`failed`), {actual} (remote hash), {index},
+    /// {rev_index}, {url}, {path}, {name}, {hash}, {size} (in bytes) and {mtime}.
+    #[clap(long, value_name = "template")]
+    format: Option<String>,
+
     /// Sort by size (useful when specifying `--last`)
     #[clap(long, short = 'S')]
     sort_size: bool,
@@ -50,6 +106,17 @@ impl Command for Verify {
         debug!("Verifying remote files..");
 
         let files: Vec<&str> = self.files.iter().map(|s| s.as_str()).collect();
+        let template = match self.format.as_deref() {
+            Some(format) => {
+                let fields: Vec<_> = ["status", "actual"]
+                    .iter()
+                    .chain(FORMAT_FIELDS.iter())
+                    .copied()
+                    .collect();
+                Some(Template::parse(format, &fields[..])?)
+            }
+            None => None,
+        };
 
         let files_to_verify = session
             .list_files()?
@@ -58,6 +125,7 @@ impl Command for Verify {
             .with_all_if_none(true)
             .select_newer(self.select_newer.as_deref())?
             .select_older(self.select_older.as_deref())?
+            .select_between(self.between.as_deref())?
             .sort_by_size(self.sort_size)?
             .revert(self.reverse)
             .last(self.last)
@@ -65,18 +133,32 @@ impl Command for Verify {
                 files.iter(),
                 session.host.prefix_length,
                 /* bail_when_missing = */ true,
+            )?
+            .with_stats(
+                self.against.is_some()
+                    || self.json
+                    || template
+                        .as_ref()
+                        .map(|t| t.uses_any(FORMAT_FIELDS_STATS))
+                        .unwrap_or(false),
             )?;
 
+        if let Some(against) = self.against.as_deref() {
+            return self.verify_against(session, config, &files_to_verify, against);
+        }
+
         let message = "Verifying...";
-        let files: Vec<_> = files_to_verify.iter().map(|e| e.1).collect();
+        let entries: Vec<_> = files_to_verify.iter().collect();
+        let files: Vec<_> = entries.iter().map(|e| e.1).collect();
 
         let num_files = files.len();
         if num_files == 0 {
-            bail!("No files to verify..");
+            crate::cli::report_empty(config, &tr!("verify.no-files"));
+            return Ok(());
         }
 
         let spinner = {
-            if !config.is_silent() {
+            if !config.is_silent() && template.is_none() && !self.failed_indices && !self.json {
                 Some(WaitingSpinner::new(format!("{} 0/{}", message, &num_files)))
             } else {
                 // Do not print anything if asfa is set to very quiet)
@@ -86,17 +168,19 @@ impl Command for Verify {
 
         let filename_max = files
             .iter()
-            .map(|f| f.file_name().unwrap().to_string_lossy().chars().count())
+            .map(|f| console::measure_text_width(&f.file_name().unwrap().to_string_lossy()))
             .max()
             .unwrap()
             + 1;
 
-        let chunk_size = 16;
+        let layout = &session.host.layout;
+        let chunk_size = session.hash_batch_size()?;
         let hashes_actual = files[..]
             .chunks(chunk_size)
             .map(|c| session.get_remote_hashes(c, session.host.prefix_length));
 
         let mut failure = Vec::new();
+        let mut hashes_found = Vec::with_capacity(num_files);
         for (idx, (files, hashes_actual)) in
             files[..].chunks(chunk_size).zip(hashes_actual).enumerate()
         {
@@ -104,24 +188,53 @@ impl Command for Verify {
                 spinner.set_message(format!("{} {}/{}", message, idx * chunk_size, &num_files))?;
             }
             let hashes_actual = hashes_actual?;
-            for (file, hash_actual) in files.iter().zip(hashes_actual) {
-                let hash_expected = file.parent().unwrap().to_string_lossy();
+            let hashes_expected: Vec<String> = files
+                .iter()
+                .map(|f| {
+                    layout
+                        .upload_folder(f)
+                        .unwrap()
+                        .to_string_lossy()
+                        .to_string()
+                })
+                .collect();
+            let verified = self.verified(session, files, &hashes_expected, &hashes_actual);
+            for (pos, ((file, hash_actual), verified)) in
+                files.iter().zip(hashes_actual).zip(verified).enumerate()
+            {
+                let hash_expected = &hashes_expected[pos];
+                if self.json {
+                    hashes_found.push((hash_actual.clone(), verified));
+                }
+                if let (Some(template), false) = (template.as_ref(), config.is_silent()) {
+                    let (list_idx, _, stat) = entries[idx * chunk_size + pos];
+                    let line = template.render(|field| match field {
+                        "status" if verified => Ok("verified".to_string()),
+                        "status" => Ok("failed".to_string()),
+                        "actual" => Ok(hash_actual.clone()),
+                        _ => {
+                            files_to_verify.format_field(session.host, field, list_idx, file, stat)
+                        }
+                    })?;
+                    println!("{}", line);
+                }
                 let filename = file.file_name().unwrap().to_string_lossy();
-                let filename_len = filename.chars().count();
+                let filename_len = console::measure_text_width(&filename);
                 let separator_len = filename_max - filename_len + 2; // enusre at least ellipsis
-                if hash_actual != hash_expected {
+                if !verified {
                     if let Some(spinner) = spinner.as_ref() {
                         let msg = format!(
                             "{} {} {} Expected: {} Found: {}",
                             color::failure.apply_to("✗"),
                             color::filename.apply_to(&filename),
                             ".".repeat(separator_len),
-                            color::success.apply_to(hash_expected),
-                            color::failure.apply_to(hash_actual),
+                            color::success.apply_to(&hash_expected),
+                            color::failure.apply_to(&hash_actual),
                         );
                         spinner.println(msg)?;
                     }
-                    failure.push(file);
+                    let (list_idx, _, _) = entries[idx * chunk_size + pos];
+                    failure.push((list_idx, hash_expected.to_string(), hash_actual));
                 } else if let Some(spinner) = spinner.as_ref() {
                     spinner.println(format!(
                         "{} {} {} {}.",
@@ -138,6 +251,28 @@ impl Command for Verify {
             spinner.finish();
         }
 
+        if self.failed_indices {
+            println!("{}", failure.iter().map(|(idx, _, _)| idx).join(" "));
+        } else if self.json && !config.is_silent() {
+            let verified: Vec<_> = files_to_verify
+                .to_json(session.host)?
+                .into_iter()
+                .zip(hashes_found)
+                .map(|(entry, (actual, verified))| VerifiedEntry {
+                    status: if verified { "verified" } else { "failed" },
+                    actual,
+                    entry,
+                })
+                .collect();
+            crate::cli::print_json(&verified)?;
+        } else if !failure.is_empty()
+            && !config.is_silent()
+            && template.is_none()
+            && std::io::stdout().is_terminal()
+        {
+            self.show_failures(files_to_verify, &failure)?;
+        }
+
         if !failure.is_empty() {
             bail!("{} files failed to verify.", failure.len());
         } else {
@@ -145,3 +280,181 @@ impl Command for Verify {
         }
     }
 }
+
+impl Verify {
+    /// Whether each of the given remote files matches its expected hash, either directly or
+    /// because it was compressed while pushing it (see `push --compress`) and its decompressed
+    /// content matches.
+    ///
+    /// All mismatching files are decompressed in a single batch.
+    fn verified(
+        &self,
+        session: &SshSession,
+        files: &[&Path],
+        expected: &[String],
+        actual: &[String],
+    ) -> Vec<bool> {
+        let mut verified: Vec<bool> = expected.iter().zip(actual).map(|(e, a)| e == a).collect();
+        let mismatched: Vec<usize> = (0..files.len()).filter(|i| !verified[*i]).collect();
+        if mismatched.is_empty() {
+            return verified;
+        }
+        let paths: Vec<&Path> = mismatched.iter().map(|i| files[*i]).collect();
+        match compress::remote_hashes(session, &paths, session.host.prefix_length) {
+            Ok(hashes) => {
+                for (i, hash) in mismatched.iter().zip(hashes) {
+                    verified[*i] = hash.as_deref() == Some(expected[*i].as_str());
+                }
+            }
+            Err(e) => warn!("Could not verify decompressed files: {:#}", e),
+        }
+        verified
+    }
+
+    /// Compare the selected files against the given manifest.
+    fn verify_against(
+        &self,
+        session: &SshSession,
+        config: &Config,
+        files: &FileListing,
+        manifest: &Path,
+    ) -> Result<()> {
+        let manifest = Manifest::load(manifest)?;
+        if manifest.host != session.host.alias {
+            warn!(
+                "Manifest was created for host '{}', not '{}'.",
+                manifest.host, session.host.alias
+            );
+        }
+        let expected: HashMap<&Path, &Entry> = manifest
+            .files
+            .iter()
+            .map(|e| (e.path.as_path(), e))
+            .collect();
+
+        let selected: Vec<_> = files.iter().collect();
+        let known: Vec<(&Path, u64, &Entry)> = selected
+            .iter()
+            .filter_map(|(_, path, stat)| {
+                Some((*path, stat.and_then(|s| s.size)?, *expected.get(path)?))
+            })
+            .collect();
+        let num_new = selected.len() - known.len();
+
+        let spinner = (!config.is_silent())
+            .then(|| WaitingSpinner::new("Comparing against manifest..".to_string()));
+        let paths: Vec<&Path> = known.iter().map(|(path, _, _)| *path).collect();
+        let mut hashes = Vec::with_capacity(paths.len());
+        let chunk_size = session.hash_batch_size()?;
+        for (idx, chunk) in paths.chunks(chunk_size).enumerate() {
+            if let Some(spinner) = spinner.as_ref() {
+                spinner.set_message(format!(
+                    "Comparing against manifest.. {}/{}",
+                    idx * chunk_size,
+                    paths.len()
+                ))?;
+            }
+            hashes.extend(session.get_remote_hashes(chunk, manifest::HASH_LENGTH)?);
+        }
+        if let Some(spinner) = spinner {
+            spinner.finish();
+        }
+
+        let mut changes = Vec::new();
+        for ((path, size, entry), hash) in known.iter().zip(hashes.iter()) {
+            if *size != entry.size {
+                changes.push(("changed", *path, format!("size {} → {}", entry.size, size)));
+            } else if *hash != entry.hash {
+                changes.push(("changed", *path, format!("hash {} → {}", entry.hash, hash)));
+            }
+        }
+        let present: HashSet<&Path> = (0..files.num_files)
+            .filter_map(|idx| files.path_of(idx))
+            .collect();
+        for entry in manifest.files.iter() {
+            if !present.contains(entry.path.as_path()) {
+                changes.push(("missing", &entry.path, String::new()));
+            }
+        }
+
+        if !config.is_silent() {
+            if changes.is_empty() {
+                println!(
+                    "All {} files match the manifest created {}.",
+                    known.len(),
+                    manifest.created
+                );
+            } else if std::io::stdout().is_terminal() {
+                let content: Vec<String> = changes
+                    .iter()
+                    .map(|(status, path, detail)| {
+                        format!(
+                            " {} {} {} {} ",
+                            color::failure.apply_to("✗"),
+                            color::failure.apply_to(status),
+                            color::filename.apply_to(path.display()),
+                            detail
+                        )
+                    })
+                    .collect();
+                draw_boxed(
+                    format!(
+                        "{} since manifest created {}:",
+                        Style::new().bold().red().bright().apply_to("Changes"),
+                        manifest.created
+                    ),
+                    content.iter().map(|s| s.as_str()),
+                    &color::frame,
+                )?;
+            } else {
+                for (status, path, detail) in changes.iter() {
+                    println!("{}\t{}\t{}", status, path.display(), detail);
+                }
+            }
+        }
+        if num_new > 0 {
+            info!("{} selected files are not part of the manifest.", num_new);
+        }
+
+        if !changes.is_empty() {
+            bail!(
+                "{} files changed or missing since manifest was created.",
+                changes.len()
+            );
+        }
+        Ok(())
+    }
+
+    /// Summarize all failed files (index, expected and actual hash) in a box.
+    fn show_failures(&self, files: FileListing, failure: &[(usize, String, String)]) -> Result<()> {
+        let indices: Vec<usize> = failure.iter().map(|(idx, _, _)| *idx).collect();
+        let failed = files.restrict_to(&indices).with_stats(true)?;
+        // The listing might be ordered differently than the failures, so match them by index.
+        let by_index: HashMap<usize, (&String, &String)> = failure
+            .iter()
+            .map(|(idx, expected, actual)| (*idx, (expected, actual)))
+            .collect();
+        let content: Vec<String> = failed
+            .format_files(Show::Filename, true, false)?
+            .into_iter()
+            .zip(failed.iter())
+            .map(|(line, (idx, _, _))| {
+                let (expected, actual) = by_index[&idx];
+                format!(
+                    "{}Expected: {} Found: {} ",
+                    line,
+                    color::success.apply_to(expected),
+                    color::failure.apply_to(actual)
+                )
+            })
+            .collect();
+        draw_boxed(
+            format!(
+                "{} to verify:",
+                Style::new().bold().red().bright().apply_to("Failed")
+            ),
+            content.iter().map(|s| s.as_str()),
+            &color::frame,
+        )
+    }
+}
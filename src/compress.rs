@@ -0,0 +1,125 @@
+//! Compression of files while pushing them (`push --compress`).
+//!
+//! Compressed uploads are stored in the folder named after the hash of the original content, so
+//! that they are found via the local file (e.g. by `check` or `push --if-absent`). The hash of the
+//! compressed file is only known while pushing (to verify the transfer) and not recorded anywhere,
+//! later checks (`verify`, `fsck`) hash the content after decompressing it on the remote site.
+
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::path::Path;
+
+use crate::spool::Spool;
+use crate::ssh::SshSession;
+
+/// Compression applied to uploaded files.
+#[derive(clap::ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// Compression indicated by the extension of the given file name, if any.
+    pub fn from_name(name: &str) -> Option<Self> {
+        [Compression::Gzip, Compression::Zstd]
+            .into_iter()
+            .find(|compression| name.ends_with(compression.extension()))
+    }
+
+    /// Extension appended to the names of compressed files.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Compression::Gzip => ".gz",
+            Compression::Zstd => ".zst",
+        }
+    }
+
+    /// Remote command decompressing stdin to stdout.
+    fn decompressor(self) -> &'static str {
+        match self {
+            Compression::Gzip => "gzip -dc",
+            Compression::Zstd => "zstd -dcq",
+        }
+    }
+
+    /// Compress the given file into a local temporary file, hashing the compressed data.
+    pub fn compress(self, path: &Path, prefix_length: u8) -> Result<Spool> {
+        let file =
+            File::open(path).with_context(|| format!("Could not open {}", path.display()))?;
+        match self {
+            Compression::Gzip => Spool::new(
+                flate2::read::GzEncoder::new(file, flate2::Compression::default()),
+                prefix_length,
+            ),
+            Compression::Zstd => {
+                Spool::new(zstd::stream::read::Encoder::new(file, 0)?, prefix_length)
+            }
+        }
+        .with_context(|| format!("Could not compress {}", path.display()))
+    }
+}
+
+/// Hashes of the content of the given remote files (relative to the base folder) after
+/// decompressing them, or None for files whose name does not indicate compression.
+///
+/// Files are decompressed in batches of `SshSession::hash_batch_size` per remote invocation.
+pub fn remote_hashes(
+    session: &SshSession,
+    paths: &[&Path],
+    length: u8,
+) -> Result<Vec<Option<String>>> {
+    let compressed: Vec<(usize, (&Path, &str))> = paths
+        .iter()
+        .enumerate()
+        .filter_map(|(i, path)| {
+            let compression = Compression::from_name(&path.file_name()?.to_string_lossy())?;
+            Some((i, (*path, compression.decompressor())))
+        })
+        .collect();
+    let mut hashes = vec![None; paths.len()];
+    for chunk in compressed.chunks(session.hash_batch_size()?) {
+        let files: Vec<(&Path, &str)> = chunk.iter().map(|(_, file)| *file).collect();
+        let hashed = session.get_remote_hashes_decompressed(&files, length)?;
+        for ((i, _), hash) in chunk.iter().zip(hashed) {
+            hashes[*i] = Some(hash);
+        }
+    }
+    Ok(hashes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn compress_roundtrip() {
+        let data = "line of a log file\n".repeat(1000);
+        let path = std::env::temp_dir().join(format!("asfa-compress-{}", std::process::id()));
+        std::fs::write(&path, &data).unwrap();
+
+        for compression in [Compression::Gzip, Compression::Zstd] {
+            let spool = compression.compress(&path, 32).unwrap();
+            assert!(spool.size() < data.len() as u64);
+            let compressed = File::open(spool.path()).unwrap();
+            let mut decompressed = String::new();
+            match compression {
+                Compression::Gzip => flate2::read::GzDecoder::new(compressed)
+                    .read_to_string(&mut decompressed)
+                    .unwrap(),
+                Compression::Zstd => zstd::stream::read::Decoder::new(compressed)
+                    .unwrap()
+                    .read_to_string(&mut decompressed)
+                    .unwrap(),
+            };
+            assert_eq!(decompressed, data);
+            assert_eq!(
+                Compression::from_name(&format!("app.log{}", compression.extension())),
+                Some(compression)
+            );
+        }
+        assert_eq!(Compression::from_name("app.log"), None);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,368 @@
+//! Share one authenticated SSH session across several invocations of asfa.
+//!
+//! `asfa daemon` connects to a host once and then listens on a unix socket. Every other
+//! invocation for the same host first tries to connect to that socket. If it succeeds, it hands
+//! over its stdin, stdout and stderr as well as its working directory, command line and the
+//! environment variables affecting asfa (`CLIENT_ENV`). The daemon then runs the command with its
+//! established session and reports back the exit code.
+//!
+//! Since the daemon writes directly to the file descriptors of the client, output (including
+//! progress bars and prompts) behaves exactly as if the client had run the command itself.
+
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+use nix::sys::socket::{recvmsg, sendmsg, ControlMessage, ControlMessageOwned, MsgFlags};
+use nix::unistd::{close, dup, dup2, geteuid};
+use std::ffi::OsString;
+use std::fs::{remove_file, symlink_metadata, DirBuilder};
+use std::io::{self, IoSlice, IoSliceMut, Read, Write};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use crate::cli::{self, Opts, UserCommand};
+use crate::ssh::SshSession;
+use crate::util::ExitCodeError;
+
+/// Sent alongside the file descriptors to identify a request.
+const MAGIC: &[u8; 4] = b"asfa";
+
+/// File descriptors handed over to the daemon: stdin, stdout and stderr.
+const STDIO: [RawFd; 3] = [0, 1, 2];
+
+/// Environment variables of the client that the daemon adopts while running its command
+/// (config and host selection, language, colors, clipboard).
+const CLIENT_ENV: &[&str] = &[
+    "ASFA_CONFIG",
+    "ASFA_HOST",
+    "CLICOLOR",
+    "CLICOLOR_FORCE",
+    "DISPLAY",
+    "LANG",
+    "LC_ALL",
+    "LC_CTYPE",
+    "LC_MESSAGES",
+    "TERM",
+    "WAYLAND_DISPLAY",
+];
+
+/// How often (in seconds) to send keepalive messages to the remote site while idle.
+const KEEPALIVE_INTERVAL: u32 = 30;
+
+/// Path of the socket of the daemon serving the given host.
+///
+/// The socket is placed in `$XDG_RUNTIME_DIR/asfa` if set, `/tmp/asfa-<user>` otherwise.
+pub fn socket_path(alias: &str) -> PathBuf {
+    let folder = match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(runtime_dir) => PathBuf::from(runtime_dir).join("asfa"),
+        None => PathBuf::from(format!("/tmp/asfa-{}", whoami::username())),
+    };
+    folder.join(format!("{}.sock", alias))
+}
+
+/// Hand the current invocation to the daemon serving the given host.
+///
+/// Returns the exit code of the command if it was run by a daemon and `None` if no daemon is
+/// running.
+pub fn dispatch(alias: &str) -> Result<Option<i32>> {
+    let path = socket_path(alias);
+    let mut stream = match UnixStream::connect(&path) {
+        Ok(stream) => stream,
+        Err(e) => {
+            log::trace!("No daemon listening on {}: {}", path.display(), e);
+            return Ok(None);
+        }
+    };
+    // Never hand our file descriptors to a socket planted by another user.
+    if let Err(e) = ensure_private(path.parent().unwrap()).and_then(|_| ensure_same_user(&stream)) {
+        log::warn!("Not using daemon at {}: {}", path.display(), e);
+        return Ok(None);
+    }
+    log::debug!("Dispatching command to daemon at {}", path.display());
+
+    sendmsg::<()>(
+        stream.as_raw_fd(),
+        &[IoSlice::new(MAGIC)],
+        &[ControlMessage::ScmRights(&STDIO)],
+        MsgFlags::empty(),
+        None,
+    )
+    .context("Could not hand over file descriptors to daemon.")?;
+
+    let cwd = std::env::current_dir()?;
+    write_bytes(&mut stream, cwd.as_os_str().as_bytes())?;
+    let args: Vec<OsString> = std::env::args_os().collect();
+    stream.write_all(&(args.len() as u32).to_be_bytes())?;
+    for arg in args.iter() {
+        write_bytes(&mut stream, arg.as_bytes())?;
+    }
+    let env: Vec<(&str, OsString)> = CLIENT_ENV
+        .iter()
+        .filter_map(|name| Some((*name, std::env::var_os(name)?)))
+        .collect();
+    stream.write_all(&(env.len() as u32).to_be_bytes())?;
+    for (name, value) in env.iter() {
+        write_bytes(&mut stream, name.as_bytes())?;
+        write_bytes(&mut stream, value.as_bytes())?;
+    }
+
+    let mut code = [0u8; 4];
+    stream
+        .read_exact(&mut code)
+        .context("Daemon hung up before reporting exit code.")?;
+    Ok(Some(i32::from_be_bytes(code)))
+}
+
+/// Serve requests with the given session until interrupted.
+pub fn serve(session: &SshSession) -> Result<()> {
+    let path = socket_path(&session.host.alias);
+    let listener = bind(&path)?;
+    listener.set_nonblocking(true)?;
+    log::info!(
+        "Serving host '{}' on {}",
+        session.host.alias,
+        path.display()
+    );
+
+    let mut next_keepalive = Instant::now();
+    loop {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                stream.set_nonblocking(false)?;
+                if let Err(e) = handle(session, stream) {
+                    log::warn!("Failed to handle request: {}", e);
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                if Instant::now() >= next_keepalive {
+                    let seconds = session
+                        .keepalive(KEEPALIVE_INTERVAL)
+                        .context("Connection to remote site lost.")?;
+                    next_keepalive = Instant::now() + Duration::from_secs(seconds.max(1).into());
+                }
+                sleep(Duration::from_millis(100));
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// Bind to the given socket path, replacing stale sockets from daemons that are gone.
+fn bind(path: &Path) -> Result<UnixListener> {
+    let folder = path.parent().unwrap();
+    DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(folder)
+        .with_context(|| format!("Could not create {}", folder.display()))?;
+    // Creating succeeds if the folder already exists, which might have been done by anyone.
+    ensure_private(folder)?;
+
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            bail!("Daemon already listening on {}", path.display());
+        }
+        log::debug!("Removing stale socket {}", path.display());
+        remove_file(path)?;
+    }
+    UnixListener::bind(path).with_context(|| format!("Could not bind to {}", path.display()))
+}
+
+/// Make sure the given folder is a directory (and no symlink) owned by the current user and
+/// inaccessible to everyone else, so that no other user can place sockets in it.
+fn ensure_private(folder: &Path) -> Result<()> {
+    let metadata = symlink_metadata(folder)
+        .with_context(|| format!("Could not inspect {}", folder.display()))?;
+    if !metadata.file_type().is_dir() {
+        bail!("{} is no directory.", folder.display());
+    }
+    if metadata.uid() != geteuid().as_raw() {
+        bail!("{} is not owned by the current user.", folder.display());
+    }
+    if metadata.mode() & 0o077 != 0 {
+        bail!("{} is accessible by other users.", folder.display());
+    }
+    Ok(())
+}
+
+/// Make sure the process at the other end of the given socket runs as the current user.
+fn ensure_same_user(stream: &UnixStream) -> Result<()> {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    let uid = nix::sys::socket::getsockopt(
+        stream.as_raw_fd(),
+        nix::sys::socket::sockopt::PeerCredentials,
+    )?
+    .uid();
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    let uid = nix::unistd::getpeereid(stream.as_raw_fd())?.0.as_raw();
+
+    if uid != geteuid().as_raw() {
+        bail!("Peer runs as different user (uid {}).", uid);
+    }
+    Ok(())
+}
+
+/// Run a single request with the file descriptors of the client in place of our own.
+fn handle(session: &SshSession, mut stream: UnixStream) -> Result<()> {
+    ensure_same_user(&stream)?;
+    let fds = receive_fds(&stream)?;
+    let cwd = PathBuf::from(OsString::from_vec(read_bytes(&mut stream)?));
+    let num_args = read_u32(&mut stream)?;
+    let args = (0..num_args)
+        .map(|_| Ok(OsString::from_vec(read_bytes(&mut stream)?)))
+        .collect::<Result<Vec<_>>>()?;
+    let num_env = read_u32(&mut stream)?;
+    let env = (0..num_env)
+        .map(|_| {
+            let name = OsString::from_vec(read_bytes(&mut stream)?);
+            let value = OsString::from_vec(read_bytes(&mut stream)?);
+            Ok((name, value))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    log::debug!("Received request: {:?}", args);
+
+    let cwd_daemon = std::env::current_dir()?;
+    let env_daemon: Vec<(&str, Option<OsString>)> = CLIENT_ENV
+        .iter()
+        .map(|name| (*name, std::env::var_os(name)))
+        .collect();
+    for name in CLIENT_ENV {
+        std::env::remove_var(name);
+    }
+    for (name, value) in env.iter() {
+        if CLIENT_ENV.iter().any(|n| name == n) {
+            std::env::set_var(name, value);
+        }
+    }
+    let level_daemon = log::max_level();
+    let saved = STDIO
+        .iter()
+        .map(|fd| dup(*fd))
+        .collect::<Result<Vec<_>, _>>()?;
+    for (fd, target) in fds.iter().zip(STDIO.iter()) {
+        dup2(*fd, *target)?;
+    }
+
+    let code = catch_unwind(AssertUnwindSafe(|| run_request(session, &cwd, args))).unwrap_or(101);
+    let _ = io::stdout().flush();
+    let _ = io::stderr().flush();
+
+    for (fd, target) in saved.iter().zip(STDIO.iter()) {
+        dup2(*fd, *target)?;
+        close(*fd)?;
+    }
+    for fd in fds {
+        close(fd)?;
+    }
+    std::env::set_current_dir(cwd_daemon)?;
+    for (name, value) in env_daemon {
+        match value {
+            Some(value) => std::env::set_var(name, value),
+            None => std::env::remove_var(name),
+        }
+    }
+    log::set_max_level(level_daemon);
+
+    stream.write_all(&code.to_be_bytes())?;
+    Ok(())
+}
+
+/// Parse and run the command line of a client, returning the exit code.
+fn run_request(session: &SshSession, cwd: &Path, args: Vec<OsString>) -> i32 {
+    let args_lossy: Vec<String> = args
+        .iter()
+        .map(|arg| arg.to_string_lossy().to_string())
+        .collect();
+    let opts = match Opts::try_parse_from(args) {
+        Ok(opts) => opts,
+        Err(e) => {
+            let _ = e.print();
+            return if e.use_stderr() { 2 } else { 0 };
+        }
+    };
+    let exit_code_on_error = opts.exit_code_on_error();
+
+    match try_run_request(session, cwd, opts, args_lossy) {
+        Ok(()) => 0,
+        Err(err) => {
+            log::error!("{}", err);
+            err.downcast_ref::<ExitCodeError>()
+                .map(|e| e.code)
+                .unwrap_or(exit_code_on_error)
+        }
+    }
+}
+
+fn try_run_request(session: &SshSession, cwd: &Path, opts: Opts, args: Vec<String>) -> Result<()> {
+    std::env::set_current_dir(cwd)
+        .with_context(|| format!("Could not change into {}", cwd.display()))?;
+
+    let cfg = cli::setup(&opts, args)?;
+    let host = cfg.select_host(opts.host.as_deref(), opts.cmd.local_files())?;
+    if host.alias != session.host.alias {
+        bail!(
+            "Daemon serves host '{}', but '{}' was requested.",
+            session.host.alias,
+            host.alias
+        );
+    }
+    if let UserCommand::Daemon(_) = opts.cmd {
+        bail!("Daemon is already running.");
+    }
+    if opts.needs_base_folder() {
+        crate::ensure_base_folder(session, &cfg)?;
+    }
+
+    opts.cmd.run(session, &cfg)
+}
+
+/// Receive the file descriptors handed over by `dispatch`.
+fn receive_fds(stream: &UnixStream) -> Result<Vec<RawFd>> {
+    let mut magic = [0u8; 4];
+    let mut iov = [IoSliceMut::new(&mut magic)];
+    let mut cmsg_buffer = nix::cmsg_space!([RawFd; 3]);
+    let msg = recvmsg::<()>(
+        stream.as_raw_fd(),
+        &mut iov,
+        Some(&mut cmsg_buffer),
+        MsgFlags::empty(),
+    )?;
+
+    let mut fds = Vec::new();
+    for cmsg in msg.cmsgs() {
+        if let ControlMessageOwned::ScmRights(received) = cmsg {
+            fds.extend(received);
+        }
+    }
+    if &magic != MAGIC || fds.len() != STDIO.len() {
+        for fd in fds {
+            close(fd)?;
+        }
+        bail!("Received malformed request.");
+    }
+    Ok(fds)
+}
+
+fn read_u32(stream: &mut UnixStream) -> Result<u32> {
+    let mut buf = [0u8; 4];
+    stream.read_exact(&mut buf)?;
+    Ok(u32::from_be_bytes(buf))
+}
+
+fn read_bytes(stream: &mut UnixStream) -> Result<Vec<u8>> {
+    let len = read_u32(stream)?;
+    let mut buf = vec![0u8; len as usize];
+    stream.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn write_bytes(stream: &mut UnixStream, bytes: &[u8]) -> Result<()> {
+    stream.write_all(&(bytes.len() as u32).to_be_bytes())?;
+    stream.write_all(bytes)?;
+    Ok(())
+}
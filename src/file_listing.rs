@@ -1,16 +1,105 @@
+use crate::at::{self, ExpirationJob};
+use crate::cache::Expirations;
 use crate::cfg::Host;
-use crate::cli::text;
+use crate::cli::{color, text};
+use crate::compress;
 use crate::ssh::SshSession;
+use crate::template::Template;
 use crate::util;
 
 use anyhow::{bail, Context, Result};
 use chrono::{Local, LocalResult, TimeZone};
 use itertools::Itertools;
 use regex::Regex;
+use serde::Serialize;
 use ssh2::FileStat;
-use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap};
 use std::path::{Path, PathBuf};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Fields available in `--format` templates for file listings.
+pub const FORMAT_FIELDS: &[&str] = &[
+    "index",
+    "rev_index",
+    "url",
+    "path",
+    "name",
+    "hash",
+    "size",
+    "mtime",
+];
+
+/// Template fields that require stats to be fetched.
+pub const FORMAT_FIELDS_STATS: &[&str] = &["size", "mtime"];
+
+/// A listed file as printed via `--json`.
+#[derive(Debug, Serialize)]
+pub struct JsonEntry {
+    /// Index as used for selecting files.
+    pub index: usize,
+
+    /// Name of the file.
+    pub filename: String,
+
+    pub url: String,
+
+    /// Path relative to the base folder.
+    pub path: PathBuf,
+
+    /// Hash the file was uploaded under.
+    pub hash: String,
+
+    /// Size in bytes.
+    pub size: Option<u64>,
+
+    /// Modification time (seconds since epoch).
+    pub mtime: Option<u64>,
+
+    /// Scheduled expiration (RFC 3339), as recorded locally when pushing the file.
+    pub expiration: Option<String>,
+}
+
+/// How files are presented in listings (see `--show` of `list` and `check`).
+#[derive(clap::ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Show {
+    /// URL under which the file is served.
+    Url,
+
+    /// Name of the file only.
+    Filename,
+
+    /// Path relative to the base folder of the host.
+    Path,
+
+    /// Absolute path on the remote site.
+    Absolute,
+}
+
+impl Show {
+    /// Select `--show`, unless overridden by the legacy `--filenames` flag.
+    pub fn or_filenames(self, filenames: bool) -> Self {
+        if filenames {
+            Show::Filename
+        } else {
+            self
+        }
+    }
+
+    /// Present the given file (relative to the host's base folder).
+    pub fn render(self, ssh: &SshSession, file: &Path) -> Result<String> {
+        Ok(match self {
+            Show::Url => ssh.host.get_url(&format!("{}", file.display()))?,
+            Show::Filename => file
+                .file_name()
+                .with_context(|| format!("Invalid remote file name: {}", file.display()))?
+                .to_string_lossy()
+                .to_string(),
+            Show::Path => file.display().to_string(),
+            Show::Absolute => ssh.prepend_base_folder(file).display().to_string(),
+        })
+    }
+}
 
 /// Helper structure to avoid re-implementing file listing capabilities for all commands.
 pub struct FileListing<'a> {
@@ -18,12 +107,25 @@ pub struct FileListing<'a> {
     all_files: HashMap<usize, PathBuf>,
     pub indices: Vec<usize>,
     pub stats: Option<HashMap<usize, FileStat>>,
+    /// Stats of all files if they were retrieved together with the listing.
+    stats_listed: Option<HashMap<usize, FileStat>>,
+    /// Pending expiration jobs if they should be shown (see `with_expirations`).
+    expirations: Option<Vec<ExpirationJob>>,
     ssh: &'a SshSession<'a>,
 }
 
 impl<'a> FileListing<'a> {
     pub fn new(ssh: &'a SshSession) -> Result<FileListing<'a>> {
-        let all_files: HashMap<_, _> = ssh.all_files()?.into_iter().enumerate().collect();
+        let (all_files, stats_listed): (HashMap<_, _>, _) = match ssh.all_files_with_stats()? {
+            Some(listed) => {
+                let (files, stats): (Vec<_>, Vec<_>) = listed.into_iter().unzip();
+                (
+                    files.into_iter().enumerate().collect(),
+                    Some(stats.into_iter().enumerate().collect()),
+                )
+            }
+            None => (ssh.all_files()?.into_iter().enumerate().collect(), None),
+        };
         let num_files = all_files.len();
 
         Ok(Self {
@@ -31,6 +133,8 @@ impl<'a> FileListing<'a> {
             all_files,
             indices: Vec::new(),
             stats: None,
+            stats_listed,
+            expirations: None,
             ssh,
         })
     }
@@ -61,6 +165,86 @@ impl<'a> FileListing<'a> {
         }
     }
 
+    /// Select all files that are empty or whose content does not match the hash in the name of
+    /// their folder (e.g., left behind by interrupted uploads).
+    pub fn by_broken(mut self, select: bool, prefix_length: u8) -> Result<Self> {
+        if !select {
+            return Ok(self);
+        }
+        let mut broken = self.empty_indices()?;
+        broken.append(&mut self.mismatched_indices(prefix_length)?);
+
+        self.indices.append(&mut broken);
+        self.indices = Self::make_unique(self.indices.drain(..));
+        Ok(self)
+    }
+
+    /// Indices of all (not only selected) files that are empty.
+    pub fn empty_indices(&mut self) -> Result<Vec<usize>> {
+        self.ensure_stats_all()?;
+        let stats = self.stats.as_ref().unwrap();
+        Ok((0..self.num_files)
+            .filter(|idx| stats.get(idx).and_then(|s| s.size) == Some(0))
+            .collect())
+    }
+
+    /// Indices of all (not only selected) non-empty files whose content does not match the hash
+    /// in the name of their folder.
+    pub fn mismatched_indices(&mut self, prefix_length: u8) -> Result<Vec<usize>> {
+        let empty = self.empty_indices()?;
+        let to_hash: Vec<usize> = (0..self.num_files)
+            .filter(|idx| !empty.contains(idx))
+            .collect();
+        let paths: Vec<&Path> = to_hash
+            .iter()
+            .map(|i| self.all_files.get(i).unwrap().as_path())
+            .collect();
+
+        let mut hashes = Vec::with_capacity(paths.len());
+        for chunk in paths.chunks(self.ssh.hash_batch_size()?) {
+            hashes.extend(self.ssh.get_remote_hashes(chunk, prefix_length)?);
+        }
+
+        // The prefix length might have changed since the file was uploaded.
+        let layout = &self.ssh.host.layout;
+        let matches_folder = |path: &Path, hash: &str| {
+            let folder = layout
+                .upload_folder(path)
+                .map(|p| p.to_string_lossy().to_string())
+                .unwrap_or_default();
+            hash.starts_with(&folder) || folder.starts_with(hash)
+        };
+        let mismatched: Vec<(usize, &Path)> = to_hash
+            .iter()
+            .zip(paths.iter())
+            .zip(hashes.iter())
+            .filter(|((_, path), hash)| !matches_folder(path, hash))
+            .map(|((idx, path), _)| (*idx, *path))
+            .collect();
+
+        // Compressed uploads are stored under the hash of their content.
+        let mismatched_paths: Vec<&Path> = mismatched.iter().map(|(_, path)| *path).collect();
+        let decompressed = compress::remote_hashes(self.ssh, &mismatched_paths, prefix_length)
+            .unwrap_or_else(|e| {
+                log::debug!("Could not hash decompressed files: {:#}", e);
+                vec![None; mismatched.len()]
+            });
+        Ok(mismatched
+            .iter()
+            .zip(decompressed.iter())
+            .filter(|((_, path), hash)| {
+                let matches = hash
+                    .as_deref()
+                    .is_some_and(|hash| matches_folder(path, hash));
+                if !matches {
+                    log::debug!("Hash mismatch for {}", path.display());
+                }
+                !matches
+            })
+            .map(|((idx, _), _)| *idx)
+            .collect())
+    }
+
     /// Select all files with corresponding indices
     pub fn by_indices(self, indices: &[i64]) -> Result<Self> {
         if !indices.is_empty() {
@@ -95,32 +279,17 @@ impl<'a> FileListing<'a> {
         prefix_length: u8,
         bail_when_missing: bool,
     ) -> Result<Self> {
-        let mut names = names.into_iter().peekable();
-        if names.peek().is_none() {
+        let names: Vec<T> = names.into_iter().collect();
+        if names.is_empty() {
             Ok(self)
         } else {
+            let found = self.indices_by_hash(names.iter(), prefix_length)?;
             let indices = {
                 let mut indices = self.indices;
 
-                let hash_to_file: HashMap<String, usize> = self
-                    .all_files
-                    .iter()
-                    .filter(|(_, path)| path.parent().is_some())
-                    .map(|(idx, path)| {
-                        let prefix = path.parent().unwrap();
-                        let truncated_prefix = prefix
-                            .to_string_lossy()
-                            .chars()
-                            .take(prefix_length as usize)
-                            .collect();
-                        (truncated_prefix, *idx)
-                    })
-                    .collect();
-
-                for file in names {
-                    let hash = util::get_hash(Path::new(file.as_ref()), prefix_length)?;
-                    match hash_to_file.get(&hash) {
-                        Some(idx) => indices.push(*idx),
+                for (file, idx) in names.iter().zip(found) {
+                    match idx {
+                        Some(idx) => indices.push(idx),
                         None => {
                             let msg = format!(
                                 "No file with same hash found on server: {}",
@@ -140,11 +309,139 @@ impl<'a> FileListing<'a> {
         }
     }
 
+    /// Select a single file either by index or by specifying a local file with the same hash.
+    ///
+    /// Bails if no (or more than one) file matches.
+    pub fn by_index_or_file(self, input: &str, prefix_length: u8) -> Result<Self> {
+        let selected = match input.parse::<i64>() {
+            Ok(idx) => self.by_indices(&[idx])?,
+            Err(_) => self.by_hash([input], prefix_length, /* bail_when_missing = */ false)?,
+        };
+
+        match selected.count() {
+            0 => match input.parse::<i64>() {
+                Ok(idx) => bail!("Invalid remote index specified: {}", idx),
+                Err(_) => bail!("File not uploaded to remote site: {}", input),
+            },
+            1 => Ok(selected),
+            n => bail!(
+                "Found {} matching remote files, this should never happen.",
+                n
+            ),
+        }
+    }
+
+    /// Get the index of the remote file with the same hash for each of the given local files
+    /// (None if the file is not present on the remote site).
+    ///
+    /// Files uploaded with a different prefix length (e.g., before `prefix_length` was changed)
+    /// are found as well, but files uploaded with the given prefix length take precedence.
+    pub fn indices_by_hash<T: AsRef<str>>(
+        &self,
+        names: impl IntoIterator<Item = T>,
+        prefix_length: u8,
+    ) -> Result<Vec<Option<usize>>> {
+        // Remote folders by length of their name, later uploads take precedence.
+        let mut hash_to_file: BTreeMap<usize, HashMap<String, usize>> = BTreeMap::new();
+        let layout = &self.ssh.host.layout;
+        for (idx, path) in self.all_files.iter() {
+            if let Some(prefix) = layout.upload_folder(path).map(|p| p.to_string_lossy()) {
+                if util::is_hash_folder(&prefix) {
+                    let by_length = hash_to_file.entry(prefix.len()).or_default();
+                    match by_length.get(prefix.as_ref()) {
+                        Some(existing) if existing > idx => {}
+                        _ => {
+                            by_length.insert(prefix.to_string(), *idx);
+                        }
+                    }
+                }
+            }
+        }
+
+        names
+            .into_iter()
+            .map(|file| {
+                let path = Path::new(file.as_ref());
+                let mut hashes: HashMap<u8, String> = HashMap::new();
+                let mut hash_of = |length: u8| -> Result<String> {
+                    // Shorter hashes are prefixes of longer ones computed with the same hasher.
+                    let full_length = if length <= 32 { 32 } else { 64 };
+                    let hash = match hashes.entry(full_length) {
+                        Entry::Occupied(hash) => hash.into_mut(),
+                        Entry::Vacant(hash) => hash.insert(util::get_hash(path, full_length)?),
+                    };
+                    Ok(hash[..length as usize].to_string())
+                };
+
+                let hash = hash_of(prefix_length)?;
+                if let Some(idx) = hash_to_file
+                    .get(&(prefix_length as usize))
+                    .and_then(|by_length| by_length.get(&hash))
+                {
+                    return Ok(Some(*idx));
+                }
+                for (length, by_length) in hash_to_file.iter() {
+                    if *length == prefix_length as usize {
+                        continue;
+                    }
+                    if let Some(idx) = by_length.get(&hash_of(*length as u8)?) {
+                        log::debug!(
+                            "Found {} uploaded with different prefix length: {}",
+                            path.display(),
+                            length
+                        );
+                        return Ok(Some(*idx));
+                    }
+                }
+                Ok(None)
+            })
+            .collect()
+    }
+
+    /// Restrict the current selection to the given indices.
+    pub fn restrict_to(mut self, indices: &[usize]) -> Self {
+        self.indices.retain(|idx| indices.contains(idx));
+        self
+    }
+
+    /// Drop pinned files (see `pin`-command) from the selection if `exclude` is set.
+    pub fn without_pinned(mut self, exclude: bool) -> Result<Self> {
+        if exclude {
+            let pins = self.ssh.pins()?;
+            if pins.is_empty() {
+                return Ok(self);
+            }
+            let layout = &self.ssh.host.layout;
+            let all_files = &self.all_files;
+            let num_selected = self.indices.len();
+            self.indices.retain(|idx| {
+                let path = all_files.get(idx).unwrap();
+                !layout
+                    .upload_folder(path)
+                    .map(|folder| pins.contains(folder))
+                    .unwrap_or(false)
+            });
+            let skipped = num_selected - self.indices.len();
+            if skipped > 0 {
+                log::warn!(
+                    "Skipping {} pinned file(s), specify --include-pinned to select them.",
+                    skipped
+                );
+            }
+        }
+        Ok(self)
+    }
+
     /// Return count of currently selected files
     pub fn count(&self) -> usize {
         self.indices.len()
     }
 
+    /// Get the remote path (relative to the base folder) of the file with the given index.
+    pub fn path_of(&self, idx: usize) -> Option<&Path> {
+        self.all_files.get(&idx).map(|p| p.as_path())
+    }
+
     /// Check if file listing has stats
     pub fn has_stats(&self) -> bool {
         self.stats.is_some()
@@ -191,25 +488,18 @@ impl<'a> FileListing<'a> {
     /// path.
     pub fn format_files(
         &self,
-        host: Option<&Host>,
-        filename_only: bool,
+        show: Show,
         with_size: bool,
         with_time: bool,
     ) -> Result<Vec<String>> {
-        let (num_digits, num_digits_rev) = (self.get_num_digits(), self.get_num_digits_rev()?);
+        let (num_digits, num_digits_rev) = (self.get_num_digits(), self.get_num_digits_rev());
         self.iter()
             .map(|(i, file, stat)| -> Result<String> {
                 Ok(format!(
-                    " {idx:width$}{sep}{rev_idx:rev_width$}{sep}{size}{mtime}{url} ",
+                    " {idx:width$}{sep}{rev_idx:rev_width$}{sep}{size}{mtime}{expires}{url} ",
                     idx = i,
                     rev_idx = i as i64 - self.num_files as i64,
-                    url = if filename_only {
-                        file.file_name().unwrap().to_string_lossy().to_string()
-                    } else if let Some(host) = host {
-                        host.get_url(&format!("{}", file.display()))?
-                    } else {
-                        file.display().to_string()
-                    },
+                    url = show.render(self.ssh, file)?,
                     width = num_digits,
                     rev_width = num_digits_rev,
                     sep = text::separator(),
@@ -226,12 +516,96 @@ impl<'a> FileListing<'a> {
                             .unwrap_or_else(|| Ok("".to_string()))?
                     } else {
                         "".to_string()
-                    }
+                    },
+                    expires = self.column_expiration(file),
                 ))
             })
             .collect()
     }
 
+    /// Format selected files according to a user-supplied template (see `FORMAT_FIELDS`).
+    ///
+    /// Stats need to be fetched beforehand if the template uses `FORMAT_FIELDS_STATS`.
+    pub fn format_template(&self, host: &Host, template: &Template) -> Result<Vec<String>> {
+        self.iter()
+            .map(|(idx, file, stat)| {
+                template.render(|field| self.format_field(host, field, idx, file, stat))
+            })
+            .collect()
+    }
+
+    /// Machine-readable entries for all selected files (see `JsonEntry`).
+    ///
+    /// Size and mtime are only included if stats were fetched beforehand.
+    pub fn to_json(&self, host: &Host) -> Result<Vec<JsonEntry>> {
+        let expirations = Expirations::load(&host.alias)
+            .map_err(|e| log::debug!("Could not load recorded expirations: {}", e))
+            .ok();
+        let expiration_of = |file: &Path| {
+            expirations.as_ref().and_then(|expirations| {
+                expirations
+                    .pending()
+                    .iter()
+                    .find(|e| file.starts_with(&e.path))
+                    .map(|e| e.date.to_rfc3339())
+            })
+        };
+        self.iter()
+            .map(|(idx, file, stat)| {
+                Ok(JsonEntry {
+                    index: idx,
+                    filename: file
+                        .file_name()
+                        .map(|n| n.to_string_lossy().to_string())
+                        .unwrap_or_default(),
+                    url: host.get_url(&format!("{}", file.display()))?,
+                    path: file.to_path_buf(),
+                    hash: host
+                        .layout
+                        .upload_folder(file)
+                        .map(|p| p.to_string_lossy().to_string())
+                        .unwrap_or_default(),
+                    size: stat.and_then(|s| s.size),
+                    mtime: stat.and_then(|s| s.mtime),
+                    expiration: expiration_of(file),
+                })
+            })
+            .collect()
+    }
+
+    /// Value of a single template field for the given file.
+    pub fn format_field(
+        &self,
+        host: &Host,
+        field: &str,
+        idx: usize,
+        file: &Path,
+        stat: Option<&FileStat>,
+    ) -> Result<String> {
+        let stat = || stat.with_context(|| format!("No stats for {}", file.display()));
+        Ok(match field {
+            "index" => idx.to_string(),
+            "rev_index" => (idx as i64 - self.num_files as i64).to_string(),
+            "url" => host.get_url(&format!("{}", file.display()))?,
+            "path" => file.display().to_string(),
+            "name" => file
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            "hash" => host
+                .layout
+                .upload_folder(file)
+                .map(|p| p.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            "size" => stat()?
+                .size
+                .with_context(|| "No file size defined!")?
+                .to_string(),
+            "mtime" => Self::format_mtime(stat()?)?,
+            _ => bail!("Unknown field: {}", field),
+        })
+    }
+
     pub fn revert(mut self, do_revert: bool) -> Self {
         if do_revert {
             self.indices.reverse();
@@ -240,11 +614,56 @@ impl<'a> FileListing<'a> {
     }
 
     pub fn select_newer(self, user_duration: Option<&str>) -> Result<Self> {
-        self.filter_by_time(user_duration, false)
+        let newer = user_duration.map(humantime::parse_duration).transpose()?;
+        self.filter_by_time(None, newer)
     }
 
     pub fn select_older(self, user_duration: Option<&str>) -> Result<Self> {
-        self.filter_by_time(user_duration, true)
+        let older = user_duration.map(humantime::parse_duration).transpose()?;
+        self.filter_by_time(older, None)
+    }
+
+    /// Select files whose age lies within the given window, e.g. `2d..7d` selects files older
+    /// than two days but newer than a week. Either bound can be omitted.
+    pub fn select_between(self, window: Option<&str>) -> Result<Self> {
+        match window {
+            Some(window) => {
+                let (min, max) = window.split_once("..").with_context(|| {
+                    format!("Invalid age window (expected <min>..<max>): {}", window)
+                })?;
+                let parse = |bound: &str| {
+                    (!bound.trim().is_empty())
+                        .then(|| humantime::parse_duration(bound.trim()))
+                        .transpose()
+                        .with_context(|| format!("Invalid duration in age window: {}", bound))
+                };
+                let (older, newer) = match (parse(min)?, parse(max)?) {
+                    (Some(min), Some(max)) if min > max => (Some(max), Some(min)),
+                    bounds => bounds,
+                };
+                self.filter_by_time(older, newer)
+            }
+            None => Ok(self),
+        }
+    }
+
+    /// Replace the size of selected files with their disk usage (see `SshSession::disk_usage`).
+    pub fn with_disk_usage(mut self, disk_usage: bool) -> Result<Self> {
+        if disk_usage {
+            self.ensure_stats()?;
+            let usage = self.ssh.disk_usage()?;
+            let stats = self.stats.as_mut().unwrap();
+            for idx in self.indices.iter() {
+                let path = self.all_files.get(idx).unwrap();
+                let stat = stats.get_mut(idx).unwrap();
+                stat.size = Some(
+                    *usage
+                        .get(path)
+                        .with_context(|| format!("No disk usage for {}", path.display()))?,
+                );
+            }
+        }
+        Ok(self)
     }
 
     pub fn sort_by_size(mut self, sort_by_size: bool) -> Result<Self> {
@@ -293,42 +712,69 @@ impl<'a> FileListing<'a> {
         Ok(self)
     }
 
-    fn column_size(&self, stat: &FileStat) -> Result<String> {
-        let possible = ["B", "K", "M", "G", "T", "P", "E"];
-        let mut size: u64 = stat.size.with_context(|| "No file size defined!")?;
-        for (i, s) in possible.iter().enumerate() {
-            // If size is >= 999.5 (which we cannot detect via integer), the printed representation
-            // will be rouned to 1000.00 -> move to next higher unit at 999
-            if 999 <= size {
-                size >>= 10;
-                continue;
-            } else {
-                return Ok(format!(
-                    "{size:>6.2}{suffix}{sep}",
-                    size = stat.size.unwrap() as f64 / (1 << (i * 10)) as f64,
-                    suffix = s,
-                    sep = text::separator()
-                ));
-            }
+    /// Show when pending expiration jobs run in `format_files`.
+    pub fn with_expirations(mut self, with_expirations: bool) -> Result<Self> {
+        if with_expirations {
+            self.expirations = Some(at::list_jobs(self.ssh)?);
         }
-        bail!("Invalid size argument provided.")
+        Ok(self)
+    }
+
+    fn column_size(&self, stat: &FileStat) -> Result<String> {
+        Ok(format!(
+            "{size}{sep}",
+            size = util::format_size(stat.size.with_context(|| "No file size defined!")?),
+            sep = text::separator()
+        ))
     }
 
     fn column_time(&self, stat: &FileStat) -> Result<String> {
+        Ok(format!(
+            "{mtime}{sep}",
+            mtime = Self::format_mtime(stat)?,
+            sep = text::separator()
+        ))
+    }
+
+    fn column_expiration(&self, file: &Path) -> String {
+        let jobs = match self.expirations.as_ref() {
+            Some(jobs) => jobs,
+            None => return "".to_string(),
+        };
+        let expires = jobs
+            .iter()
+            .find(|job| file.starts_with(&job.path))
+            .map(|job| match job.date {
+                Some(date) => date.format("%Y-%m-%d %H:%M").to_string(),
+                None => "?".to_string(),
+            })
+            .unwrap_or_default();
+        format!(
+            "{expires}{sep}",
+            expires = color::expire.apply_to(format!("{:16}", expires)),
+            sep = text::separator()
+        )
+    }
+
+    fn format_mtime(stat: &FileStat) -> Result<String> {
         if let LocalResult::Single(mtime) =
             Local.timestamp_opt(stat.mtime.with_context(|| "File has no mtime.")? as i64, 0)
         {
-            Ok(format!(
-                "{mtime}{sep}",
-                mtime = mtime.format("%Y-%m-%d %H:%M:%S"),
-                sep = text::separator()
-            ))
+            Ok(mtime.format("%Y-%m-%d %H:%M:%S").to_string())
         } else {
             bail!("failed to convert time");
         }
     }
 
     fn ensure_stats(&mut self) -> Result<()> {
+        if let (None, Some(listed)) = (self.stats.as_ref(), self.stats_listed.as_ref()) {
+            self.stats = Some(
+                self.indices
+                    .iter()
+                    .filter_map(|idx| Some((*idx, listed.get(idx)?.clone())))
+                    .collect(),
+            );
+        }
         if self.stats.is_none() {
             let paths = self
                 .indices
@@ -341,6 +787,25 @@ impl<'a> FileListing<'a> {
         Ok(())
     }
 
+    /// Make sure stats are available for all files, not only the selected ones.
+    fn ensure_stats_all(&mut self) -> Result<()> {
+        let stats = self.stats.get_or_insert_with(HashMap::new);
+        if let Some(listed) = self.stats_listed.as_ref() {
+            stats.extend(listed.iter().map(|(idx, stat)| (*idx, stat.clone())));
+        }
+        let missing: Vec<usize> = (0..self.num_files)
+            .filter(|idx| !stats.contains_key(idx))
+            .collect();
+        if !missing.is_empty() {
+            let paths = missing
+                .iter()
+                .map(|i| self.all_files.get(i).unwrap().as_path());
+            let raw_stats = self.ssh.stat(paths)?;
+            stats.extend(missing.into_iter().zip(raw_stats));
+        }
+        Ok(())
+    }
+
     fn make_unique<I: IntoIterator<Item = usize>>(indices: I) -> Vec<usize> {
         indices.into_iter().unique().collect()
     }
@@ -357,57 +822,52 @@ impl<'a> FileListing<'a> {
     }
 
     /// Get number of digits for reverse index
-    fn get_num_digits_rev(&self) -> Result<usize> {
+    fn get_num_digits_rev(&self) -> usize {
         let mut num_digits = 0;
-        let mut num = self.num_files
-            - self
-                .iter()
-                .map(|f| f.0)
-                .min()
-                .with_context(|| "No files to list.")
-                .unwrap_or(0);
+        // Nothing selected needs no digits at all.
+        let mut num = self.iter().map(|f| self.num_files - f.0).max().unwrap_or(0);
         while num > 0 {
             num /= 10;
             num_digits += 1;
         }
-        Ok(num_digits + 1) /* minus sign */
+        num_digits + 1 /* minus sign */
     }
 
     /// Helper function that filters selected files by date.
     ///
     /// If select_older == true, then only files older than user_duration will be kept;
     /// if false, only files newer than user_duration will be kept.
-    fn filter_by_time(mut self, user_duration: Option<&str>, select_older: bool) -> Result<Self> {
-        if let Some(user_duration) = user_duration {
-            let duration = humantime::parse_duration(user_duration)?;
-            self.ensure_stats()?;
-            let stats = self.stats.as_ref().unwrap();
-
-            let now = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .expect("Time went backwards.");
-            let cutoff = now
+    /// Only keep files older than `older` and newer than `newer` (both relative to now).
+    fn filter_by_time(mut self, older: Option<Duration>, newer: Option<Duration>) -> Result<Self> {
+        if older.is_none() && newer.is_none() {
+            return Ok(self);
+        }
+        self.ensure_stats()?;
+        let stats = self.stats.as_ref().unwrap();
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Time went backwards.");
+        let cutoff = |duration: Duration| -> Result<u64> {
+            Ok(now
                 .checked_sub(duration)
-                .context("Invalid duration specified.")?;
-            let cutoff_s = cutoff.as_secs();
-
-            let indices: Vec<_> = self
-                .indices
-                .into_iter()
-                .filter(|idx| {
-                    let mtime = stats.get(idx).unwrap().mtime.unwrap();
-                    if select_older {
-                        mtime <= cutoff_s
-                    } else {
-                        mtime >= cutoff_s
-                    }
-                })
-                .collect();
+                .context("Invalid duration specified.")?
+                .as_secs())
+        };
+        let cutoff_older = older.map(cutoff).transpose()?;
+        let cutoff_newer = newer.map(cutoff).transpose()?;
+
+        let indices: Vec<_> = self
+            .indices
+            .into_iter()
+            .filter(|idx| {
+                let mtime = stats.get(idx).unwrap().mtime.unwrap();
+                cutoff_older.map(|c| mtime <= c).unwrap_or(true)
+                    && cutoff_newer.map(|c| mtime >= c).unwrap_or(true)
+            })
+            .collect();
 
-            Ok(Self { indices, ..self })
-        } else {
-            Ok(self)
-        }
+        Ok(Self { indices, ..self })
     }
 }
 
@@ -444,3 +904,62 @@ impl<'a> Iterator for FileListingIter<'a> {
         Some((idx, file, stat))
     }
 }
+
+/// Select entries not created by asfa (see `SshSession::foreign_entries`) by index (negative
+/// indices count from the end) or by regex matching their name, keeping the original index.
+pub fn select_foreign(
+    entries: Vec<(PathBuf, FileStat)>,
+    indices: &[i64],
+    filter: Option<&str>,
+    all: bool,
+) -> Result<Vec<(usize, PathBuf, FileStat)>> {
+    let num_entries = entries.len() as i64;
+    let mut selected = Vec::with_capacity(indices.len());
+    for idx in indices {
+        let resolved = if *idx < 0 { num_entries + idx } else { *idx };
+        if !(0..num_entries).contains(&resolved) {
+            bail!("Invalid index: {}", idx);
+        }
+        selected.push(resolved as usize);
+    }
+    let re = filter.map(Regex::new).transpose()?;
+    Ok(entries
+        .into_iter()
+        .enumerate()
+        .filter(|(idx, (path, _))| {
+            all || selected.contains(idx)
+                || re
+                    .as_ref()
+                    .map(|re| re.is_match(&path.to_string_lossy()))
+                    .unwrap_or(false)
+        })
+        .map(|(idx, (path, stat))| (idx, path, stat))
+        .collect())
+}
+
+/// Format entries not created by asfa as `<index> <kind> <size> <path>`.
+pub fn format_foreign(entries: &[(usize, PathBuf, FileStat)]) -> Vec<String> {
+    let width = entries
+        .iter()
+        .map(|(idx, _, _)| idx.to_string().len())
+        .max()
+        .unwrap_or(1);
+    entries
+        .iter()
+        .map(|(idx, path, stat)| {
+            format!(
+                " {idx:width$}{sep}{kind:6}{sep}{size:>7}{sep}{path} ",
+                idx = idx,
+                width = width,
+                sep = text::separator(),
+                kind = if stat.is_dir() { "folder" } else { "file" },
+                size = if stat.is_dir() {
+                    "".to_string()
+                } else {
+                    stat.size.map(util::format_size).unwrap_or_default()
+                },
+                path = path.display()
+            )
+        })
+        .collect()
+}
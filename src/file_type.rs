@@ -0,0 +1,113 @@
+//! Default expirations depending on the type of the uploaded file, e.g.:
+//!
+//! ```yaml
+//! expire_by_type:
+//!   png: 7days
+//!   image: 14days
+//!   archive: 30days
+//! ```
+//!
+//! Keys are either file extensions or one of the groups in `GROUPS`. Extensions take precedence
+//! over groups, files matching neither use the regular `expire` setting.
+
+use anyhow::{bail, Context, Result};
+use std::path::Path;
+use yaml_rust::{yaml::Hash, Yaml};
+
+/// Groups of file types (by extension) that can be used in `expire_by_type`.
+const GROUPS: &[(&str, &[&str])] = &[
+    (
+        "archive",
+        &[
+            "7z", "bz2", "gz", "rar", "tar", "tbz2", "tgz", "txz", "tzst", "xz", "zip", "zst",
+        ],
+    ),
+    (
+        "audio",
+        &["aac", "flac", "m4a", "mp3", "ogg", "opus", "wav"],
+    ),
+    (
+        "document",
+        &[
+            "doc", "docx", "odp", "ods", "odt", "pdf", "ppt", "pptx", "xls", "xlsx",
+        ],
+    ),
+    (
+        "image",
+        &[
+            "bmp", "gif", "heic", "jpeg", "jpg", "png", "svg", "tif", "tiff", "webp",
+        ],
+    ),
+    (
+        "text",
+        &["csv", "json", "log", "md", "txt", "xml", "yaml", "yml"],
+    ),
+    ("video", &["avi", "m4v", "mkv", "mov", "mp4", "webm"]),
+];
+
+/// Mapping from file extensions or groups to default expirations.
+#[derive(Debug, Clone, Default)]
+pub struct ExpireByType {
+    /// Extension (lowercase, without dot) or group mapped to the expiration delay.
+    entries: Vec<(String, String)>,
+}
+
+impl ExpireByType {
+    pub fn from_yaml(dict: &Hash) -> Result<Self> {
+        let mut entries = Vec::new();
+        for (key, value) in dict.iter() {
+            let key = match key {
+                Yaml::String(key) => key.trim_start_matches('.').to_lowercase(),
+                invalid => bail!("Invalid file type: {:?}", invalid),
+            };
+            let delay = match value {
+                Yaml::String(delay) => delay.clone(),
+                invalid => bail!("Invalid expiration for '{}': {:?}", key, invalid),
+            };
+            crate::at::validate_delay(&delay)
+                .with_context(|| format!("Invalid expiration for '{}'.", key))?;
+            entries.push((key, delay));
+        }
+        Ok(Self { entries })
+    }
+
+    /// Default expiration for the file with the given name, if any.
+    pub fn expire_for(&self, name: &Path) -> Option<&str> {
+        let extension = name.extension()?.to_string_lossy().to_lowercase();
+        let lookup = |key: &str| {
+            self.entries
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, delay)| delay.as_str())
+        };
+        lookup(&extension).or_else(|| {
+            GROUPS
+                .iter()
+                .filter(|(_, extensions)| extensions.contains(&extension.as_str()))
+                .find_map(|(group, _)| lookup(group))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use yaml_rust::YamlLoader;
+
+    #[test]
+    fn expire_by_extension_or_group() {
+        let yaml = YamlLoader::load_from_str("png: 7days\nimage: 14days\narchive: 30days").unwrap();
+        let expire = ExpireByType::from_yaml(yaml[0].as_hash().unwrap()).unwrap();
+        assert_eq!(
+            expire.expire_for(Path::new("screenshot.PNG")),
+            Some("7days")
+        );
+        assert_eq!(expire.expire_for(Path::new("photo.jpg")), Some("14days"));
+        assert_eq!(
+            expire.expire_for(Path::new("backup.tar.gz")),
+            Some("30days")
+        );
+        assert_eq!(expire.expire_for(Path::new("notes.txt")), None);
+        assert_eq!(expire.expire_for(Path::new("Makefile")), None);
+    }
+}
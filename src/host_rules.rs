@@ -0,0 +1,168 @@
+//! Default host depending on the context asfa is run in (Wi-Fi network, local user or the files
+//! being pushed).
+
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use std::cell::OnceCell;
+use std::path::PathBuf;
+use std::process::Command;
+use yaml_rust::{yaml, Yaml};
+
+use crate::util::get_string_from;
+
+/// Rules selecting the default host, e.g.:
+///
+/// ```yaml
+/// host_rules:
+///   - host: internal
+///     ssid: Office-WiFi
+///   - host: work
+///     path: ^/home/me/work/
+/// ```
+///
+/// The first rule whose conditions all hold selects the host.
+#[derive(Debug, Clone, Default)]
+pub struct HostRules {
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+    host: String,
+    /// Name of the Wi-Fi network currently connected to.
+    ssid: Option<String>,
+    /// Name of the local user.
+    user: Option<String>,
+    /// Matched against the absolute paths of all files to push.
+    path: Option<Regex>,
+}
+
+impl HostRules {
+    pub fn from_yaml(rules: &yaml::Array) -> Result<Self> {
+        let rules = rules
+            .iter()
+            .map(|rule| match rule {
+                Yaml::Hash(dict) => {
+                    for key in dict.keys() {
+                        match key.as_str() {
+                            Some("host" | "ssid" | "user" | "path") => {}
+                            _ => bail!("Unknown condition in 'host_rules': {:?}", key),
+                        }
+                    }
+                    Ok(Rule {
+                        host: get_string_from(dict, "host")?
+                            .cloned()
+                            .context("Every entry in 'host_rules' needs a 'host'.")?,
+                        ssid: get_string_from(dict, "ssid")?.cloned(),
+                        user: get_string_from(dict, "user")?.cloned(),
+                        path: get_string_from(dict, "path")?
+                            .map(|path| Regex::new(path))
+                            .transpose()?,
+                    })
+                }
+                _ => bail!("Entries in 'host_rules' need to be dictionaries."),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { rules })
+    }
+
+    /// Hosts referred to by any rule.
+    pub fn hosts(&self) -> impl Iterator<Item = &str> {
+        self.rules.iter().map(|rule| rule.host.as_str())
+    }
+
+    /// Host selected by the first matching rule when pushing the given files (empty for other
+    /// commands), if any.
+    pub fn select(&self, files: &[PathBuf]) -> Option<&str> {
+        let ssid = OnceCell::new();
+        let ssid = || ssid.get_or_init(current_ssid).clone();
+        let user = whoami::username();
+        let rule = self.rules.iter().find(|rule| {
+            rule.user.as_ref().map(|u| *u == user).unwrap_or(true)
+                && rule
+                    .path
+                    .as_ref()
+                    .map(|path| matches_all(path, files))
+                    .unwrap_or(true)
+                && rule
+                    .ssid
+                    .as_ref()
+                    .map(|s| ssid().as_ref() == Some(s))
+                    .unwrap_or(true)
+        })?;
+        log::debug!("Selected host {} via 'host_rules'.", rule.host);
+        Some(&rule.host)
+    }
+}
+
+/// Whether there are files and all of them match the given regex (as absolute paths).
+fn matches_all(regex: &Regex, files: &[PathBuf]) -> bool {
+    let cwd = std::env::current_dir().unwrap_or_default();
+    !files.is_empty()
+        && files.iter().all(|file| {
+            let absolute = if file.is_absolute() {
+                file.to_path_buf()
+            } else {
+                cwd.join(file)
+            };
+            regex.is_match(&absolute.to_string_lossy())
+        })
+}
+
+/// Name of the Wi-Fi network currently connected to, as reported by the first available tool.
+fn current_ssid() -> Option<String> {
+    let output = |cmd: &str, args: &[&str]| -> Option<String> {
+        let output = Command::new(cmd).args(args).output().ok()?;
+        output
+            .status
+            .success()
+            .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+    };
+    let ssid = output("iwgetid", &["-r"])
+        .map(|out| out.trim().to_string())
+        .filter(|ssid| !ssid.is_empty())
+        .or_else(|| {
+            output("nmcli", &["-t", "-f", "active,ssid", "dev", "wifi"])?
+                .lines()
+                .find_map(|line| line.strip_prefix("yes:").map(str::to_string))
+        })
+        .or_else(|| {
+            output("networksetup", &["-getairportnetwork", "en0"])?
+                .trim()
+                .strip_prefix("Current Wi-Fi Network: ")
+                .map(str::to_string)
+        });
+    log::debug!("Current Wi-Fi network: {:?}", ssid);
+    ssid
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use yaml_rust::YamlLoader;
+
+    fn parse(yaml: &str) -> Result<HostRules> {
+        match &YamlLoader::load_from_str(yaml).unwrap()[0] {
+            Yaml::Array(rules) => HostRules::from_yaml(rules),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn select_by_path_and_user() {
+        let rules = parse(&format!(
+            "- host: work\n  path: ^/home/me/work/\n- host: mine\n  user: {}\n- host: other\n",
+            whoami::username()
+        ))
+        .unwrap();
+        let work = [PathBuf::from("/home/me/work/a.pdf")];
+        assert_eq!(rules.select(&work), Some("work"));
+        let mixed = [work[0].clone(), PathBuf::from("/tmp/b.pdf")];
+        assert_eq!(rules.select(&mixed), Some("mine"));
+        assert_eq!(rules.select(&[]), Some("mine"));
+        assert_eq!(rules.hosts().collect::<Vec<_>>(), ["work", "mine", "other"]);
+
+        assert!(parse("- path: ^/home\n").is_err());
+        assert!(parse("- host: a\n  network: 10.0.0.0/8\n").is_err());
+    }
+}
@@ -0,0 +1,22 @@
+//! Helpers to query the webserver serving the uploaded files.
+
+use anyhow::Result;
+
+use crate::parallel;
+
+/// Number of requests performed in parallel.
+const NUM_WORKERS: usize = 8;
+
+/// Send a HEAD-request to each of the given URLs in parallel and return the HTTP status codes (in
+/// the same order).
+pub fn head_all(urls: &[String]) -> Vec<Result<reqwest::StatusCode>> {
+    let client = reqwest::blocking::Client::new();
+    parallel::map(urls, NUM_WORKERS, |url| {
+        log::debug!("HEAD {}", url);
+        client
+            .head(url)
+            .send()
+            .map(|r| r.status())
+            .map_err(|e| e.into())
+    })
+}
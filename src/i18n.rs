@@ -0,0 +1,217 @@
+//! Localization of user-facing messages (prompts, summaries and errors).
+//!
+//! Messages are looked up by key in the bundle of the selected language (`locales/<code>.yaml`,
+//! compiled into the binary) and fall back to English if missing. Placeholders of the form
+//! `{name}` are filled via the `tr!`-macro:
+//!
+//! ```ignore
+//! tr!("clean.scheduled", count = 3, date = date)
+//! ```
+//!
+//! To add a language, add its bundle to `locales/` and a variant to `Language`.
+
+use anyhow::{bail, Result};
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::{Mutex, OnceLock};
+use yaml_rust::{Yaml, YamlLoader};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Language {
+    English,
+    German,
+}
+
+impl Language {
+    const ALL: &'static [Language] = &[Language::English, Language::German];
+
+    /// Code used in config and locale environment variables.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Language::English => "en",
+            Language::German => "de",
+        }
+    }
+
+    fn bundle_source(&self) -> &'static str {
+        match self {
+            Language::English => include_str!("../locales/en.yaml"),
+            Language::German => include_str!("../locales/de.yaml"),
+        }
+    }
+
+    /// Parse a language code as given via `language` in config.
+    pub fn from_code(code: &str) -> Result<Self> {
+        match Self::ALL.iter().find(|l| l.code() == code) {
+            Some(language) => Ok(*language),
+            None => bail!(
+                "Unsupported language (expected one of: {}): {}",
+                Self::ALL
+                    .iter()
+                    .map(|l| l.code())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                code
+            ),
+        }
+    }
+
+    /// Language of the locale (`LC_ALL`, `LC_MESSAGES` or `LANG`, whichever is set first),
+    /// English if unsupported.
+    pub fn from_env() -> Self {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .find(|value| !value.is_empty())
+            .and_then(|locale| Self::from_locale(&locale))
+            .unwrap_or(Language::English)
+    }
+
+    /// Language of a locale such as `de_DE.UTF-8`.
+    fn from_locale(locale: &str) -> Option<Self> {
+        let code = locale.split(['_', '.', '@']).next()?;
+        Self::ALL.iter().find(|l| l.code() == code).copied()
+    }
+
+    fn bundle(&self) -> &'static HashMap<String, String> {
+        static BUNDLES: OnceLock<Vec<HashMap<String, String>>> = OnceLock::new();
+        let bundles = BUNDLES.get_or_init(|| {
+            Self::ALL
+                .iter()
+                .map(|l| parse_bundle(l.bundle_source()).expect("Invalid message bundle."))
+                .collect()
+        });
+        let idx = Self::ALL.iter().position(|l| l == self).unwrap();
+        &bundles[idx]
+    }
+}
+
+static LANGUAGE: Mutex<Option<Language>> = Mutex::new(None);
+
+/// Set the language of messages for the current invocation.
+pub fn set_language(language: Language) {
+    *LANGUAGE.lock().unwrap() = Some(language);
+}
+
+fn language() -> Language {
+    *LANGUAGE
+        .lock()
+        .unwrap()
+        .get_or_insert_with(Language::from_env)
+}
+
+fn parse_bundle(source: &str) -> Result<HashMap<String, String>> {
+    let docs = YamlLoader::load_from_str(source)?;
+    let mut bundle = HashMap::new();
+    if let Some(Yaml::Hash(dict)) = docs.first() {
+        for (key, value) in dict.iter() {
+            match (key, value) {
+                (Yaml::String(key), Yaml::String(value)) => {
+                    bundle.insert(key.clone(), value.clone());
+                }
+                _ => bail!("Invalid message: {:?}", key),
+            }
+        }
+    }
+    Ok(bundle)
+}
+
+/// Look up the message with the given key and fill in its placeholders, use via `tr!`.
+pub fn translate(key: &str, args: &[(&str, &dyn Display)]) -> String {
+    let template = language()
+        .bundle()
+        .get(key)
+        .or_else(|| Language::English.bundle().get(key))
+        .map(|s| s.as_str())
+        .unwrap_or(key);
+    fill(template, args)
+}
+
+/// Replace all `{name}` placeholders in a single pass, so that values containing braces (e.g.
+/// file names) are never mistaken for placeholders themselves.
+fn fill(template: &str, args: &[(&str, &dyn Display)]) -> String {
+    let mut msg = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        msg.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let value = rest.find('}').and_then(|end| {
+            args.iter()
+                .find(|(name, _)| *name == &rest[1..end])
+                .map(|(_, value)| (end, value))
+        });
+        match value {
+            Some((end, value)) => {
+                msg.push_str(&value.to_string());
+                rest = &rest[end + 1..];
+            }
+            None => {
+                msg.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    msg.push_str(rest);
+    msg
+}
+
+/// Localized message for the given key, e.g. `tr!("push.failed", failed = 1, total = 2)`.
+#[macro_export]
+macro_rules! tr {
+    ($key:literal $(, $name:ident = $value:expr)* $(,)?) => {
+        $crate::i18n::translate(
+            $key,
+            &[$((stringify!($name), &$value as &dyn std::fmt::Display)),*],
+        )
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn bundles_are_complete() {
+        let placeholder = Regex::new(r"\{[a-z_]+\}").unwrap();
+        let placeholders = |msg: &str| -> BTreeSet<String> {
+            placeholder
+                .find_iter(msg)
+                .map(|m| m.as_str().to_string())
+                .collect()
+        };
+        let english = Language::English.bundle();
+        for language in Language::ALL {
+            let bundle = language.bundle();
+            let mut keys: Vec<_> = bundle.keys().collect();
+            keys.sort();
+            let mut expected: Vec<_> = english.keys().collect();
+            expected.sort();
+            assert_eq!(keys, expected, "keys of '{}' differ", language.code());
+            for (key, msg) in bundle.iter() {
+                assert_eq!(placeholders(msg), placeholders(&english[key]), "{}", key);
+            }
+        }
+    }
+
+    #[test]
+    fn select_and_fill() {
+        assert_eq!(Language::from_locale("de_DE.UTF-8"), Some(Language::German));
+        assert_eq!(Language::from_locale("C"), None);
+        assert_eq!(
+            fill(
+                "{count} files at {date}",
+                &[("count", &3), ("date", &"noon")]
+            ),
+            "3 files at noon"
+        );
+        assert_eq!(
+            fill(
+                "{path} ({size}) {unknown}",
+                &[("path", &"{size}.txt"), ("size", &"1 KiB")]
+            ),
+            "{size}.txt (1 KiB) {unknown}"
+        );
+    }
+}
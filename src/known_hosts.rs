@@ -0,0 +1,364 @@
+//! Verify the host key of the remote site against OpenSSH's `known_hosts` files.
+
+use anyhow::{bail, Context, Result};
+use dialoguer::{theme::ColorfulTheme, Confirm};
+use expanduser::expanduser;
+use itertools::Itertools;
+use ssh2::{CheckResult, HashType, HostKeyType, KnownHostFileKind, MethodType, Session};
+use std::collections::HashSet;
+use std::fs::{create_dir_all, read_to_string, OpenOptions};
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
+use crate::cfg::StrictHostKeyChecking;
+use crate::ssh::SshError;
+use crate::tr;
+
+const USER_KNOWN_HOSTS: &str = "~/.ssh/known_hosts";
+const SYSTEM_KNOWN_HOSTS: &str = "/etc/ssh/ssh_known_hosts";
+
+/// Prefer the host key algorithms of keys already known for the given address (`host:port`)
+/// during the handshake (like OpenSSH), so that hosts offering several keys present a known one.
+pub fn prefer_known_types(
+    session: &Session,
+    address: &str,
+    mode: StrictHostKeyChecking,
+) -> Result<()> {
+    if mode == StrictHostKeyChecking::No {
+        return Ok(());
+    }
+    let (hostname, port) = split_address(address)?;
+    let lines = read_lines()?;
+    let known = known_types(session, &lines, hostname, port);
+    if known.is_empty() {
+        return Ok(());
+    }
+    let (preferred, others): (Vec<&str>, Vec<&str>) = session
+        .supported_algs(MethodType::HostKey)?
+        .into_iter()
+        .partition(|alg| known.contains(entry_key_type(alg)));
+    if !preferred.is_empty() {
+        let prefs = preferred.iter().chain(others.iter()).join(",");
+        log::trace!("Preferring host key algorithms: {}", prefs);
+        session.method_pref(MethodType::HostKey, &prefs)?;
+    }
+    Ok(())
+}
+
+/// Check the host key presented during the handshake by the given address (`host:port`).
+///
+/// Unknown keys are added to `~/.ssh/known_hosts` depending on `mode`, keys differing from the
+/// known ones (also if the host is only known by keys of another type) or marked as `@revoked`
+/// are always refused (unless checking is disabled).
+pub fn verify(session: &Session, address: &str, mode: StrictHostKeyChecking) -> Result<()> {
+    if mode == StrictHostKeyChecking::No {
+        log::debug!("Not verifying host key (`strict_host_key_checking: no`).");
+        return Ok(());
+    }
+    let (hostname, port) = split_address(address)?;
+    let (key, key_type) = session
+        .host_key()
+        .context("Remote site did not present a host key.")?;
+    let key_type = key_type_name(key_type)?;
+    let fingerprint = fingerprint(session);
+    let entry = entry_name(hostname, port);
+
+    let lines = read_lines()?;
+    if lines.iter().any(|(_, line)| is_revoked(line, key)) {
+        bail!(SshError::HostKeyRevoked {
+            host: entry,
+            key_type: key_type.to_string(),
+            fingerprint,
+        });
+    }
+    let known = known_types(session, &lines, hostname, port);
+    if !known.is_empty() && !known.contains(key_type) {
+        log::debug!(
+            "{} is only known by keys of type {}.",
+            entry,
+            known.iter().sorted().join(", ")
+        );
+        bail!(SshError::HostKeyMismatch {
+            host: entry,
+            key_type: key_type.to_string(),
+            fingerprint,
+        });
+    }
+
+    let user_file = expanduser(USER_KNOWN_HOSTS)?;
+    let mut known_hosts = session.known_hosts()?;
+    // Only consider keys of the presented type, libssh2 would otherwise report a mismatch for
+    // entries of the same host with a different key type.
+    for (path, line) in lines
+        .iter()
+        .filter(|(_, line)| line_key_type(line) == Some(key_type))
+    {
+        if let Err(e) = known_hosts.read_str(line, KnownHostFileKind::OpenSSH) {
+            log::debug!("Skipping entry in {}: {}", path.display(), e);
+        }
+    }
+
+    match known_hosts.check_port(hostname, port, key) {
+        CheckResult::Match => {
+            log::trace!("Host key of {} is known.", entry);
+            Ok(())
+        }
+        CheckResult::Mismatch => bail!(SshError::HostKeyMismatch {
+            host: entry,
+            key_type: key_type.to_string(),
+            fingerprint,
+        }),
+        CheckResult::Failure => bail!("Could not check host key of {}.", entry),
+        CheckResult::NotFound => {
+            let accept = match mode {
+                StrictHostKeyChecking::AcceptNew => true,
+                StrictHostKeyChecking::Ask
+                    if std::io::stdin().is_terminal() && std::io::stderr().is_terminal() =>
+                {
+                    Confirm::with_theme(&ColorfulTheme::default())
+                        .with_prompt(tr!(
+                            "host-key.confirm-add",
+                            host = entry,
+                            key_type = key_type,
+                            fingerprint = fingerprint
+                        ))
+                        .default(false)
+                        .interact()?
+                }
+                _ => false,
+            };
+            if !accept {
+                bail!(SshError::UnknownHostKey {
+                    host: entry,
+                    key_type: key_type.to_string(),
+                    fingerprint,
+                });
+            }
+            append(&user_file, &entry_line(&entry, key_type, key))?;
+            log::info!(
+                "Added {} key of {} to {}.",
+                key_type,
+                entry,
+                user_file.display()
+            );
+            Ok(())
+        }
+    }
+}
+
+/// All lines of the user's and the system-wide `known_hosts` files (if present), together with
+/// the file they stem from.
+fn read_lines() -> Result<Vec<(PathBuf, String)>> {
+    let mut lines = Vec::new();
+    for path in [
+        expanduser(USER_KNOWN_HOSTS)?,
+        PathBuf::from(SYSTEM_KNOWN_HOSTS),
+    ] {
+        if !path.exists() {
+            continue;
+        }
+        let content = read_to_string(&path)
+            .with_context(|| format!("Could not read known hosts: {}", path.display()))?;
+        lines.extend(content.lines().map(|line| (path.clone(), line.to_string())));
+    }
+    Ok(lines)
+}
+
+/// Key types of all entries for the given host.
+fn known_types<'a>(
+    session: &Session,
+    lines: &'a [(PathBuf, String)],
+    hostname: &str,
+    port: u16,
+) -> HashSet<&'a str> {
+    lines
+        .iter()
+        .filter(|(_, line)| matches_host(session, line, hostname, port))
+        .filter_map(|(_, line)| line_key_type(line))
+        .collect()
+}
+
+/// Whether the given `known_hosts` line is an entry for the given host (regardless of its key).
+fn matches_host(session: &Session, line: &str, hostname: &str, port: u16) -> bool {
+    if line_key_type(line).is_none() {
+        return false;
+    }
+    // libssh2 resolves (hashed) host names, a dummy key only matches the host.
+    match session.known_hosts() {
+        Ok(mut known_hosts) => {
+            known_hosts
+                .read_str(line, KnownHostFileKind::OpenSSH)
+                .is_ok()
+                && matches!(
+                    known_hosts.check_port(hostname, port, &[0]),
+                    CheckResult::Match | CheckResult::Mismatch
+                )
+        }
+        Err(_) => false,
+    }
+}
+
+/// Whether the given `known_hosts` line marks the given key as `@revoked`.
+///
+/// Revoked keys are refused for all hosts, regardless of the host patterns of the line.
+fn is_revoked(line: &str, key: &[u8]) -> bool {
+    let mut fields = line.split_whitespace();
+    fields.next() == Some("@revoked")
+        && fields
+            .nth(2)
+            .and_then(|encoded| base64::decode(encoded).ok())
+            .is_some_and(|revoked| revoked == key)
+}
+
+/// Split `host:port` (as passed to `TcpStream::connect`).
+pub fn split_address(address: &str) -> Result<(&str, u16)> {
+    match address.rsplit_once(':') {
+        Some((host, port)) => Ok((
+            host,
+            port.parse()
+                .with_context(|| format!("Invalid port: {}", address))?,
+        )),
+        None => Ok((address, 22)),
+    }
+}
+
+/// Name of the host in `known_hosts`, which includes the port unless it is the default.
+fn entry_name(hostname: &str, port: u16) -> String {
+    if port == 22 {
+        hostname.to_string()
+    } else {
+        format!("[{}]:{}", hostname, port)
+    }
+}
+
+fn entry_line(entry: &str, key_type: &str, key: &[u8]) -> String {
+    format!("{} {} {}\n", entry, key_type, base64::encode(key))
+}
+
+/// Key type of the given `known_hosts` line (`None` for comments and marked entries).
+fn line_key_type(line: &str) -> Option<&str> {
+    let line = line.trim_start();
+    if line.starts_with('#') || line.starts_with('@') {
+        return None;
+    }
+    line.split_whitespace().nth(1)
+}
+
+/// Key type used in `known_hosts` for the given host key algorithm.
+fn entry_key_type(algorithm: &str) -> &str {
+    if algorithm.starts_with("rsa-sha2-") {
+        "ssh-rsa"
+    } else {
+        algorithm
+    }
+}
+
+fn key_type_name(key_type: HostKeyType) -> Result<&'static str> {
+    Ok(match key_type {
+        HostKeyType::Rsa => "ssh-rsa",
+        HostKeyType::Dss => "ssh-dss",
+        HostKeyType::Ecdsa256 => "ecdsa-sha2-nistp256",
+        HostKeyType::Ecdsa384 => "ecdsa-sha2-nistp384",
+        HostKeyType::Ecdsa521 => "ecdsa-sha2-nistp521",
+        HostKeyType::Ed25519 => "ssh-ed25519",
+        HostKeyType::Unknown => bail!("Remote site presented a host key of unknown type."),
+    })
+}
+
+/// Fingerprint of the host key in the format printed by OpenSSH.
+fn fingerprint(session: &Session) -> String {
+    match session.host_key_hash(HashType::Sha256) {
+        Some(hash) => format!(
+            "SHA256:{}",
+            base64::encode_config(hash, base64::STANDARD_NO_PAD)
+        ),
+        None => "(unknown fingerprint)".to_string(),
+    }
+}
+
+fn append(path: &Path, line: &str) -> Result<()> {
+    if let Some(folder) = path.parent() {
+        create_dir_all(folder)?;
+    }
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(line.as_bytes()))
+        .with_context(|| format!("Could not add host key to {}.", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blob(fill: u8) -> Vec<u8> {
+        let mut blob = vec![0, 0, 0, 11];
+        blob.extend_from_slice(b"ssh-ed25519");
+        blob.extend_from_slice(&[0, 0, 0, 32]);
+        blob.extend_from_slice(&[fill; 32]);
+        blob
+    }
+
+    #[test]
+    fn check_added_entries() {
+        assert_eq!(
+            split_address("example.org:2222").unwrap(),
+            ("example.org", 2222)
+        );
+        assert_eq!(entry_name("example.org", 22), "example.org");
+
+        let line = entry_line(&entry_name("example.org", 2222), "ssh-ed25519", &blob(1));
+        assert_eq!(line_key_type(&line), Some("ssh-ed25519"));
+        assert_eq!(line_key_type("@revoked * ssh-ed25519 AAAA"), None);
+
+        let session = Session::new().unwrap();
+        let mut known_hosts = session.known_hosts().unwrap();
+        known_hosts
+            .read_str(line.trim_end(), KnownHostFileKind::OpenSSH)
+            .unwrap();
+        assert!(matches!(
+            known_hosts.check_port("example.org", 2222, &blob(1)),
+            CheckResult::Match
+        ));
+        assert!(matches!(
+            known_hosts.check_port("example.org", 2222, &blob(2)),
+            CheckResult::Mismatch
+        ));
+        assert!(matches!(
+            known_hosts.check_port("example.org", 22, &blob(1)),
+            CheckResult::NotFound
+        ));
+    }
+
+    #[test]
+    fn known_by_other_type_or_revoked() {
+        let session = Session::new().unwrap();
+        let lines: Vec<(PathBuf, String)> = [
+            "# comment".to_string(),
+            entry_line("example.org", "ssh-rsa", &blob(1)),
+            entry_line("[example.net]:2222", "ssh-ed25519", &blob(2)),
+            format!("@revoked * ssh-ed25519 {}", base64::encode(blob(3))),
+        ]
+        .into_iter()
+        .map(|line| (PathBuf::from("known_hosts"), line.trim_end().to_string()))
+        .collect();
+
+        assert_eq!(
+            known_types(&session, &lines, "example.org", 22),
+            HashSet::from(["ssh-rsa"])
+        );
+        assert_eq!(
+            known_types(&session, &lines, "example.net", 2222),
+            HashSet::from(["ssh-ed25519"])
+        );
+        assert!(known_types(&session, &lines, "example.net", 22).is_empty());
+        assert!(known_types(&session, &lines, "example.com", 22).is_empty());
+
+        assert!(lines.iter().any(|(_, line)| is_revoked(line, &blob(3))));
+        assert!(!lines.iter().any(|(_, line)| is_revoked(line, &blob(2))));
+
+        assert_eq!(entry_key_type("rsa-sha2-512"), "ssh-rsa");
+        assert_eq!(entry_key_type("ssh-ed25519"), "ssh-ed25519");
+    }
+}
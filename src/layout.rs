@@ -0,0 +1,109 @@
+//! Shape of uploads below the base folder of a host.
+//!
+//! Listings of the remote site are derived from the layout instead of assuming `<hash>/<file>`
+//! everywhere, so that hosts can store uploads differently without touching every command.
+
+use anyhow::{bail, Result};
+use std::path::Path;
+
+use crate::util;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Layout {
+    /// Number of components of the path of an uploaded file relative to the base folder, the
+    /// first one being the folder named after the hash.
+    depth: usize,
+}
+
+impl Default for Layout {
+    /// `<hash>/<filename>`
+    fn default() -> Self {
+        Self { depth: 2 }
+    }
+}
+
+impl Layout {
+    /// Layout with uploaded files `depth` components below the base folder, configured via
+    /// `layout: {depth: …}` for sites whose uploads are nested deeper than `<hash>/<file>`.
+    pub fn new(depth: usize) -> Result<Self> {
+        if depth < 2 {
+            bail!("'depth' needs to be at least 2 (<hash>/<file>): {}", depth);
+        }
+        Ok(Self { depth })
+    }
+
+    /// Fail for commands that can only handle the default layout because they create new
+    /// uploads or hash folders.
+    pub fn require_default(&self, host: &str) -> Result<()> {
+        if *self != Self::default() {
+            bail!(
+                "Host '{}' sets a custom 'layout', this is only supported for the default layout \
+                (<hash>/<file>).",
+                host
+            );
+        }
+        Ok(())
+    }
+
+    /// Number of components of the path of an uploaded file relative to the base folder.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Arguments to `find` selecting all uploaded files below the base folder (and possibly some
+    /// foreign files, see `matches`).
+    pub fn find_filter(&self) -> String {
+        format!("-mindepth {0} -maxdepth {0} -type f", self.depth)
+    }
+
+    /// Whether the given path (relative to the base folder) has the shape of an uploaded file.
+    pub fn matches(&self, path: &Path) -> bool {
+        path.components().count() == self.depth
+            && self
+                .upload_folder(path)
+                .map(|folder| util::is_hash_folder(&folder.to_string_lossy()))
+                .unwrap_or(false)
+    }
+
+    /// Folder (relative to the base folder) containing everything belonging to the given upload,
+    /// which is removed when cleaning it.
+    pub fn upload_folder<'a>(&self, path: &'a Path) -> Option<&'a Path> {
+        path.components()
+            .next()
+            .map(|folder| Path::new(folder.as_os_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_layout() {
+        let layout = Layout::default();
+        assert_eq!(layout.find_filter(), "-mindepth 2 -maxdepth 2 -type f");
+        assert!(layout.matches(Path::new("V66lLtli0Ei4hw3tNkCTXOcweBrneNjt/file.txt")));
+        assert!(!layout.matches(Path::new("file.txt")));
+        assert!(!layout.matches(Path::new("manual/file.txt")));
+        assert!(!layout.matches(Path::new("V66lLtli0Ei4hw3tNkCTXOcweBrneNjt/sub/file.txt")));
+        assert_eq!(
+            layout.upload_folder(Path::new("V66lLtli0Ei4hw3tNkCTXOcweBrneNjt/file.txt")),
+            Some(Path::new("V66lLtli0Ei4hw3tNkCTXOcweBrneNjt"))
+        );
+        assert!(layout.require_default("host").is_ok());
+    }
+
+    #[test]
+    fn nested_layout() {
+        let layout = Layout::new(3).unwrap();
+        assert_eq!(layout.find_filter(), "-mindepth 3 -maxdepth 3 -type f");
+        assert!(layout.matches(Path::new("V66lLtli0Ei4hw3tNkCTXOcweBrneNjt/sub/file.txt")));
+        assert!(!layout.matches(Path::new("V66lLtli0Ei4hw3tNkCTXOcweBrneNjt/file.txt")));
+        assert_eq!(
+            layout.upload_folder(Path::new("V66lLtli0Ei4hw3tNkCTXOcweBrneNjt/sub/file.txt")),
+            Some(Path::new("V66lLtli0Ei4hw3tNkCTXOcweBrneNjt"))
+        );
+        assert!(layout.require_default("host").is_err());
+        assert!(Layout::new(1).is_err());
+    }
+}
@@ -2,80 +2,161 @@
 #![forbid(unsafe_code)]
 
 mod at;
+mod bundle;
+mod cache;
+mod capabilities;
 mod cfg;
 mod cli;
+mod clipboard;
 mod cmd;
+mod compress;
+mod daemon;
 mod file_listing;
+mod file_type;
+mod host_rules;
+mod http;
+mod i18n;
+mod known_hosts;
+mod layout;
+mod manifest;
+mod metrics;
+mod notify;
 mod openssh;
+mod parallel;
+mod qr;
+mod schedule;
+mod share_code;
+mod shortener;
+mod spool;
 mod ssh;
+mod template;
+mod theme;
+mod timings;
+mod trace;
+mod tunnel;
 mod util;
 
 use anyhow::{bail, Result};
-use cmd::Command;
+use dialoguer::{theme::ColorfulTheme, Confirm};
 use log::trace;
 use ssh::SshSession;
+use std::io::IsTerminal;
+use std::time::Instant;
 
 use simple_logger::SimpleLogger;
 
 use clap::Parser;
 
 fn main() {
-    if let Err(err) = try_main() {
+    let opts = cli::Opts::parse();
+    let exit_code_on_error = opts.exit_code_on_error();
+    if opts.timings {
+        timings::enable();
+    }
+    if let Some(path) = &opts.trace_remote {
+        trace::enable(path.clone());
+    }
+
+    let result = try_main(opts);
+    timings::report();
+    if let Err(e) = trace::write() {
+        log::warn!("{:#}", e);
+    }
+    if let Err(err) = result {
         log::error!("{}", err);
-        std::process::exit(1);
+        std::process::exit(
+            err.downcast_ref::<util::ExitCodeError>()
+                .map(|e| e.code)
+                .unwrap_or(exit_code_on_error),
+        );
     }
 }
 
-fn try_main() -> Result<()> {
-    let opts = cli::Opts::parse();
-
-    opts.verify()?;
-
-    let level = match (opts.loglevel.as_deref(), opts.verbose, opts.quiet) {
-        (Some("trace"), _, _) => log::LevelFilter::Trace,
-        (Some("debug"), _, _) => log::LevelFilter::Debug,
-        (Some("info"), _, _) => log::LevelFilter::Info,
-        (Some("warn"), _, _) => log::LevelFilter::Warn,
-        (Some("error"), _, _) => log::LevelFilter::Error,
-        (None, v, 0) if v > 1 => log::LevelFilter::Trace,
-        (None, 1, 0) => log::LevelFilter::Debug,
-        (None, 0, 0) => log::LevelFilter::Info,
-        (None, 0, 1) => log::LevelFilter::Warn,
-        (None, 0, 2) => log::LevelFilter::Error,
-        (None, 0, _) => log::LevelFilter::Off,
-        _ => {
-            bail!("Restriction of loglevel in clap failed!");
-        }
-    };
-
-    SimpleLogger::new().with_level(level).init()?;
+fn try_main(opts: cli::Opts) -> Result<()> {
+    SimpleLogger::new()
+        .with_level(opts.loglevel_filter()?)
+        .init()?;
 
     trace!("Opts: {:?}", opts);
 
-    let env_cfg_path = std::env::var("ASFA_CONFIG").ok();
-
-    let cfg = {
-        let mut cfg = cfg::load(&opts.config.or(env_cfg_path))?;
-        cfg.loglevel = level;
-        cfg
-    };
-    let host = cfg.get_host(opts.host)?;
+    let cfg = cli::setup(&opts, std::env::args())?;
+    if let cli::UserCommand::ShellInit(shell_init) = &opts.cmd {
+        return shell_init.print();
+    }
+    if let cli::UserCommand::Completions(completions) = &opts.cmd {
+        return completions.print();
+    }
+    if let cli::UserCommand::Hosts(hosts) = &opts.cmd {
+        return hosts.run_local(&cfg);
+    }
+    let host = cfg.select_host(opts.host.as_deref(), opts.cmd.local_files())?;
 
     trace!("Config file: {:#?}", cfg);
     trace!("Host: {:?}", host);
 
-    let session = SshSession::connect(&host)?;
+    if let cli::UserCommand::Status(status) = &opts.cmd {
+        if status.cached {
+            return status.run_cached(host, &cfg);
+        }
+    }
+    if let cli::UserCommand::CompleteRemote(complete) = &opts.cmd {
+        return complete.print(host);
+    }
+    if let cli::UserCommand::Expire(expire) = &opts.cmd {
+        if expire.upcoming {
+            return expire.run_upcoming(host, &cfg);
+        }
+    }
+
+    if opts.use_daemon() {
+        if let Some(code) = daemon::dispatch(&host.alias)? {
+            // The daemon already reported any errors.
+            std::process::exit(code);
+        }
+    }
 
-    use cli::UserCommand::*;
-    match opts.cmd {
-        // there is no dispatch over all enum variants? Boo!
-        Check(cmd) => cmd.run(&session, &cfg),
-        Clean(cmd) => cmd.run(&session, &cfg),
-        List(cmd) => cmd.run(&session, &cfg),
-        Mv(cmd) => cmd.run(&session, &cfg),
-        Push(cmd) => cmd.run(&session, &cfg),
-        Rename(cmd) => cmd.run(&session, &cfg),
-        Verify(cmd) => cmd.run(&session, &cfg),
-    }?;
+    let start = Instant::now();
+    let session = SshSession::connect(host).inspect_err(|_| {
+        // Unreachable sites are the most common failure of unattended runs.
+        metrics::record(
+            cfg.metrics_file.as_deref(),
+            &host.alias,
+            opts.cmd.name(),
+            start.elapsed(),
+            false,
+        );
+    })?;
+    if opts.needs_base_folder() {
+        ensure_base_folder(&session, &cfg)?;
+    }
+
+    opts.cmd.run(&session, &cfg)?;
     Ok(())
 }
+
+/// Make sure the base folder of the host exists, offering to create it if missing.
+pub fn ensure_base_folder(session: &SshSession, cfg: &cfg::Config) -> Result<()> {
+    if session.base_folder_exists()? {
+        return Ok(());
+    }
+    let host = session.host;
+    let interactive = std::io::stdin().is_terminal() && std::io::stdout().is_terminal();
+    if interactive && !host.read_only && !cfg.is_silent() {
+        let create = Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt(tr!(
+                "base-folder.confirm-create",
+                folder = host.folder.display(),
+                host = host.alias
+            ))
+            .default(false)
+            .interact()?;
+        if create {
+            return session.create_base_folder();
+        }
+    }
+    bail!(tr!(
+        "base-folder.missing",
+        folder = host.folder.display(),
+        host = host.alias
+    ));
+}
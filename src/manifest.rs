@@ -0,0 +1,72 @@
+//! Manifest of all files on a remote site including their sizes and full hashes.
+//!
+//! Manifests are exported via `export-manifest` and compared against via `verify --against` to
+//! detect changes over time (bit rot, tampering) independently of the hash prefixes in URLs.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Hash length used in manifests (the longest supported one).
+pub const HASH_LENGTH: u8 = 64;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Alias of the host the manifest was created for.
+    pub host: String,
+
+    /// Creation time (RFC 3339).
+    pub created: String,
+
+    /// All files present on the remote site when the manifest was created.
+    pub files: Vec<Entry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Entry {
+    /// Path relative to the base folder.
+    pub path: PathBuf,
+
+    /// Size in bytes.
+    pub size: u64,
+
+    /// Modification time (seconds since epoch).
+    pub mtime: u64,
+
+    /// Hash of the full content (see `HASH_LENGTH`).
+    pub hash: String,
+
+    /// Short code of the upload (see `push --code`).
+    #[serde(default)]
+    pub code: Option<String>,
+}
+
+impl Manifest {
+    /// Read a manifest from the given file (`-` for stdin).
+    pub fn load(path: &Path) -> Result<Self> {
+        let parsed = if path == Path::new("-") {
+            serde_json::from_reader(std::io::stdin().lock())
+        } else {
+            let file = File::open(path)
+                .with_context(|| format!("Could not open manifest: {}", path.display()))?;
+            serde_json::from_reader(BufReader::new(file))
+        };
+        parsed.with_context(|| format!("Invalid manifest: {}", path.display()))
+    }
+
+    /// Write the manifest to the given file (`-` for stdout).
+    pub fn store(&self, path: &Path) -> Result<()> {
+        let mut writer: Box<dyn Write> = if path == Path::new("-") {
+            Box::new(std::io::stdout().lock())
+        } else {
+            Box::new(BufWriter::new(File::create(path).with_context(|| {
+                format!("Could not create manifest: {}", path.display())
+            })?))
+        };
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writeln!(writer)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,180 @@
+//! Export statistics of operations in the textfile-collector format of Prometheus'
+//! node_exporter.
+//!
+//! If `metrics_file` is configured, it is rewritten after every command, adding to the counters
+//! already contained in it. Uploads are counted while pushing via `uploaded`.
+
+use anyhow::{Context, Result};
+use nix::fcntl::{flock, FlockArg};
+use std::collections::BTreeMap;
+use std::fs::{create_dir_all, read_to_string, rename, write, OpenOptions};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const OPERATIONS: &str = "asfa_operations_total";
+const FAILURES: &str = "asfa_operation_failures_total";
+const DURATION: &str = "asfa_operation_duration_seconds_total";
+const LAST_RUN: &str = "asfa_last_operation_timestamp_seconds";
+const UPLOADED_FILES: &str = "asfa_uploaded_files_total";
+const UPLOADED_BYTES: &str = "asfa_uploaded_bytes_total";
+
+/// Exported metrics with type and help text.
+const METRICS: &[(&str, &str, &str)] = &[
+    (OPERATIONS, "counter", "Number of commands run."),
+    (FAILURES, "counter", "Number of commands that failed."),
+    (DURATION, "counter", "Time spent running commands."),
+    (LAST_RUN, "gauge", "Time the command was last run."),
+    (UPLOADED_FILES, "counter", "Number of files uploaded."),
+    (UPLOADED_BYTES, "counter", "Number of bytes uploaded."),
+];
+
+/// Files and bytes uploaded by the current command.
+static UPLOADS: Mutex<(u64, u64)> = Mutex::new((0, 0));
+
+/// Count a file of the given size as uploaded by the current command.
+pub fn uploaded(bytes: u64) {
+    let mut uploads = UPLOADS.lock().unwrap_or_else(|e| e.into_inner());
+    uploads.0 += 1;
+    uploads.1 += bytes;
+}
+
+/// Samples by metric name and labels.
+type Samples = BTreeMap<(String, String), f64>;
+
+/// Add the outcome of a command (and the uploads it performed) to the metrics file, if any.
+///
+/// Failing to do so only results in a warning, the command itself already ran.
+pub fn record(path: Option<&Path>, host: &str, command: &str, duration: Duration, success: bool) {
+    let (files, bytes) = std::mem::take(&mut *UPLOADS.lock().unwrap_or_else(|e| e.into_inner()));
+    if let Some(path) = path {
+        if let Err(e) = update(path, host, command, duration, success, (files, bytes)) {
+            log::warn!("Could not update metrics in {}: {:#}", path.display(), e);
+        }
+    }
+}
+
+fn update(
+    path: &Path,
+    host: &str,
+    command: &str,
+    duration: Duration,
+    success: bool,
+    (files, bytes): (u64, u64),
+) -> Result<()> {
+    if let Some(folder) = path.parent() {
+        create_dir_all(folder)?;
+    }
+
+    // Commands might run concurrently (e.g. from several cron jobs).
+    let lock_path = path.with_extension("lock");
+    let lock = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(false)
+        .open(&lock_path)
+        .with_context(|| format!("Could not open {}", lock_path.display()))?;
+    flock(lock.as_raw_fd(), FlockArg::LockExclusive)?;
+
+    let mut samples = if path.exists() {
+        parse(&read_to_string(path)?)
+    } else {
+        Samples::new()
+    };
+    let by_command = labels(&[("command", command), ("host", host)]);
+    let by_host = labels(&[("host", host)]);
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as f64;
+
+    let mut add = |name: &str, labels: &str, value: f64| {
+        *samples
+            .entry((name.to_string(), labels.to_string()))
+            .or_default() += value;
+    };
+    add(OPERATIONS, &by_command, 1.0);
+    add(FAILURES, &by_command, if success { 0.0 } else { 1.0 });
+    add(DURATION, &by_command, duration.as_secs_f64());
+    if files > 0 {
+        add(UPLOADED_FILES, &by_host, files as f64);
+        add(UPLOADED_BYTES, &by_host, bytes as f64);
+    }
+    samples.insert((LAST_RUN.to_string(), by_command), now);
+
+    // The collector must never see a partially written file.
+    let tmp = path.with_extension("tmp");
+    write(&tmp, format(&samples)).with_context(|| format!("Could not write {}", tmp.display()))?;
+    rename(&tmp, path)?;
+    Ok(())
+}
+
+fn labels(pairs: &[(&str, &str)]) -> String {
+    pairs
+        .iter()
+        .map(|(name, value)| {
+            format!(
+                "{}=\"{}\"",
+                name,
+                value
+                    .replace('\\', "\\\\")
+                    .replace('"', "\\\"")
+                    .replace('\n', "\\n")
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Read the samples of known metrics from a previously written file.
+fn parse(content: &str) -> Samples {
+    content
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let (series, value) = line.rsplit_once(' ')?;
+            let (name, labels) = match series.split_once('{') {
+                Some((name, labels)) => (name, labels.strip_suffix('}')?),
+                None => (series, ""),
+            };
+            if !METRICS.iter().any(|(known, _, _)| *known == name) {
+                return None;
+            }
+            Some(((name.to_string(), labels.to_string()), value.parse().ok()?))
+        })
+        .collect()
+}
+
+fn format(samples: &Samples) -> String {
+    let mut formatted = String::new();
+    for (name, kind, help) in METRICS.iter() {
+        formatted.push_str(&format!(
+            "# HELP {} {}\n# TYPE {} {}\n",
+            name, help, name, kind
+        ));
+        for ((_, labels), value) in samples.iter().filter(|((n, _), _)| n == name) {
+            formatted.push_str(&format!("{}{{{}}} {}\n", name, labels, value));
+        }
+    }
+    formatted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip_samples() {
+        let mut samples = Samples::new();
+        let by_command = labels(&[("command", "push"), ("host", "my \"site\"")]);
+        assert_eq!(by_command, r#"command="push",host="my \"site\"""#);
+        samples.insert((OPERATIONS.to_string(), by_command.clone()), 3.0);
+        samples.insert((DURATION.to_string(), by_command), 1.5);
+
+        let formatted = format(&samples);
+        assert!(formatted.contains("# TYPE asfa_operations_total counter\n"));
+        assert!(formatted.contains("asfa_operation_duration_seconds_total{command=\"push\""));
+
+        assert_eq!(parse(&formatted), samples);
+        // Metrics of others are dropped.
+        assert_eq!(parse(&format!("{}node_load1 1\n", formatted)), samples);
+    }
+}
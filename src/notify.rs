@@ -0,0 +1,50 @@
+//! Desktop notifications.
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Local};
+use notify_rust::Notification;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use crate::util::shell_quote;
+
+/// Show a desktop notification.
+///
+/// Failures (e.g., if no notification daemon is running) are only logged.
+pub fn notify(summary: &str, body: &str) {
+    if let Err(e) = Notification::new()
+        .appname("asfa")
+        .summary(summary)
+        .body(body)
+        .show()
+    {
+        log::warn!("Could not show desktop notification: {}", e);
+    }
+}
+
+/// Show a desktop notification (via `notify-send`) at the given time, scheduled with the local
+/// `at`-daemon.
+pub fn schedule(date: DateTime<Local>, summary: &str, body: &str) -> Result<()> {
+    let mut at = Command::new("at")
+        .args(["-t", &date.format("%Y%m%d%H%M").to_string()])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .context("Could not run local `at` to schedule notification.")?;
+    at.stdin
+        .take()
+        .context("Could not pass notification to local `at`.")?
+        .write_all(
+            format!(
+                "notify-send --app-name=asfa {} {}\n",
+                shell_quote(summary),
+                shell_quote(body)
+            )
+            .as_bytes(),
+        )?;
+    if !at.wait()?.success() {
+        bail!("Local `at` failed to schedule notification.");
+    }
+    Ok(())
+}
@@ -111,6 +111,15 @@ impl OpenSshConfig {
         }
     }
 
+    /// Jump host(s) to connect through (`ProxyJump`), if configured.
+    pub fn proxy_jump(&self) -> Option<String> {
+        if let Some(OpenSshConfigEntry::Single(proxy_jump)) = self.raw.get("proxyjump") {
+            Some(proxy_jump.to_string())
+        } else {
+            None
+        }
+    }
+
     pub fn user(&self) -> Option<String> {
         if let Some(OpenSshConfigEntry::Single(user)) = self.raw.get("user") {
             Some(user.to_string())
@@ -0,0 +1,80 @@
+//! Running work concurrently without exceeding configured limits (see `max_parallel_transfers`
+//! and `max_parallel_commands`).
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+
+/// Apply `f` to all given items using at most `num_workers` threads and return the results in the
+/// same order as the items.
+pub fn map<T, R, F>(items: &[T], num_workers: usize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new((0..items.len()).map(|_| None).collect::<Vec<_>>());
+
+    thread::scope(|scope| {
+        for _ in 0..num_workers.max(1).min(items.len()) {
+            scope.spawn(|| loop {
+                let idx = next.fetch_add(1, Ordering::Relaxed);
+                let item = match items.get(idx) {
+                    Some(item) => item,
+                    None => break,
+                };
+                let result = f(item);
+                results.lock().unwrap()[idx] = Some(result);
+            });
+        }
+    });
+
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|r| r.unwrap())
+        .collect()
+}
+
+/// Upper bound on how many operations of a kind may run at the same time, shared between
+/// threads.
+#[derive(Debug)]
+pub struct Limit {
+    max: usize,
+    running: Mutex<usize>,
+    freed: Condvar,
+}
+
+/// Slot of a `Limit` that is released when dropped.
+pub struct Slot<'a> {
+    limit: &'a Limit,
+}
+
+impl Limit {
+    pub fn new(max: usize) -> Self {
+        Self {
+            max: max.max(1),
+            running: Mutex::new(0),
+            freed: Condvar::new(),
+        }
+    }
+
+    /// Wait until less than the maximum number of operations are running and occupy a slot.
+    pub fn acquire(&self) -> Slot<'_> {
+        let mut running = self.running.lock().unwrap();
+        while *running >= self.max {
+            running = self.freed.wait(running).unwrap();
+        }
+        *running += 1;
+        Slot { limit: self }
+    }
+}
+
+impl<'a> Drop for Slot<'a> {
+    fn drop(&mut self) {
+        *self.limit.running.lock().unwrap() -= 1;
+        self.limit.freed.notify_one();
+    }
+}
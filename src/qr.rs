@@ -0,0 +1,35 @@
+//! Render URLs as QR codes made of unicode blocks, e.g. to open them on a phone.
+
+use anyhow::{Context, Result};
+use qrcode::render::unicode::Dense1x2;
+use qrcode::QrCode;
+
+/// Render the given URL as QR code (two modules per character vertically).
+///
+/// Colors are inverted so that the code (including its quiet zone) is drawn with blocks and stays
+/// scannable on the usual dark terminal background.
+pub fn render(url: &str) -> Result<String> {
+    let code = QrCode::new(url.as_bytes())
+        .with_context(|| format!("Could not encode {} as QR code.", url))?;
+    Ok(code
+        .render::<Dense1x2>()
+        .dark_color(Dense1x2::Light)
+        .light_color(Dense1x2::Dark)
+        .build())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_url() {
+        let code = render("https://my-domain.eu/asfa/0123456789abcdef/file.txt").unwrap();
+        let lines: Vec<&str> = code.lines().collect();
+        let width = lines[0].chars().count();
+        assert!(lines.iter().all(|line| line.chars().count() == width));
+        // Two modules per character vertically, the (light) quiet zone is drawn in blocks.
+        assert_eq!(lines.len(), (width + 1) / 2);
+        assert!(lines[0].chars().all(|c| c == '█'));
+    }
+}
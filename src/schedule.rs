@@ -0,0 +1,125 @@
+//! Upload speed limits depending on the local time of day.
+
+use anyhow::{bail, Context, Result};
+use chrono::{Local, NaiveTime};
+use yaml_rust::{yaml::Hash, Yaml};
+
+/// Speed limits for several time windows, e.g.:
+///
+/// ```yaml
+/// limit_schedule:
+///   "08:00-18:00": 1Mbit
+///   "18:00-08:00": unlimited
+/// ```
+///
+/// The first window containing the current time applies.
+#[derive(Debug, Clone, Default)]
+pub struct LimitSchedule {
+    windows: Vec<Window>,
+}
+
+#[derive(Debug, Clone)]
+struct Window {
+    start: NaiveTime,
+    end: NaiveTime,
+    /// Limit in bytes per second (None for unlimited).
+    limit: Option<usize>,
+}
+
+impl Window {
+    fn contains(&self, time: NaiveTime) -> bool {
+        if self.start < self.end {
+            self.start <= time && time < self.end
+        } else {
+            // window wraps around midnight
+            self.start <= time || time < self.end
+        }
+    }
+}
+
+impl LimitSchedule {
+    pub fn from_yaml(dict: &Hash) -> Result<Self> {
+        let mut windows = Vec::new();
+        for (window, limit) in dict.iter() {
+            let (window, limit) = match (window, limit) {
+                (Yaml::String(window), Yaml::String(limit)) => (window, limit),
+                _ => bail!("Entries in 'limit_schedule' need to be strings."),
+            };
+            let (start, end) = window.split_once('-').with_context(|| {
+                format!("Invalid time window (expected HH:MM-HH:MM): {}", window)
+            })?;
+            windows.push(Window {
+                start: parse_time(start)?,
+                end: parse_time(end)?,
+                limit: parse_limit(limit)?,
+            });
+        }
+        Ok(Self { windows })
+    }
+
+    /// Limit (in bytes per second) applying right now.
+    pub fn current_limit(&self) -> Option<usize> {
+        self.limit_at(Local::now().time())
+    }
+
+    /// Limit (in bytes per second) applying at the given time of day.
+    pub fn limit_at(&self, time: NaiveTime) -> Option<usize> {
+        self.windows
+            .iter()
+            .find(|w| w.contains(time))
+            .and_then(|w| w.limit)
+    }
+}
+
+fn parse_time(time: &str) -> Result<NaiveTime> {
+    NaiveTime::parse_from_str(time.trim(), "%H:%M")
+        .with_context(|| format!("Invalid time (expected HH:MM): {}", time))
+}
+
+/// Parse a limit such as `1Mbit`, `500kbit`, `2MByte` or `100kByte` (`/s` is optional) into bytes
+/// per second.
+fn parse_limit(limit: &str) -> Result<Option<usize>> {
+    let limit = limit.trim();
+    if ["unlimited", "none", "no"].contains(&limit) {
+        return Ok(None);
+    }
+    let limit = limit.strip_suffix("/s").unwrap_or(limit);
+    let split = limit
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(limit.len());
+    let (value, unit) = limit.split_at(split);
+    let value: f64 = value
+        .parse()
+        .with_context(|| format!("Invalid limit: {}", limit))?;
+
+    // Same conversion as for `push --limit-mbits` and `push --limit-kbytes`.
+    let bytes_per_sec = match unit.trim() {
+        "Mbit" | "Mbits" => value * 1024.0 * 1024.0 / 8.0,
+        "kbit" | "kbits" => value * 1024.0 / 8.0,
+        "MB" | "MByte" => value * 1024.0 * 1024.0,
+        "kB" | "kByte" => value * 1024.0,
+        "B" | "Byte" | "" => value,
+        unit => bail!("Invalid unit in limit: {}", unit),
+    };
+    Ok(Some(bytes_per_sec as usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use yaml_rust::YamlLoader;
+
+    #[test]
+    fn schedule_wraps_around_midnight() {
+        let yaml =
+            YamlLoader::load_from_str("\"08:00-18:00\": 1Mbit\n\"18:00-08:00\": unlimited\n")
+                .unwrap();
+        let schedule = LimitSchedule::from_yaml(yaml[0].as_hash().unwrap()).unwrap();
+
+        let at = |time| schedule.limit_at(NaiveTime::parse_from_str(time, "%H:%M").unwrap());
+        assert_eq!(at("12:00"), Some(131072));
+        assert_eq!(at("08:00"), Some(131072));
+        assert_eq!(at("18:00"), None);
+        assert_eq!(at("03:00"), None);
+    }
+}
@@ -0,0 +1,69 @@
+//! Short human-friendly codes identifying uploads, e.g. to dictate links over the phone.
+//!
+//! Codes are derived from the hash folder of an upload, so they never need to be stored: Any
+//! upload can be resolved back from its code by listing the remote site (see `resolve`-command).
+
+use sha2::{Digest, Sha256};
+use std::path::Path;
+
+/// Crockford's base32 alphabet, which avoids letters easily confused when spoken or read (I, L, O,
+/// U).
+const ALPHABET: &[u8; 32] = b"0123456789abcdefghjkmnpqrstvwxyz";
+
+/// Number of characters in a code (40 bit).
+const LENGTH: usize = 8;
+
+/// Code for the upload in the given hash folder (relative to the base folder), e.g. `k3f9-x2ab`.
+pub fn of_folder(folder: &Path) -> String {
+    let digest = Sha256::digest(folder.to_string_lossy().as_bytes());
+    let bits = digest[..5]
+        .iter()
+        .fold(0u64, |bits, byte| (bits << 8) | *byte as u64);
+    let code: String = (0..LENGTH)
+        .map(|i| ALPHABET[((bits >> (5 * (LENGTH - 1 - i))) & 0x1f) as usize] as char)
+        .collect();
+    format!("{}-{}", &code[..LENGTH / 2], &code[LENGTH / 2..])
+}
+
+/// Code for the upload containing the given file (relative to the base folder).
+pub fn of_file(path: &Path) -> Option<String> {
+    path.components()
+        .next()
+        .map(|folder| of_folder(Path::new(folder.as_os_str())))
+}
+
+/// Bring a code as entered by the user into canonical form, i.e. ignore case, separators and
+/// common misreadings.
+pub fn normalize(code: &str) -> String {
+    let code: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| match c.to_ascii_lowercase() {
+            'o' => '0',
+            'i' | 'l' => '1',
+            c => c,
+        })
+        .collect();
+    if code.len() == LENGTH {
+        format!("{}-{}", &code[..LENGTH / 2], &code[LENGTH / 2..])
+    } else {
+        code
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn code_survives_dictation() {
+        let code = of_folder(Path::new("V66lLtli0Ei4hw3tNkCTXOcweBrneNjt"));
+        assert_eq!(code.len(), LENGTH + 1);
+        assert_eq!(
+            of_file(Path::new("V66lLtli0Ei4hw3tNkCTXOcweBrneNjt/file.txt")),
+            Some(code.clone())
+        );
+        assert_eq!(normalize(&code.to_uppercase().replace('-', " ")), code);
+        assert_eq!(normalize("OIL0-abcd"), "0110-abcd");
+    }
+}
@@ -0,0 +1,106 @@
+//! Shortening of URLs via a YOURLS- or Shlink-compatible API.
+
+use anyhow::{bail, Context, Result};
+use yaml_rust::yaml::Hash;
+
+use crate::util::{get_required, get_string_from};
+
+/// Service to shorten URLs with, e.g.:
+///
+/// ```yaml
+/// shortener:
+///   endpoint: https://s.example.com/yourls-api.php
+///   token: 1234abcd
+///   api: yourls
+/// ```
+#[derive(Debug, Clone)]
+pub struct Shortener {
+    /// Which kind of API the endpoint provides.
+    api: Api,
+
+    /// URL of the API endpoint.
+    endpoint: String,
+
+    /// Token (YOURLS signature or Shlink API key) to authenticate with.
+    token: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Api {
+    /// `action=shorturl` form request authenticated via `signature`.
+    Yourls,
+
+    /// JSON request to `/rest/v3/short-urls` authenticated via `X-Api-Key` header.
+    Shlink,
+}
+
+impl Shortener {
+    pub fn from_yaml(dict: &Hash) -> Result<Self> {
+        let endpoint = get_required(dict, "endpoint", get_string_from)?
+            .trim_end_matches('/')
+            .to_string();
+        let token = get_string_from(dict, "token")?.cloned();
+        let api = match get_string_from(dict, "api")?.map(|s| s.as_str()) {
+            None | Some("yourls") => Api::Yourls,
+            Some("shlink") => Api::Shlink,
+            Some(api) => bail!(
+                "Invalid value for 'api' (expected 'yourls' or 'shlink'): {}",
+                api
+            ),
+        };
+        Ok(Self {
+            api,
+            endpoint,
+            token,
+        })
+    }
+
+    /// Shorten the given URL.
+    pub fn shorten(&self, url: &str) -> Result<String> {
+        let client = reqwest::blocking::Client::new();
+        let request = match self.api {
+            Api::Yourls => {
+                let mut form = vec![("action", "shorturl"), ("format", "json"), ("url", url)];
+                if let Some(token) = self.token.as_deref() {
+                    form.push(("signature", token));
+                }
+                client.post(&self.endpoint).form(&form)
+            }
+            Api::Shlink => {
+                let request = client
+                    .post(format!("{}/rest/v3/short-urls", self.endpoint))
+                    .header(reqwest::header::CONTENT_TYPE, "application/json")
+                    .body(serde_json::json!({ "longUrl": url }).to_string());
+                match self.token.as_deref() {
+                    Some(token) => request.header("X-Api-Key", token),
+                    None => request,
+                }
+            }
+        };
+        log::debug!("Shortening {} via {}", url, self.endpoint);
+
+        let response = request
+            .send()
+            .with_context(|| format!("Could not reach URL shortener: {}", self.endpoint))?;
+        let status = response.status();
+        let body: serde_json::Value = serde_json::from_slice(&response.bytes()?)
+            .with_context(|| format!("URL shortener returned invalid JSON ({})", status))?;
+
+        // YOURLS reports an already shortened URL with an error status but still includes it.
+        let key = match self.api {
+            Api::Yourls => "shorturl",
+            Api::Shlink => "shortUrl",
+        };
+        match body.get(key).and_then(|s| s.as_str()) {
+            Some(short) => Ok(short.to_string()),
+            None => bail!(
+                "URL shortener did not return a short URL ({}): {}",
+                status,
+                body.get("message")
+                    .or_else(|| body.get("detail"))
+                    .and_then(|m| m.as_str())
+                    .unwrap_or("no details")
+            ),
+        }
+    }
+}
@@ -0,0 +1,114 @@
+//! Local spool for data of unknown length (e.g. piped into `push -`).
+//!
+//! The data is hashed while being written to a temporary file, so that it only has to be read
+//! once before it is uploaded to the folder named after its hash.
+
+use anyhow::{Context, Result};
+use std::fs::{remove_file, File, OpenOptions};
+use std::io::{ErrorKind, Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+
+use crate::util::{self, StreamingHash};
+
+/// Temporary file only readable by the current user, removed once dropped.
+pub struct TempFile {
+    path: PathBuf,
+}
+
+impl TempFile {
+    /// Create a new temporary file with a random name, refusing to reuse existing files.
+    pub fn create() -> Result<(Self, File)> {
+        let path = std::env::temp_dir().join(format!(
+            "asfa-{}-{}",
+            std::process::id(),
+            util::random_token(8)
+        ));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path)
+            .with_context(|| format!("Could not create {}", path.display()))?;
+        Ok((Self { path }, file))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        if let Err(e) = remove_file(&self.path) {
+            log::warn!("Could not remove {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+/// Temporary copy of streamed data, removed once dropped.
+pub struct Spool {
+    temp: TempFile,
+    hash: String,
+    size: u64,
+}
+
+impl Spool {
+    /// Copy everything from `reader` to a new temporary file, computing its hash on the fly.
+    pub fn new<R: Read>(mut reader: R, prefix_length: u8) -> Result<Self> {
+        let (temp, mut file) = TempFile::create()?;
+        let mut spool = Self {
+            temp,
+            hash: String::new(),
+            size: 0,
+        };
+
+        let mut hash = StreamingHash::new(prefix_length)?;
+        let mut buffer = vec![0; 64 * 1024];
+        loop {
+            let read = match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e).context("Could not read data to upload."),
+            };
+            hash.update(&buffer[..read]);
+            file.write_all(&buffer[..read])
+                .with_context(|| format!("Could not write to {}", spool.path().display()))?;
+            spool.size += read as u64;
+        }
+        spool.hash = hash.finish();
+        Ok(spool)
+    }
+
+    pub fn path(&self) -> &Path {
+        self.temp.path()
+    }
+
+    pub fn hash(&self) -> &str {
+        &self.hash
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hash_while_spooling() {
+        let data: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        let spool = Spool::new(&data[..], 32).unwrap();
+        assert_eq!(spool.size(), data.len() as u64);
+        assert_eq!(spool.hash(), util::get_hash_from(&data[..], 32).unwrap());
+        assert_eq!(std::fs::read(spool.path()).unwrap(), data);
+
+        let path = spool.path().to_path_buf();
+        drop(spool);
+        assert!(!path.exists());
+    }
+}
@@ -1,25 +1,117 @@
-use crate::cfg::{Auth, Host};
+use crate::cache;
+use crate::capabilities::Capabilities;
+use crate::cfg::{Auth, Host, TransferProtocol};
 use crate::file_listing::FileListing;
+use crate::known_hosts;
 use crate::openssh::OpenSshConfig;
+use crate::parallel::Limit;
+use crate::timings;
+use crate::trace;
+use crate::tunnel;
+use crate::util;
 
 use anyhow::{bail, Context, Result};
 use expanduser::expanduser;
-use indicatif::{ProgressBar, ProgressIterator};
+use indicatif::ProgressIterator;
 use itertools::Itertools;
 use log::{debug, error, info};
+use regex::Regex;
 use rpassword::prompt_password;
 use ssh2::Session as RawSession;
-use ssh2::{FileStat, KeyboardInteractivePrompt, Prompt};
+use ssh2::{FileStat, KeyboardInteractivePrompt, OpenFlags, OpenType, Prompt, Sftp};
 use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::prelude::*;
 use std::io::{BufReader, Error as IOError, ErrorKind};
 use std::iter::{IntoIterator, Iterator};
-use std::net::TcpStream;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::os::unix::net::UnixStream;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
 use std::time::{Duration, Instant};
+use thiserror::Error;
 use whoami::username;
 
+/// Name of the audit log in the base folder of a host.
+pub const AUDIT_LOG: &str = ".asfa-audit.log";
+
+/// Name of the file in the base folder of a host listing pinned uploads (see `pin`-command).
+pub const PINS: &str = ".asfa-pins";
+
+/// Name of the file in the base folder of a host listing links handed out to single recipients
+/// (see `push --recipient`).
+pub const RECIPIENTS: &str = ".asfa-recipients";
+
+/// Lock file guarding updates of `RECIPIENTS` by concurrent asfa processes.
+const RECIPIENTS_LOCK: &str = ".asfa-recipients.lock";
+
+/// Locks older than this are considered left behind by crashed processes and broken.
+const STALE_LOCK: Duration = Duration::from_secs(60);
+
+/// Number of bytes shown when previewing the content of remote files.
+const PREVIEW_BYTES: usize = 24;
+
+/// Files hashed per remote invocation for each millisecond of round trip latency (see
+/// `SshSession::hash_batch_size`), within the given bounds.
+const HASH_BATCH_PER_MS: usize = 4;
+const HASH_BATCH_SIZE_MIN: usize = 16;
+const HASH_BATCH_SIZE_MAX: usize = 512;
+
+/// Common failure classes, reported with hints on how to resolve them.
+#[derive(Debug, Error)]
+pub enum SshError {
+    #[error("Could not resolve hostname '{0}'. Check `hostname` in the configuration (or `HostName` in ~/.ssh/config).")]
+    UnknownHost(String),
+
+    #[error("Connection to {0} refused. Is an SSH server running and listening on that port?")]
+    ConnectionRefused(String),
+
+    #[error("Connection to {0} timed out. Check the hostname/port and whether a firewall blocks the connection.")]
+    TimedOut(String),
+
+    #[error("Could not authenticate as '{user}' on {host}. Check `user` and `auth` in the configuration, whether your key is loaded in the agent (`ssh-add -l`) or run with `-vv` for details.")]
+    AuthFailed { user: String, host: String },
+
+    #[error(
+        "{msg} Permission denied: Check that the remote user may write to `folder` ({folder})."
+    )]
+    PermissionDenied { msg: String, folder: String },
+
+    #[error("{msg} `{tool}` is not installed on the remote site.")]
+    MissingTool { msg: String, tool: String },
+
+    #[error("Host key of {host} ({key_type} {fingerprint}) is not known. Verify the fingerprint and connect once interactively (or add the key to ~/.ssh/known_hosts), or set `strict_host_key_checking: accept-new`.")]
+    UnknownHostKey {
+        host: String,
+        key_type: String,
+        fingerprint: String,
+    },
+
+    #[error("Host key of {host} ({key_type} {fingerprint}) does not match the one in known_hosts: Someone could be eavesdropping on you (man-in-the-middle attack) or the host key has just been changed. If the change is expected, remove the old key via `ssh-keygen -R '{host}'`.")]
+    HostKeyMismatch {
+        host: String,
+        key_type: String,
+        fingerprint: String,
+    },
+
+    #[error("Host key of {host} ({key_type} {fingerprint}) is marked as revoked in known_hosts, refusing to connect.")]
+    HostKeyRevoked {
+        host: String,
+        key_type: String,
+        fingerprint: String,
+    },
+
+    #[error("Host '{0}' does not allow to execute remote commands (`exec: false`), this operation is only available with a remote shell.")]
+    ExecDisabled(String),
+}
+
+/// Encode the hex digest at the start of a line printed by the remote hasher like local hashes.
+fn encode_remote_hash(line: &str, length: u8) -> Option<String> {
+    let digest = hex::decode(line.split_whitespace().next()?).ok()?;
+    Some(base64::encode_config(digest, base64::URL_SAFE)[..length as usize].to_string())
+}
+
 fn ensure_port(hostname: &str) -> String {
     log::debug!("Raw hostname: {}", hostname);
     if hostname.contains(':') {
@@ -29,45 +121,637 @@ fn ensure_port(hostname: &str) -> String {
     }
 }
 
+/// Remove the given remote folder and its contents via SFTP.
+fn remove_recursively(sftp: &Sftp, folder: &Path, removed: &mut dyn FnMut(&str)) -> Result<()> {
+    for (path, stat) in sftp.readdir(folder)? {
+        if stat.is_dir() {
+            remove_recursively(sftp, &path, removed)?;
+        } else {
+            sftp.unlink(&path)?;
+            removed(&format!("removed '{}'", path.display()));
+        }
+    }
+    sftp.rmdir(folder)?;
+    removed(&format!("removed directory '{}'", folder.display()));
+    Ok(())
+}
+
+/// Overwrite all regular files below the given folder with zeros via SFTP (symlinks are skipped).
+fn shred_recursively(sftp: &Sftp, folder: &Path) -> Result<()> {
+    let zeros = [0u8; 64 * 1024];
+    for (path, stat) in sftp.readdir(folder)? {
+        if stat.is_dir() {
+            shred_recursively(sftp, &path)?;
+        } else if stat.is_file() {
+            let mut file = sftp.open_mode(&path, OpenFlags::WRITE, 0o644, OpenType::File)?;
+            let mut remaining = stat.size.unwrap_or(0);
+            while remaining > 0 {
+                let len = remaining.min(zeros.len() as u64) as usize;
+                file.write_all(&zeros[..len])?;
+                remaining -= len as u64;
+            }
+            file.fsync()?;
+            info!("shredded '{}'", path.display());
+        }
+    }
+    Ok(())
+}
+
+/// Create the given folder (relative to `base`) and all missing parents below `base` via SFTP.
+///
+/// Folders created concurrently in between are fine, just like with `mkdir -p`.
+fn make_folders(sftp: &Sftp, base: &Path, path: &Path) -> Result<()> {
+    let mut folder = base.to_path_buf();
+    for component in path.components() {
+        folder.push(component);
+        if sftp.stat(&folder).map(|s| s.is_dir()).unwrap_or(false) {
+            continue;
+        }
+        if let Err(e) = sftp.mkdir(&folder, 0o755) {
+            if !sftp.stat(&folder).map(|s| s.is_dir()).unwrap_or(false) {
+                bail!(
+                    "Could not create remote folder: {} Error: {}",
+                    folder.display(),
+                    e
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Start of a shell loop matching the target of each symlink in the given folder, to be completed
+/// by `case`-patterns and `esac; done`.
+///
+/// Unlike `find -lname`, this also works with BSD find.
+fn links_pointing(folder: &Path) -> String {
+    format!(
+        "[ ! -d {dir} ] || for link in {dir}/* {dir}/.[!.]*; do \
+            [ -L \"$link\" ] || continue; case \"$(readlink \"$link\")\" in",
+        dir = util::shell_quote(&folder.to_string_lossy())
+    )
+}
+
+/// A link to an upload folder handed out to a single recipient (see `push --recipient`).
+///
+/// The link is named by a random token in the host's links folder, so that access logs reveal
+/// which recipient's URL was used.
+pub struct RecipientLink {
+    pub token: String,
+    pub recipient: String,
+}
+
+impl RecipientLink {
+    /// Path of the link (relative to the host's base-folder).
+    pub fn path(&self, host: &Host) -> PathBuf {
+        host.links_folder.join(&self.token)
+    }
+}
+
 pub struct SshSession<'a> {
     raw: RawSession,
     pub host: &'a Host,
     cfg_openssh: Option<OpenSshConfig>,
+    hash_batch_size: OnceLock<usize>,
+    capabilities: OnceLock<Capabilities>,
+    /// Remote commands and transfers currently running (in case the session is shared between
+    /// threads), bounded by `max_parallel_commands` and `max_parallel_transfers`.
+    commands: Limit,
+    transfers: Limit,
+    /// Authentication method that succeeded, e.g. `publickey (agent)`.
+    auth_method: OnceLock<&'static str>,
+    /// Time it took to connect, perform the handshake and authenticate.
+    connect_duration: Duration,
+    /// Whether sending files via SCP failed, so that SFTP is used right away for all further
+    /// transfers (`transfer_protocol: auto`).
+    scp_unavailable: AtomicBool,
 }
 
 impl<'a> SshSession<'a> {
-    /// Adjust the group of the remote target recursively
+    /// Adjust the group of the remote target (relative to the current host's base-folder)
+    /// recursively
     pub fn adjust_group(&self, file: &Path, group: &str) -> Result<()> {
-        let cmd = format!("chown -R :{} \"{}\"", group, file.display());
-        let mut channel = self.raw.channel_session()?;
-
-        channel.exec(&cmd).context("Could not adjust group.")?;
+        let file = self.prepend_base_folder(file);
+        self.exec_remote(&format!(
+            "chgrp -R {} {}",
+            util::shell_quote(group),
+            util::shell_quote(&file.to_string_lossy())
+        ))?
+        .expect_with(|_| {
+            format!(
+                "Could not change group of {} to '{}' (is the remote user member of it?).",
+                file.display(),
+                group
+            )
+        })?;
         Ok(())
     }
 
+    /// Append the given operation on the given paths (relative to the current host's base-folder)
+    /// to the audit log on the remote site, if enabled for the host.
+    ///
+    /// Failing to write the audit log is reported but never fatal.
+    pub fn audit(&self, operation: &str, paths: &[&Path]) {
+        if !self.host.audit_log || paths.is_empty() {
+            return;
+        }
+        let line = std::iter::once(chrono::Local::now().to_rfc3339())
+            .chain(std::iter::once(format!(
+                "{}@{}",
+                username(),
+                whoami::fallible::hostname().unwrap_or_default()
+            )))
+            .chain(std::iter::once(operation.to_string()))
+            .chain(paths.iter().map(|p| p.display().to_string()))
+            .join("\t");
+        let logged = if self.host.exec {
+            self.exec_remote(&format!(
+                "printf '%s\\n' {} >> {}",
+                util::shell_quote(&line),
+                util::shell_quote(
+                    &self
+                        .prepend_base_folder(Path::new(AUDIT_LOG))
+                        .to_string_lossy()
+                )
+            ))
+            .and_then(|cmd| cmd.expect("Could not append to audit log."))
+            .map(|_| ())
+        } else {
+            self.raw
+                .sftp()
+                .and_then(|sftp| {
+                    sftp.open_mode(
+                        &self.sftp_path(Path::new(AUDIT_LOG)),
+                        OpenFlags::WRITE | OpenFlags::APPEND | OpenFlags::CREATE,
+                        0o644,
+                        OpenType::File,
+                    )
+                })
+                .map_err(anyhow::Error::from)
+                .and_then(|mut file| Ok(writeln!(file, "{}", line)?))
+                .context("Could not append to audit log.")
+        };
+        if let Err(e) = logged {
+            log::warn!("{:#}", e);
+        }
+    }
+
+    /// Non-empty lines of the given bookkeeping file in the base folder (empty if missing).
+    fn read_lines(&self, name: &str, what: &str) -> Result<Vec<String>> {
+        let sftp = self.raw.sftp()?;
+        let path = self.sftp_path(Path::new(name));
+        if sftp.stat(&path).is_err() {
+            return Ok(vec![]);
+        }
+        let mut content = String::new();
+        sftp.open(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|mut file| Ok(file.read_to_string(&mut content)?))
+            .with_context(|| format!("Could not read {}.", what))?;
+        Ok(content
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .map(String::from)
+            .collect())
+    }
+
+    /// Replace the content of the given bookkeeping file in the base folder.
+    fn write_lines(
+        &self,
+        name: &str,
+        what: &str,
+        lines: impl Iterator<Item = String>,
+    ) -> Result<()> {
+        let content: String = lines.map(|l| l + "\n").collect();
+        self.raw
+            .sftp()?
+            .create(&self.sftp_path(Path::new(name)))
+            .map_err(anyhow::Error::from)
+            .and_then(|mut file| Ok(file.write_all(content.as_bytes())?))
+            .with_context(|| format!("Could not write {}.", what))
+    }
+
+    /// Upload folders (relative to the current host's base-folder) that are pinned, i.e.
+    /// protected from deletion and expiration.
+    pub fn pins(&self) -> Result<HashSet<PathBuf>> {
+        Ok(self
+            .read_lines(PINS, "pinned uploads")?
+            .into_iter()
+            .map(PathBuf::from)
+            .collect())
+    }
+
+    /// Replace the pinned upload folders (relative to the current host's base-folder).
+    pub fn set_pins(&self, pins: &HashSet<PathBuf>) -> Result<()> {
+        self.replace_lines(
+            PINS,
+            "pinned uploads",
+            pins.iter().map(|p| p.display().to_string()).sorted(),
+        )
+    }
+
+    /// Replace the content of the given bookkeeping file via rename, so that concurrent readers
+    /// never see partially written content.
+    ///
+    /// Plain SFTP (version 3) cannot rename onto existing files, so the replacement is only atomic
+    /// if remote commands are allowed.
+    fn replace_lines(
+        &self,
+        name: &str,
+        what: &str,
+        lines: impl Iterator<Item = String>,
+    ) -> Result<()> {
+        let temp = format!("{}.{}", name, util::random_token(8));
+        self.write_lines(&temp, what, lines)?;
+        if self.host.exec {
+            self.exec_remote(&format!(
+                "mv -f {} {}",
+                util::shell_quote(&self.prepend_base_folder(Path::new(&temp)).to_string_lossy()),
+                util::shell_quote(&self.prepend_base_folder(Path::new(name)).to_string_lossy())
+            ))?
+            .expect_with(|_| format!("Could not write {}.", what))?;
+            return Ok(());
+        }
+        let temp = self.sftp_path(Path::new(&temp));
+        let path = self.sftp_path(Path::new(name));
+        let sftp = self.raw.sftp()?;
+        if sftp.stat(&path).is_ok() {
+            sftp.unlink(&path)
+                .with_context(|| format!("Could not write {}.", what))?;
+        }
+        sftp.rename(&temp, &path, None)
+            .with_context(|| format!("Could not write {}.", what))
+    }
+
+    /// Run `f` while holding the given lock file in the base folder, waiting for other processes
+    /// holding it.
+    fn with_remote_lock<T>(&self, name: &str, f: impl FnOnce() -> Result<T>) -> Result<T> {
+        let sftp = self.raw.sftp()?;
+        let path = self.sftp_path(Path::new(name));
+        let start = Instant::now();
+        loop {
+            match sftp.open_mode(
+                &path,
+                OpenFlags::WRITE | OpenFlags::CREATE | OpenFlags::EXCLUSIVE,
+                0o600,
+                OpenType::File,
+            ) {
+                Ok(_) => break,
+                Err(e) => {
+                    let age = sftp
+                        .stat(&path)
+                        .ok()
+                        .and_then(|s| s.mtime)
+                        .and_then(|mtime| {
+                            (std::time::UNIX_EPOCH + Duration::from_secs(mtime))
+                                .elapsed()
+                                .ok()
+                        });
+                    match age {
+                        Some(age) if age > STALE_LOCK => {
+                            log::warn!("Breaking stale lock: {}", path.display());
+                            sftp.unlink(&path).ok();
+                        }
+                        // The lock vanished in between.
+                        None if sftp.stat(&path).is_err() => {}
+                        _ if start.elapsed() > STALE_LOCK => {
+                            return Err(e).with_context(|| {
+                                format!("Timed out waiting for lock: {}", path.display())
+                            })
+                        }
+                        _ => std::thread::sleep(Duration::from_millis(200)),
+                    }
+                }
+            }
+        }
+        let result = f();
+        if let Err(e) = sftp.unlink(&path) {
+            log::warn!("Could not remove lock {}: {}", path.display(), e);
+        }
+        result
+    }
+
+    /// Links handed out to single recipients via `push --recipient`.
+    pub fn recipient_links(&self) -> Result<Vec<RecipientLink>> {
+        Ok(self
+            .read_lines(RECIPIENTS, "recipient links")?
+            .iter()
+            .filter_map(|l| l.split_once('\t'))
+            .map(|(token, recipient)| RecipientLink {
+                token: token.to_string(),
+                recipient: recipient.to_string(),
+            })
+            .collect())
+    }
+
+    /// Update the links handed out to single recipients via `f`.
+    ///
+    /// The links are locked on the remote site meanwhile, so that concurrent asfa processes do
+    /// not lose each other's updates.
+    pub fn update_recipient_links<T>(
+        &self,
+        f: impl FnOnce(&mut Vec<RecipientLink>) -> Result<T>,
+    ) -> Result<T> {
+        self.with_remote_lock(RECIPIENTS_LOCK, || {
+            let mut links = self.recipient_links()?;
+            let result = f(&mut links)?;
+            self.replace_lines(
+                RECIPIENTS,
+                "recipient links",
+                links
+                    .iter()
+                    .map(|l| format!("{}\t{}", l.token, l.recipient)),
+            )?;
+            Ok(result)
+        })
+    }
+
+    /// Upload folder (relative to the current host's base-folder) the given recipient link points
+    /// to, `None` if the link no longer exists (e.g. because the upload was removed).
+    pub fn recipient_link_target(&self, link: &RecipientLink) -> Result<Option<PathBuf>> {
+        let sftp = self.raw.sftp()?;
+        let path = self.sftp_path(&link.path(self.host));
+        match sftp.readlink(&path) {
+            Ok(target) => Ok(Some(
+                target
+                    .components()
+                    .filter(|c| matches!(c, std::path::Component::Normal(_)))
+                    .collect(),
+            )),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Remove the given recipient link from the remote site (but not from `recipient_links`).
+    pub fn remove_recipient_link(&self, link: &RecipientLink) -> Result<()> {
+        let path = self.sftp_path(&link.path(self.host));
+        self.raw
+            .sftp()?
+            .unlink(&path)
+            .with_context(|| format!("Could not remove link: {}", path.display()))
+    }
+
     /// List all files present (relative to the current host's base-folder).
     pub fn all_files(&self) -> Result<Vec<PathBuf>> {
+        if self.via_sftp()? {
+            return Ok(self
+                .all_files_sftp()?
+                .into_iter()
+                .map(|(path, _)| path)
+                .collect());
+        }
         let files = self
             .exec_remote(&format!(
-                "find '{}' -mindepth 2 -maxdepth 2 -type f -print0 | xargs -0r ls -1rt",
-                self.host.folder.display()
+                "find '{}' {} -print0 | {} ls -1rt",
+                self.host.folder.display(),
+                self.host.layout.find_filter(),
+                self.capabilities()?.xargs()
             ))?
             .expect("Could not list remote files.")?;
 
         log::trace!("{}", files.stdout());
 
-        Ok(files
+        let files: Vec<_> = files
+            .stdout()
+            .lines()
+            .filter_map(|s| Path::new(s).strip_prefix(&self.host.folder).ok())
+            .filter(|p| self.is_upload(p))
+            .map(|p| p.to_path_buf())
+            .collect();
+        cache::update(&self.host.alias, |c| c.set_files(&files));
+        Ok(files)
+    }
+
+    /// List all files present together with their stats (relative to the current host's
+    /// base-folder), in the same order as `all_files`.
+    ///
+    /// Retrieving both in a single invocation needs either GNU find or `stat` (e.g. on BSD), None
+    /// is returned if neither is available on the remote site.
+    pub fn all_files_with_stats(&self) -> Result<Option<Vec<(PathBuf, FileStat)>>> {
+        if self.via_sftp()? {
+            return Ok(Some(self.all_files_sftp()?));
+        }
+        let capabilities = self.capabilities()?;
+        let folder = self.host.folder.display();
+        let cmd = if capabilities.gnu {
+            format!(
+                "find '{}' {} -printf '%T@ %s %P\\n'",
+                folder,
+                self.host.layout.find_filter()
+            )
+        } else if let (true, Some(stat)) = (capabilities.has("xargs"), capabilities.stat_command())
+        {
+            format!(
+                "cd '{}' && find . {} -print0 | {} {}",
+                folder,
+                self.host.layout.find_filter(),
+                capabilities.xargs(),
+                stat
+            )
+        } else {
+            return Ok(None);
+        };
+        let listed = self.exec_remote(&cmd)?;
+        if listed.exit_status() != 0 {
+            debug!(
+                "Could not list files with stats: {}",
+                listed.stderr().trim()
+            );
+            return Ok(None);
+        }
+        log::trace!("{}", listed.stdout());
+
+        // Lines that cannot be parsed stem from files not uploaded by asfa (e.g., with newlines
+        // in their name), they are skipped just like other foreign files.
+        let files = listed
             .stdout()
             .lines()
-            .map(|s| {
-                Path::new(s)
+            .filter_map(|l| {
+                let mut parts = l.splitn(3, ' ');
+                let mtime: f64 = parts.next().and_then(|s| s.parse().ok())?;
+                let size: u64 = parts.next().and_then(|s| s.parse().ok())?;
+                let path = Path::new(parts.next()?);
+                // `stat` reports paths relative to the base folder as `./<hash>/<file>`
+                let path = path.strip_prefix(".").unwrap_or(path).to_path_buf();
+                if !self.is_upload(&path) {
+                    debug!("Skipping foreign file: {}", path.display());
+                    return None;
+                }
+                Some((
+                    path,
+                    FileStat {
+                        size: Some(size),
+                        uid: None,
+                        gid: None,
+                        perm: None,
+                        atime: None,
+                        mtime: Some(mtime as u64),
+                    },
+                ))
+            })
+            .collect();
+        Ok(Some(self.set_listing(files)))
+    }
+
+    /// List all files present together with their stats (relative to the current host's
+    /// base-folder) only relying on SFTP, in the same order as `all_files`.
+    fn all_files_sftp(&self) -> Result<Vec<(PathBuf, FileStat)>> {
+        let sftp = self.raw.sftp()?;
+        let mut files = Vec::new();
+        let mut folders = vec![self.sftp_folder().to_path_buf()];
+        for level in 1..=self.host.layout.depth() {
+            let mut subfolders = Vec::new();
+            for folder in folders.iter() {
+                let listed = sftp.readdir(folder);
+                let listed = if level == 1 {
+                    listed.context("Could not list remote files.")?
+                } else {
+                    listed?
+                };
+                for (path, stat) in listed {
+                    if level < self.host.layout.depth() {
+                        if stat.is_dir() {
+                            subfolders.push(path);
+                        }
+                        continue;
+                    }
+                    let file = path.strip_prefix(self.sftp_folder())?;
+                    if stat.is_file() && self.is_upload(file) {
+                        files.push((file.to_path_buf(), stat));
+                    }
+                }
+            }
+            folders = subfolders;
+        }
+        Ok(self.set_listing(files))
+    }
+
+    /// Sort listed files like `ls -rt` (oldest first, ties broken by reverse name) and remember
+    /// them in the local cache.
+    fn set_listing(&self, mut files: Vec<(PathBuf, FileStat)>) -> Vec<(PathBuf, FileStat)> {
+        files.sort_by(|(path_a, stat_a), (path_b, stat_b)| {
+            stat_a
+                .mtime
+                .cmp(&stat_b.mtime)
+                .then_with(|| path_b.cmp(path_a))
+        });
+        cache::update(&self.host.alias, |c| {
+            c.set_files(&files.iter().map(|(p, _)| p.clone()).collect::<Vec<_>>())
+        });
+        files
+    }
+
+    /// Get the disk usage (allocated blocks, in bytes) of all files present (relative to the
+    /// current host's base-folder), which differs from their size for sparse files.
+    pub fn disk_usage(&self) -> Result<HashMap<PathBuf, u64>> {
+        let usage = self
+            .exec_remote(&format!(
+                "find '{}' {} -print0 | {} du -k",
+                self.host.folder.display(),
+                self.host.layout.find_filter(),
+                self.capabilities()?.xargs()
+            ))?
+            .expect("Could not get disk usage of remote files.")?;
+
+        usage
+            .stdout()
+            .lines()
+            .map(|l| {
+                let (kbytes, path) = l
+                    .split_once('\t')
+                    .with_context(|| format!("Invalid output of du: {}", l))?;
+                let path = Path::new(path)
                     .strip_prefix(&self.host.folder)
-                    .unwrap()
-                    .to_path_buf()
+                    .with_context(|| format!("Unexpected path in output of du: {}", path))?;
+                Ok((path.to_path_buf(), kbytes.parse::<u64>()? * 1024))
             })
+            .collect()
+    }
+
+    /// List all top-level folders present (relative to the current host's base-folder).
+    pub fn all_folders(&self) -> Result<Vec<PathBuf>> {
+        if self.via_sftp()? {
+            return Ok(self
+                .raw
+                .sftp()?
+                .readdir(self.sftp_folder())
+                .context("Could not list remote folders.")?
+                .into_iter()
+                .filter(|(_, stat)| stat.is_dir())
+                .filter_map(|(p, _)| Some(p.strip_prefix(self.sftp_folder()).ok()?.to_path_buf()))
+                .collect());
+        }
+        let folders = self
+            .exec_remote(&format!(
+                "find '{}' -mindepth 1 -maxdepth 1 -type d",
+                self.host.folder.display()
+            ))?
+            .expect("Could not list remote folders.")?;
+
+        Ok(folders
+            .stdout()
+            .lines()
+            .filter_map(|s| Path::new(s).strip_prefix(&self.host.folder).ok())
+            .map(|p| p.to_path_buf())
             .collect())
     }
 
+    /// Whether the given path (relative to the current host's base-folder) follows the layout of
+    /// uploaded files.
+    fn is_upload(&self, path: &Path) -> bool {
+        self.host.layout.matches(path)
+            && path.components().next() != self.host.links_folder.components().next()
+    }
+
+    /// List all entries in the base folder that were not created by asfa (relative to the current
+    /// host's base-folder), e.g. files copied there manually or folders not named like a hash.
+    ///
+    /// Named links as well as the audit log, pins and the list of recipient links are never
+    /// reported.
+    pub fn foreign_entries(&self) -> Result<Vec<(PathBuf, FileStat)>> {
+        let links_folder = self.host.links_folder.components().next();
+        let mut entries: Vec<_> = self
+            .raw
+            .sftp()?
+            .readdir(self.sftp_folder())
+            .context("Could not list remote folder.")?
+            .into_iter()
+            .filter_map(|(path, stat)| {
+                let path = path.strip_prefix(self.sftp_folder()).ok()?.to_path_buf();
+                let name = path.to_string_lossy();
+                let foreign = if stat.file_type().is_symlink()
+                    || [AUDIT_LOG, PINS, RECIPIENTS, RECIPIENTS_LOCK].contains(&name.as_ref())
+                {
+                    false
+                } else if stat.is_dir() {
+                    !util::is_hash_folder(&name) && path.components().next() != links_folder
+                } else {
+                    true
+                };
+                foreign.then_some((path, stat))
+            })
+            .collect();
+        entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Ok(entries)
+    }
+
+    /// Remove an entry reported by `foreign_entries` (relative to the current host's
+    /// base-folder).
+    pub fn remove_foreign(&self, path: &Path) -> Result<()> {
+        util::ensure_inside_base_folder(path)?;
+        let full = self.sftp_path(path);
+        let sftp = self.raw.sftp()?;
+        if sftp.lstat(&full)?.is_dir() {
+            self.remove_folder(path)
+        } else {
+            debug!("Removing: {}", full.display());
+            sftp.unlink(&full)
+                .with_context(|| format!("Could not remove remote file: {}", full.display()))?;
+            info!("removed '{}'", full.display());
+            Ok(())
+        }
+    }
+
     /// Try all defined authentication methods in order
     fn auth(&self, auth: &Auth) -> Result<()> {
         log::trace!("Authenticating…");
@@ -78,6 +762,7 @@ impl<'a> SshSession<'a> {
             if let Err(e) = self.auth_agent() {
                 log::debug!("Agent authentication failed: {}", e);
             }
+            self.record_auth_method("publickey (agent)");
         }
 
         // Check private key from user configuration
@@ -98,14 +783,17 @@ impl<'a> SshSession<'a> {
             } else if auth.from_openssh {
                 self.auth_private_keys_openssh();
             }
+            self.record_auth_method("publickey");
         }
 
         if !self.raw.authenticated() && methods.contains("password") {
             if let Some(password) = self.host.password.as_ref() {
                 debug!("Authenticating with plaintext password.");
+                let _span = timings::span("auth: password");
                 if let Err(e) = self.raw.userauth_password(&self.get_username(), &password) {
                     log::debug!("Password authenication failed: {}", e);
                 }
+                self.record_auth_method("password");
             }
         }
 
@@ -119,6 +807,7 @@ impl<'a> SshSession<'a> {
             if let Err(e) = self.auth_interactive() {
                 log::debug!("Interactive password authenication failed: {}", e);
             }
+            self.record_auth_method("password (interactive)");
         }
 
         if !self.raw.authenticated() && auth.interactive && methods.contains("keyboard-interactive")
@@ -126,6 +815,7 @@ impl<'a> SshSession<'a> {
             if let Err(e) = self.auth_keyboard_interactive() {
                 log::debug!("Interactive password authenication failed: {}", e);
             }
+            self.record_auth_method("keyboard-interactive");
         }
 
         if self.raw.authenticated() {
@@ -143,8 +833,26 @@ impl<'a> SshSession<'a> {
         }
     }
 
+    /// Remember the given method if it was the first to authenticate the session.
+    fn record_auth_method(&self, method: &'static str) {
+        if self.raw.authenticated() {
+            let _ = self.auth_method.set(method);
+        }
+    }
+
+    /// Authentication method that succeeded, e.g. `publickey (agent)`.
+    pub fn auth_method(&self) -> Option<&'static str> {
+        self.auth_method.get().copied()
+    }
+
+    /// Time it took to connect, perform the handshake and authenticate.
+    pub fn connect_duration(&self) -> Duration {
+        self.connect_duration
+    }
+
     fn auth_agent(&self) -> Result<()> {
         log::debug!("Trying to authenticate via agent..");
+        let _span = timings::span("auth: agent");
         let mut agent = self.raw.agent().unwrap();
         agent.connect().unwrap();
         agent.list_identities().unwrap();
@@ -168,6 +876,7 @@ impl<'a> SshSession<'a> {
             "Interactive authentication enabled for host {}",
             self.host.alias
         );
+        let _span = timings::span("auth: password (incl. user input)");
         let password = prompt_password(&format!(
             "Interactive authentication enabled. Enter password for {}:",
             self.host.alias
@@ -178,6 +887,7 @@ impl<'a> SshSession<'a> {
     }
 
     fn auth_keyboard_interactive(&self) -> Result<()> {
+        let _span = timings::span("auth: keyboard-interactive (incl. user input)");
         Ok(self.raw.userauth_keyboard_interactive(
             &self.get_username(),
             &mut InteractivePrompt::default(),
@@ -205,6 +915,7 @@ impl<'a> SshSession<'a> {
         };
 
         let private_key_file = &expanduser(private_key_file)?;
+        let _span = timings::span(format!("auth: private key {}", private_key_file.display()));
         self.raw
             .userauth_pubkey_file(username, None, private_key_file, password.as_deref())?;
 
@@ -245,6 +956,7 @@ impl<'a> SshSession<'a> {
     ///
     /// First try authenticating with all agent identities then use an interactive password, if enabled.
     pub fn connect(host: &'a Host) -> Result<Self> {
+        let start = Instant::now();
         let auth: &Auth = &host.auth;
 
         let cfg_openssh = {
@@ -263,82 +975,280 @@ impl<'a> SshSession<'a> {
             }
         };
 
-        let tcp = {
-            let hostname = {
-                // Priority:
-                // 1. Fully specified hostname with port.
-                // 2. Information from openSSH
-                // 3. Hostname/alias
-                if host.hostname.is_some() && host.hostname.clone().unwrap().contains(':') {
-                    host.hostname.clone().unwrap()
-                } else if let Some(hostname) = cfg_openssh.as_ref().and_then(|c| c.hostname()) {
-                    hostname
-                } else {
-                    host.hostname.clone().unwrap_or_else(|| host.alias.clone())
-                }
-            };
-            let full_hostname = ensure_port(&hostname);
-            log::debug!("Connecting to: {}", full_hostname);
-            TcpStream::connect(full_hostname)?
+        let hostname = {
+            // Priority:
+            // 1. Fully specified hostname with port.
+            // 2. Information from openSSH
+            // 3. Hostname/alias
+            if host.hostname.is_some() && host.hostname.clone().unwrap().contains(':') {
+                host.hostname.clone().unwrap()
+            } else if let Some(hostname) = cfg_openssh.as_ref().and_then(|c| c.hostname()) {
+                hostname
+            } else {
+                host.hostname.clone().unwrap_or_else(|| host.alias.clone())
+            }
         };
+        let address = ensure_port(&hostname);
+        let proxy_jump = host
+            .proxy_jump
+            .clone()
+            .or_else(|| cfg_openssh.as_ref().and_then(|c| c.proxy_jump()));
 
         let mut sess = RawSession::new()?;
-        sess.set_tcp_stream(tcp);
-        sess.handshake()?;
+        match proxy_jump.as_deref().and_then(tunnel::hops) {
+            Some(hops) => sess.set_tcp_stream(Self::tunnel(host, &hops, &address)?),
+            None => sess.set_tcp_stream(Self::connect_tcp(&hostname, &address)?),
+        }
+        known_hosts::prefer_known_types(&sess, &address, auth.strict_host_key_checking)?;
+        {
+            let _span = timings::span("handshake");
+            sess.handshake()?;
+        }
+        known_hosts::verify(&sess, &address, auth.strict_host_key_checking)?;
 
-        let ssh_session = SshSession {
+        let mut ssh_session = SshSession {
             raw: sess,
             host,
             cfg_openssh,
+            hash_batch_size: OnceLock::new(),
+            capabilities: OnceLock::new(),
+            commands: Limit::new(host.max_parallel_commands),
+            transfers: Limit::new(host.max_parallel_transfers),
+            auth_method: OnceLock::new(),
+            connect_duration: Duration::ZERO,
+            scp_unavailable: AtomicBool::new(false),
         };
 
         ssh_session.auth(auth)?;
+        ssh_session.connect_duration = start.elapsed();
 
         if ssh_session.raw.authenticated() {
             log::trace!("Authenticated.");
             Ok(ssh_session)
         } else {
-            error!("Could not authenticate ssh session, check your authentication settings!");
-            Err(anyhow::Error::new(IOError::new(
-                ErrorKind::PermissionDenied,
-                "Authentication failed.",
-            )))
+            Err(SshError::AuthFailed {
+                user: ssh_session.get_username(),
+                host: host.alias.clone(),
+            }
+            .into())
         }
     }
 
+    fn connect_tcp(hostname: &str, address: &str) -> Result<TcpStream> {
+        log::debug!("Connecting to: {}", address);
+        let addresses: Vec<_> = {
+            let _span = timings::span(format!("resolve {}", address));
+            address
+                .to_socket_addrs()
+                .map_err(|_| {
+                    SshError::UnknownHost(hostname.split(':').next().unwrap().to_string())
+                })?
+                .collect()
+        };
+        let _span = timings::span(format!("connect {}", address));
+        TcpStream::connect(&addresses[..]).map_err(|e| match e.kind() {
+            ErrorKind::ConnectionRefused => SshError::ConnectionRefused(address.to_string()).into(),
+            ErrorKind::TimedOut => SshError::TimedOut(address.to_string()).into(),
+            _ => anyhow::Error::new(e).context(format!("Could not connect to {}", address)),
+        })
+    }
+
+    /// Connect to the last of the given jump hosts (through the others) and open a tunnel to the
+    /// given address from there.
+    fn tunnel(host: &Host, hops: &[&str], address: &str) -> Result<UnixStream> {
+        let (hop, previous) = hops.split_last().context("No jump host given.")?;
+        let (user, hostname) = tunnel::split_user(hop);
+        // Jump hosts are authenticated like the remote site, but never with its password.
+        let jump = Host {
+            alias: hostname.to_string(),
+            hostname: Some(hostname.to_string()),
+            user: user.map(String::from),
+            password: None,
+            proxy_jump: Some(if previous.is_empty() {
+                "none".to_string()
+            } else {
+                previous.join(",")
+            }),
+            ..host.clone()
+        };
+        log::debug!("Connecting to {} via jump host {}", address, hop);
+        let session = SshSession::connect(&jump)
+            .with_context(|| format!("Could not connect to jump host {}", hop))?;
+        let (target, port) = known_hosts::split_address(address)?;
+        let channel = {
+            let _span = timings::span(format!("tunnel {}", address));
+            session
+                .raw
+                .channel_direct_tcpip(target, port, None)
+                .with_context(|| format!("Could not reach {} from jump host {}", address, hop))?
+        };
+        Ok(tunnel::spawn(&session.raw, channel)?)
+    }
+
     pub fn exec_remote(&self, cmd: &str) -> Result<ExecutedRemoteCommand> {
+        if !self.host.exec {
+            debug!("Not executing (`exec: false`): {}", cmd);
+            bail!(SshError::ExecDisabled(self.host.alias.clone()));
+        }
+        let _slot = self.commands.acquire();
         ExecutedRemoteCommand::new(self, cmd)
     }
 
+    /// Send a keepalive message if one is due so that the server does not drop an idle session.
+    ///
+    /// Returns the number of seconds until the next keepalive message needs to be sent.
+    pub fn keepalive(&self, interval: u32) -> Result<u32> {
+        self.raw.set_keepalive(true, interval);
+        Ok(self.raw.keepalive_send()?)
+    }
+
     /// Get listing of files
     pub fn list_files(&self) -> Result<FileListing> {
         FileListing::new(&self)
     }
 
+    /// Tools available on the remote site, probed on first use and cached locally afterwards.
+    pub fn capabilities(&self) -> Result<&Capabilities> {
+        if let Some(capabilities) = self.capabilities.get() {
+            return Ok(capabilities);
+        }
+        let capabilities = match Capabilities::load(&self.host.alias)? {
+            Some(capabilities) if self.host.exec => capabilities,
+            _ => self.probe_capabilities()?,
+        };
+        Ok(self.capabilities.get_or_init(|| capabilities))
+    }
+
+    /// Whether to list, stat and delete files via SFTP because the remote site runs busybox or
+    /// does not allow to execute commands at all (see `shell`/`exec` in the host config).
+    fn via_sftp(&self) -> Result<bool> {
+        Ok(!self.host.exec || self.host.busybox || self.capabilities()?.busybox)
+    }
+
+    /// Probe the tools available on the remote site (ignoring cached results) and cache them.
+    pub fn probe_capabilities(&self) -> Result<Capabilities> {
+        if !self.host.exec {
+            return Ok(Capabilities::none());
+        }
+        let probed = self
+            .exec_remote(&Capabilities::probe_command())?
+            .expect("Could not probe remote capabilities.")?;
+        let capabilities = Capabilities::parse(probed.stdout())?;
+        debug!("Remote capabilities: {:?}", capabilities);
+        capabilities.store(&self.host.alias);
+        Ok(capabilities)
+    }
+
+    /// Check whether the base folder of the current host exists.
+    pub fn base_folder_exists(&self) -> Result<bool> {
+        if !self.host.exec {
+            return Ok(self
+                .raw
+                .sftp()?
+                .stat(self.sftp_folder())
+                .map(|stat| stat.is_dir())
+                .unwrap_or(false));
+        }
+        Ok(self
+            .exec_remote(&format!("[ -d '{}' ]", self.host.folder.display()))?
+            .exit_status()
+            == 0)
+    }
+
+    /// Create the base folder of the current host (including missing parents), assigning the
+    /// configured group and setgid-bit (see `make_upload_folder`).
+    pub fn create_base_folder(&self) -> Result<()> {
+        if !self.host.exec {
+            return make_folders(&self.raw.sftp()?, Path::new("/"), self.sftp_folder());
+        }
+        let folder = util::shell_quote(&self.host.folder.to_string_lossy());
+        let cmd = match self.host.group.as_deref() {
+            Some(group) => format!(
+                "mkdir -p {folder} && chgrp {group} {folder} && chmod g+s {folder}",
+                folder = folder,
+                group = util::shell_quote(group)
+            ),
+            None => format!("mkdir -p {}", folder),
+        };
+        self.exec_remote(&cmd)?.expect_with(|_| {
+            format!(
+                "Could not create base folder: {}",
+                self.host.folder.display()
+            )
+        })?;
+        Ok(())
+    }
+
     /// Make folder on the remote site if it does not exist (relative to the current host's
     /// base-folder).
+    ///
+    /// A single `mkdir -p` is used so that concurrent uploads of the same content (e.g., from two
+    /// machines) do not fail if the folder is created in between. Missing parents of the base
+    /// folder itself are only created if `create_parent_folders` is set for the host.
     pub fn make_folder(&self, path: &Path) -> Result<()> {
-        let path = self.prepend_base_folder(path);
-        let path_str = path.display();
-        let folder_exists = {
-            let cmd = self.exec_remote(&format!("[ -d \"{}\" ]", path_str))?;
-
-            cmd.exit_status() == 0
+        let full = self.prepend_base_folder(path);
+        if !self.host.exec {
+            let sftp = self.raw.sftp()?;
+            return if self.host.create_parent_folders {
+                make_folders(&sftp, Path::new("/"), &self.sftp_path(path))
+            } else {
+                make_folders(&sftp, self.sftp_folder(), path)
+            };
+        }
+        let cmd = if self.host.create_parent_folders {
+            format!("mkdir -p {}", util::shell_quote(&full.to_string_lossy()))
+        } else {
+            format!(
+                "cd {} && mkdir -p {}",
+                util::shell_quote(&self.host.folder.to_string_lossy()),
+                util::shell_quote(&path.to_string_lossy())
+            )
         };
-
-        if !folder_exists {
-            let cmd = self.exec_remote(&format!("mkdir \"{}\"", path_str))?;
-            if cmd.exit_status() != 0 {
-                bail!(
-                    "Could not create remote folder: {} Error: {}",
-                    path_str,
-                    cmd.stderr()
-                )
-            }
+        let cmd = self.exec_remote(&cmd)?;
+        if cmd.exit_status() != 0 {
+            bail!(
+                "Could not create remote folder: {} Error: {}",
+                full.display(),
+                cmd.stderr()
+            )
         }
         Ok(())
     }
 
+    /// Make folder for an upload on the remote site (relative to the current host's base-folder)
+    /// so that everything placed in it belongs to the host's `group` right away.
+    ///
+    /// The folder is assigned the group and the setgid-bit, so files created in it inherit the
+    /// group without any window in which the webserver cannot read them. Returns false if that
+    /// was not possible and the group needs to be adjusted after the upload instead (see
+    /// `adjust_group`).
+    ///
+    /// Without remote commands (`exec: false`), the group can only be inherited from a setgid base
+    /// folder.
+    pub fn make_upload_folder(&self, path: &Path) -> Result<bool> {
+        self.make_folder(path)?;
+        let group = match self.host.group.as_deref() {
+            Some(group) if self.host.exec => group,
+            _ => return Ok(true),
+        };
+        let folder = self.prepend_base_folder(path);
+        let cmd = self.exec_remote(&format!(
+            "chgrp {group} {folder} && chmod g+s {folder}",
+            group = util::shell_quote(group),
+            folder = util::shell_quote(&folder.to_string_lossy())
+        ))?;
+        if cmd.exit_status() != 0 {
+            debug!(
+                "Could not set group '{}' on {}, adjusting it after upload: {}",
+                group,
+                folder.display(),
+                cmd.stderr().trim()
+            );
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
     /// Make remote file on remote side and return path to it.
     pub fn mktemp(&self) -> Result<Tempfile> {
         let tmp = self
@@ -350,6 +1260,7 @@ impl<'a> SshSession<'a> {
     /// Get all available authentication methods
     pub fn get_auth_methods(&self) -> Result<HashSet<String>> {
         log::trace!("Getting auth methods.");
+        let _span = timings::span("auth: query methods");
         let methods = self
             .raw
             .auth_methods(&self.get_username())?
@@ -378,6 +1289,32 @@ impl<'a> SshSession<'a> {
             .map(|v| v.into_iter().next().unwrap())
     }
 
+    /// Number of paths to hand to `get_remote_hashes` at once.
+    ///
+    /// Unless configured, it is chosen such that the round trip of each remote invocation is
+    /// small compared to hashing (small) files: Connections with high latency hash more files per
+    /// invocation.
+    pub fn hash_batch_size(&self) -> Result<usize> {
+        if let Some(size) = self.host.hash_batch_size {
+            return Ok(size);
+        }
+        if let Some(size) = self.hash_batch_size.get() {
+            return Ok(*size);
+        }
+        let start = Instant::now();
+        self.exec_remote("true")?
+            .expect("Could not measure latency.")?;
+        let latency = start.elapsed();
+        let size = (latency.as_millis() as usize * HASH_BATCH_PER_MS)
+            .clamp(HASH_BATCH_SIZE_MIN, HASH_BATCH_SIZE_MAX);
+        debug!(
+            "Round trip took {} ms, hashing {} files per invocation.",
+            latency.as_millis(),
+            size
+        );
+        Ok(*self.hash_batch_size.get_or_init(|| size))
+    }
+
     /// Get hash of the remote file (relative to the current host's base-folder).
     pub fn get_remote_hashes(&self, paths: &[&Path], length: u8) -> Result<Vec<String>> {
         let mut paths: Vec<String> = paths
@@ -386,28 +1323,18 @@ impl<'a> SshSession<'a> {
             .map(|p| format!("\"{}\"", p.display()))
             .collect();
         let num_paths = paths.len();
-        let hasher = if length == 0 {
-            bail!("Length cannot be zero!");
-        } else if length <= 32 {
-            "sha256sum"
-        } else if length <= 64 {
-            "sha512sum"
-        } else {
-            bail!("Length should be equal to or smaller than 64.");
-        };
-        paths.insert(0, hasher.to_string());
+        let hasher = self.capabilities()?.hasher(length)?;
+        paths.insert(0, hasher.clone());
 
         let cmd = paths.join(" ");
         let cmd_remote_hashes = self.exec_remote(&cmd)?.expect_with(|rc| match rc {
-            127 => format!("{} not found on remote site.", hasher),
+            127 => format!("`{}` not found on remote site.", hasher),
             _ => String::from("Unexpected remote error."),
         })?;
         let hashes: Vec<_> = cmd_remote_hashes
             .stdout
             .lines()
-            .filter_map(|l| l.split_whitespace().next())
-            .filter_map(|h| hex::decode(h).ok())
-            .map(|h| base64::encode_config(h, base64::URL_SAFE)[..length as usize].to_string())
+            .filter_map(|l| encode_remote_hash(l, length))
             .collect();
 
         if hashes.len() != num_paths {
@@ -417,6 +1344,95 @@ impl<'a> SshSession<'a> {
         Ok(hashes)
     }
 
+    /// Get hashes of the contents of the given remote files (relative to the current host's
+    /// base-folder) after piping each through its decompressor, in a single remote invocation.
+    pub fn get_remote_hashes_decompressed(
+        &self,
+        files: &[(&Path, &str)],
+        length: u8,
+    ) -> Result<Vec<String>> {
+        let hasher = self.capabilities()?.hasher(length)?;
+        // Failing to decompress yields the hash of no data, which does not match either.
+        let cmd = files
+            .iter()
+            .map(|(path, decompressor)| {
+                format!(
+                    "{} < {} | {}",
+                    decompressor,
+                    util::shell_quote(&self.prepend_base_folder(path).to_string_lossy()),
+                    hasher
+                )
+            })
+            .join("; ");
+        let hashed = self
+            .exec_remote(&cmd)?
+            .expect("Could not hash decompressed files.")?;
+        let hashes: Vec<_> = hashed
+            .stdout()
+            .lines()
+            .filter_map(|l| encode_remote_hash(l, length))
+            .collect();
+        if hashes.len() != files.len() {
+            bail!(
+                "Computed {} hashes for {} decompressed files.",
+                hashes.len(),
+                files.len()
+            );
+        }
+        Ok(hashes)
+    }
+
+    /// Short preview of the content of each given file (relative to the current host's
+    /// base-folder): Its MIME type (if `file` is available on the remote site) and first bytes.
+    pub fn preview(&self, paths: &[&Path]) -> Result<Vec<String>> {
+        let mime_types: Vec<Option<String>> = if self.host.exec && self.capabilities()?.has("file")
+        {
+            let mut mime_types = Vec::with_capacity(paths.len());
+            for chunk in paths.chunks(self.hash_batch_size()?) {
+                let quoted: Vec<String> = chunk
+                    .iter()
+                    .map(|p| util::shell_quote(&self.prepend_base_folder(p).to_string_lossy()))
+                    .collect();
+                let detected = self
+                    .exec_remote(&format!("file -b --mime-type -- {}", quoted.join(" ")))?
+                    .expect("Could not detect MIME types.")?;
+                let lines: Vec<_> = detected.stdout().lines().collect();
+                if lines.len() != chunk.len() {
+                    bail!(
+                        "Detected {} MIME types for {} files.",
+                        lines.len(),
+                        chunk.len()
+                    );
+                }
+                mime_types.extend(lines.into_iter().map(|l| Some(l.trim().to_string())));
+            }
+            mime_types
+        } else {
+            vec![None; paths.len()]
+        };
+
+        let sftp = self.raw.sftp()?;
+        paths
+            .iter()
+            .zip(mime_types)
+            .map(|(path, mime_type)| {
+                let mut head = Vec::with_capacity(PREVIEW_BYTES);
+                sftp.open(self.sftp_path(path))
+                    .map_err(anyhow::Error::from)
+                    .and_then(|file| Ok(file.take(PREVIEW_BYTES as u64).read_to_end(&mut head)?))
+                    .with_context(|| format!("Could not read remote file: {}", path.display()))?;
+                let head: String = String::from_utf8_lossy(&head)
+                    .chars()
+                    .map(|c| if c.is_control() { '.' } else { c })
+                    .collect();
+                Ok(match mime_type {
+                    Some(mime_type) => format!("{} \"{}\"", mime_type, head),
+                    None => format!("\"{}\"", head),
+                })
+            })
+            .collect()
+    }
+
     pub fn prepend_base_folder(&self, path: &Path) -> PathBuf {
         let mut buf = PathBuf::new();
         buf.push(&self.host.folder);
@@ -424,20 +1440,221 @@ impl<'a> SshSession<'a> {
         buf
     }
 
+    /// Base folder of the current host as seen via SFTP/SCP (see `remote_path`).
+    fn sftp_folder(&self) -> &Path {
+        self.host
+            .remote_path
+            .as_deref()
+            .unwrap_or(&self.host.folder)
+    }
+
+    /// Prepend the base folder as seen via SFTP/SCP to the given path.
+    fn sftp_path(&self, path: &Path) -> PathBuf {
+        self.sftp_folder().join(path)
+    }
+
+    /// Move the given folder (relative to the current host's base-folder) to a new name that must
+    /// not exist yet and update all named symlinks pointing into it.
+    pub fn move_folder(&self, old: &Path, new: &Path) -> Result<()> {
+        util::ensure_inside_base_folder(old)?;
+        util::ensure_inside_base_folder(new)?;
+        let folder_old = self.prepend_base_folder(old);
+        let folder_new = self.prepend_base_folder(new);
+        self.exec_remote(&format!(
+            "[ ! -e {new} ] && mv {old} {new}",
+            old = util::shell_quote(&folder_old.to_string_lossy()),
+            new = util::shell_quote(&folder_new.to_string_lossy())
+        ))?
+        .expect_with(|_| format!("Could not move to {} (already exists?)", new.display()))?;
+        cache::update(&self.host.alias, |c| c.move_folder(old, new));
+
+        let links_folder = self.prepend_base_folder(&self.host.links_folder);
+        // Rewrite the targets via parameter expansion: quoted parts of patterns match literally,
+        // so no characters in the folder names need escaping (unlike with sed).
+        self.exec_remote(&format!(
+            "old={old}; new={new}; {links} \
+                *\"$old/\"*) target=\"$(readlink \"$link\")\"; \
+                ln -sfn \"${{target%%\"$old/\"*}}$new/${{target#*\"$old/\"}}\" \"$link\";; \
+            esac; done",
+            links = links_pointing(&links_folder),
+            old = util::shell_quote(&old.to_string_lossy()),
+            new = util::shell_quote(&new.to_string_lossy())
+        ))?
+        .expect("Could not update symlinks to moved folder.")?;
+        Ok(())
+    }
+
     /// Remove the given folder and its contents (relative to the current host's base-folder)
+    ///
+    /// Paths leaving the base folder are refused (see `util::ensure_inside_base_folder`).
     pub fn remove_folder(&self, path: &Path) -> Result<()> {
+        self.remove_folder_reporting(path, &mut |line| info!("{}", line))
+            .map(|_| ())
+    }
+
+    /// Like `remove_folder`, but report every removed path (as printed by `rm -v`) as soon as it
+    /// is removed. Returns the number of removed paths.
+    pub fn remove_folder_reporting(
+        &self,
+        path: &Path,
+        removed: &mut dyn FnMut(&str),
+    ) -> Result<usize> {
+        util::ensure_inside_base_folder(path)?;
+        cache::update(&self.host.alias, |c| c.remove_folder(path));
+        cache::Expirations::forget_folder(&self.host.alias, path);
+        let mut count = 0;
+        let mut removed = |line: &str| {
+            count += 1;
+            removed(line);
+        };
+        if self.via_sftp()? {
+            let path = self.sftp_path(path);
+            let sftp = self.raw.sftp()?;
+            // Never follow symlinks into folders that might lie outside the base folder.
+            if sftp.lstat(&path).map(|s| s.is_dir()).unwrap_or(false) {
+                debug!("Removing: {}", path.display());
+                remove_recursively(&sftp, &path, &mut removed).with_context(|| {
+                    format!("Could not remove remote folder: {}", path.display())
+                })?;
+            }
+            self.remove_links_to(&path)?;
+            return Ok(count);
+        }
         let path = self.prepend_base_folder(path);
         let path_str = path.display();
         debug!("Removing: {}", path_str);
-        let cmd = format!("[ -d \"{}\" ] && rm -rvf \"{}\"", path_str, path_str);
-        let mut channel = self.raw.channel_session()?;
-        channel
-            .exec(&cmd)
-            .with_context(|| format!("Could not remove remote folder: {}", path_str))?;
-        let mut s = String::new();
-        channel.read_to_string(&mut s)?;
-        for l in s.lines() {
-            info!("{}", l);
+        let quoted = util::shell_quote(&path_str.to_string());
+        let cmd = format!("[ -d {} ] && rm -rvf {}", quoted, quoted);
+        {
+            let _slot = self.commands.acquire();
+            let _span = timings::span(format!("exec: {}", cmd));
+            let start = Instant::now();
+            let mut channel = self.raw.channel_session()?;
+            channel
+                .exec(&cmd)
+                .with_context(|| format!("Could not remove remote folder: {}", path_str))?;
+            // Report lines while `rm` is still running, removing large folders takes a while.
+            let mut output = String::new();
+            for line in BufReader::new(&mut channel).lines() {
+                let line = line?;
+                removed(&line);
+                output.push_str(&line);
+                output.push('\n');
+            }
+            trace::record_channel(&self.host.alias, &cmd, start, &mut channel, &output);
+        }
+        self.remove_links_to(&path)?;
+        Ok(count)
+    }
+
+    /// Number of paths (including the folder itself) below each of the given folders (relative to
+    /// the current host's base-folder), i.e. the number of lines `rm -rv` prints when removing
+    /// them. None if it cannot be determined via remote commands.
+    pub fn count_entries(&self, folders: &[&Path]) -> Result<Option<Vec<u64>>> {
+        if folders.is_empty() || self.via_sftp()? {
+            return Ok(None);
+        }
+        let quoted = folders
+            .iter()
+            .map(|folder| {
+                util::shell_quote(&self.prepend_base_folder(folder).display().to_string())
+            })
+            .join(" ");
+        let counted = self
+            .exec_remote(&format!(
+                "for f in {}; do find \"$f\" | wc -l; done",
+                quoted
+            ))?
+            .expect("Could not count remote files.")?;
+        let counts = counted
+            .stdout()
+            .lines()
+            .map(|line| line.trim().parse())
+            .collect::<Result<Vec<u64>, _>>()
+            .context("Could not parse number of remote files.")?;
+        if counts.len() != folders.len() {
+            bail!("Could not count remote files.");
+        }
+        Ok(Some(counts))
+    }
+
+    /// Overwrite all files in the given folder (relative to the current host's base-folder) with
+    /// zeros, e.g. before removing it via `remove_folder`.
+    ///
+    /// Uses `shred` on the remote site if available and `dd` otherwise (or SFTP if commands
+    /// cannot be executed). Symlinks are never followed.
+    pub fn shred_folder(&self, path: &Path) -> Result<()> {
+        util::ensure_inside_base_folder(path)?;
+        if self.via_sftp()? {
+            let path = self.sftp_path(path);
+            let sftp = self.raw.sftp()?;
+            if sftp.lstat(&path).map(|s| s.is_dir()).unwrap_or(false) {
+                debug!("Shredding: {}", path.display());
+                shred_recursively(&sftp, &path).with_context(|| {
+                    format!("Could not overwrite remote folder: {}", path.display())
+                })?;
+            }
+            return Ok(());
+        }
+        let overwrite = if self.capabilities()?.has("shred") {
+            "shred -n 0 -z -- \"$f\""
+        } else {
+            "dd if=/dev/zero of=\"$f\" bs=4096 \
+                count=$(( ($(wc -c < \"$f\") + 4095) / 4096 )) conv=notrunc 2>/dev/null"
+        };
+        let folder = util::shell_quote(&self.prepend_base_folder(path).to_string_lossy());
+        debug!("Shredding: {}", folder);
+        let shredded = self
+            .exec_remote(&format!(
+                "[ -d {folder} ] || exit 0; \
+                find {folder} -type f -exec sh -c 'for f; do {overwrite} || exit 1; echo \"$f\"; done' \
+                sh {{}} + && sync",
+                folder = folder,
+                overwrite = overwrite
+            ))?
+            .expect_with(|_| format!("Could not overwrite remote folder: {}", path.display()))?;
+        for l in shredded.stdout().lines() {
+            info!("shredded '{}'", l);
+        }
+        Ok(())
+    }
+
+    /// Remove all named symlinks in the host's links folder pointing into the given (absolute)
+    /// folder.
+    fn remove_links_to(&self, folder: &Path) -> Result<()> {
+        let name = match folder.file_name() {
+            Some(name) => name.to_string_lossy(),
+            None => return Ok(()),
+        };
+        if self.via_sftp()? {
+            let links_folder = self.sftp_path(&self.host.links_folder);
+            let sftp = self.raw.sftp()?;
+            let listed = match sftp.readdir(&links_folder) {
+                Ok(listed) => listed,
+                Err(_) => return Ok(()),
+            };
+            for (link, stat) in listed {
+                if !stat.file_type().is_symlink() {
+                    continue;
+                }
+                let target = sftp.readlink(&link)?;
+                if target.to_string_lossy().contains(&format!("{}/", name)) {
+                    sftp.unlink(&link)?;
+                    info!("Removed link: {}", link.display());
+                }
+            }
+            return Ok(());
+        }
+        let links_folder = self.prepend_base_folder(&self.host.links_folder);
+        let removed = self
+            .exec_remote(&format!(
+                "{links} *{name}/*) echo \"$link\"; rm \"$link\";; esac; done",
+                links = links_pointing(&links_folder),
+                name = util::shell_quote(&name)
+            ))?
+            .expect("Could not remove symlinks to removed folder.")?;
+        for l in removed.stdout().lines() {
+            info!("Removed link: {}", l);
         }
         Ok(())
     }
@@ -447,29 +1664,40 @@ impl<'a> SshSession<'a> {
         &self,
         paths: I,
     ) -> Result<Vec<FileStat>> {
-        if self.stat_bulk_available()? {
-            self.stat_bulk(paths)
-        } else {
-            self.stat_fallback(paths)
+        let capabilities = self.capabilities()?;
+        if !self.via_sftp()? && capabilities.has("find") && capabilities.has("xargs") {
+            if let Some(stat) = capabilities.stat_command() {
+                return self.stat_bulk(paths, &format!("{} {}", capabilities.xargs(), stat));
+            }
         }
+        self.stat_fallback(paths)
     }
 
     /// Get stats about a remote files (relative to the current host's base-folder)
     ///
-    /// Faster version getting relevant information en bulk via find and xargs.
+    /// Faster version getting relevant information en bulk via find and xargs (`stat` is the full
+    /// command receiving the null-separated paths).
     pub fn stat_bulk<'b, I: IntoIterator<Item = &'b Path> + Clone>(
         &self,
         paths: I,
+        stat: &str,
     ) -> Result<Vec<FileStat>> {
         // It is easier to simply check all files and then filter later..
+        let _slot = self.commands.acquire();
         let mut channel = self.raw.channel_session()?;
         let cmd = format!(
-            "find '{}' -mindepth 2 -maxdepth 2 -type f -print0 | xargs -0 stat -c '%Y %s %n'",
-            &self.host.folder.display()
+            "find '{}' {} -print0 | {}",
+            &self.host.folder.display(),
+            self.host.layout.find_filter(),
+            stat
         );
+        let span = timings::span(format!("exec: {}", cmd));
+        let start = Instant::now();
         channel.exec(&cmd)?;
         let mut raw = String::new();
         channel.read_to_string(&mut raw)?;
+        trace::record_channel(&self.host.alias, &cmd, start, &mut channel, &raw);
+        drop(span);
 
         // Generate stats for all retrieved files
         let stats_map: HashMap<_, _> = raw
@@ -518,7 +1746,7 @@ impl<'a> SshSession<'a> {
         debug!("Getting remote stats (fallback)…");
         let paths: Vec<_> = paths.into_iter().collect();
 
-        let bar = ProgressBar::new(paths.len() as u64);
+        let bar = crate::cli::progress_bar(paths.len() as u64);
         bar.set_style(
             crate::cli::style_progress_bar_count().expect("couldn't create progress bar"),
         );
@@ -527,7 +1755,7 @@ impl<'a> SshSession<'a> {
         let sftp = self.raw.sftp()?;
         let mut filestats = Vec::with_capacity(paths.len());
         for elem in paths.iter().progress_with(bar) {
-            filestats.push(sftp.stat(&self.prepend_base_folder(elem))?);
+            filestats.push(sftp.stat(&self.sftp_path(elem))?);
         }
         debug!("Getting remote stats (fallback)… done");
         Ok(filestats)
@@ -535,7 +1763,7 @@ impl<'a> SshSession<'a> {
 
     /// Get stat for a single remote file (relative to base folder).
     pub fn stat_single(&self, path: &Path) -> Result<FileStat> {
-        let path = self.prepend_base_folder(path);
+        let path = self.sftp_path(path);
         let sftp = self.raw.sftp()?;
         Ok(sftp.stat(&path)?)
     }
@@ -547,12 +1775,11 @@ impl<'a> SshSession<'a> {
         path_local: &Path,
         path_remote: &Path,
         limit_speed_bytes_per_second: Option<usize>,
-    ) -> Result<()> {
-        let path_remote = self.prepend_base_folder(path_remote);
+    ) -> Result<String> {
         debug!(
             "Uploading: '{}' → '{}'",
             path_local.display(),
-            path_remote.display()
+            self.prepend_base_folder(path_remote).display()
         );
         let local_file = File::open(path_local).context("Could not open local file.")?;
         let size = local_file
@@ -560,24 +1787,76 @@ impl<'a> SshSession<'a> {
             .context("Could not get metadata of local file.")?
             .len();
 
-        let mut remote_file = {
-            match self.raw.scp_send(&path_remote, 0o644, size, None) {
-                Ok(file) => file,
-                Err(error) => bail!(format!(
-                    "Could not create remote file: {} Error: {}",
-                    path_remote.display(),
-                    error
-                )),
+        self.upload_reader(local_file, size, path_remote, limit_speed_bytes_per_second)
+    }
+
+    /// Create the given remote file (as seen via SFTP) to write `size` bytes to, with the given
+    /// permissions.
+    ///
+    /// Uses SCP or SFTP according to `transfer_protocol`, falling back to SFTP for servers on
+    /// which SCP is disabled. Hosts without remote commands always use SFTP.
+    fn send_file(&self, path: &Path, mode: i32, size: u64) -> Result<Box<dyn Write>, ssh2::Error> {
+        let protocol = if self.host.exec {
+            self.host.transfer_protocol
+        } else {
+            TransferProtocol::Sftp
+        };
+        if protocol != TransferProtocol::Sftp && !self.scp_unavailable.load(Ordering::Relaxed) {
+            match self.raw.scp_send(path, mode, size, None) {
+                Ok(channel) => return Ok(Box::new(channel)),
+                Err(error) if protocol == TransferProtocol::Auto => {
+                    debug!(
+                        "Sending {} via SCP failed, falling back to SFTP: {}",
+                        path.display(),
+                        error
+                    );
+                    self.scp_unavailable.store(true, Ordering::Relaxed);
+                }
+                Err(error) => return Err(error),
             }
+        }
+        let sftp = self.raw.sftp()?;
+        let file = sftp.open_mode(
+            path,
+            OpenFlags::WRITE | OpenFlags::CREATE | OpenFlags::TRUNCATE,
+            mode,
+            OpenType::File,
+        )?;
+        Ok(Box::new(file))
+    }
+
+    /// Upload `size` bytes read from `reader` to the given remote path (relative to the current
+    /// host's base-folder).
+    ///
+    /// Returns the hash (with the host's prefix length) of the bytes actually written, so that it
+    /// can be compared against the hash computed beforehand without hashing on the remote site.
+    pub fn upload_reader<R: Read>(
+        &self,
+        reader: R,
+        size: u64,
+        path_remote: &Path,
+        limit_speed_bytes_per_second: Option<usize>,
+    ) -> Result<String> {
+        let path_remote = self.sftp_path(path_remote);
+        let _slot = self.transfers.acquire();
+        let _span = timings::span(format!("transfer {}", path_remote.display()));
+
+        let mut remote_file = match self.send_file(&path_remote, 0o644, size) {
+            Ok(file) => file,
+            Err(error) => bail!(format!(
+                "Could not create remote file: {} Error: {}",
+                path_remote.display(),
+                error
+            )),
         };
 
-        let bar = ProgressBar::new(local_file.metadata()?.len());
+        let bar = crate::cli::progress_bar(size);
         bar.set_style(
             crate::cli::style_progress_bar_transfer().expect("couldn't create progress bar"),
         );
-        let mut reader = BufReader::new(local_file);
+        let mut reader = BufReader::new(reader);
+        let mut digest = util::StreamingHash::new(self.host.prefix_length)?;
 
-        let start = Instant::now();
         let mut written_total = 0;
         let mut ui_update_last = Instant::now();
         let mut ui_update_written: u128 = 0;
@@ -585,18 +1864,38 @@ impl<'a> SshSession<'a> {
 
         let timestep = Duration::from_millis(50);
 
+        // An explicitly given limit takes precedence over the configured schedule, which is
+        // re-evaluated periodically during long uploads.
+        let limit_current =
+            || limit_speed_bytes_per_second.or_else(|| self.host.limit_schedule.current_limit());
+        let limit_check_every = Duration::from_secs(1);
+        let mut limit = limit_current();
+        let mut limit_checked = Instant::now();
+        let mut limit_start = Instant::now();
+        let mut limit_written: u128 = 0;
+
         loop {
             let now = Instant::now();
+            if now.duration_since(limit_checked) > limit_check_every {
+                let updated = limit_current();
+                if updated != limit {
+                    debug!("Changing upload limit to {:?} bytes/s", updated);
+                    limit = updated;
+                    limit_start = now;
+                    limit_written = 0;
+                }
+                limit_checked = now;
+            }
             let buf = &reader.fill_buf()?;
-            let to_write = match limit_speed_bytes_per_second {
+            let to_write = match limit {
                 None => buf.len(),
                 Some(limit_bytes_per_sec) => {
-                    let total_duration = now.duration_since(start);
+                    let total_duration = now.duration_since(limit_start);
                     if total_duration.as_micros() == 0 {
                         buf.len()
                     } else {
                         let current_avg_bytes_per_sec =
-                            written_total * 1_000_000 / total_duration.as_micros();
+                            limit_written * 1_000_000 / total_duration.as_micros();
 
                         if current_avg_bytes_per_sec > limit_bytes_per_sec as u128 {
                             // crude limit -> if we exceed speed limit just sleep
@@ -616,12 +1915,14 @@ impl<'a> SshSession<'a> {
                     .write(&buf[..to_write])
                     .context("Failed to write chunk to remote file.")?;
 
-                if limit_speed_bytes_per_second.is_some() {
+                if limit.is_some() {
                     remote_file.flush()?;
                 }
+                digest.update(&buf[..written]);
                 reader.consume(written);
                 log::trace!("Wrote {} bytes", written);
                 written_total += written as u128;
+                limit_written += written as u128;
 
                 if now.duration_since(ui_update_last) > ui_update_every {
                     bar.inc((written_total - ui_update_written) as u64);
@@ -633,22 +1934,56 @@ impl<'a> SshSession<'a> {
             }
         }
 
-        Ok(())
+        if written_total != size as u128 {
+            bail!(
+                "Uploaded {} bytes to {} but expected {}.",
+                written_total,
+                path_remote.display(),
+                size
+            );
+        }
+
+        Ok(digest.finish())
     }
 
-    /// Check if necessary utilities for fast stat generation are available.
-    fn stat_bulk_available(&self) -> Result<bool> {
-        let mut channel = self.raw.channel_session()?;
-        let check = "which find && which xargs && which stat";
-        channel.exec(check)?;
-        Ok(channel.exit_status()? == 0)
+    /// Create or replace a symlink at `link` pointing to `target` (both relative to the current
+    /// host's base-folder). The symlink target is stored relative to the link location.
+    pub fn symlink(&self, target: &Path, link: &Path) -> Result<()> {
+        if link.file_name().is_none()
+            || link
+                .components()
+                .any(|c| !matches!(c, std::path::Component::Normal(_)))
+        {
+            bail!("Invalid link name: {}", link.display());
+        }
+        let target_relative: PathBuf = (1..link.components().count())
+            .map(|_| "..")
+            .collect::<PathBuf>()
+            .join(target);
+        let link = self.prepend_base_folder(link);
+
+        self.exec_remote(&symlink_command(&target_relative, &link))?
+            .expect("Could not create remote symlink.")?;
+        Ok(())
     }
 }
 
+/// Shell command creating (or replacing) `link` pointing to `target`, creating parent folders.
+fn symlink_command(target: &Path, link: &Path) -> String {
+    format!(
+        "mkdir -p {} && ln -sfn {} {}",
+        util::shell_quote(&link.parent().unwrap_or(Path::new(".")).to_string_lossy()),
+        util::shell_quote(&target.to_string_lossy()),
+        util::shell_quote(&link.to_string_lossy())
+    )
+}
+
 /// Wrapper for executed remote commands
 #[derive(Debug)]
 pub struct ExecutedRemoteCommand {
     cmd: String,
+    /// Base folder of the host the command was executed on (for error messages).
+    folder: String,
     exit_status: i32,
     stdout: String,
     stderr: String,
@@ -656,6 +1991,8 @@ pub struct ExecutedRemoteCommand {
 
 impl ExecutedRemoteCommand {
     fn new(ssh: &SshSession, cmd: &str) -> Result<Self> {
+        let _span = timings::span(format!("exec: {}", cmd));
+        let start = Instant::now();
         let mut channel = ssh.raw.channel_session()?;
         log::trace!("Executing remotely: {}", cmd);
         channel
@@ -668,9 +2005,18 @@ impl ExecutedRemoteCommand {
         channel.wait_close()?;
 
         let exit_status = channel.exit_status()?;
+        trace::record(
+            &ssh.host.alias,
+            cmd,
+            start,
+            Some(exit_status),
+            &stdout,
+            &stderr,
+        );
 
         let cmd = Self {
             cmd: cmd.to_string(),
+            folder: ssh.host.folder.display().to_string(),
             stdout,
             stderr,
             exit_status,
@@ -711,6 +2057,22 @@ impl ExecutedRemoteCommand {
             self.stdout,
             self.stderr
         );
+        lazy_static::lazy_static! {
+            static ref NOT_FOUND: Regex = Regex::new(r"([^\s:]+): (command )?not found").unwrap();
+        }
+        let msg = msg.to_string();
+        if self.exit_status == 127 {
+            if let Some(tool) = NOT_FOUND.captures(&self.stderr).map(|c| c[1].to_string()) {
+                return Err(SshError::MissingTool { msg, tool }.into());
+            }
+        }
+        if self.stderr.contains("Permission denied") {
+            return Err(SshError::PermissionDenied {
+                msg,
+                folder: self.folder.clone(),
+            }
+            .into());
+        }
         bail!("{}", msg);
     }
 
@@ -754,8 +2116,7 @@ impl<'a> Tempfile<'a> {
         let size = content.len() as u64;
         let mut remote_file = self
             .session
-            .raw
-            .scp_send(&self.path, 0o755, size, None)
+            .send_file(&self.path, 0o755, size)
             .with_context(|| format!("Could not create remote file: {}", self.path.display()))?;
 
         remote_file.write_all(content.as_bytes())?;
@@ -805,3 +2166,28 @@ impl KeyboardInteractivePrompt for InteractivePrompt {
             .collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn symlink_names_are_quoted() {
+        let dir = std::env::temp_dir().join(format!("asfa-symlink-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let link = dir.join("links").join("x';touch pwned;'");
+        let status = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(symlink_command(Path::new("../target"), &link))
+            .current_dir(&dir)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        assert_eq!(
+            std::fs::read_link(&link).unwrap(),
+            PathBuf::from("../target")
+        );
+        assert!(!dir.join("pwned").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,118 @@
+//! Mini-language for user-defined output formats (`--format`).
+//!
+//! Templates consist of literal text and `{field}` placeholders. `{{` and `}}` produce literal
+//! braces and `\t`, `\n` as well as `\\` are unescaped so that templates can be written in single
+//! quotes on the command line.
+
+use anyhow::{bail, Result};
+
+/// A parsed output template.
+#[derive(Debug)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug)]
+enum Part {
+    Literal(String),
+    Field(String),
+}
+
+impl Template {
+    /// Parse the given template, only allowing fields from `known`.
+    pub fn parse(raw: &str, known: &[&str]) -> Result<Self> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = raw.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some('t') => literal.push('\t'),
+                    Some('n') => literal.push('\n'),
+                    Some('\\') => literal.push('\\'),
+                    Some(other) => {
+                        literal.push('\\');
+                        literal.push(other);
+                    }
+                    None => literal.push('\\'),
+                },
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut field = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => field.push(c),
+                            None => bail!("Unterminated field in format: {}", raw),
+                        }
+                    }
+                    if !known.contains(&field.as_str()) {
+                        bail!(
+                            "Unknown field '{{{}}}' in format, available: {}",
+                            field,
+                            known
+                                .iter()
+                                .map(|k| format!("{{{}}}", k))
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        );
+                    }
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(Part::Field(field));
+                }
+                '}' => bail!("Unmatched '}}' in format (use '}}}}' for a literal brace)."),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+        Ok(Self { parts })
+    }
+
+    /// Check if any of the given fields is used in the template.
+    pub fn uses_any(&self, fields: &[&str]) -> bool {
+        self.parts
+            .iter()
+            .any(|p| matches!(p, Part::Field(f) if fields.contains(&f.as_str())))
+    }
+
+    /// Render the template, looking up the value of each field via `lookup`.
+    pub fn render<F: FnMut(&str) -> Result<String>>(&self, mut lookup: F) -> Result<String> {
+        let mut rendered = String::new();
+        for part in self.parts.iter() {
+            match part {
+                Part::Literal(literal) => rendered.push_str(literal),
+                Part::Field(field) => rendered.push_str(&lookup(field)?),
+            }
+        }
+        Ok(rendered)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_template() {
+        let template = Template::parse(r"{index}\t{url} {{literal}}", &["index", "url"]).unwrap();
+        let rendered = template.render(|field| Ok(format!("<{}>", field))).unwrap();
+        assert_eq!(rendered, "<index>\t<url> {literal}");
+        assert!(template.uses_any(&["url"]));
+        assert!(!template.uses_any(&["size"]));
+
+        assert!(Template::parse("{unknown}", &["index"]).is_err());
+        assert!(Template::parse("{index", &["index"]).is_err());
+    }
+}
@@ -0,0 +1,263 @@
+//! Colors, frame characters and handling of long lines in terminal output, configurable via
+//! `theme`, e.g.:
+//!
+//! ```yaml
+//! theme:
+//!   frame: black.bright
+//!   filename: blue.bold
+//!   frames: ascii
+//! ```
+
+use anyhow::{bail, Result};
+use console::Style;
+use yaml_rust::{yaml::Hash, Yaml};
+
+use crate::util::{get_string_from, yaml_string};
+
+/// Attributes that can be combined with colors, see `console::Style::from_dotted_str`.
+const ATTRIBUTES: &[&str] = &[
+    "black",
+    "red",
+    "green",
+    "yellow",
+    "blue",
+    "magenta",
+    "cyan",
+    "white",
+    "bright",
+    "on_black",
+    "on_red",
+    "on_green",
+    "on_yellow",
+    "on_blue",
+    "on_magenta",
+    "on_cyan",
+    "on_white",
+    "on_bright",
+    "bold",
+    "dim",
+    "underlined",
+    "blink",
+    "reverse",
+    "strikethrough",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Theme {
+    /// Bars in histograms (see `stats`).
+    pub dot: Style,
+
+    /// Expiration dates.
+    pub expire: Style,
+
+    /// Failed checks and errors.
+    pub failure: Style,
+
+    /// File names.
+    pub filename: Style,
+
+    /// Frames around listings and column separators.
+    pub frame: Style,
+
+    /// Characters to draw frames with.
+    pub frames: Frames,
+
+    /// How lines in frames wider than the terminal are shortened.
+    pub long_lines: LongLines,
+
+    /// Successful checks.
+    pub success: Style,
+}
+
+/// Characters to draw frames with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Frames {
+    /// Box-drawing characters, e.g. `┌──┐`.
+    Unicode,
+
+    /// Plain ASCII characters for terminals without UTF-8 support, e.g. `+--+`.
+    Ascii,
+}
+
+/// How lines in frames wider than the terminal are shortened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LongLines {
+    /// Continue the last column in the next line, keeping the other columns aligned.
+    Wrap,
+
+    /// Cut off the end of the line, e.g. `https://example.com/abc/lon…`.
+    EllipsisEnd,
+
+    /// Cut out the middle of the last column, e.g. `https://exam…/long-name.pdf`.
+    EllipsisMiddle,
+}
+
+/// Characters used by `cli::draw_boxed`.
+pub struct FrameChars {
+    pub top_left: char,
+    pub top_right: char,
+    pub bottom_left: char,
+    pub bottom_right: char,
+    pub header_left: char,
+    pub header_right: char,
+    pub horizontal: char,
+    pub vertical: char,
+    pub join_top: char,
+    pub join_bottom: char,
+    /// Arrow pointing from old to new names (e.g. when renaming).
+    pub arrow: &'static str,
+}
+
+impl Frames {
+    /// Box-drawing characters unless the locale (`LC_ALL`, `LC_CTYPE` or `LANG`, whichever is set
+    /// first) does not use UTF-8.
+    pub fn detect() -> Self {
+        let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+            .iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .find(|value| !value.is_empty());
+        match locale {
+            Some(locale) if !Self::is_utf8(&locale) => Frames::Ascii,
+            _ => Frames::Unicode,
+        }
+    }
+
+    fn is_utf8(locale: &str) -> bool {
+        let locale = locale.to_lowercase();
+        locale.contains("utf-8") || locale.contains("utf8")
+    }
+
+    pub fn chars(self) -> FrameChars {
+        match self {
+            Frames::Unicode => FrameChars {
+                top_left: '┌',
+                top_right: '┐',
+                bottom_left: '└',
+                bottom_right: '┘',
+                header_left: '┤',
+                header_right: '├',
+                horizontal: '─',
+                vertical: '│',
+                join_top: '┬',
+                join_bottom: '┴',
+                arrow: "→",
+            },
+            Frames::Ascii => FrameChars {
+                top_left: '+',
+                top_right: '+',
+                bottom_left: '+',
+                bottom_right: '+',
+                header_left: '|',
+                header_right: '|',
+                horizontal: '-',
+                vertical: '|',
+                join_top: '+',
+                join_bottom: '+',
+                arrow: "->",
+            },
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            dot: Style::new().cyan(),
+            expire: Style::new().red(),
+            failure: Style::new().red().bright(),
+            filename: Style::new().blue().bright(),
+            frame: Style::new().blue(),
+            frames: Frames::detect(),
+            long_lines: LongLines::Wrap,
+            success: Style::new().green().bright(),
+        }
+    }
+}
+
+impl Theme {
+    pub fn from_yaml(dict: &Hash) -> Result<Self> {
+        let mut theme = Self::default();
+        for (key, style) in [
+            ("dot", &mut theme.dot),
+            ("expire", &mut theme.expire),
+            ("failure", &mut theme.failure),
+            ("filename", &mut theme.filename),
+            ("frame", &mut theme.frame),
+            ("success", &mut theme.success),
+        ] {
+            // Colors of the 256-color palette are read as integers.
+            match dict.get(&yaml_string(key)) {
+                None => {}
+                Some(Yaml::Integer(color)) => *style = parse_style(&color.to_string())?,
+                Some(_) => {
+                    if let Some(value) = get_string_from(dict, key)? {
+                        *style = parse_style(value)?;
+                    }
+                }
+            }
+        }
+        theme.frames = match get_string_from(dict, "frames")?.map(|s| s.as_str()) {
+            None | Some("auto") => theme.frames,
+            Some("unicode") => Frames::Unicode,
+            Some("ascii") => Frames::Ascii,
+            Some(frames) => bail!(
+                "Invalid value for 'frames' (expected 'auto', 'unicode' or 'ascii'): {}",
+                frames
+            ),
+        };
+        theme.long_lines = match get_string_from(dict, "long_lines")?.map(|s| s.as_str()) {
+            None | Some("wrap") => LongLines::Wrap,
+            Some("ellipsis-end") => LongLines::EllipsisEnd,
+            Some("ellipsis-middle") => LongLines::EllipsisMiddle,
+            Some(long_lines) => bail!(
+                "Invalid value for 'long_lines' (expected 'wrap', 'ellipsis-end' or \
+                'ellipsis-middle'): {}",
+                long_lines
+            ),
+        };
+        Ok(theme)
+    }
+}
+
+/// Parse a style given as dot-separated attributes, e.g. `blue.bold`, `208` (256-color palette)
+/// or `plain` for no styling at all.
+fn parse_style(style: &str) -> Result<Style> {
+    if style == "plain" {
+        return Ok(Style::new());
+    }
+    for part in style.split('.') {
+        let color = part.strip_prefix("on_").unwrap_or(part);
+        if !ATTRIBUTES.contains(&part) && color.parse::<u8>().is_err() {
+            bail!(
+                "Invalid style '{}': Unknown attribute '{}' (expected colors such as 'blue', \
+                'on_white' or '208' and attributes such as 'bold' or 'bright').",
+                style,
+                part
+            );
+        }
+    }
+    Ok(Style::from_dotted_str(style))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use yaml_rust::YamlLoader;
+
+    #[test]
+    fn parse_theme() {
+        let yaml =
+            YamlLoader::load_from_str("frame: black.bright\nsuccess: 34\nframes: ascii").unwrap();
+        let theme = Theme::from_yaml(yaml[0].as_hash().unwrap()).unwrap();
+        assert_eq!(theme.frame, Style::new().black().bright());
+        assert_eq!(theme.success, Style::new().color256(34));
+        assert_eq!(theme.frames, Frames::Ascii);
+
+        assert!(Frames::is_utf8("en_US.UTF-8"));
+        assert!(Frames::is_utf8("de_DE.utf8"));
+        assert!(!Frames::is_utf8("C"));
+
+        let yaml = YamlLoader::load_from_str("frame: blu").unwrap();
+        assert!(Theme::from_yaml(yaml[0].as_hash().unwrap()).is_err());
+    }
+}
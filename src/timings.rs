@@ -0,0 +1,91 @@
+//! Timing of connection phases and remote operations, to pinpoint whether slowness stems from
+//! authentication, latency or the transfer itself.
+//!
+//! Every span is logged at trace level when it ends and, if enabled via `--timings`, summarized
+//! once the command finished.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// Longest label shown in the summary, remote commands can get rather long.
+const MAX_LABEL_LENGTH: usize = 72;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static START: OnceLock<Instant> = OnceLock::new();
+static FINISHED: Mutex<Vec<Finished>> = Mutex::new(Vec::new());
+
+struct Finished {
+    label: String,
+    /// Since the start of the program.
+    start: Duration,
+    duration: Duration,
+}
+
+/// Record all spans to be summarized via `report`.
+pub fn enable() {
+    START.get_or_init(Instant::now);
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Time the given operation until the returned guard is dropped.
+pub fn span<S: Into<String>>(label: S) -> Span {
+    START.get_or_init(Instant::now);
+    Span {
+        label: label.into(),
+        start: Instant::now(),
+    }
+}
+
+#[must_use = "the span ends when dropped"]
+pub struct Span {
+    label: String,
+    start: Instant,
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        let duration = self.start.elapsed();
+        log::trace!("[timing] {}: {:.3?}", self.label, duration);
+        if ENABLED.load(Ordering::Relaxed) {
+            let start = self.start - *START.get_or_init(Instant::now);
+            if let Ok(mut finished) = FINISHED.lock() {
+                finished.push(Finished {
+                    label: std::mem::take(&mut self.label),
+                    start,
+                    duration,
+                });
+            }
+        }
+    }
+}
+
+/// Print all recorded spans in the order they started to stderr.
+pub fn report() {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let mut finished = match FINISHED.lock() {
+        Ok(finished) => finished,
+        Err(_) => return,
+    };
+    finished.sort_by_key(|f| f.start);
+    eprintln!("{:>9} {:>9}  operation", "start", "duration");
+    for span in finished.iter() {
+        let label = if span.label.chars().count() > MAX_LABEL_LENGTH {
+            let shortened: String = span.label.chars().take(MAX_LABEL_LENGTH - 1).collect();
+            format!("{}…", shortened)
+        } else {
+            span.label.clone()
+        };
+        eprintln!(
+            "{:>8.3}s {:>8.3}s  {}",
+            span.start.as_secs_f64(),
+            span.duration.as_secs_f64(),
+            label
+        );
+    }
+    if let Some(start) = START.get() {
+        eprintln!("{:>9} {:>8.3}s  total", "", start.elapsed().as_secs_f64());
+    }
+}
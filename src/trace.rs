@@ -0,0 +1,154 @@
+//! Record every remote command with its duration, exit code and (truncated) output, written as
+//! JSON once the command finished (`--trace-remote <file>`).
+//!
+//! Meant to be attached to bug reports, so that issues on unusual remote sites can be debugged
+//! without asking for terminal scrollback.
+
+use anyhow::{Context, Result};
+use chrono::Local;
+use serde::Serialize;
+use ssh2::Channel;
+use std::fs::File;
+use std::io::{BufWriter, Read};
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+
+/// Bytes of stdout/stderr kept per command.
+const MAX_OUTPUT_LENGTH: usize = 4096;
+
+static TARGET: OnceLock<PathBuf> = OnceLock::new();
+static EXECUTED: Mutex<Vec<Executed>> = Mutex::new(Vec::new());
+
+#[derive(Serialize)]
+struct Trace<'a> {
+    version: &'a str,
+    args: Vec<String>,
+    commands: &'a [Executed],
+}
+
+#[derive(Serialize)]
+struct Executed {
+    host: String,
+    command: String,
+    started: String,
+    duration_secs: f64,
+    /// `None` if the exit status was not retrieved.
+    exit_status: Option<i32>,
+    stdout: String,
+    stderr: String,
+}
+
+/// Record all remote commands, to be written to the given file via `write`.
+pub fn enable(path: PathBuf) {
+    TARGET.get_or_init(|| path);
+}
+
+/// Whether remote commands are recorded.
+pub fn enabled() -> bool {
+    TARGET.get().is_some()
+}
+
+/// Record a remote command that was started at `start` and has finished.
+pub fn record(
+    host: &str,
+    command: &str,
+    start: Instant,
+    exit_status: Option<i32>,
+    stdout: &str,
+    stderr: &str,
+) {
+    if !enabled() {
+        return;
+    }
+    let duration = start.elapsed();
+    let executed = Executed {
+        host: host.to_string(),
+        command: command.to_string(),
+        started: (Local::now() - chrono::Duration::from_std(duration).unwrap_or_default())
+            .to_rfc3339(),
+        duration_secs: duration.as_secs_f64(),
+        exit_status,
+        stdout: truncate(stdout),
+        stderr: truncate(stderr),
+    };
+    if let Ok(mut executed_all) = EXECUTED.lock() {
+        executed_all.push(executed);
+    }
+}
+
+/// Record a remote command whose stdout was already read from the given channel.
+///
+/// Stderr and the exit status are only retrieved if tracing is enabled.
+pub fn record_channel(
+    host: &str,
+    command: &str,
+    start: Instant,
+    channel: &mut Channel,
+    stdout: &str,
+) {
+    if !enabled() {
+        return;
+    }
+    let mut stderr = String::new();
+    let exit_status = channel
+        .stderr()
+        .read_to_string(&mut stderr)
+        .ok()
+        .and_then(|_| channel.wait_close().ok())
+        .and_then(|_| channel.exit_status().ok());
+    record(host, command, start, exit_status, stdout, &stderr);
+}
+
+/// Write all recorded commands to the file given to `enable` (if any).
+pub fn write() -> Result<()> {
+    let path = match TARGET.get() {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+    let executed = EXECUTED.lock().unwrap_or_else(|e| e.into_inner());
+    let trace = Trace {
+        version: clap::crate_version!(),
+        args: std::env::args().collect(),
+        commands: &executed,
+    };
+    let file =
+        File::create(path).with_context(|| format!("Could not create {}", path.display()))?;
+    serde_json::to_writer_pretty(BufWriter::new(file), &trace)
+        .with_context(|| format!("Could not write trace to {}", path.display()))?;
+    log::info!(
+        "Wrote {} remote command(s) to {}",
+        executed.len(),
+        path.display()
+    );
+    Ok(())
+}
+
+fn truncate(output: &str) -> String {
+    if output.len() <= MAX_OUTPUT_LENGTH {
+        return output.to_string();
+    }
+    let mut end = MAX_OUTPUT_LENGTH;
+    while !output.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!(
+        "{}… ({} bytes truncated)",
+        &output[..end],
+        output.len() - end
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncate_output() {
+        assert_eq!(truncate("short"), "short");
+        let long = "ä".repeat(MAX_OUTPUT_LENGTH);
+        let truncated = truncate(&long);
+        assert!(truncated.ends_with(&format!("… ({} bytes truncated)", MAX_OUTPUT_LENGTH)));
+        assert!(truncated.len() < long.len());
+    }
+}
@@ -0,0 +1,132 @@
+//! Reach the remote site through jump hosts (OpenSSH's `ProxyJump`).
+//!
+//! The connection to the remote site is tunneled through a `direct-tcpip` channel of the session
+//! with the (last) jump host. Since libssh2 needs a socket to run a session on, traffic is
+//! forwarded between the channel and one end of a local socket pair in a background thread.
+
+use ssh2::{Channel, Session};
+use std::io::{ErrorKind, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::time::Duration;
+
+/// Hops of a `ProxyJump` specification (`[user@]host[:port]`, comma-separated), `None` if
+/// jumping is disabled (`none`).
+pub fn hops(spec: &str) -> Option<Vec<&str>> {
+    let hops: Vec<&str> = spec
+        .split(',')
+        .map(str::trim)
+        .filter(|hop| !hop.is_empty())
+        .collect();
+    if hops.is_empty() || hops == ["none"] {
+        None
+    } else {
+        Some(hops)
+    }
+}
+
+/// Split a hop into user (if any) and `host[:port]`.
+pub fn split_user(hop: &str) -> (Option<&str>, &str) {
+    match hop.rsplit_once('@') {
+        Some((user, hostname)) => (Some(user), hostname),
+        None => (None, hop),
+    }
+}
+
+/// Start forwarding between the given channel and a new socket, which is returned.
+///
+/// The session the channel belongs to is switched to non-blocking mode and must not be used for
+/// anything else. Forwarding stops once either side is closed.
+pub fn spawn(session: &Session, channel: Channel) -> std::io::Result<UnixStream> {
+    let (ours, theirs) = UnixStream::pair()?;
+    theirs.set_nonblocking(true)?;
+    session.set_blocking(false);
+    std::thread::spawn(move || {
+        if let Err(e) = forward(channel, theirs) {
+            log::debug!("Tunnel closed: {}", e);
+        }
+    });
+    Ok(ours)
+}
+
+fn forward(mut channel: Channel, mut local: UnixStream) -> std::io::Result<()> {
+    let mut buffer = vec![0; 32 * 1024];
+    let mut to_remote: Vec<u8> = Vec::new();
+    let mut to_local: Vec<u8> = Vec::new();
+    loop {
+        let mut idle = true;
+
+        if to_remote.is_empty() {
+            match local.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(read) => to_remote.extend_from_slice(&buffer[..read]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
+            }
+        }
+        if !to_remote.is_empty() {
+            match channel.write(&to_remote) {
+                Ok(written) => {
+                    idle &= written == 0;
+                    to_remote.drain(..written);
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        if to_local.is_empty() {
+            match channel.read(&mut buffer) {
+                Ok(0) if channel.eof() => break,
+                Ok(read) => to_local.extend_from_slice(&buffer[..read]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
+            }
+        }
+        if !to_local.is_empty() {
+            match local.write(&to_local) {
+                Ok(written) => {
+                    idle &= written == 0;
+                    to_local.drain(..written);
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        if idle {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+    // Flush what is left towards the remote site before closing the channel.
+    while !to_remote.is_empty() {
+        match channel.write(&to_remote) {
+            Ok(written) => {
+                to_remote.drain(..written);
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                std::thread::sleep(Duration::from_millis(1))
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_hops() {
+        assert_eq!(hops("none"), None);
+        assert_eq!(
+            hops("admin@bastion:2222, gateway"),
+            Some(vec!["admin@bastion:2222", "gateway"])
+        );
+        assert_eq!(
+            split_user("admin@bastion:2222"),
+            (Some("admin"), "bastion:2222")
+        );
+        assert_eq!(split_user("gateway"), (None, "gateway"));
+    }
+}
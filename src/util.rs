@@ -3,7 +3,7 @@ use log::error;
 use std::fs::File;
 use std::io::prelude::*;
 use std::io::BufReader;
-use std::path::Path;
+use std::path::{Component, Path};
 use thiserror::Error;
 use yaml_rust::{yaml, Yaml};
 
@@ -13,30 +13,59 @@ pub struct InvalidYamlTypeError {
     key: String,
 }
 
+/// Error that makes asfa exit with a specific exit code.
+#[derive(Debug, Error)]
+#[error("{msg}")]
+pub struct ExitCodeError {
+    pub code: i32,
+    msg: String,
+}
+
+impl ExitCodeError {
+    pub fn new<T: Into<String>>(code: i32, msg: T) -> Self {
+        Self {
+            code,
+            msg: msg.into(),
+        }
+    }
+}
+
 /// Helper function static yaml strings.
 pub fn yaml_string(s: &str) -> Yaml {
     Yaml::String(String::from(s))
 }
 
+/// Format the given number of bytes with a binary unit suffix, e.g. ` 12.50M`.
+pub fn format_size(bytes: u64) -> String {
+    let possible = ["B", "K", "M", "G", "T", "P", "E"];
+    let mut size = bytes;
+    for (i, s) in possible.iter().enumerate() {
+        // If size is >= 999.5 (which we cannot detect via integer), the printed representation
+        // will be rouned to 1000.00 -> move to next higher unit at 999
+        if 999 <= size && i + 1 < possible.len() {
+            size >>= 10;
+        } else {
+            return format!(
+                "{size:>6.2}{suffix}",
+                size = bytes as f64 / (1u64 << (i * 10)) as f64,
+                suffix = s
+            );
+        }
+    }
+    unreachable!()
+}
+
 /// Get hash digest of given file with chosen length
 pub fn get_hash(path: &Path, length: u8) -> Result<String> {
-    let hash = if length == 0 {
-        bail!("Length cannot be zero!");
-    } else if length <= 32 {
-        get_explicit_hash::<sha2::Sha256>(path)?
-    } else if length <= 64 {
-        get_explicit_hash::<sha2::Sha512>(path)?
-    } else {
-        bail!("Length should be equal to or smaller than 64.");
-    };
-    Ok(hash[..length as usize].to_string())
+    get_hash_from(File::open(path)?, length)
 }
 
-fn get_explicit_hash<Hasher: sha2::Digest>(path: &Path) -> Result<String> {
-    let mut hash = Hasher::new();
-    let mut reader = BufReader::new(File::open(path)?);
+/// Get hash digest of all data read from `reader` with chosen length
+pub fn get_hash_from<R: Read>(reader: R, length: u8) -> Result<String> {
+    let mut hash = StreamingHash::new(length)?;
+    let mut reader = BufReader::new(reader);
     loop {
-        let buf = &reader.fill_buf()?;
+        let buf = reader.fill_buf()?;
         let to_write = buf.len();
         if to_write > 0 {
             hash.update(buf);
@@ -45,7 +74,108 @@ fn get_explicit_hash<Hasher: sha2::Digest>(path: &Path) -> Result<String> {
             break;
         }
     }
-    Ok(base64::encode_config(hash.finalize(), base64::URL_SAFE))
+    Ok(hash.finish())
+}
+
+/// Hash digest computed incrementally from chunks of data, e.g. while uploading them.
+///
+/// Yields the same digest as `get_hash_from` for the concatenated chunks.
+pub struct StreamingHash {
+    hasher: Hasher,
+    length: u8,
+}
+
+enum Hasher {
+    Sha256(sha2::Sha256),
+    Sha512(sha2::Sha512),
+}
+
+impl StreamingHash {
+    pub fn new(length: u8) -> Result<Self> {
+        use sha2::Digest;
+        let hasher = if length == 0 {
+            bail!("Length cannot be zero!");
+        } else if length <= 32 {
+            Hasher::Sha256(sha2::Sha256::new())
+        } else if length <= 64 {
+            Hasher::Sha512(sha2::Sha512::new())
+        } else {
+            bail!("Length should be equal to or smaller than 64.");
+        };
+        Ok(Self { hasher, length })
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        use sha2::Digest;
+        match &mut self.hasher {
+            Hasher::Sha256(hasher) => hasher.update(data),
+            Hasher::Sha512(hasher) => hasher.update(data),
+        }
+    }
+
+    /// Digest of all data so far, shortened to the chosen length.
+    pub fn finish(self) -> String {
+        use sha2::Digest;
+        let hash = match self.hasher {
+            Hasher::Sha256(hasher) => base64::encode_config(hasher.finalize(), base64::URL_SAFE),
+            Hasher::Sha512(hasher) => base64::encode_config(hasher.finalize(), base64::URL_SAFE),
+        };
+        hash[..self.length as usize].to_string()
+    }
+}
+
+/// Range of supported hash (i.e. prefix) lengths, limited by the length of base64-encoded SHA-512
+/// digests used for lengths above 32.
+pub const MIN_PREFIX_LENGTH: usize = 8;
+pub const MAX_PREFIX_LENGTH: usize = 64;
+
+/// Random string of the given length looking like a hash as returned by `get_hash_from`.
+pub fn random_token(length: u8) -> String {
+    let bytes: Vec<u8> = (0..length).map(|_| rand::random()).collect();
+    let mut token = base64::encode_config(bytes, base64::URL_SAFE);
+    token.truncate(length as usize);
+    token
+}
+
+/// Whether the given folder name could have been created by asfa, i.e. is a (possibly shortened)
+/// hash as returned by `get_hash_from`.
+pub fn is_hash_folder(name: &str) -> bool {
+    (MIN_PREFIX_LENGTH..=MAX_PREFIX_LENGTH).contains(&name.len())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Make sure the given path (relative to a host's base folder) stays inside the base folder and
+/// does not refer to the base folder itself, so that it is safe to remove or move.
+///
+/// Only the path itself is checked (it is not resolved on the remote site), i.e. absolute paths
+/// and `..` components are refused.
+pub fn ensure_inside_base_folder(path: &Path) -> Result<()> {
+    let mut depth = 0;
+    for component in path.components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir => bail!(
+                "Refusing to touch path outside of base folder: {}",
+                path.display()
+            ),
+            Component::RootDir | Component::Prefix(_) => bail!(
+                "Refusing to touch absolute path (expected path relative to base folder): {}",
+                path.display()
+            ),
+        }
+    }
+    if depth == 0 {
+        bail!("Refusing to touch the base folder itself.");
+    }
+    Ok(())
+}
+
+/// Quote the given string for use in a remote shell command.
+pub fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
 }
 
 macro_rules! make_yaml_getter {
@@ -100,3 +230,39 @@ pub mod test {
             .context("Failed to set up logger for tests.")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refuse_paths_outside_base_folder() {
+        assert!(ensure_inside_base_folder(Path::new("V66lLtli0Ei4hw3tNkCTXOcweBrneNjt")).is_ok());
+        assert!(ensure_inside_base_folder(Path::new("./abc/file.txt")).is_ok());
+        assert!(ensure_inside_base_folder(Path::new("abc/../../etc")).is_err());
+        assert!(ensure_inside_base_folder(Path::new("..")).is_err());
+        assert!(ensure_inside_base_folder(Path::new("/etc")).is_err());
+        assert!(ensure_inside_base_folder(Path::new("")).is_err());
+        assert!(ensure_inside_base_folder(Path::new("./.")).is_err());
+    }
+
+    #[test]
+    fn streaming_hash_matches() {
+        let data = b"avoid sending file attachments".repeat(1000);
+        for length in [8, 32, 64] {
+            let mut hash = StreamingHash::new(length).unwrap();
+            for chunk in data.chunks(777) {
+                hash.update(chunk);
+            }
+            assert_eq!(hash.finish(), get_hash_from(&data[..], length).unwrap());
+        }
+        assert!(StreamingHash::new(0).is_err());
+        assert!(StreamingHash::new(65).is_err());
+    }
+
+    #[test]
+    fn quote_for_shell() {
+        assert_eq!(shell_quote("a b"), "'a b'");
+        assert_eq!(shell_quote("it's $(x)"), "'it'\\''s $(x)'");
+    }
+}